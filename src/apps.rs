@@ -0,0 +1,186 @@
+// The stream an application plays is recorded with `parec` which can follow a single sink input,
+// the ALSA devices cpal opens can only record whole sinks and sources.
+
+use crate::{clock::Clock, stream::Callback, threads};
+use anyhow::{anyhow, bail, Result};
+use std::{
+    io::Read,
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// Channels an application is recorded with.
+pub const CHANNELS: u16 = 2;
+/// Frames read from `parec` at a time.
+const FRAMES_PER_READ: usize = 256;
+
+/// Parses `app:<name>` given to `--source` to the name of the application.
+pub fn parse_source(source: &str) -> Result<String> {
+    match source.split_once(':') {
+        Some(("app", name)) if !name.trim().is_empty() => Ok(name.trim().to_string()),
+        _ => bail!("Expected a source like app:Firefox."),
+    }
+}
+
+/// Records what an application plays while it is running.
+pub struct Capture {
+    child: Child,
+    playing: Arc<AtomicBool>,
+}
+
+impl Capture {
+    /// Starts recording the stream of an application as 32 bit float, samples are passed on once it plays.
+    pub fn start(
+        app: &str,
+        sample_rate: u32,
+        clock: Clock,
+        mut callback: Callback<f32>,
+    ) -> Result<Self> {
+        let sink_input = sink_input(app)?;
+        let mut child = Command::new("parec")
+            .arg(format!("--monitor-stream={sink_input}"))
+            .arg(format!("--rate={sample_rate}"))
+            .arg(format!("--channels={CHANNELS}"))
+            .args(["--format=float32le", "--raw", "--latency-msec=10"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| {
+                anyhow!("Can not run parec, is PulseAudio or PipeWire installed? {err}")
+            })?;
+        let mut stdout = child.stdout.take().expect("parec has no stdout.");
+        let playing = Arc::new(AtomicBool::new(false));
+        let playing_in_thread = Arc::clone(&playing);
+        let app = app.to_string();
+
+        std::thread::spawn(move || {
+            threads::tune(threads::Kind::Audio);
+            let mut bytes = vec![0_u8; FRAMES_PER_READ * CHANNELS as usize * 4];
+            let mut samples = Vec::with_capacity(FRAMES_PER_READ * CHANNELS as usize);
+            // Ends when parec exits, also when the capture is dropped.
+            while stdout.read_exact(&mut bytes).is_ok() {
+                if !playing_in_thread.load(Ordering::Relaxed) {
+                    continue;
+                }
+                samples.clear();
+                samples.extend(bytes.chunks_exact(4).map(|sample| {
+                    f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]])
+                }));
+                // The last frame was captured just now, the buffer started one buffer earlier.
+                #[allow(clippy::cast_possible_wrap)]
+                let captured_at =
+                    Clock::offset_by_frames(clock.now(), -(FRAMES_PER_READ as isize), sample_rate);
+                callback(&samples, captured_at);
+            }
+            if playing_in_thread.load(Ordering::Relaxed) {
+                eprintln!("Stopped recording {app}, its stream ended.");
+            }
+        });
+
+        Ok(Self { child, playing })
+    }
+
+    pub fn play(&self) {
+        self.playing.store(true, Ordering::Relaxed);
+    }
+
+    pub fn pause(&self) {
+        self.playing.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        self.pause();
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+/// Finds the sink input an application plays to.
+fn sink_input(app: &str) -> Result<u32> {
+    let output = Command::new("pactl")
+        .args(["list", "sink-inputs"])
+        .env("LC_ALL", "C")
+        .output()
+        .map_err(|err| anyhow!("Can not run pactl, is PulseAudio or PipeWire installed? {err}"))?;
+    if !output.status.success() {
+        bail!(
+            "pactl failed, is PulseAudio or PipeWire running? {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let streams = parse_sink_inputs(&String::from_utf8_lossy(&output.stdout));
+    streams
+        .iter()
+        .find(|(_, names)| names.iter().any(|name| name.eq_ignore_ascii_case(app)))
+        .map(|(index, _)| *index)
+        .ok_or_else(|| {
+            anyhow!(
+                "{app} is not playing anything, applications which play now: {}.",
+                streams
+                    .iter()
+                    .filter_map(|(_, names)| names.first().cloned())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+}
+
+/// Parses the output of `pactl list sink-inputs` to the index of every sink input and the names of its application.
+///
+/// Sink inputs start with a `Sink Input #<index>` line followed by indented `key = "value"` lines of their properties.
+fn parse_sink_inputs(output: &str) -> Vec<(u32, Vec<String>)> {
+    let mut streams: Vec<(u32, Vec<String>)> = Vec::new();
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(index) = line.strip_prefix("Sink Input #") {
+            if let Ok(index) = index.trim().parse() {
+                streams.push((index, Vec::new()));
+            }
+        } else if let Some((key, value)) = line.split_once(" = ") {
+            if let Some((_, names)) = streams.last_mut() {
+                if matches!(key, "application.name" | "application.process.binary") {
+                    names.push(value.trim().trim_matches('"').to_string());
+                }
+            }
+        }
+    }
+    streams
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sink_inputs_from_pactl() {
+        let output = r#"Sink Input #42
+	Driver: protocol-native.c
+	Sink: 0
+	Sample Specification: float32le 2ch 48000Hz
+	Properties:
+		media.name = "AudioStream"
+		application.name = "Firefox"
+		application.process.binary = "firefox"
+Sink Input #57
+	Driver: protocol-native.c
+	Properties:
+		application.name = "Chromium"
+		application.process.binary = "chromium"
+"#;
+        assert_eq!(
+            parse_sink_inputs(output),
+            [
+                (42, vec!["Firefox".to_string(), "firefox".to_string()]),
+                (57, vec!["Chromium".to_string(), "chromium".to_string()])
+            ]
+        );
+        assert_eq!(parse_source("app:Firefox").unwrap(), "Firefox");
+        assert!(parse_source("Firefox").is_err());
+        assert!(parse_source("app:").is_err());
+    }
+}
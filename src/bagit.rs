@@ -0,0 +1,209 @@
+use crate::takes;
+use anyhow::{bail, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{Read, Write},
+};
+
+const BUFFER: usize = 1 << 16;
+
+/// A file of the bag, with its path relative to the bag and its SHA-256.
+struct Entry {
+    path: String,
+    checksum: String,
+}
+
+/// Packages takes into a `BagIt` 1.0 bag (RFC 8493) with SHA-256 manifests, for the ingest of archives and libraries.
+///
+/// Every take is copied into `data/` with its files and sidecars, `info` are more `Label: Value` lines of `bag-info.txt`.
+pub fn export(take_dirs: &[Utf8PathBuf], bag: &Utf8Path, info: &[String]) -> Result<()> {
+    if bag.exists() && bag.read_dir_utf8()?.next().is_some() {
+        bail!("{bag} is not empty, bags are written into a new directory.");
+    }
+    for line in info {
+        if !line.contains(':') {
+            bail!("{line} is not a bag-info line, use e.g. \"Source-Organization: City Archive\".");
+        }
+    }
+    let mut payload = Vec::new();
+    let mut octets = 0;
+    let mut identifiers = Vec::new();
+    for take in take_dirs {
+        if takes::is_claimed(take) {
+            println!("{take} is being recorded, it is left out.");
+            continue;
+        }
+        let name = take.file_name().unwrap_or(take.as_str());
+        for file in files(take)? {
+            let path = format!("data/{name}/{file}");
+            let (checksum, length) = copy(&take.join(&file), &bag.join(&path))?;
+            octets += length;
+            payload.push(Entry { path, checksum });
+        }
+        identifiers.push(takes::take_id(take).unwrap_or_else(|| name.to_string()));
+    }
+    if payload.is_empty() {
+        bail!("There are no takes to export.");
+    }
+
+    let mut tags = vec![write_tag(
+        bag,
+        "bagit.txt",
+        "BagIt-Version: 1.0\nTag-File-Character-Encoding: UTF-8\n",
+    )?];
+    let mut bag_info = format!(
+        "Bagging-Date: {}\nBag-Software-Agent: smrec {}\nPayload-Oxum: {octets}.{}\n",
+        chrono::Local::now().format("%Y-%m-%d"),
+        env!("CARGO_PKG_VERSION"),
+        payload.len()
+    );
+    for identifier in &identifiers {
+        // Writing to a string does not fail.
+        let _ = writeln!(bag_info, "External-Identifier: {identifier}");
+    }
+    for line in info {
+        let _ = writeln!(bag_info, "{}", line.trim());
+    }
+    tags.push(write_tag(bag, "bag-info.txt", &bag_info)?);
+    tags.push(write_tag(bag, "manifest-sha256.txt", &manifest(&payload))?);
+    write_tag(bag, "tagmanifest-sha256.txt", &manifest(&tags))?;
+
+    println!(
+        "{} takes with {} files are bagged in {bag}.",
+        identifiers.len(),
+        payload.len()
+    );
+    Ok(())
+}
+
+/// Paths of the files of a take relative to it, without the markers of locked and claimed takes.
+fn files(take: &Utf8Path) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    let mut directories = vec![Utf8PathBuf::new()];
+    while let Some(directory) = directories.pop() {
+        for entry in take.join(&directory).read_dir_utf8()? {
+            let entry = entry?;
+            if entry.file_name().starts_with('.') {
+                continue;
+            }
+            let relative = directory.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                directories.push(relative);
+            } else {
+                files.push(relative.into_string().replace('\\', "/"));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Copies a file and returns its SHA-256 and length, read once for both.
+fn copy(from: &Utf8Path, to: &Utf8Path) -> Result<(String, u64)> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut reader = File::open(from).with_context(|| format!("Error reading {from}."))?;
+    let mut writer = File::create(to).with_context(|| format!("Error writing {to}."))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; BUFFER];
+    let mut length = 0;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        writer.write_all(&buffer[..read])?;
+        length += read as u64;
+    }
+    writer.sync_all()?;
+    Ok((hex(&hasher.finalize()), length))
+}
+
+fn write_tag(bag: &Utf8Path, name: &str, text: &str) -> Result<Entry> {
+    std::fs::create_dir_all(bag)?;
+    std::fs::write(bag.join(name), text)?;
+    Ok(Entry {
+        path: name.to_string(),
+        checksum: hex(&Sha256::digest(text.as_bytes())),
+    })
+}
+
+/// Lines of a checksum and a path, with the characters which would break a line percent-encoded.
+fn manifest(entries: &[Entry]) -> String {
+    entries.iter().fold(String::new(), |mut manifest, entry| {
+        let path = entry
+            .path
+            .replace('%', "%25")
+            .replace('\n', "%0A")
+            .replace('\r', "%0D");
+        let _ = writeln!(manifest, "{}  {path}", entry.checksum);
+        manifest
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn takes_are_bagged_with_checksums() {
+        let dir = TempDir::new("bagit");
+        let base = dir.path();
+        let take = base.join("rec_20231120_120000");
+        std::fs::create_dir_all(&take).unwrap();
+        std::fs::write(take.join("Boom.wav"), "abc").unwrap();
+        std::fs::write(
+            take.join("take.toml"),
+            "id = \"01HFKQ7Z6S3M0V4W5X6Y7Z8A9B\"\n",
+        )
+        .unwrap();
+        std::fs::write(take.join(".locked"), "").unwrap();
+
+        let bag = base.join("bag");
+        export(
+            &[take],
+            &bag,
+            &["Source-Organization: City Archive".to_string()],
+        )
+        .unwrap();
+
+        let manifest = std::fs::read_to_string(bag.join("manifest-sha256.txt")).unwrap();
+        let lines = manifest.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  data/rec_20231120_120000/Boom.wav"
+        );
+        assert!(lines[1].ends_with("  data/rec_20231120_120000/take.toml"));
+        assert_eq!(
+            std::fs::read_to_string(bag.join("data/rec_20231120_120000/Boom.wav")).unwrap(),
+            "abc"
+        );
+        let bag_info = std::fs::read_to_string(bag.join("bag-info.txt")).unwrap();
+        assert!(bag_info.contains("Payload-Oxum: 37.2\n"));
+        assert!(bag_info.contains("External-Identifier: 01HFKQ7Z6S3M0V4W5X6Y7Z8A9B\n"));
+        assert!(bag_info.ends_with("Source-Organization: City Archive\n"));
+        assert_eq!(
+            std::fs::read_to_string(bag.join("tagmanifest-sha256.txt"))
+                .unwrap()
+                .lines()
+                .count(),
+            3
+        );
+        // Bags are not written over.
+        assert!(export(&[base.join("rec_20231120_120000")], &bag, &[]).is_err());
+    }
+}
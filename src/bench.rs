@@ -0,0 +1,168 @@
+use crate::{
+    inject::{Generator, Signal},
+    wav::{create_writer, update_header, write_input_data, ChannelFormat},
+    WriterHandle,
+};
+use anyhow::Result;
+use camino::Utf8Path;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Frames written at a time, a common buffer size of audio callbacks.
+const BUFFER_FRAMES: usize = 512;
+/// Bytes of a 32 bit float sample, the format benchmarks are written in.
+const SAMPLE_BYTES: usize = 4;
+
+/// How writing a session went.
+#[derive(Debug)]
+struct Report {
+    bytes: u64,
+    /// From the first buffer until the files were on disk.
+    elapsed: Duration,
+    /// How long writing a buffer may take before the next one arrives.
+    budget: Duration,
+    longest: Duration,
+    /// Buffers which took longer than the budget to write.
+    stalls: usize,
+}
+
+/// Writes synthetic audio of a session through the writers of a recording in real time and reports whether the disk keeps up.
+///
+/// The files are written to a temporary directory in the output directory and removed afterwards.
+pub fn run(out: &Utf8Path, channels: usize, sample_rate: u32, seconds: u64) -> Result<()> {
+    let directory = out.join(format!("smrec_bench_{}", std::process::id()));
+    std::fs::create_dir_all(&directory)?;
+    println!("Writing {channels} channels at {sample_rate} Hz to {out} for {seconds} seconds...");
+    let report = bench(
+        &directory,
+        channels,
+        sample_rate,
+        Duration::from_secs(seconds),
+    );
+    std::fs::remove_dir_all(&directory).ok();
+    let report = report?;
+
+    #[allow(clippy::cast_precision_loss)]
+    let (required, sustained) = (
+        (channels * sample_rate as usize * SAMPLE_BYTES) as f64 / 1e6,
+        report.bytes as f64 / 1e6 / report.elapsed.as_secs_f64(),
+    );
+    println!("Required throughput: {required:.1} MB/s.");
+    println!("Sustained throughput: {sustained:.1} MB/s.");
+    println!(
+        "Longest buffer write: {:.1} ms of {:.1} ms available.",
+        report.longest.as_secs_f64() * 1000.0,
+        report.budget.as_secs_f64() * 1000.0
+    );
+    if report.stalls == 0 {
+        println!("No stalls, the disk can handle this session.");
+    } else {
+        println!(
+            "{} buffers stalled, audio would have been dropped. Record fewer channels, a lower sample rate or to a faster disk.",
+            report.stalls
+        );
+    }
+    Ok(())
+}
+
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+fn bench(
+    directory: &Utf8Path,
+    channels: usize,
+    sample_rate: u32,
+    duration: Duration,
+) -> Result<Report> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let paths = (1..=channels)
+        .map(|channel| directory.join(format!("chn_{channel}.wav")))
+        .collect::<Vec<_>>();
+    let writers = paths
+        .iter()
+        .map(|path| {
+            Ok(Arc::new(Mutex::new(Some(create_writer(
+                path, spec, None, false,
+            )?))))
+        })
+        .collect::<Result<Vec<WriterHandle>>>()?;
+
+    let budget = Duration::from_secs_f64(BUFFER_FRAMES as f64 / f64::from(sample_rate));
+    let buffers = (duration.as_secs_f64() / budget.as_secs_f64()).ceil() as u32;
+    let mut generator = Generator::new(Signal::Sine(1000.0), sample_rate);
+    let mut samples = vec![0.0_f32; BUFFER_FRAMES];
+    let (mut longest, mut stalls, mut frames_since_header_update) = (Duration::ZERO, 0, 0);
+
+    let start = Instant::now();
+    for buffer in 0..buffers {
+        // Buffers arrive at the pace of a device.
+        if let Some(wait) = (start + budget * buffer).checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+        let began = Instant::now();
+        generator.fill(&mut samples, 1);
+        for writer in &writers {
+            write_input_data::<f32, f32>(&samples, writer, ChannelFormat::Native);
+        }
+        // Headers are updated every second like while recording.
+        frames_since_header_update += BUFFER_FRAMES;
+        if frames_since_header_update >= sample_rate as usize {
+            frames_since_header_update = 0;
+            writers.iter().for_each(update_header);
+        }
+        let took = began.elapsed();
+        longest = longest.max(took);
+        if took > budget {
+            stalls += 1;
+        }
+    }
+    for writer in &writers {
+        let writer = writer.lock().unwrap().take();
+        if let Some(writer) = writer {
+            writer.finalize()?;
+        }
+    }
+    // Counts the time until the data is on the disk rather than in the cache of the system.
+    for path in &paths {
+        std::fs::File::open(path)?.sync_all()?;
+    }
+
+    Ok(Report {
+        bytes: (u64::from(buffers) * BUFFER_FRAMES as u64 * SAMPLE_BYTES as u64) * channels as u64,
+        elapsed: start.elapsed(),
+        budget,
+        longest,
+        stalls,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn bench_writes_every_channel() {
+        let dir = TempDir::new("bench_test");
+        let directory = dir.path();
+
+        let report = bench(directory, 3, 8000, Duration::from_millis(200)).unwrap();
+        // 200 ms are 4 buffers of 512 frames at 8 kHz.
+        assert_eq!(report.bytes, 4 * 512 * 4 * 3);
+        assert!(report.elapsed >= report.budget * 3);
+        for channel in 1..=3 {
+            let reader =
+                hound::WavReader::open(dir.path().join(format!("chn_{channel}.wav"))).unwrap();
+            assert_eq!(reader.duration(), 4 * 512);
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicI64, Ordering},
+    time::Instant,
+};
+
+/// Format of the date stamp in take directory names.
+const DIRNAME_FORMAT: &str = "%Y%m%d_%H%M%S";
+
+/// A wall clock which reads the system time when a take starts and advances monotonically from there.
+///
+/// Timestamps of a take stay ordered and consistent with the audio stream even if the system time is
+/// stepped (e.g. by NTP) while recording, the next take follows the system time again.
+#[derive(Debug, Clone, Copy)]
+pub struct Clock {
+    /// When the session started.
+    started: DateTime<Utc>,
+    /// The system time read at `instant`.
+    wall: DateTime<Utc>,
+    instant: Instant,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        let wall = Utc::now();
+        Self {
+            started: wall,
+            wall,
+            instant: Instant::now(),
+        }
+    }
+
+    /// When the clock was started, the start of the session.
+    pub const fn started(&self) -> DateTime<Utc> {
+        self.started
+    }
+
+    /// Reads the system time again, when a take starts.
+    ///
+    /// Takes after a step of the system time or after the system slept, which the monotonic clock does not count on
+    /// Linux and macOS, are stamped with the time they really started. The session keeps its start.
+    pub fn reanchor(&mut self) {
+        self.wall = Utc::now();
+        self.instant = Instant::now();
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.at(Instant::now())
+    }
+
+    pub fn at(&self, instant: Instant) -> DateTime<Utc> {
+        self.wall
+            + chrono::Duration::from_std(instant.saturating_duration_since(self.instant))
+                .unwrap_or_else(|_| chrono::Duration::zero())
+    }
+
+    /// Frames elapsed since the clock was started at the given sample rate.
+    pub fn frames_since_start(&self, sample_rate: u32) -> u64 {
+        u64::try_from(Self::frames_between(self.started, self.now(), sample_rate)).unwrap_or(0)
+    }
+
+    /// Moves a time by a number of frames at the given sample rate.
+    #[allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
+    pub fn offset_by_frames(date: DateTime<Utc>, frames: isize, sample_rate: u32) -> DateTime<Utc> {
+        date + chrono::Duration::nanoseconds(
+            (frames as i128 * 1_000_000_000 / i128::from(sample_rate.max(1))) as i64,
+        )
+    }
+
+    /// Frames from one time to another at the given sample rate, negative when the other one is earlier.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn frames_between(from: DateTime<Utc>, to: DateTime<Utc>, sample_rate: u32) -> isize {
+        let scaled =
+            i128::from((to - from).num_nanoseconds().unwrap_or_default()) * i128::from(sample_rate);
+        // Rounded to the nearest frame.
+        ((scaled + scaled.signum() * 500_000_000) / 1_000_000_000) as isize
+    }
+
+    /// The time the first frame of an input buffer was captured by the device.
+    ///
+    /// Accurate to the jitter of the callback, which reads the clock when it runs, not to the frame.
+    pub fn capture_time(&self, info: &cpal::InputCallbackInfo) -> DateTime<Utc> {
+        let timestamp = info.timestamp();
+        // Stream instants have a backend specific origin so only the distance
+        // between the callback and the capture is meaningful to us.
+        let capture_latency = timestamp
+            .callback
+            .duration_since(&timestamp.capture)
+            .unwrap_or_default();
+        let now = Instant::now();
+        self.at(now.checked_sub(capture_latency).unwrap_or(now))
+    }
+}
+
+/// When the last beat of an external MIDI clock was heard, kept for the click of the monitor stream.
+///
+/// The beat is an atomic so the audio callbacks never wait for it.
+#[derive(Debug, Default)]
+pub struct BeatClock {
+    /// Nanoseconds since the Unix epoch, 0 before the first beat.
+    beat: AtomicI64,
+}
+
+impl BeatClock {
+    /// Records when the last beat was heard.
+    pub fn set(&self, at: DateTime<Utc>) {
+        self.beat.store(
+            at.timestamp_nanos_opt().unwrap_or_default(),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub fn last(&self) -> Option<DateTime<Utc>> {
+        match self.beat.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(Utc.timestamp_nanos(nanos)),
+        }
+    }
+}
+
+/// The time zone which take names and metadata timestamps are presented in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampZone {
+    #[default]
+    Utc,
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl FromStr for TimestampZone {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "utc" => Ok(Self::Utc),
+            "local" => Ok(Self::Local),
+            offset => parse_offset(offset).map(Self::Fixed),
+        }
+    }
+}
+
+impl TimestampZone {
+    fn offset_at(self, date: DateTime<Utc>) -> FixedOffset {
+        match self {
+            Self::Utc => FixedOffset::east_opt(0).expect("Zero offset is always valid."),
+            Self::Local => *date.with_timezone(&Local).offset(),
+            Self::Fixed(offset) => offset,
+        }
+    }
+
+    /// Formats the date as `YYYYMMDD_HHMMSS` in this zone.
+    pub fn dirname(self, date: DateTime<Utc>) -> String {
+        date.with_timezone(&self.offset_at(date))
+            .format(DIRNAME_FORMAT)
+            .to_string()
+    }
+
+    /// Reads back a date which was formatted with [`TimestampZone::dirname`].
+    pub fn parse_dirname(self, dirname: &str) -> Option<DateTime<Utc>> {
+        let date = NaiveDateTime::parse_from_str(dirname, DIRNAME_FORMAT).ok()?;
+        let date = match self {
+            Self::Utc => Utc.from_utc_datetime(&date).fixed_offset(),
+            Self::Local => Local.from_local_datetime(&date).earliest()?.fixed_offset(),
+            Self::Fixed(offset) => offset.from_local_datetime(&date).single()?,
+        };
+        Some(date.with_timezone(&Utc))
+    }
+
+    pub fn rfc3339(self, date: DateTime<Utc>) -> String {
+        date.with_timezone(&self.offset_at(date)).to_rfc3339()
+    }
+}
+
+/// Parses offsets in the form of `+02:00`, `-0530` or `+2`.
+fn parse_offset(offset: &str) -> Result<FixedOffset> {
+    let (sign, rest) = match offset.chars().next() {
+        Some('+') => (1, &offset[1..]),
+        Some('-') => (-1, &offset[1..]),
+        _ => bail!("Timestamp must be local, utc or an offset like +02:00 but it is {offset}."),
+    };
+
+    let digits = rest.replace(':', "");
+    let (hours, minutes) = match digits.len() {
+        1 | 2 => (digits.as_str(), "0"),
+        4 => digits.split_at(2),
+        _ => bail!("Can not parse timestamp offset {offset}."),
+    };
+    let hours = hours.parse::<i32>()?;
+    let minutes = minutes.parse::<i32>()?;
+    if minutes >= 60 {
+        bail!("Can not parse timestamp offset {offset}.");
+    }
+
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+        .ok_or_else(|| anyhow!("Timestamp offset {offset} is out of range."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_timestamp_zones() {
+        assert_eq!("utc".parse::<TimestampZone>().unwrap(), TimestampZone::Utc);
+        assert_eq!(
+            "Local".parse::<TimestampZone>().unwrap(),
+            TimestampZone::Local
+        );
+        assert_eq!(
+            "+02:00".parse::<TimestampZone>().unwrap(),
+            TimestampZone::Fixed(FixedOffset::east_opt(7200).unwrap())
+        );
+        assert_eq!(
+            "-0530".parse::<TimestampZone>().unwrap(),
+            TimestampZone::Fixed(FixedOffset::west_opt(19800).unwrap())
+        );
+        assert!("+25:00".parse::<TimestampZone>().is_err());
+        assert!("02:00".parse::<TimestampZone>().is_err());
+    }
+
+    #[test]
+    fn dirname_round_trip() {
+        let zone = "+02:00".parse::<TimestampZone>().unwrap();
+        let date = Utc.with_ymd_and_hms(2023, 11, 20, 23, 30, 0).unwrap();
+        assert_eq!(zone.dirname(date), "20231121_013000");
+        assert_eq!(zone.parse_dirname("20231121_013000"), Some(date));
+    }
+
+    #[test]
+    fn frames_between_round_trip() {
+        let start = Utc.with_ymd_and_hms(2023, 11, 20, 12, 0, 0).unwrap();
+        for frames in [-48001, -1, 0, 1, 7, 48000] {
+            let date = Clock::offset_by_frames(start, frames, 48000);
+            assert_eq!(Clock::frames_between(start, date, 48000), frames);
+        }
+
+        let mut clock = Clock::new();
+        let started = clock.started();
+        clock.reanchor();
+        assert_eq!(clock.started(), started);
+        assert!(clock.now() >= started);
+        assert!(clock.frames_since_start(48000) < 48000);
+    }
+}
@@ -1,22 +1,61 @@
-use crate::{wav::spec_from_config, WriterHandles};
+#[cfg(feature = "hooks")]
+use crate::hooks::{self, Hooks};
+#[cfg(feature = "midi")]
+use crate::midi::{MidiArming, MidiMonitor};
+#[cfg(feature = "osc")]
+use crate::osc::OscFeedback;
+use crate::{
+    clock::{Clock, TimestampZone},
+    dedupe,
+    events::{self, Event},
+    failover::FailoverConfig,
+    files,
+    inject::Signal,
+    ixml::Ixml,
+    ntp,
+    peaks::Peaks,
+    pipe,
+    processing::Processing,
+    schedule::Schedule,
+    session::SessionFormat,
+    setlist::Setlist,
+    spool::{self, Budget},
+    syslog,
+    takes::{self, Take},
+    tap::Taps,
+    timeline::Timeline,
+    types::Action,
+    verify,
+    wav::{create_writer, spec_from_config, ChannelFormat},
+};
 use anyhow::{anyhow, bail, Result};
-use camino::Utf8PathBuf;
-use chrono::{Datelike, Timelike, Utc};
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
 use cpal::{
     traits::{DeviceTrait, HostTrait},
     SupportedStreamConfig,
 };
 use serde::{
     de::{self, Deserializer, MapAccess, Visitor},
-    Deserialize,
+    Deserialize, Serialize,
 };
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fmt,
+    fmt::Write as _,
+    io::Write,
+    net::SocketAddr,
+    ops::Range,
     str::FromStr,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
+/// How often the headers of the files are updated while recording.
+const HEADER_UPDATE_SECS: u32 = 1;
+/// Level in dBFS which every channel stays below while it is silent, for `--split-on-silence`.
+const SILENCE_DB: f32 = -50.0;
+
 /// Chooses which channels to record.
 pub fn choose_channels_to_record(
     include: Option<Vec<usize>>,
@@ -50,10 +89,72 @@ pub fn choose_channels_to_record(
     }
 }
 
+/// Validates `--host` against the hosts of this build and offers them as its possible values.
+#[derive(Clone)]
+pub struct HostParser;
+
+impl clap::builder::TypedValueParser for HostParser {
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        _arg: Option<&clap::Arg>,
+        value: &std::ffi::OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        let value = value.to_string_lossy();
+        #[cfg(feature = "mock")]
+        if value.eq_ignore_ascii_case(crate::mock::HOST) {
+            return Ok(crate::mock::HOST.to_string());
+        }
+        let available = cpal::available_hosts();
+        if let Some(host_id) = available
+            .iter()
+            .find(|host_id| host_id.name().eq_ignore_ascii_case(&value))
+        {
+            return Ok(host_id.name().to_string());
+        }
+
+        let names = available
+            .iter()
+            .map(cpal::HostId::name)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut message =
+            format!("Host {value} is not available, the available hosts are: {names}.\n");
+        if value.eq_ignore_ascii_case("asio") {
+            message.push_str(
+                "ASIO is only available on Windows in builds with the asio feature of cpal.\n",
+            );
+        } else if value.eq_ignore_ascii_case("jack") {
+            message.push_str("JACK is only available in builds with the jack feature of cpal.\n");
+        }
+        Err(clap::Error::raw(clap::error::ErrorKind::InvalidValue, message).with_cmd(cmd))
+    }
+
+    fn possible_values(
+        &self,
+    ) -> Option<Box<dyn Iterator<Item = clap::builder::PossibleValue> + '_>> {
+        let hosts = cpal::available_hosts()
+            .into_iter()
+            .map(|host_id| clap::builder::PossibleValue::new(host_id.name()));
+        #[cfg(feature = "mock")]
+        let hosts = hosts.chain(Some(clap::builder::PossibleValue::new(crate::mock::HOST)));
+        Some(Box::new(hosts))
+    }
+}
+
 /// Chooses the host to use.
+///
+/// The mock host records generated devices, the default host is still used for everything else.
 pub fn choose_host(host: Option<String>) -> Result<cpal::Host> {
+    #[cfg(feature = "mock")]
+    if host.as_deref() == Some(crate::mock::HOST) {
+        return Ok(cpal::default_host());
+    }
+
     #[cfg(target_os = "windows")]
-    if host.as_ref().map_or(false, |host| host == "Asio") {
+    if host.as_ref().is_some_and(|host| host == "ASIO") {
         return Ok(cpal::host_from_id(cpal::HostId::Asio).expect("Failed to initialise ASIO host."));
     }
 
@@ -74,46 +175,386 @@ pub fn choose_host(host: Option<String>) -> Result<cpal::Host> {
 }
 
 /// Chooses the device to use.
+///
+/// A device may be given as a list of preferences separated by commas, e.g. `UMC1820*,Scarlett*,default`,
+/// the first one which is connected is used. `*` matches any text and `default` the default input device.
 pub fn choose_device(host: &cpal::Host, device: Option<String>) -> Result<cpal::Device> {
+    let Some(chosen_device_name) = device else {
+        // Try to use the default device when not provided.
+        return host
+            .default_input_device()
+            .ok_or_else(|| anyhow::anyhow!("No default audio device found."));
+    };
+    let mut devices = host
+        .devices()?
+        .map(|device| (device.name().unwrap_or_default(), device))
+        .collect::<Vec<_>>();
+    // Names which contain commas or asterisks themselves are still found as they are.
+    if let Some(position) = devices
+        .iter()
+        .position(|(name, _)| *name == chosen_device_name)
+    {
+        return Ok(devices.swap_remove(position).1);
+    }
+    for preference in chosen_device_name.split(',').map(str::trim) {
+        if preference == "default" {
+            if let Some(device) = host.default_input_device() {
+                println!("Using the default input device.");
+                return Ok(device);
+            }
+        } else if let Some(position) = devices
+            .iter()
+            .position(|(name, _)| matches_pattern(preference, name))
+        {
+            let (name, device) = devices.swap_remove(position);
+            println!("Using device {name}.");
+            return Ok(device);
+        }
+    }
+    bail!("Provided device {chosen_device_name} not found.")
+}
+
+/// Whether a device name matches a pattern in which `*` matches any text.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let parts = parts.collect::<Vec<_>>();
+    let Some((last, middle)) = parts.split_last() else {
+        // No asterisk.
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Chooses the output device to use.
+pub fn choose_output_device(host: &cpal::Host, device: Option<String>) -> Result<cpal::Device> {
     if let Some(chosen_device_name) = device {
-        let devices = host.devices()?;
+        let devices = host.output_devices()?;
         let device = devices
             .enumerate()
             .find(|(_device_index, device)| device.name().expect("Later") == chosen_device_name);
         if let Some((_, device)) = device {
             Ok(device)
         } else {
-            bail!("Provided device {chosen_device_name} not found.")
+            bail!("Provided output device {chosen_device_name} not found.")
         }
     } else {
-        // Try to use the default device when not provided.
-        host.default_input_device()
-            .ok_or_else(|| anyhow::anyhow!("No default audio device found."))
+        // Try to use the default output device when not provided.
+        host.default_output_device()
+            .ok_or_else(|| anyhow::anyhow!("No default output audio device found."))
     }
 }
 
+/// The part of the configuration which can be dumped and restored at runtime.
+#[derive(Serialize, Deserialize)]
+struct ConfigDump {
+    /// 1 indexed channels to record.
+    channels: Vec<usize>,
+    channel_names: BTreeMap<String, String>,
+}
+
+/// Which names win when both the configuration and the device name a channel.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelNameSource {
+    #[default]
+    Config,
+    Device,
+}
+
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Deserialize, Clone, Debug)]
 pub struct SmrecConfig {
-    #[serde(deserialize_with = "deserialize_usize_keys_greater_than_0")]
+    #[serde(default, deserialize_with = "deserialize_usize_keys_greater_than_0")]
     channel_names: HashMap<usize, String>,
+    #[serde(default)]
+    channel_name_source: ChannelNameSource,
+    /// Tracks which device channels are renumbered to in the output, both 1 indexed.
+    #[serde(default, deserialize_with = "deserialize_channel_map")]
+    map: HashMap<usize, usize>,
+    /// Latencies of the devices by name, compensated so devices recorded together line up.
+    #[serde(default)]
+    latency: HashMap<String, Latency>,
+    /// Format overrides of channels, 1 indexed.
+    #[serde(default, deserialize_with = "deserialize_channel_formats")]
+    channel_formats: HashMap<usize, ChannelFormat>,
+    /// 1 indexed channels to record when neither `--include` nor `--exclude` is given.
+    #[serde(default)]
+    include: Option<Vec<usize>>,
+    /// 1 indexed channels not to record when neither `--include` nor `--exclude` is given.
+    #[serde(default)]
+    exclude: Option<Vec<usize>>,
+    /// Channel names reported by the device, 0 indexed.
+    #[serde(skip)]
+    device_channel_names: Vec<String>,
+    /// DAW session to write next to every take.
+    #[serde(default)]
+    session: Option<SessionFormat>,
+    /// Whether to write a peaks file next to every channel.
+    #[serde(default)]
+    peaks: bool,
+    /// Whether to measure the loudness of every file and record its replay gain.
+    #[serde(default)]
+    replaygain: bool,
+    /// Whether to write an acoustic fingerprint of the mixdown of every take.
+    #[serde(default)]
+    fingerprint: bool,
+    /// Whether to tag every file with its channel name, date, album and track number.
+    #[serde(default)]
+    tags: bool,
+    /// NTP server which the system clock is compared to when a take starts.
+    #[serde(default)]
+    ntp: Option<String>,
+    /// Names of silent placeholder files written alongside the recorded channels.
+    #[serde(default)]
+    virtual_channels: Vec<String>,
+    #[serde(default)]
+    schedule: Schedule,
+    #[cfg(feature = "osc")]
+    #[serde(default)]
+    osc_feedback: OscFeedback,
+    #[cfg(feature = "midi")]
+    #[serde(default)]
+    midi_arming: MidiArming,
+    #[cfg(feature = "midi")]
+    #[serde(default)]
+    midi_monitor: MidiMonitor,
+    /// Titles of the songs a MIDI Song Select picks, by song number.
+    #[cfg(feature = "midi")]
+    #[serde(default, deserialize_with = "deserialize_setlist")]
+    setlist: HashMap<u8, String>,
+    /// A peer recorder which takes over when this one stops responding or the other way around.
+    #[serde(default)]
+    failover: Option<FailoverConfig>,
+    /// Labels of the markers which numbered cues drop, so a show is logged with the same words every night.
+    #[serde(default, deserialize_with = "deserialize_cues")]
+    cues: HashMap<u8, String>,
+    /// Milliseconds in which a start or stop repeating the previous one is dropped when MIDI and OSC both control the recorder, 0 turns it off.
+    #[serde(default)]
+    dedupe_ms: Option<u64>,
+    /// Name of the recorder, prefixed to take names.
+    #[serde(default)]
+    instance: Option<String>,
+    /// Metadata of the iXML chunks, merged with the command line.
+    #[serde(default)]
+    ixml: Option<Ixml>,
     #[serde(skip)]
     channels_to_record: Vec<usize>,
     #[serde(skip)]
     out_path: Option<String>,
+    /// Label of the next take.
+    #[serde(skip)]
+    next_take_name: Option<String>,
+    #[serde(skip)]
+    append: bool,
+    #[serde(skip)]
+    clock: Clock,
+    #[serde(skip)]
+    timestamp: TimestampZone,
+    /// Threshold in dBFS to start takes on the next transient.
+    #[serde(skip)]
+    start_on_transient: Option<f32>,
+    /// Gain in dB of the safety copies of the channels.
+    #[serde(skip)]
+    safety: Option<f32>,
+    /// Whether takes are placed on a sample timeline shared by the whole run.
+    #[serde(skip)]
+    session_timeline: bool,
+    /// Bytes of memory which audio is kept in before it is written to disk.
+    #[serde(skip)]
+    ram_buffer: Option<u64>,
+    /// What happens when the memory is full because the disk fell behind.
+    #[serde(skip)]
+    ram_buffer_policy: spool::Policy,
+    /// Whether the files of takes are opened only while they are written, when the system allows too few open files.
+    #[serde(skip)]
+    deferred_open: bool,
+    /// Where the recorded channels are streamed to for monitoring.
+    #[serde(skip)]
+    monitor: Option<SocketAddr>,
+    /// Whether a new take is started at the new sample rate when the device changes it while recording.
+    #[serde(skip)]
+    restart_on_rate_change: bool,
+    /// A signal recorded instead of the inputs.
+    #[serde(skip)]
+    inject: Option<Signal>,
+    /// Where streams report that the device changed its sample rate.
+    #[serde(skip)]
+    to_main_thread: Option<crossbeam::channel::Sender<Action>>,
+    /// Gain and gate of the device channels, changed remotely.
+    #[serde(skip)]
+    processing: Arc<Processing>,
+    /// Channels handed on from the streams for the whole session, e.g. to the guard track.
+    #[serde(skip)]
+    taps: Arc<Taps>,
+    /// Where the ends of the files are sent to be read back, none unless `--verify` is given.
+    #[serde(skip)]
+    verifier: Option<crossbeam::channel::Sender<verify::Check>>,
+    /// Sample rate stamped into the files instead of the one they are recorded at.
+    #[serde(skip)]
+    playback_rate: Option<u32>,
+    /// Silence after which the running take is split.
+    #[serde(skip)]
+    split_on_silence: Option<Duration>,
+    /// Average level in dBFS RMS which the channels are compared to when a take stops, none without a report.
+    #[serde(skip)]
+    gain_target: Option<f32>,
+    /// Entries of `--setlist` which label the new takes in order.
+    #[serde(skip)]
+    running_order: Option<Setlist>,
+    /// Whether the ULID of the take is added to the names of its files.
+    #[serde(skip)]
+    id_in_file_names: bool,
+    /// Command which transcribes the mixdown of every finalized take.
+    #[serde(skip)]
+    transcribe: Option<String>,
     #[serde(skip)]
     cpal_stream_config: Option<SupportedStreamConfig>,
+    /// Hooks of `hooks.lua` next to the configuration file.
+    #[cfg(feature = "hooks")]
+    #[serde(skip)]
+    hooks: Option<Hooks>,
 }
 
 impl SmrecConfig {
+    /// Loads the configuration for the channels of the devices, options of the command line are set on it after.
+    #[allow(clippy::too_many_lines)]
     pub fn new(
         config_path: Option<String>,
         out_path: Option<String>,
-        channels_to_record: Vec<usize>,
+        include: Option<Vec<usize>>,
+        exclude: Option<Vec<usize>>,
+        device_channel_names: Vec<String>,
         cpal_stream_config: SupportedStreamConfig,
     ) -> Result<Self> {
+        let path = Self::path(config_path)?;
+        #[cfg(feature = "hooks")]
+        let hooks = Hooks::load(&path.with_file_name("hooks.lua"))?;
+        #[cfg(not(feature = "hooks"))]
+        if path.with_file_name("hooks.lua").exists() {
+            eprintln!("Warning: hooks.lua is ignored, smrec is built without the hooks feature.");
+        }
+
+        if path.exists() {
+            let text = std::fs::read_to_string(&path)?;
+            let (mut config, warnings) =
+                Self::parse(&text, Some(cpal_stream_config.channels() as usize))
+                    .map_err(|err| anyhow!("Can not read {path}: {err}"))?;
+            for warning in warnings {
+                eprintln!("Warning: {path}: {warning}");
+            }
+            // Channel flags replace the channel selection of the configuration as a whole.
+            config.channels_to_record = if include.is_some() || exclude.is_some() {
+                choose_channels_to_record(include, exclude, &cpal_stream_config)?
+            } else {
+                choose_channels_to_record(
+                    config.include.clone(),
+                    config.exclude.clone(),
+                    &cpal_stream_config,
+                )?
+            };
+            config.device_channel_names = device_channel_names;
+            config.complete_channel_names();
+            config.virtual_channels = config
+                .virtual_channels
+                .iter()
+                .map(|name| with_wav_extension(name))
+                .collect();
+            config.processing = Arc::new(Processing::new(cpal_stream_config.channels() as usize));
+            config.cpal_stream_config = Some(cpal_stream_config);
+            config.out_path = out_path;
+            config.validate_instance()?;
+            config.validate_tracks(&config.channels_to_record)?;
+            #[cfg(feature = "hooks")]
+            {
+                config.hooks = hooks;
+            }
+            return Ok(config);
+        }
+
+        let mut config = Self {
+            channel_names: HashMap::new(),
+            channel_name_source: ChannelNameSource::default(),
+            map: HashMap::new(),
+            latency: HashMap::new(),
+            channel_formats: HashMap::new(),
+            include: None,
+            exclude: None,
+            device_channel_names,
+            session: None,
+            peaks: false,
+            replaygain: false,
+            fingerprint: false,
+            tags: false,
+            ntp: None,
+            virtual_channels: Vec::new(),
+            schedule: Schedule::default(),
+            #[cfg(feature = "osc")]
+            osc_feedback: OscFeedback::default(),
+            #[cfg(feature = "midi")]
+            midi_arming: MidiArming::default(),
+            #[cfg(feature = "midi")]
+            midi_monitor: MidiMonitor::default(),
+            #[cfg(feature = "midi")]
+            setlist: HashMap::new(),
+            failover: None,
+            cues: HashMap::new(),
+            dedupe_ms: None,
+            instance: None,
+            ixml: None,
+            channels_to_record: choose_channels_to_record(include, exclude, &cpal_stream_config)?,
+            out_path,
+            next_take_name: None,
+            append: false,
+            clock: Clock::new(),
+            timestamp: TimestampZone::default(),
+            start_on_transient: None,
+            safety: None,
+            session_timeline: false,
+            ram_buffer: None,
+            ram_buffer_policy: spool::Policy::default(),
+            deferred_open: false,
+            monitor: None,
+            restart_on_rate_change: false,
+            inject: None,
+            to_main_thread: None,
+            processing: Arc::new(Processing::new(cpal_stream_config.channels() as usize)),
+            taps: Arc::default(),
+            verifier: None,
+            playback_rate: None,
+            split_on_silence: None,
+            gain_target: None,
+            running_order: None,
+            id_in_file_names: false,
+            transcribe: None,
+            cpal_stream_config: Some(cpal_stream_config),
+            #[cfg(feature = "hooks")]
+            hooks,
+        };
+        config.validate_instance()?;
+        config.complete_channel_names();
+        Ok(config)
+    }
+
+    /// The 1 indexed track of the output which a 0 indexed channel of the device is recorded to.
+    fn track(&self, channel: usize) -> usize {
+        self.map.get(&(channel + 1)).copied().unwrap_or(channel + 1)
+    }
+
+    /// Where the configuration is read from, `--config`, `./.smrec/config.toml` or `~/.smrec/config.toml`.
+    pub fn path(config_path: Option<String>) -> Result<Utf8PathBuf> {
         let current_dir_config = Utf8PathBuf::from("./.smrec/config.toml");
 
-        let path = if let Some(path) = config_path {
+        Ok(if let Some(path) = config_path {
             Utf8PathBuf::from_str(&path)?
         } else if current_dir_config.exists() {
             current_dir_config
@@ -129,55 +570,450 @@ impl SmrecConfig {
             })?
             .join(".smrec")
             .join("config.toml")
+        })
+    }
+
+    /// Parses a configuration file, returning what is ignored or does not fit the device as warnings.
+    ///
+    /// Channel numbers are only checked when the channel count of the device is given.
+    pub fn parse(text: &str, channels: Option<usize>) -> Result<(Self, Vec<String>)> {
+        let mut ignored = Vec::new();
+        let config: Self = serde_ignored::deserialize(toml::Deserializer::new(text), |path| {
+            // Optional tables show up as `?` in the path.
+            ignored.push(path.to_string().replace(".?", ""));
+        })?;
+        // Dates and times are not read into key spans, keys are not located in documents with them.
+        let spans = if ignored.is_empty() {
+            None
+        } else {
+            toml::from_str::<KeySpans>(text).ok()
         };
+        let location = |key: &str| spans.as_ref().and_then(|spans| locate(text, spans, key));
+        let mut warnings = ignored
+            .iter()
+            .map(|key| match location(key) {
+                Some((line, column)) => {
+                    format!("Unknown key {key} at line {line}, column {column} is ignored.")
+                }
+                None => format!("Unknown key {key} is ignored."),
+            })
+            .collect::<Vec<_>>();
+        if let Some(channels) = channels {
+            warnings.extend(config.out_of_range(channels));
+        }
+        Ok((config, warnings))
+    }
 
-        if path.exists() {
-            let config = std::fs::read_to_string(path)?;
-            let mut config: Self = toml::from_str(&config)?;
-            config.channels_to_record = channels_to_record;
-
-            config.channels_to_record.iter().for_each(|channel| {
-                if config.channel_names.contains_key(&(channel + 1)) {
-                    let name = config.channel_names.get(&(channel + 1)).unwrap();
-                    if !std::path::Path::new(name)
-                        .extension()
-                        .map_or(false, |ext| ext.eq_ignore_ascii_case("wav"))
-                    {
-                        config
-                            .channel_names
-                            .insert(*channel + 1, format!("{name}.wav"));
-                    }
-                } else {
-                    config
-                        .channel_names
-                        .insert(*channel + 1, format!("chn_{}.wav", channel + 1));
+    /// Lists the channels and tracks of the configuration which the device does not have.
+    fn out_of_range(&self, channels: usize) -> Vec<String> {
+        let tracks = (0..channels)
+            .map(|channel| self.track(channel))
+            .collect::<Vec<_>>();
+        let sections: [(&str, Vec<usize>, &[usize]); 5] = [
+            (
+                "[channel_names]",
+                self.channel_names.keys().copied().collect(),
+                &tracks,
+            ),
+            (
+                "[channel_formats]",
+                self.channel_formats.keys().copied().collect(),
+                &tracks,
+            ),
+            ("[map]", self.map.keys().copied().collect(), &[]),
+            ("include", self.include.clone().unwrap_or_default(), &[]),
+            ("exclude", self.exclude.clone().unwrap_or_default(), &[]),
+        ];
+        sections
+            .into_iter()
+            .filter_map(|(section, mut numbers, tracks)| {
+                numbers.retain(|number| *number > channels && !tracks.contains(number));
+                numbers.sort_unstable();
+                numbers.dedup();
+                (!numbers.is_empty()).then(|| {
+                    format!(
+                        "{section} refers to {}, the device has {channels} channels.",
+                        numbers
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Makes sure no two recorded channels end up on the same track.
+    fn validate_tracks(&self, channels: &[usize]) -> Result<()> {
+        let mut tracks = HashMap::with_capacity(channels.len());
+        for channel in channels {
+            if let Some(other) = tracks.insert(self.track(*channel), channel) {
+                bail!(
+                    "Channels {} and {} are both recorded to track {}, check [map] in the configuration.",
+                    other + 1,
+                    channel + 1,
+                    self.track(*channel)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_instance(&self) -> Result<()> {
+        if let Some(instance) = &self.instance {
+            if instance.is_empty() || instance.contains(['/', '\\']) {
+                bail!("{instance} is not a valid instance name.");
+            }
+        }
+        Ok(())
+    }
+
+    /// Files of the guard track are named after this path in the output directory and their part number.
+    pub fn guard_path_stem(&self) -> Result<Utf8PathBuf> {
+        let prefix = self.instance.as_ref().map_or_else(
+            || "guard_".to_string(),
+            |instance| format!("{instance}_guard_"),
+        );
+        Ok(self.out_base()?.join(format!(
+            "{prefix}{}",
+            self.timestamp.dirname(self.clock.started())
+        )))
+    }
+
+    pub fn instance(&self) -> Option<&str> {
+        self.instance.as_deref()
+    }
+
+    /// Take directory names start with this, `rec_` or `<instance>_rec_`.
+    pub fn take_prefix(&self) -> String {
+        self.instance
+            .as_ref()
+            .map_or_else(|| "rec_".to_string(), |instance| format!("{instance}_rec_"))
+    }
+
+    /// Makes sure every recorded channel has a name which ends with `.wav`.
+    ///
+    /// Names come from the configuration, then from the device and `chn_N.wav` is used if neither names the channel.
+    /// Configured names and `N` refer to the track the channel is recorded to.
+    fn complete_channel_names(&mut self) {
+        for channel in &self.channels_to_record {
+            let track = self.track(*channel);
+            let configured = self.channel_names.get(&track);
+            let from_device = self
+                .device_channel_names
+                .get(*channel)
+                .filter(|name| !name.is_empty());
+            let name = match (configured, from_device) {
+                (Some(_), Some(from_device))
+                    if self.channel_name_source == ChannelNameSource::Device =>
+                {
+                    from_device.clone()
                 }
-            });
-            config.cpal_stream_config = Some(cpal_stream_config);
-            config.out_path = out_path;
-            return Ok(config);
+                (Some(name), _) | (None, Some(name)) => name.clone(),
+                (None, None) => format!("chn_{track}"),
+            };
+            self.channel_names.insert(track, with_wav_extension(&name));
+        }
+    }
+
+    /// Serializes the channels and their names, e.g. to send them as a sysex dump.
+    pub fn dump(&self) -> Result<String> {
+        Ok(toml::to_string(&ConfigDump {
+            channels: self.channels_to_record.iter().map(|i| i + 1).collect(),
+            channel_names: self
+                .channel_names
+                .iter()
+                .map(|(channel, name)| (channel.to_string(), name.clone()))
+                .collect(),
+        })?)
+    }
+
+    /// Applies a dump made by [`SmrecConfig::dump`], it takes effect from the next take on.
+    pub fn restore(&mut self, dump: &str) -> Result<()> {
+        let dump: ConfigDump = toml::from_str(dump)?;
+        let available = self.supported_cpal_stream_config().channels() as usize;
+
+        let mut channels_to_record = Vec::with_capacity(dump.channels.len());
+        for channel in dump.channels {
+            if channel < 1 || channel > available {
+                bail!("Channel {channel} does not exist, the device has {available} channels.");
+            }
+            channels_to_record.push(channel - 1);
+        }
+        if channels_to_record.is_empty() {
+            bail!("There must be at least one channel to record.");
+        }
+        // Recorded channels are expected in ascending order.
+        channels_to_record.sort_unstable();
+        channels_to_record.dedup();
+
+        let mut channel_names = HashMap::with_capacity(dump.channel_names.len());
+        for (channel, name) in dump.channel_names {
+            let channel = channel.parse::<usize>()?;
+            if channel < 1 {
+                bail!("Channel numbers start from 1.");
+            }
+            let Some(name) = file_label(&name) else {
+                bail!("Channel {channel} can not be named {name:?}.");
+            };
+            channel_names.insert(channel, name);
         }
 
-        let mut channel_names = HashMap::new();
-        for channel in &channels_to_record {
-            channel_names.insert(*channel + 1, format!("chn_{}.wav", channel + 1));
+        self.validate_tracks(&channels_to_record)?;
+        self.channels_to_record = channels_to_record;
+        self.channel_names = channel_names;
+        self.complete_channel_names();
+
+        Ok(())
+    }
+
+    /// Records a channel of the device too, it takes effect from the next take on.
+    pub fn include_channel(&mut self, channel: usize) -> Result<()> {
+        let available = self.supported_cpal_stream_config().channels() as usize;
+        if channel >= available {
+            bail!(
+                "Channel {} does not exist, the device has {available} channels.",
+                channel + 1
+            );
         }
-        Ok(Self {
-            channel_names,
-            channels_to_record,
-            out_path,
-            cpal_stream_config: Some(cpal_stream_config),
-        })
+        if self.channels_to_record.contains(&channel) {
+            bail!("Channel {} is already recorded.", channel + 1);
+        }
+
+        let mut channels_to_record = self.channels_to_record.clone();
+        channels_to_record.push(channel);
+        channels_to_record.sort_unstable();
+        self.validate_tracks(&channels_to_record)?;
+        self.channels_to_record = channels_to_record;
+        self.complete_channel_names();
+
+        Ok(())
+    }
+
+    /// Stops recording a channel of the device, it takes effect from the next take on.
+    pub fn exclude_channel(&mut self, channel: usize) -> Result<()> {
+        let Some(position) = self.channels_to_record.iter().position(|c| *c == channel) else {
+            bail!("Channel {} is not recorded.", channel + 1);
+        };
+        if self.channels_to_record.len() == 1 {
+            bail!("There must be at least one channel to record.");
+        }
+        self.channels_to_record.remove(position);
+
+        Ok(())
+    }
+
+    /// Checks the recorded channels against the channel count the devices report now.
+    pub fn validate_channel_count(&self, available: usize) -> Result<()> {
+        let missing = self
+            .channels_to_record
+            .iter()
+            .filter(|channel| **channel >= available)
+            .map(|channel| (channel + 1).to_string())
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            bail!(
+                "channel(s) {} are recorded but the devices have {available} channels now, check --include and --exclude",
+                missing.join(", ")
+            );
+        }
+        Ok(())
     }
 
     pub fn supported_cpal_stream_config(&self) -> SupportedStreamConfig {
         self.cpal_stream_config.clone().unwrap()
     }
 
+    /// Continues the most recent take with the first take of the run.
+    pub const fn set_append(&mut self, append: bool) {
+        self.append = append;
+    }
+
+    /// The time zone of take names and metadata timestamps.
+    pub const fn set_timestamp(&mut self, timestamp: TimestampZone) {
+        self.timestamp = timestamp;
+    }
+
+    /// Starts takes on the next transient reaching the threshold in dBFS.
+    pub const fn set_start_on_transient(&mut self, threshold_db: Option<f32>) {
+        self.start_on_transient = threshold_db;
+    }
+
+    /// Records every channel a second time attenuated by the gain in dB.
+    pub const fn set_safety(&mut self, gain_db: Option<f32>) {
+        self.safety = gain_db;
+    }
+
+    /// Places all takes of the run on one sample timeline.
+    pub const fn set_session_timeline(&mut self, session_timeline: bool) {
+        self.session_timeline = session_timeline;
+    }
+
+    /// Holds up to the given bytes of the takes in memory before they are written.
+    pub const fn set_ram_buffer(&mut self, bytes: Option<u64>) {
+        self.ram_buffer = bytes;
+    }
+
+    pub const fn set_ram_buffer_policy(&mut self, policy: spool::Policy) {
+        self.ram_buffer_policy = policy;
+    }
+
+    /// Streams the recorded channels to the address for monitoring.
+    pub const fn set_monitor(&mut self, monitor: Option<SocketAddr>) {
+        self.monitor = monitor;
+    }
+
+    /// Starts a new take at the new rate when a device changes its sample rate instead of stopping.
+    pub const fn set_restart_on_rate_change(&mut self, restart: bool) {
+        self.restart_on_rate_change = restart;
+    }
+
+    /// Records a generated signal instead of the inputs.
+    pub const fn set_inject(&mut self, signal: Option<Signal>) {
+        self.inject = signal;
+    }
+
+    /// Names the recorder, given over the one of the configuration.
+    pub fn set_instance(&mut self, instance: Option<String>) -> Result<()> {
+        if instance.is_some() {
+            self.instance = instance;
+        }
+        self.validate_instance()
+    }
+
+    /// iXML metadata, what is given is used over the configuration.
+    pub fn set_ixml(&mut self, ixml: Ixml) {
+        self.ixml = ixml.or(self.ixml.take());
+    }
+
+    /// Raises the limit of open files for the files of takes, they are opened only while they are written when the
+    /// system does not allow as many.
+    pub fn fit_open_files(&mut self) -> Result<()> {
+        let needed = files::needed(
+            self.channels_to_write().len(),
+            self.safety.is_some(),
+            self.peaks,
+        );
+        let limit = files::raise(needed)?;
+        if limit >= needed {
+            return Ok(());
+        }
+        // The memory is written out by a thread for every file, which keeps it open.
+        if self.ram_buffer.is_some() {
+            bail!("Takes need {needed} open files but the system allows {limit}, raise the hard limit with `ulimit -Hn` or record without --ram-buffer.");
+        }
+        println!("Takes need {needed} open files but the system allows {limit}, files are opened only while they are written.");
+        self.deferred_open = true;
+        Ok(())
+    }
+
+    /// Splits takes after the given silence, the following take starts with the next sound.
+    pub const fn set_split_on_silence(&mut self, silence: Option<Duration>) {
+        self.split_on_silence = silence;
+    }
+
+    /// Adds the ULID of the take to the names of its files, so they can be told apart once they leave the take directory.
+    pub const fn set_id_in_file_names(&mut self, id_in_file_names: bool) {
+        self.id_in_file_names = id_in_file_names;
+    }
+
+    /// Runs a speech-to-text command on the mixdown of every take once it is finalized.
+    pub fn set_transcribe(&mut self, command: Option<String>) {
+        self.transcribe = command;
+    }
+
+    /// Reports how the channels compare to an average level in dBFS RMS when a take stops.
+    pub const fn set_gain_target(&mut self, target_db: Option<f32>) {
+        self.gain_target = target_db;
+    }
+
+    pub const fn gain_target(&self) -> Option<f32> {
+        self.gain_target
+    }
+
+    /// Level in dBFS which starts writing a take, none when takes are written from their start.
+    pub fn onset_threshold_db(&self) -> Option<f32> {
+        self.start_on_transient
+            .or_else(|| self.split_on_silence.map(|_| SILENCE_DB))
+    }
+
+    pub const fn monitor(&self) -> Option<SocketAddr> {
+        self.monitor
+    }
+
+    /// Frames the audio of a device is moved earlier by, 0 for devices without a configured latency.
+    pub fn latency_frames(&self, device: &str, sample_rate: u32) -> usize {
+        self.latency
+            .get(device)
+            .map_or(0, |latency| latency.frames(sample_rate))
+    }
+
+    pub const fn restart_on_rate_change(&self) -> bool {
+        self.restart_on_rate_change
+    }
+
+    pub const fn inject(&self) -> Option<Signal> {
+        self.inject
+    }
+
+    pub const fn to_main_thread(&self) -> Option<&crossbeam::channel::Sender<Action>> {
+        self.to_main_thread.as_ref()
+    }
+
+    pub const fn processing(&self) -> &Arc<Processing> {
+        &self.processing
+    }
+
+    pub const fn taps(&self) -> &Arc<Taps> {
+        &self.taps
+    }
+
+    /// Hands the channels on from the streams from now on, before the first take starts.
+    pub fn set_taps(&mut self, taps: Taps) {
+        self.taps = Arc::new(taps);
+    }
+
+    /// Lets streams report sample rate changes of the device to the main thread.
+    pub fn set_to_main_thread(&mut self, to_main_thread: crossbeam::channel::Sender<Action>) {
+        self.to_main_thread = Some(to_main_thread);
+    }
+
+    /// Reads back the end of the files of the following takes while they are recorded.
+    pub fn set_verifier(&mut self, verifier: crossbeam::channel::Sender<verify::Check>) {
+        self.verifier = Some(verifier);
+    }
+
+    /// Replaces the configuration the devices are recorded with, e.g. after they changed their sample rate.
+    pub const fn set_stream_config(&mut self, config: SupportedStreamConfig) {
+        self.cpal_stream_config = Some(config);
+    }
+
     pub fn channels_to_record(&self) -> &[usize] {
         &self.channels_to_record
     }
 
+    /// Recorded channels whose names are named pipes, 0 indexed with the pipe they are streamed into.
+    pub fn pipes(&self) -> Vec<(usize, Utf8PathBuf)> {
+        self.channels_to_record
+            .iter()
+            .filter_map(|channel| {
+                let name = Utf8PathBuf::from(self.channel_names.get(&self.track(*channel))?);
+                pipe::is_fifo(&name).then_some((*channel, name))
+            })
+            .collect()
+    }
+
+    /// Recorded channels which are written into the files of takes, the ones streamed into pipes are not.
+    pub fn channels_to_write(&self) -> Vec<usize> {
+        let pipes = self.pipes();
+        self.channels_to_record
+            .iter()
+            .copied()
+            .filter(|channel| pipes.iter().all(|(piped, _)| piped != channel))
+            .collect()
+    }
+
     pub fn channel_count(&self) -> usize {
         self.channels_to_record.len()
     }
@@ -185,26 +1021,13 @@ impl SmrecConfig {
     pub fn get_channel_name_from_0_indexed_channel_num(&self, index: usize) -> Result<String> {
         Ok(self
             .channel_names
-            .get(&(index + 1))
+            .get(&self.track(index))
             .ok_or_else(|| anyhow!("Channel {} does not exist.", index + 1))?
             .to_string())
     }
 
-    pub fn writers(&self) -> Result<WriterHandles> {
-        let now = Utc::now();
-
-        // Format the date for YYYYMMDD_HHMMSS
-        let dirname_date = format!(
-            "{:04}{:02}{:02}_{:02}{:02}{:02}",
-            now.year(),
-            now.month(),
-            now.day(),
-            now.hour(),
-            now.minute(),
-            now.second()
-        );
-
-        // Stamp base directory with date.
+    /// The directory which take directories are created in.
+    pub fn out_base(&self) -> Result<Utf8PathBuf> {
         let base = if let Some(out) = &self.out_path {
             Utf8PathBuf::from_str(out)?
         } else {
@@ -215,66 +1038,988 @@ impl SmrecConfig {
             bail!("Output path which is provided {base} does not exist.");
         }
 
-        let base = base.join(format!("rec_{dirname_date}"));
+        Ok(base)
+    }
 
-        // Create the base directory if it does not exist.
-        if !base.exists() {
-            std::fs::create_dir_all(&base)?;
+    /// Stamps the files of the following takes with a sample rate other than the one they are recorded at,
+    /// e.g. for a tape played back at a different speed than it was recorded at. `None` stamps the recorded one.
+    pub fn set_playback_rate(&mut self, rate: Option<u32>) -> Result<()> {
+        if rate == Some(0) {
+            bail!("The playback rate must be above 0 Hz.");
         }
+        self.playback_rate = rate;
+        Ok(())
+    }
 
-        // Make writers.
-        let mut writers = Vec::new();
-        for channel_num in &self.channels_to_record {
-            let name = self.get_channel_name_from_0_indexed_channel_num(*channel_num)?;
-            let spec = spec_from_config(&self.supported_cpal_stream_config());
-            let writer = hound::WavWriter::create(base.join(&name), spec)
-                .expect("Failed to create wav writer.");
-            writers.push(Arc::new(Mutex::new(Some(writer))));
+    /// Changes the output directory, it must exist.
+    pub fn set_out(&mut self, path: String) -> Result<()> {
+        if !Utf8Path::new(&path).is_dir() {
+            bail!("Output path {path} is not a directory.");
         }
+        self.out_path = Some(path);
+        Ok(())
+    }
 
-        Ok(Arc::new(writers))
+    /// Labels the next new take, path separators in the label are replaced.
+    pub fn set_next_take_name(&mut self, name: Option<String>) {
+        self.next_take_name = name.and_then(|name| take_label(&name));
     }
-}
 
-fn deserialize_usize_keys_greater_than_0<'de, D>(
-    deserializer: D,
-) -> Result<HashMap<usize, String>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    struct UsizeKeyVisitor;
+    /// Labels the new takes with the entries of a setlist, from its first entry on.
+    pub fn set_setlist(&mut self, setlist: Option<Setlist>) {
+        self.running_order = setlist;
+    }
 
-    impl<'de> Visitor<'de> for UsizeKeyVisitor {
-        type Value = HashMap<usize, String>;
+    pub const fn setlist_mut(&mut self) -> Option<&mut Setlist> {
+        self.running_order.as_mut()
+    }
 
-        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            formatter.write_str("a map with string keys that represent usizes")
-        }
+    /// Label of the next new take, the one it was given or else the current entry of the setlist.
+    fn next_take_label(&self) -> Option<String> {
+        self.next_take_name.clone().or_else(|| {
+            self.running_order
+                .as_ref()
+                .and_then(Setlist::current)
+                .and_then(take_label)
+        })
+    }
 
-        fn visit_map<M>(self, mut access: M) -> Result<Self::Value, M::Error>
-        where
-            M: MapAccess<'de>,
-        {
-            let mut map = HashMap::with_capacity(access.size_hint().unwrap_or(0));
-            while let Some((key, value)) = access.next_entry::<String, String>()? {
-                let usize_key = key.parse::<usize>().map_err(de::Error::custom)?;
-                if usize_key < 1 {
-                    return Err(de::Error::custom(
-                        "a usize key must be greater than or equal to 1",
-                    ));
-                }
-                map.insert(usize_key, value);
-            }
-            Ok(map)
-        }
+    #[cfg(feature = "osc")]
+    pub const fn osc_feedback(&self) -> OscFeedback {
+        self.osc_feedback
     }
 
-    deserializer.deserialize_map(UsizeKeyVisitor)
+    #[cfg(feature = "midi")]
+    pub const fn midi_arming(&self) -> MidiArming {
+        self.midi_arming
+    }
+
+    #[cfg(feature = "midi")]
+    pub const fn midi_monitor(&self) -> &MidiMonitor {
+        &self.midi_monitor
+    }
+
+    /// Title of a song of the setlist, `song_<number>` for songs which are not in it.
+    #[cfg(feature = "midi")]
+    pub fn song_title(&self, song: u8) -> String {
+        self.setlist
+            .get(&song)
+            .cloned()
+            .unwrap_or_else(|| format!("song_{song}"))
+    }
+
+    pub const fn failover(&self) -> Option<FailoverConfig> {
+        self.failover
+    }
+
+    /// Label of a cue of `[cues]`, none for cues which are not in it.
+    pub fn cue(&self, cue: u8) -> Option<&str> {
+        self.cues.get(&cue).map(String::as_str)
+    }
+
+    /// Window in which a repeated start or stop is dropped, none when it is turned off.
+    pub fn dedupe_window(&self) -> Option<Duration> {
+        let window = self
+            .dedupe_ms
+            .map_or(dedupe::DEFAULT_WINDOW, Duration::from_millis);
+        (!window.is_zero()).then_some(window)
+    }
+
+    pub const fn schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+
+    /// Logs a transport event of a take to the session-wide event log and runs its hook, failures are only reported.
+    ///
+    /// Gives where the take is afterwards, the stop hook can rename it.
+    pub fn log_event(&self, event: Event, take: &Utf8Path) -> Utf8PathBuf {
+        let sample_rate = self.supported_cpal_stream_config().sample_rate().0;
+        let wall_clock = self.timestamp.rfc3339(self.clock.now());
+        let session_sample = self.clock.frames_since_start(sample_rate);
+        let name = take.file_name().unwrap_or(take.as_str());
+        if let Err(err) = self
+            .out_base()
+            .and_then(|base| events::append(&base, &wall_clock, session_sample, event, name))
+        {
+            eprintln!("Error logging {event:?} event: {err}");
+        }
+        syslog::log(
+            syslog::Severity::Notice,
+            event.as_str(),
+            &[
+                ("take", name),
+                ("session_sample", &session_sample.to_string()),
+            ],
+            match event {
+                Event::Start => "Take started.",
+                Event::Stop => "Take stopped.",
+                Event::Split => "Take split.",
+            },
+        );
+        #[cfg(feature = "hooks")]
+        if let Some(name) = self
+            .hooks
+            .as_ref()
+            .and_then(|hooks| hooks.run(event.as_str(), Some(take), wall_clock))
+        {
+            match hooks::rename_take(take, &name) {
+                Ok(renamed) => return renamed,
+                Err(err) => eprintln!("Error renaming {take}: {err}"),
+            }
+        }
+        take.to_path_buf()
+    }
+
+    /// Calls the meters hook while takes are recorded, see [`Hooks::watch_meters`].
+    #[cfg_attr(
+        not(feature = "hooks"),
+        allow(unused_variables, clippy::missing_const_for_fn)
+    )]
+    pub fn watch_meters(&self, writers: &Arc<Mutex<Option<Take>>>) {
+        #[cfg(feature = "hooks")]
+        if let Some(hooks) = &self.hooks {
+            hooks.watch_meters(
+                Arc::clone(writers),
+                Arc::clone(&self.processing),
+                self.timestamp,
+            );
+        }
+    }
+
+    /// Runs the hook of a transport change which does not belong to a take, such as arming.
+    #[cfg_attr(
+        not(feature = "hooks"),
+        allow(unused_variables, clippy::missing_const_for_fn)
+    )]
+    pub fn run_hook(&self, name: &'static str) {
+        #[cfg(feature = "hooks")]
+        if let Some(hooks) = &self.hooks {
+            // Only stopped takes are renamed.
+            let _ = hooks.run(name, None, self.timestamp.rfc3339(self.clock.now()));
+        }
+    }
+
+    /// Reads the system time again for the take which starts, see [`Clock::reanchor`].
+    pub fn reanchor_clock(&mut self) {
+        self.clock.reanchor();
+    }
+
+    /// The session clock used for naming and metadata.
+    pub const fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// Makes the writers of the next take, with `--append` only the first take of a run continues the last one.
+    #[allow(clippy::too_many_lines)]
+    pub fn writers(&mut self) -> Result<Take> {
+        let now = self.clock.now();
+
+        // Stamp base directory with date.
+        let out_base = self.out_base()?;
+
+        let last_take = if self.append {
+            match takes::last_take_directory(&out_base, &self.take_prefix())? {
+                Some(last_take) if takes::is_locked(&last_take) => {
+                    println!("Most recent take {last_take} is locked, starting a new take.");
+                    None
+                }
+                Some(last_take) if takes::is_claimed(&last_take) => {
+                    println!(
+                        "Most recent take {last_take} is being recorded by another recorder, starting a new take."
+                    );
+                    None
+                }
+                last_take => last_take,
+            }
+        } else {
+            None
+        };
+
+        let (base, part, take_number, id) = if let Some(last_take) = last_take {
+            // A take which did not finish has no sidecar yet, its parts can not share its id.
+            let id = takes::take_id(&last_take).unwrap_or_else(takes::new_id);
+            let part = self.next_part_number(&last_take, &id)?;
+            // Parts keep the take number of the take they continue.
+            let take_number = self
+                .ixml
+                .as_ref()
+                .and_then(|ixml| takes::take_number(&last_take, ixml.scene()));
+            takes::claim(&last_take, self.instance.as_deref().unwrap_or("smrec"))?;
+            println!("Appending part {part} to {last_take}");
+            (last_take, part, take_number, id)
+        } else {
+            let mut name = format!("{}{}", self.take_prefix(), self.timestamp.dirname(now));
+            if let Some(label) = self.next_take_label() {
+                name = format!("{name}_{label}");
+            }
+            let take = takes::create_directory(&out_base, &name)?;
+            takes::claim(&take, self.instance.as_deref().unwrap_or("smrec"))?;
+            (take, 1, None, takes::new_id())
+        };
+
+        // The claim is given up again when the take can not be started.
+        let take = self
+            .take_in(&out_base, base.clone(), part, take_number, id)
+            .and_then(|take| {
+                if part > 1 {
+                    write_part_sidecar(&base, part, now, self.timestamp)?;
+                }
+                Ok(take)
+            });
+        if take.is_err() {
+            takes::release(&base)?;
+        }
+        self.append = false;
+        take
+    }
+
+    /// Makes the writers of a take in a claimed directory.
+    #[allow(clippy::too_many_lines)]
+    fn take_in(
+        &self,
+        out_base: &Utf8Path,
+        base: Utf8PathBuf,
+        part: usize,
+        take_number: Option<u32>,
+        id: String,
+    ) -> Result<Take> {
+        let ixml = match (&self.ixml, take_number) {
+            (Some(ixml), Some(take_number)) => Some((ixml.clone(), take_number)),
+            (Some(ixml), None) => Some((
+                ixml.clone(),
+                takes::next_take_number(out_base, ixml.scene())?,
+            )),
+            (None, _) => None,
+        };
+
+        let channels_to_write = self.channels_to_write();
+        // The memory is shared by all writers of the take.
+        let budget = self
+            .ram_buffer
+            .map(|bytes| Arc::new(Budget::new(bytes, self.ram_buffer_policy)));
+
+        // Make writers.
+        let mut writers = Vec::new();
+        let mut files = Vec::new();
+        let mut safety_writers = Vec::new();
+        let mut formats = Vec::new();
+        let mut peaks = Vec::new();
+        let mut bytes_per_second = 0;
+        let file_name = |name: &str| self.file_name(name, &id, part);
+        for channel_num in &channels_to_write {
+            let name = file_name(&self.get_channel_name_from_0_indexed_channel_num(*channel_num)?);
+            let format = self
+                .channel_formats
+                .get(&self.track(*channel_num))
+                .copied()
+                .unwrap_or_default();
+            let mut spec = format.spec(&self.supported_cpal_stream_config());
+            let copies = if self.safety.is_some() { 2 } else { 1 };
+            bytes_per_second += copies
+                * u64::from(spec.sample_rate)
+                * u64::from(spec.channels)
+                * u64::from(spec.bits_per_sample / 8);
+            spec.sample_rate = self.playback_rate.unwrap_or(spec.sample_rate);
+            let writer =
+                create_writer(&base.join(&name), spec, budget.as_ref(), self.deferred_open)?;
+            writers.push(Arc::new(Mutex::new(Some(writer))));
+            if self.safety.is_some() {
+                let writer = create_writer(
+                    &base.join(safety_file_name(&name)),
+                    spec,
+                    budget.as_ref(),
+                    self.deferred_open,
+                )?;
+                safety_writers.push(Arc::new(Mutex::new(Some(writer))));
+            }
+            formats.push(format);
+            if self.peaks {
+                peaks.push(Peaks::create(
+                    &base.join(&name).with_extension("dat"),
+                    spec.sample_rate,
+                    self.deferred_open,
+                )?);
+            }
+            files.push(name);
+        }
+
+        let virtual_channels = self
+            .virtual_channels
+            .iter()
+            .map(|name| base.join(file_name(name)))
+            .collect();
+
+        Ok(Take {
+            id,
+            writers: Arc::new(writers),
+            safety: self
+                .safety
+                .map(|db| (10.0_f32.powf(db / 20.0), Arc::new(safety_writers))),
+            files,
+            formats,
+            peaks,
+            markers: Vec::new(),
+            channels: channels_to_write
+                .iter()
+                .map(|channel| self.track(*channel))
+                .collect(),
+            ixml,
+            virtual_channels,
+            spec: hound::WavSpec {
+                sample_rate: self
+                    .playback_rate
+                    .unwrap_or_else(|| self.supported_cpal_stream_config().sample_rate().0),
+                ..spec_from_config(&self.supported_cpal_stream_config())
+            },
+            bytes_per_second,
+            directory: base,
+            part,
+            sample_rate: self.supported_cpal_stream_config().sample_rate().0,
+            playback_rate: self.playback_rate,
+            started_at: None,
+            stop_at: None,
+            stopping: false,
+            onset_threshold: self
+                .onset_threshold_db()
+                .map(|dbfs| 10.0_f32.powf(dbfs / 20.0)),
+            silence: self
+                .split_on_silence
+                .map(|silence| (10.0_f32.powf(SILENCE_DB / 20.0), silence)),
+            last_sound: None,
+            session: self.session,
+            timestamp: self.timestamp,
+            timeline: self
+                .session_timeline
+                .then(|| Timeline::new(out_base, &self.clock, self.timestamp)),
+            replaygain: self.replaygain,
+            fingerprint: self.fingerprint,
+            tags: self.tags,
+            ntp: self.ntp.as_deref().map(ntp::Query::start),
+            transcribe: self.transcribe.clone(),
+            // Updating the headers would write out the memory buffer every time.
+            header_update_frames: self.ram_buffer.is_none().then(|| {
+                HEADER_UPDATE_SECS as usize
+                    * self.supported_cpal_stream_config().sample_rate().0 as usize
+            }),
+            verifier: self.verifier.clone(),
+            budget,
+        })
+    }
+
+    /// The name of the file of a channel in a part of a take.
+    fn file_name(&self, name: &str, id: &str, part: usize) -> String {
+        if self.id_in_file_names {
+            part_file_name(&takes::file_name_with_id(name, id), part)
+        } else {
+            part_file_name(name, part)
+        }
+    }
+
+    /// Finds the first part number which is free for all recorded channels in a take directory.
+    fn next_part_number(&self, take: &Utf8Path, id: &str) -> Result<usize> {
+        let mut part = 1;
+        for channel_num in &self.channels_to_write() {
+            let name = self.get_channel_name_from_0_indexed_channel_num(*channel_num)?;
+            while take.join(self.file_name(&name, id, part)).exists() {
+                part += 1;
+            }
+        }
+        Ok(part)
+    }
+}
+
+/// How late a device delivers its audio, configured per device name in `[latency]`.
+///
+/// Integers are samples, strings with an `ms` suffix are milliseconds.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(try_from = "LatencyValue")]
+pub enum Latency {
+    Frames(usize),
+    Millis(f64),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LatencyValue {
+    Frames(usize),
+    Text(String),
+}
+
+impl TryFrom<LatencyValue> for Latency {
+    type Error = anyhow::Error;
+
+    fn try_from(value: LatencyValue) -> Result<Self> {
+        match value {
+            LatencyValue::Frames(frames) => Ok(Self::Frames(frames)),
+            LatencyValue::Text(text) => text.parse(),
+        }
+    }
+}
+
+impl FromStr for Latency {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let trimmed = s.trim();
+        if let Some(millis) = trimmed.strip_suffix("ms") {
+            let millis = millis
+                .trim()
+                .parse::<f64>()
+                .map_err(|err| anyhow!("Invalid latency {s}: {err}"))?;
+            if !millis.is_finite() || millis < 0.0 {
+                bail!("Invalid latency {s}, it can not be negative.");
+            }
+            return Ok(Self::Millis(millis));
+        }
+        trimmed
+            .trim_end_matches("samples")
+            .trim()
+            .parse()
+            .map(Self::Frames)
+            .map_err(|_| {
+                anyhow!(
+                    "Invalid latency {s}, use samples such as 512 or milliseconds such as 10ms."
+                )
+            })
+    }
+}
+
+impl Latency {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn frames(self, sample_rate: u32) -> usize {
+        match self {
+            Self::Frames(frames) => frames,
+            Self::Millis(millis) => (millis * f64::from(sample_rate) / 1000.0).round() as usize,
+        }
+    }
+}
+
+/// Parses a size in bytes such as `512M`, `2G` or `2GiB`, suffixes are powers of 1024.
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let digits = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(digits);
+    let number = number
+        .parse::<f64>()
+        .map_err(|err| anyhow!("Invalid size {size}: {err}"))?;
+    let multiplier: u64 = match unit
+        .trim()
+        .trim_end_matches(['b', 'B'])
+        .trim_end_matches(['i', 'I'])
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "" => 1,
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        "t" => 1 << 40,
+        _ => bail!("Invalid size {size}, use a suffix of K, M, G or T."),
+    };
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    let bytes = (number * multiplier as f64) as u64;
+    if bytes == 0 {
+        bail!("Size {size} is too small.");
+    }
+    Ok(bytes)
+}
+
+/// Parses a gain such as `-12dB`, `-12 dB` or `-12`.
+pub fn parse_gain_db(gain: &str) -> Result<f32> {
+    let db = gain
+        .trim()
+        .trim_end_matches(|c: char| c.eq_ignore_ascii_case(&'d') || c.eq_ignore_ascii_case(&'b'))
+        .trim()
+        .parse::<f32>()
+        .map_err(|err| anyhow!("Invalid gain {gain}: {err}"))?;
+    if !db.is_finite() {
+        bail!("Invalid gain {gain}.");
+    }
+    Ok(db)
+}
+
+/// Parses the gain of the safety copies, which are attenuated so they do not clip before the recorded channels.
+pub fn parse_safety_db(gain: &str) -> Result<f32> {
+    let db = parse_gain_db(gain)?;
+    if db > 0.0 {
+        bail!("The safety copy is attenuated, its gain {gain} must be 0 dB or less.");
+    }
+    Ok(db)
+}
+
+/// Parses a duration such as `3s`, `500ms`, `2m` or `3` for seconds.
+pub fn parse_duration(duration: &str) -> Result<Duration> {
+    let trimmed = duration.trim();
+    let (number, unit) = match (trimmed.strip_suffix("ms"), trimmed.strip_suffix('m')) {
+        (Some(millis), _) => (millis, 0.001),
+        (None, Some(minutes)) => (minutes, 60.0),
+        (None, None) => (trimmed.trim_end_matches('s'), 1.0),
+    };
+    let secs = number
+        .trim()
+        .parse::<f64>()
+        .map_err(|err| anyhow!("Invalid duration {duration}: {err}"))?
+        * unit;
+    Duration::try_from_secs_f64(secs)
+        .ok()
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| anyhow!("Invalid duration {duration}, it must be above 0."))
+}
+
+/// Makes the file name of the safety copy of a channel, `chn_1.wav` becomes `chn_1_safety.wav`.
+pub fn safety_file_name(name: &str) -> String {
+    let path = Utf8Path::new(name);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(extension)) => format!("{stem}_safety.{extension}"),
+        _ => format!("{name}_safety"),
+    }
+}
+
+fn with_wav_extension(name: &str) -> String {
+    // Pipes are written into as they are named.
+    if pipe::is_fifo(Utf8Path::new(name))
+        || Utf8Path::new(name)
+            .extension()
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("wav"))
+    {
+        name.to_string()
+    } else {
+        format!("{name}.wav")
+    }
+}
+
+/// Makes the file name for a continuation part, `chn_1.wav` becomes `chn_1_part2.wav`.
+fn part_file_name(name: &str, part: usize) -> String {
+    if part < 2 {
+        return name.to_string();
+    }
+    let path = Utf8Path::new(name);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(extension)) => format!("{stem}_part{part}.{extension}"),
+        _ => format!("{name}_part{part}"),
+    }
+}
+
+/// Records when a continuation part started relative to the start of its take.
+fn write_part_sidecar(
+    take: &Utf8Path,
+    part: usize,
+    now: DateTime<Utc>,
+    timestamp: TimestampZone,
+) -> Result<()> {
+    let take_start = take
+        .file_name()
+        .and_then(takes::take_stamp)
+        .and_then(|date| timestamp.parse_dirname(date));
+
+    let mut sidecar = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(take.join("parts.toml"))?;
+
+    writeln!(sidecar, "[[parts]]")?;
+    writeln!(sidecar, "part = {part}")?;
+    writeln!(sidecar, "started_at = \"{}\"", timestamp.rfc3339(now))?;
+    if let Some(take_start) = take_start {
+        #[allow(clippy::cast_precision_loss)]
+        let offset = (now - take_start).num_milliseconds() as f64 / 1000.0;
+        writeln!(sidecar, "offset_secs = {offset}")?;
+    }
+    writeln!(sidecar)?;
+
+    Ok(())
+}
+
+/// Deserializes a map of channel formats keyed by 1 indexed channels or ranges of them, e.g. `1-8`.
+fn deserialize_channel_formats<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<usize, ChannelFormat>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut formats = HashMap::new();
+    for (key, format) in HashMap::<String, ChannelFormat>::deserialize(deserializer)? {
+        let (first, last) = key.split_once('-').unwrap_or((&key, &key));
+        let first = first.trim().parse::<usize>().map_err(de::Error::custom)?;
+        let last = last.trim().parse::<usize>().map_err(de::Error::custom)?;
+        if first < 1 || last < first {
+            return Err(de::Error::custom(format!(
+                "{key} is not a channel or a range of channels starting from 1"
+            )));
+        }
+        for channel in first..=last {
+            formats.insert(channel, format);
+        }
+    }
+    Ok(formats)
+}
+
+/// Deserializes a map of 1 indexed device channels to the distinct 1 indexed tracks they are recorded to.
+fn deserialize_channel_map<'de, D>(deserializer: D) -> Result<HashMap<usize, usize>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut map = HashMap::new();
+    for (channel, track) in HashMap::<String, usize>::deserialize(deserializer)? {
+        let channel = channel.trim().parse::<usize>().map_err(de::Error::custom)?;
+        if channel < 1 || track < 1 {
+            return Err(de::Error::custom(format!(
+                "channel {channel} and track {track} must both start from 1"
+            )));
+        }
+        if map.values().any(|mapped| *mapped == track) {
+            return Err(de::Error::custom(format!(
+                "track {track} is mapped more than once"
+            )));
+        }
+        map.insert(channel, track);
+    }
+    Ok(map)
+}
+
+/// A label which can be part of a take directory name, none when nothing is left of it.
+fn take_label(name: &str) -> Option<String> {
+    let label = name.trim().replace(['/', '\\'], "_");
+    (!label.is_empty()).then_some(label)
+}
+
+/// A name of a file in the take directory, for names which come from outside like a sysex dump.
+///
+/// Unlike configured names they can not point to other directories or pipes.
+fn file_label(name: &str) -> Option<String> {
+    take_label(&name.replace(':', "_")).filter(|label| label.chars().any(|c| c != '.'))
+}
+
+/// Deserializes song titles keyed by the song numbers of MIDI Song Select, 0 to 127.
+#[cfg(feature = "midi")]
+fn deserialize_setlist<'de, D>(deserializer: D) -> Result<HashMap<u8, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    HashMap::<String, String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(song, title)| match song.trim().parse::<u8>() {
+            Ok(song) if song <= 127 => Ok((song, title)),
+            _ => Err(de::Error::custom(format!(
+                "song {song} must be a number from 0 to 127"
+            ))),
+        })
+        .collect()
+}
+
+/// Deserializes marker labels keyed by cue numbers, 1 to 128 like the programs of MIDI Program Change.
+fn deserialize_cues<'de, D>(deserializer: D) -> Result<HashMap<u8, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    HashMap::<String, String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(cue, label)| match cue.trim().parse::<u8>() {
+            Ok(cue) if (1..=128).contains(&cue) => Ok((cue, label)),
+            _ => Err(de::Error::custom(format!(
+                "cue {cue} must be a number from 1 to 128"
+            ))),
+        })
+        .collect()
+}
+
+fn deserialize_usize_keys_greater_than_0<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<usize, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct UsizeKeyVisitor;
+
+    impl<'de> Visitor<'de> for UsizeKeyVisitor {
+        type Value = HashMap<usize, String>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a map with string keys that represent usizes")
+        }
+
+        fn visit_map<M>(self, mut access: M) -> Result<Self::Value, M::Error>
+        where
+            M: MapAccess<'de>,
+        {
+            let mut map = HashMap::with_capacity(access.size_hint().unwrap_or(0));
+            while let Some((key, value)) = access.next_entry::<String, String>()? {
+                let usize_key = key.parse::<usize>().map_err(de::Error::custom)?;
+                if usize_key < 1 {
+                    return Err(de::Error::custom(
+                        "a usize key must be greater than or equal to 1",
+                    ));
+                }
+                map.insert(usize_key, value);
+            }
+            Ok(map)
+        }
+    }
+
+    deserializer.deserialize_map(UsizeKeyVisitor)
+}
+
+/// Writes a configuration, failing if the file exists so an edited configuration is not lost.
+pub fn init(path: &Utf8Path, text: &str) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .map_err(|err| anyhow!("Can not write {path}: {err}"))
+}
+
+/// Makes a commented configuration naming every channel, after the device where it names them.
+///
+/// Everything else is commented out with its default value.
+#[allow(clippy::too_many_lines)]
+pub fn starter(devices: &[String], device_channel_names: &[String]) -> String {
+    let mut text = format!(
+        "# smrec configuration for {}.
+# Commented out keys show their defaults, `smrec config validate` checks the file.
+
+# Whether the names of the device win over [channel_names], \"config\" or \"device\".
+# channel_name_source = \"config\"
+
+# Device channels to record or not to record, e.g. [1, 2], replaced by --include and --exclude.
+# include = []
+# exclude = []
+
+# Name of the recorder, prefixed to take names.
+# instance = \"stage\"
+
+# Milliseconds in which a repeated start or stop is dropped when MIDI and OSC both control the recorder, 0 turns it off.
+# dedupe_ms = 300
+
+# Peaks files next to every channel.
+# peaks = false
+
+# Loudness and peak of every file in take.toml.
+# replaygain = false
+
+# Acoustic fingerprint of the mixdown in take.toml, to find the same take on other drives.
+# fingerprint = false
+
+# Title, date, album and track number tags in every file, for players and converters.
+# tags = false
+
+# NTP server the clock is compared to at the start of every take, the offset is written to take.toml.
+# ntp = \"pool.ntp.org\"
+
+# DAW session next to every take.
+# session = \"reaper\"
+
+# Silent placeholder files written with the length of every take.
+# virtual_channels = []
+
+[channel_names]
+",
+        devices.join(", ")
+    );
+    for (channel, name) in device_channel_names.iter().enumerate() {
+        let name = if name.is_empty() {
+            format!("chn_{}", channel + 1)
+        } else {
+            name.clone()
+        };
+        let _ = writeln!(text, "{} = {}", channel + 1, toml::Value::String(name));
+    }
+    let _ = write!(
+        text,
+        "
+# Tracks device channels are recorded as.
+# [map]
+# 1 = 1
+
+# Formats of the channels, wav, wav:16, wav:24, wav:32 or wav:f32.
+# [channel_formats]
+# 1-{} = \"wav\"
+
+# How late devices deliver their audio, in samples or ms, so devices recorded together line up.
+# [latency]
+",
+        device_channel_names.len().max(1)
+    );
+    for device in devices {
+        let _ = writeln!(text, "# {} = \"0ms\"", toml::Value::String(device.clone()));
+    }
+    text.push_str(
+        "
+# Daily recording windows in local time.
+# [schedule]
+# windows = []
+
+# Metadata of the iXML chunks.
+# [ixml]
+# project = \"\"
+# scene = \"\"
+# tape = \"\"
+
+# A peer recorder which watches this one through heartbeats, the standby starts recording
+# when the primary stops responding while recording, with the \"armed\" takeover also while armed.
+# [failover]
+# role = \"standby\"
+# listen = \"0.0.0.0:9100\"
+# peer = \"192.168.1.10:9100\"
+# interval_ms = 250
+# timeout_ms = 2000
+# takeover = \"recording\"
+
+# Labels of the markers dropped by numbered cues, /smrec/cue or MIDI Program Change.
+# [cues]
+# 1 = \"Act 1\"
+# 2 = \"Intermission\"
+",
+    );
+    #[cfg(feature = "osc")]
+    text.push_str(
+        "
+# Continuous OSC feedback.
+# [osc_feedback]
+# rate = 10
+# queue = 32
+",
+    );
+    #[cfg(feature = "midi")]
+    text.push_str(
+        "
+# CC numbers of arming and disarming.
+# [midi_arming]
+# arm = 18
+# disarm = 19
+
+# CC numbers which solo and mute channels 1, 2, ... in the monitor stream,
+# and a click on the beats of a received MIDI clock.
+# [midi_monitor]
+# solo = [20, 21, 22, 23]
+# mute = [52, 53, 54, 55]
+# click = true
+
+# Titles of the next take by the song number of a MIDI Song Select.
+# [setlist]
+# 0 = \"Neon Nights\"
+",
+    );
+    text
+}
+
+/// Reports what is wrong with a configuration file, failing if anything is.
+pub fn validate(path: &Utf8Path, channels: Option<usize>) -> Result<()> {
+    let text =
+        std::fs::read_to_string(path).map_err(|err| anyhow!("Can not read {path}: {err}"))?;
+    let (_, warnings) =
+        SmrecConfig::parse(&text, channels).map_err(|err| anyhow!("{path}: {err}"))?;
+    if channels.is_none() {
+        println!("No input device is available, channel numbers are not checked.");
+    }
+    if warnings.is_empty() {
+        println!("{path} is valid.");
+        return Ok(());
+    }
+    for warning in &warnings {
+        println!("{warning}");
+    }
+    bail!("Found {} problem(s) in {path}.", warnings.len())
+}
+
+/// The keys of a TOML document where they are written, with the keys inside their values.
+///
+/// Elements of arrays are keyed by their index like in the paths of ignored keys.
+#[derive(Default)]
+struct KeySpans(Vec<(String, Range<usize>, Self)>);
+
+impl<'de> Deserialize<'de> for KeySpans {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct KeySpansVisitor;
+
+        impl<'de> Visitor<'de> for KeySpansVisitor {
+            type Value = KeySpans;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a TOML value")
+            }
+
+            fn visit_bool<E>(self, _: bool) -> Result<Self::Value, E> {
+                Ok(KeySpans::default())
+            }
+
+            fn visit_i64<E>(self, _: i64) -> Result<Self::Value, E> {
+                Ok(KeySpans::default())
+            }
+
+            fn visit_u64<E>(self, _: u64) -> Result<Self::Value, E> {
+                Ok(KeySpans::default())
+            }
+
+            fn visit_f64<E>(self, _: f64) -> Result<Self::Value, E> {
+                Ok(KeySpans::default())
+            }
+
+            fn visit_str<E>(self, _: &str) -> Result<Self::Value, E> {
+                Ok(KeySpans::default())
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let mut keys = Vec::new();
+                while let Some(value) = seq.next_element::<toml::Spanned<KeySpans>>()? {
+                    let span = value.span();
+                    keys.push((keys.len().to_string(), span, value.into_inner()));
+                }
+                Ok(KeySpans(keys))
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut keys = Vec::new();
+                while let Some(key) = map.next_key::<toml::Spanned<String>>()? {
+                    let span = key.span();
+                    keys.push((key.into_inner(), span, map.next_value()?));
+                }
+                Ok(KeySpans(keys))
+            }
+        }
+
+        deserializer.deserialize_any(KeySpansVisitor)
+    }
+}
+
+/// Finds the line and column, both 1 indexed, of the last key of a dotted path.
+fn locate(text: &str, mut keys: &KeySpans, path: &str) -> Option<(usize, usize)> {
+    let mut span = None;
+    for segment in path.split('.') {
+        let (_, key_span, inner) = keys.0.iter().find(|(key, ..)| key == segment)?;
+        span = Some(key_span.start);
+        keys = inner;
+    }
+    let before = text.get(..span?)?;
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    Some((
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    ))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::TempDir;
 
     #[test]
     fn deserialize_external_config() {
@@ -296,4 +2041,474 @@ mod tests {
             assert_eq!(key.to_string(), value.replace("channel_", ""));
         });
     }
+
+    #[test]
+    fn channel_flags_replace_configured_channels() {
+        let dir = TempDir::new("channels");
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "exclude = [2]\n\n[channel_names]\n").unwrap();
+
+        let channels = |include: Option<Vec<usize>>, exclude: Option<Vec<usize>>| {
+            SmrecConfig::new(
+                Some(path.to_string()),
+                None,
+                include,
+                exclude,
+                Vec::new(),
+                SupportedStreamConfig::new(
+                    4,
+                    cpal::SampleRate(48000),
+                    cpal::SupportedBufferSize::Unknown,
+                    cpal::SampleFormat::F32,
+                ),
+            )
+            .map(|config| config.channels_to_record().to_vec())
+        };
+
+        assert_eq!(channels(None, None).unwrap(), [0, 2, 3]);
+        assert_eq!(channels(Some(vec![4]), None).unwrap(), [3]);
+        assert_eq!(channels(None, Some(vec![1])).unwrap(), [1, 2, 3]);
+
+        std::fs::write(&path, "include = [1]\nexclude = [2]\n\n[channel_names]\n").unwrap();
+        assert!(channels(None, None).is_err());
+        assert_eq!(channels(Some(vec![2]), None).unwrap(), [1]);
+    }
+
+    #[test]
+    fn takes_are_stamped_with_the_playback_rate() {
+        let dir = TempDir::new("playback");
+        let out = dir.path();
+        let mut config = SmrecConfig::new(
+            Some(out.join("config.toml").to_string()),
+            Some(out.to_string()),
+            None,
+            None,
+            Vec::new(),
+            SupportedStreamConfig::new(
+                1,
+                cpal::SampleRate(48000),
+                cpal::SupportedBufferSize::Unknown,
+                cpal::SampleFormat::I16,
+            ),
+        )
+        .unwrap();
+        assert!(config.set_playback_rate(Some(0)).is_err());
+        config.set_playback_rate(Some(44100)).unwrap();
+
+        let take = config.writers().unwrap();
+        let (directory, id) = (take.directory.clone(), take.id.clone());
+        assert_eq!(take.sample_rate, 48000);
+        take.finalize().unwrap();
+        assert_eq!(takes::take_id(&directory), Some(id));
+
+        let reader = hound::WavReader::open(directory.join("chn_1.wav")).unwrap();
+        assert_eq!(reader.spec().sample_rate, 44100);
+        let sidecar = std::fs::read_to_string(directory.join("take.toml")).unwrap();
+        assert!(sidecar.contains("sample_rate = 48000\nplayback_rate = 44100\n"));
+    }
+
+    #[test]
+    fn only_the_first_take_is_appended() {
+        for id_in_file_names in [false, true] {
+            let dir = TempDir::new("append");
+            let out = dir.path();
+            let config = |append| {
+                let mut config = SmrecConfig::new(
+                    Some(out.join("config.toml").to_string()),
+                    Some(out.to_string()),
+                    None,
+                    None,
+                    Vec::new(),
+                    SupportedStreamConfig::new(
+                        1,
+                        cpal::SampleRate(48000),
+                        cpal::SupportedBufferSize::Unknown,
+                        cpal::SampleFormat::I16,
+                    ),
+                )
+                .unwrap();
+                config.set_append(append);
+                config.set_id_in_file_names(id_in_file_names);
+                config
+            };
+            let first = config(false).writers().unwrap();
+            let (directory, id) = (first.directory.clone(), first.id.clone());
+            first.finalize().unwrap();
+
+            let mut config = config(true);
+            let appended = config.writers().unwrap();
+            assert_eq!((&appended.directory, appended.part), (&directory, 2));
+            let name = if id_in_file_names {
+                format!("chn_1_{id}_part2.wav")
+            } else {
+                "chn_1_part2.wav".to_string()
+            };
+            assert_eq!(appended.files, [name]);
+            appended.finalize().unwrap();
+            let parts = std::fs::read_to_string(directory.join("parts.toml")).unwrap();
+            assert_eq!(parts.matches("[[parts]]").count(), 1);
+            assert!(parts.contains("part = 2\n"));
+
+            let next = config.writers().unwrap();
+            assert_ne!(next.directory, directory);
+            assert_eq!(next.part, 1);
+            next.finalize().unwrap();
+        }
+    }
+
+    #[test]
+    fn channels_are_included_and_excluded_between_takes() {
+        let mut config: SmrecConfig =
+            toml::from_str("[channel_names]\n5 = \"Room\"\n[map]\n3 = 1\n").unwrap();
+        config.cpal_stream_config = Some(SupportedStreamConfig::new(
+            8,
+            cpal::SampleRate(48000),
+            cpal::SupportedBufferSize::Unknown,
+            cpal::SampleFormat::F32,
+        ));
+        config.channels_to_record = vec![1, 3];
+        config.complete_channel_names();
+
+        config.include_channel(4).unwrap();
+        config.include_channel(0).unwrap();
+        assert_eq!(config.channels_to_record(), [0, 1, 3, 4]);
+        assert_eq!(
+            config
+                .get_channel_name_from_0_indexed_channel_num(4)
+                .unwrap(),
+            "Room.wav"
+        );
+        assert!(config.include_channel(4).is_err());
+        assert!(config.include_channel(8).is_err());
+        // Channel 3 is mapped to the track of channel 1.
+        assert!(config.include_channel(2).is_err());
+
+        config.exclude_channel(1).unwrap();
+        assert!(config.exclude_channel(1).is_err());
+        config.exclude_channel(0).unwrap();
+        config.exclude_channel(4).unwrap();
+        assert!(config.exclude_channel(3).is_err());
+        assert_eq!(config.channels_to_record(), [3]);
+
+        // The interface was switched to a mode with fewer channels.
+        config.validate_channel_count(4).unwrap();
+        assert!(config.validate_channel_count(3).is_err());
+    }
+
+    #[test]
+    fn restored_channel_names_stay_in_the_take() {
+        let mut config: SmrecConfig = toml::from_str("[channel_names]\n").unwrap();
+        config.cpal_stream_config = Some(SupportedStreamConfig::new(
+            2,
+            cpal::SampleRate(48000),
+            cpal::SupportedBufferSize::Unknown,
+            cpal::SampleFormat::F32,
+        ));
+        config
+            .restore(
+                "channels = [1, 2]\n[channel_names]\n1 = \"../../etc/Kick\"\n2 = \"C:\\\\Snare\"\n",
+            )
+            .unwrap();
+        assert_eq!(
+            config
+                .get_channel_name_from_0_indexed_channel_num(0)
+                .unwrap(),
+            ".._.._etc_Kick.wav"
+        );
+        assert_eq!(
+            config
+                .get_channel_name_from_0_indexed_channel_num(1)
+                .unwrap(),
+            "C__Snare.wav"
+        );
+        assert!(config
+            .restore("channels = [1]\n[channel_names]\n1 = \"..\"\n")
+            .is_err());
+        assert!(config.pipes().is_empty());
+    }
+
+    #[test]
+    fn mapped_channels_are_named_after_their_tracks() {
+        let mut config: SmrecConfig = toml::from_str(
+            r#"
+            [channel_names]
+            2 = "Snare"
+
+            [map]
+            7 = 1
+            8 = 2
+
+            [channel_formats]
+            1 = "wav:16"
+            "#,
+        )
+        .unwrap();
+        config.channels_to_record = vec![6, 7, 2];
+        config.complete_channel_names();
+
+        let name = |channel| {
+            config
+                .get_channel_name_from_0_indexed_channel_num(channel)
+                .unwrap()
+        };
+        assert_eq!(name(6), "chn_1.wav");
+        assert_eq!(name(7), "Snare.wav");
+        assert_eq!(name(2), "chn_3.wav");
+        assert_eq!(
+            config.channel_formats.get(&config.track(6)),
+            Some(&ChannelFormat::Int16)
+        );
+
+        assert!(config.validate_tracks(&[6, 7, 2]).is_ok());
+        assert!(config.validate_tracks(&[6, 0]).is_err());
+        assert!(toml::from_str::<SmrecConfig>("[channel_names]\n[map]\n1 = 2\n3 = 2").is_err());
+        assert!(toml::from_str::<SmrecConfig>("[channel_names]\n[map]\n1 = 0").is_err());
+    }
+
+    #[test]
+    fn virtual_channels_get_wav_names() {
+        let config: SmrecConfig = toml::from_str(
+            r#"
+        virtual_channels = ["Overdub Vox", "Guitar DI.WAV"]
+
+        [channel_names]
+        1 = "Kick"
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config
+                .virtual_channels
+                .iter()
+                .map(|name| with_wav_extension(name))
+                .collect::<Vec<_>>(),
+            vec!["Overdub Vox.wav", "Guitar DI.WAV"]
+        );
+    }
+
+    #[test]
+    fn channel_formats_with_ranges() {
+        let config: SmrecConfig = toml::from_str(
+            r#"
+        [channel_names]
+
+        [channel_formats]
+        1-3 = "wav:24"
+        9 = "WAV:F32"
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.channel_formats.len(), 4);
+        assert_eq!(config.channel_formats[&2], ChannelFormat::Int24);
+        assert_eq!(config.channel_formats[&9], ChannelFormat::Float32);
+
+        assert!(
+            toml::from_str::<SmrecConfig>("[channel_names]\n[channel_formats]\n1 = \"opus\"")
+                .unwrap_err()
+                .to_string()
+                .contains("compressed formats are out of scope")
+        );
+        assert!(
+            toml::from_str::<SmrecConfig>("[channel_names]\n[channel_formats]\n0-2 = \"wav\"")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn safety_gains_and_file_names() {
+        assert!((parse_gain_db("-12dB").unwrap() + 12.0).abs() < f32::EPSILON);
+        assert!((parse_gain_db(" -6 db").unwrap() + 6.0).abs() < f32::EPSILON);
+        assert!((parse_gain_db("-20").unwrap() + 20.0).abs() < f32::EPSILON);
+        assert!(parse_gain_db("loud").is_err());
+        assert!(parse_safety_db("0dB").unwrap().abs() < f32::EPSILON);
+        assert!(parse_safety_db("12dB").is_err());
+        assert_eq!(parse_duration("3s").unwrap(), Duration::from_secs(3));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration(" 1.5m").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("1.5").unwrap(), Duration::from_millis(1500));
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("-3s").is_err());
+        assert_eq!(
+            safety_file_name("chn_1_part2.wav"),
+            "chn_1_part2_safety.wav"
+        );
+    }
+
+    #[test]
+    fn hosts_are_validated() {
+        let command = clap::Command::new("smrec")
+            .arg(clap::Arg::new("host").long("host").value_parser(HostParser));
+        let error = command
+            .clone()
+            .try_get_matches_from(["smrec", "--host", "Asio2"])
+            .unwrap_err();
+        assert_eq!(error.kind(), clap::error::ErrorKind::InvalidValue);
+
+        let error = command
+            .clone()
+            .try_get_matches_from(["smrec", "--host", "asio"])
+            .err();
+        if let Some(error) = error {
+            assert!(error.to_string().contains("asio feature"));
+        }
+
+        if let Some(host_id) = cpal::available_hosts().first() {
+            let matches = command
+                .try_get_matches_from(["smrec", "--host", &host_id.name().to_lowercase()])
+                .unwrap();
+            assert_eq!(matches.get_one::<String>("host").unwrap(), host_id.name());
+        }
+    }
+
+    #[test]
+    fn ram_buffer_sizes() {
+        assert_eq!(parse_size("2G").unwrap(), 2 << 30);
+        assert_eq!(parse_size("512MiB").unwrap(), 512 << 20);
+        assert_eq!(parse_size("1.5k").unwrap(), 1536);
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert!(parse_size("2X").is_err());
+        assert!(parse_size("0").is_err());
+    }
+
+    #[test]
+    fn continuation_part_file_names() {
+        assert_eq!(part_file_name("chn_1.wav", 1), "chn_1.wav");
+        assert_eq!(part_file_name("chn_1.wav", 2), "chn_1_part2.wav");
+        assert_eq!(part_file_name("Kick.WAV", 3), "Kick_part3.WAV");
+    }
+
+    #[test]
+    fn unknown_keys_and_missing_channels_are_reported() {
+        let text = r#"
+[channel_names]
+1 = "Kick"
+10 = "Room"
+
+[channel_name]
+2 = "Snare"
+
+[map]
+3 = 10
+
+[ixml]
+scenee = "12A"
+"#;
+        let (config, warnings) = SmrecConfig::parse(text, Some(4)).unwrap();
+        assert_eq!(config.channel_names.get(&1).unwrap(), "Kick");
+        assert_eq!(
+            warnings,
+            [
+                "Unknown key channel_name at line 6, column 2 is ignored.",
+                "Unknown key ixml.scenee at line 13, column 1 is ignored.",
+            ]
+        );
+        let (_, warnings) =
+            SmrecConfig::parse("ixml = { scene = \"12\", scenee = \"12A\" }", None).unwrap();
+        assert_eq!(
+            warnings,
+            ["Unknown key ixml.scenee at line 1, column 24 is ignored."]
+        );
+
+        // Track 10 is only there when channel 3 is mapped to it.
+        let (_, warnings) = SmrecConfig::parse(&text.replace("3 = 10", "3 = 2"), Some(4)).unwrap();
+        assert!(warnings
+            .contains(&"[channel_names] refers to 10, the device has 4 channels.".to_string()));
+        let (_, warnings) = SmrecConfig::parse(text, Some(2)).unwrap();
+        assert!(warnings.contains(&"[map] refers to 3, the device has 2 channels.".to_string()));
+
+        let err = SmrecConfig::parse("[channel_names]\n1 = 2", None).unwrap_err();
+        assert!(err.to_string().contains("line 2, column 5"));
+    }
+
+    #[cfg(feature = "midi")]
+    #[test]
+    fn songs_are_titled_from_the_setlist() {
+        let (config, warnings) =
+            SmrecConfig::parse("[setlist]\n0 = \"Neon Nights\"\n", None).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(config.song_title(0), "Neon Nights");
+        assert_eq!(config.song_title(7), "song_7");
+        assert!(SmrecConfig::parse("[setlist]\n128 = \"Encore\"\n", None).is_err());
+    }
+
+    #[test]
+    fn cues_are_labelled_from_the_configuration() {
+        let (config, warnings) =
+            SmrecConfig::parse("[cues]\n1 = \"Act 1\"\n2 = \"Intermission\"\n", None).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(config.cue(2), Some("Intermission"));
+        assert_eq!(config.cue(3), None);
+        assert!(SmrecConfig::parse("[cues]\n0 = \"Preshow\"\n", None).is_err());
+        assert!(SmrecConfig::parse("[cues]\n129 = \"Encore\"\n", None).is_err());
+    }
+
+    #[test]
+    fn starter_configuration_names_every_channel() {
+        let text = starter(
+            &["XR18".to_string()],
+            &[
+                "Kick \"In\"".to_string(),
+                String::new(),
+                "Snare".to_string(),
+            ],
+        );
+        let (config, warnings) = SmrecConfig::parse(&text, Some(3)).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(config.channel_names.get(&1).unwrap(), "Kick \"In\"");
+        assert_eq!(config.channel_names.get(&2).unwrap(), "chn_2");
+        assert_eq!(config.channel_names.get(&3).unwrap(), "Snare");
+
+        // Uncommenting the defaults keeps the configuration valid.
+        let uncommented = text
+            .lines()
+            .map(|line| match line.strip_prefix("# ") {
+                Some(key) if key.starts_with('[') || key.contains(" = ") => key,
+                _ => line,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert!(SmrecConfig::parse(&uncommented, Some(3))
+            .unwrap()
+            .1
+            .is_empty());
+    }
+
+    #[test]
+    fn latencies_in_samples_and_milliseconds() {
+        let config: SmrecConfig = toml::from_str(
+            r#"
+            [latency]
+            "XR18" = 256
+            "UMC1820" = "10ms"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.latency_frames("XR18", 48000), 256);
+        assert_eq!(config.latency_frames("UMC1820", 48000), 480);
+        assert_eq!(config.latency_frames("UMC1820", 44100), 441);
+        assert_eq!(config.latency_frames("Built-in", 48000), 0);
+
+        assert_eq!(
+            "512 samples".parse::<Latency>().unwrap(),
+            Latency::Frames(512)
+        );
+        assert!("-2ms".parse::<Latency>().is_err());
+        assert!("10s".parse::<Latency>().is_err());
+    }
+
+    #[test]
+    fn device_names_match_patterns() {
+        assert!(matches_pattern("UMC1820*", "UMC1820 USB Audio"));
+        assert!(matches_pattern("*Scarlett*", "Focusrite Scarlett 2i2"));
+        assert!(matches_pattern(
+            "Built-in*Input",
+            "Built-in Microphone Input"
+        ));
+        assert!(matches_pattern("XR18", "XR18"));
+        assert!(!matches_pattern("XR18", "XR18 USB"));
+        assert!(!matches_pattern("UMC1820*", "Scarlett 2i2"));
+        assert!(!matches_pattern("Scarlett*2i2", "Scarlett 2i"));
+    }
 }
@@ -0,0 +1,137 @@
+// Printing from audio and MIDI callbacks goes through here, so a flood of messages never holds up a real-time thread.
+
+use crate::syslog;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+/// Messages waiting to be printed, more are dropped.
+const QUEUE: usize = 256;
+/// Lines printed at most per window, the rest are counted and reported.
+const MAX_LINES: usize = 10;
+const WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Info,
+    Error,
+}
+
+static QUEUED: OnceLock<crossbeam::channel::Sender<(Level, String)>> = OnceLock::new();
+/// Messages which did not fit in the queue.
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+/// Whether `--verbose` is given.
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+/// Prints how something was decided with `--verbose`, the message is only made then.
+///
+/// Only for the main thread, e.g. while devices are opened, it prints directly.
+pub fn trace(message: impl FnOnce() -> String) {
+    if VERBOSE.load(Ordering::Relaxed) {
+        println!("[verbose] {}", message());
+    }
+}
+
+/// Prints a message to stdout from the printing thread.
+pub fn info(message: String) {
+    queue(Level::Info, message);
+}
+
+/// Prints a message to stderr from the printing thread.
+pub fn error(message: String) {
+    queue(Level::Error, message);
+}
+
+fn queue(level: Level, message: String) {
+    let sender = QUEUED.get_or_init(|| {
+        let (sender, receiver) = crossbeam::channel::bounded(QUEUE);
+        std::thread::spawn(move || print(&receiver));
+        sender
+    });
+    // Never waits, a full queue means the console is flooded anyway.
+    if sender.try_send((level, message)).is_err() {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn print(receiver: &crossbeam::channel::Receiver<(Level, String)>) {
+    let mut limit = Limit::new();
+    while let Ok((level, message)) = receiver.recv() {
+        let (print, suppressed) = limit.admit(Instant::now());
+        let suppressed = suppressed + DROPPED.swap(0, Ordering::Relaxed);
+        if suppressed > 0 {
+            eprintln!("{suppressed} message(s) were suppressed.");
+        }
+        match (print, level) {
+            (true, Level::Info) => {
+                println!("{message}");
+                syslog::log(syslog::Severity::Info, "message", &[], &message);
+            }
+            (true, Level::Error) => {
+                eprintln!("{message}");
+                syslog::log(syslog::Severity::Error, "message", &[], &message);
+            }
+            (false, _) => {}
+        }
+    }
+}
+
+/// Lets a number of lines through per window.
+struct Limit {
+    window_start: Option<Instant>,
+    lines: usize,
+    suppressed: usize,
+}
+
+impl Limit {
+    const fn new() -> Self {
+        Self {
+            window_start: None,
+            lines: 0,
+            suppressed: 0,
+        }
+    }
+
+    /// Whether a line may be printed now, and how many were suppressed in the window which just ended.
+    fn admit(&mut self, now: Instant) -> (bool, usize) {
+        let mut ended = 0;
+        let elapsed = self
+            .window_start
+            .and_then(|start| now.checked_duration_since(start));
+        if elapsed.is_none_or(|elapsed| elapsed >= WINDOW) {
+            self.window_start = Some(now);
+            self.lines = 0;
+            ended = std::mem::take(&mut self.suppressed);
+        }
+        if self.lines < MAX_LINES {
+            self.lines += 1;
+            (true, ended)
+        } else {
+            self.suppressed += 1;
+            (false, ended)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floods_are_limited_per_window() {
+        let mut limit = Limit::new();
+        let now = Instant::now();
+        let admitted = (0..25).filter(|_| limit.admit(now).0).count();
+        assert_eq!(admitted, MAX_LINES);
+        assert_eq!(limit.admit(now + WINDOW), (true, 15));
+        assert_eq!(limit.admit(now + WINDOW), (true, 0));
+    }
+}
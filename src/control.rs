@@ -0,0 +1,101 @@
+use crate::{syslog, types::Action};
+use anyhow::Result;
+use crossbeam::channel::{Receiver, Sender};
+
+/// A way to control the recorder remotely, such as OSC or MIDI.
+///
+/// Surfaces send the actions they receive to the main thread and publish the events the main thread sends them,
+/// a new frontend only needs to implement this to be wired like the others.
+pub trait ControlSurface {
+    /// Starts sending received actions to `to_main_thread` and publishing what arrives on `events`.
+    fn start(&mut self, to_main_thread: Sender<Action>, events: Receiver<Action>) -> Result<()>;
+}
+
+/// The surfaces in use, they keep running as long as this lives.
+#[derive(Default)]
+pub struct Surfaces(Vec<Box<dyn ControlSurface>>);
+
+impl Surfaces {
+    pub fn add(&mut self, surface: impl ControlSurface + 'static) {
+        self.0.push(Box::new(surface));
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Starts every surface, each of them is sent every event which arrives on `events`.
+    pub fn start(
+        &mut self,
+        to_main_thread: &Sender<Action>,
+        events: Receiver<Action>,
+    ) -> Result<()> {
+        let mut outlets = Vec::with_capacity(self.0.len());
+        for surface in &mut self.0 {
+            let (outlet, surface_events) = crossbeam::channel::unbounded();
+            surface.start(to_main_thread.clone(), surface_events)?;
+            outlets.push(outlet);
+        }
+        std::thread::spawn(move || fan_out(&events, &outlets));
+        Ok(())
+    }
+}
+
+fn fan_out(events: &Receiver<Action>, outlets: &[Sender<Action>]) {
+    while let Ok(event) = events.recv() {
+        syslog::action(&event);
+        for outlet in outlets {
+            // A surface which stopped listening does not hold up the others.
+            outlet.send(event.clone()).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records what it is told and forwards one action.
+    struct Recorder(Sender<Action>);
+
+    impl ControlSurface for Recorder {
+        fn start(
+            &mut self,
+            to_main_thread: Sender<Action>,
+            events: Receiver<Action>,
+        ) -> Result<()> {
+            to_main_thread.send(Action::Arm)?;
+            let published = self.0.clone();
+            std::thread::spawn(move || {
+                while let Ok(event) = events.recv() {
+                    published.send(event).ok();
+                }
+            });
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn every_surface_is_told_every_event() {
+        let (published, received) = crossbeam::channel::unbounded();
+        let mut surfaces = Surfaces::default();
+        surfaces.add(Recorder(published.clone()));
+        surfaces.add(Recorder(published));
+        assert_eq!(surfaces.len(), 2);
+
+        let (to_main_thread, from_surfaces) = crossbeam::channel::unbounded();
+        let (to_surfaces, events) = crossbeam::channel::unbounded();
+        surfaces.start(&to_main_thread, events).unwrap();
+        assert_eq!(from_surfaces.try_iter().count(), 2);
+
+        to_surfaces.send(Action::Start).unwrap();
+        let timeout = std::time::Duration::from_secs(1);
+        for _ in 0..2 {
+            assert!(matches!(received.recv_timeout(timeout), Ok(Action::Start)));
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{takes::Take, title};
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    panic::PanicHookInfo,
+    sync::{Arc, Mutex, OnceLock, TryLockError},
+};
+
+/// Log lines kept for a crash report.
+const LOG_LINES: usize = 200;
+
+/// Where crash reports go and what goes into them, set with `--crash-reports`.
+struct Crash {
+    directory: Utf8PathBuf,
+    /// The command line and the configuration as they were when the run started.
+    snapshot: String,
+    /// The running take, its files are finalized before the report is written when the recorder goes down.
+    writers: Arc<Mutex<Option<Take>>>,
+}
+
+static CRASH: OnceLock<Crash> = OnceLock::new();
+static LINES: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Writes a report into the directory when `smrec` panics, after finalizing the files of the running take when the
+/// main thread panicked and the recorder goes down with it.
+///
+/// Nothing leaves the machine, the report is only written to the disk.
+pub fn enable(directory: Utf8PathBuf, snapshot: String, writers: Arc<Mutex<Option<Take>>>) {
+    println!("Crash reports are written to {directory}.");
+    CRASH
+        .set(Crash {
+            directory,
+            snapshot,
+            writers,
+        })
+        .ok();
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        if let Some(crash) = CRASH.get() {
+            crash.write(info);
+        }
+    }));
+}
+
+/// Keeps a log message for the crash report, only the last ones are kept.
+pub fn remember(event: &str, message: &str) {
+    if CRASH.get().is_none() {
+        return;
+    }
+    let line = format!(
+        "{} {event} {message}",
+        Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+    );
+    // A line is not worth waiting for, e.g. in the audio threads.
+    if let Ok(mut lines) = LINES.try_lock() {
+        if lines.len() == LOG_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+}
+
+impl Crash {
+    fn write(&self, info: &PanicHookInfo) {
+        let thread = std::thread::current();
+        // Other threads panicking leave the take recording, e.g. a control surface, it is not finalized under it.
+        let finalized = if thread.name() == Some("main") {
+            // Saving the recording matters more than the report.
+            match self.writers.try_lock() {
+                Ok(mut writers) => finalize(writers.take()),
+                Err(TryLockError::Poisoned(poisoned)) => finalize(poisoned.into_inner().take()),
+                Err(TryLockError::WouldBlock) => {
+                    "The running take was held by the crashed thread and could not be finalized."
+                        .to_string()
+                }
+            }
+        } else {
+            "The recorder kept running, the take was left recording.".to_string()
+        };
+        let now = Utc::now();
+        let lines = LINES
+            .try_lock()
+            .map(|lines| lines.iter().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        let report = report(
+            now,
+            &format!(
+                "Thread {} panicked: {info}",
+                thread.name().unwrap_or("unnamed")
+            ),
+            &std::backtrace::Backtrace::force_capture().to_string(),
+            &finalized,
+            &self.snapshot,
+            &lines,
+        );
+        let path = self
+            .directory
+            .join(format!("crash_{}.txt", now.format("%Y%m%d_%H%M%S")));
+        match std::fs::create_dir_all(&self.directory).and_then(|()| std::fs::write(&path, report))
+        {
+            Ok(()) => eprintln!("A crash report is written to {path}."),
+            Err(err) => eprintln!("Error writing the crash report to {path}: {err}"),
+        }
+    }
+}
+
+/// Finalizes the files of the take, without the analysis, hooks and sidecar of a take which is stopped.
+fn finalize(take: Option<Take>) -> String {
+    let Some(take) = take else {
+        return "No take was running.".to_string();
+    };
+    let directory = take.directory.clone();
+    match take.finalize_files() {
+        Ok(_) => format!("The files of the running take {directory} were finalized."),
+        Err(err) => format!("Error finalizing the running take {directory}: {err}"),
+    }
+}
+
+/// The command line, the configuration file and the channels the run started with.
+pub fn snapshot(config_path: &Utf8Path, channels: &str) -> String {
+    let config = std::fs::read_to_string(config_path)
+        .unwrap_or_else(|err| format!("# {config_path} can not be read: {err}\n"));
+    format!(
+        "Command line: {}\n\n# {config_path}\n{config}\n# Channels\n{channels}",
+        title::command_line()
+    )
+}
+
+fn report(
+    now: DateTime<Utc>,
+    panic: &str,
+    backtrace: &str,
+    finalized: &str,
+    snapshot: &str,
+    lines: &[String],
+) -> String {
+    let mut report = String::new();
+    // Writing to a string does not fail.
+    let _ = writeln!(
+        report,
+        "smrec {} crashed at {}.",
+        env!("CARGO_PKG_VERSION"),
+        now.to_rfc3339_opts(SecondsFormat::Millis, true)
+    );
+    let _ = writeln!(report, "{panic}\n\n{finalized}\n");
+    let _ = writeln!(report, "## Backtrace\n\n{backtrace}\n");
+    let _ = writeln!(report, "## Configuration\n\n{snapshot}\n");
+    let _ = writeln!(report, "## Last {} log lines\n", lines.len());
+    for line in lines {
+        let _ = writeln!(report, "{line}");
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn reports_have_the_panic_the_take_and_the_log() {
+        let now = Utc.with_ymd_and_hms(2023, 11, 20, 12, 0, 0).unwrap();
+        let report = report(
+            now,
+            "Thread main panicked: src/main.rs:1:1:\nboom",
+            "0: smrec::main",
+            &finalize(None),
+            "Command line: smrec --osc",
+            &["2023-11-20T11:59:59.000Z start Take started.".to_string()],
+        );
+        assert!(report.starts_with(&format!(
+            "smrec {} crashed at 2023-11-20T12:00:00.000Z.\nThread main panicked",
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert!(report.contains("boom\n\nNo take was running.\n"));
+        assert!(report.contains("## Backtrace\n\n0: smrec::main\n"));
+        assert!(report.contains("## Configuration\n\nCommand line: smrec --osc\n"));
+        assert!(report
+            .ends_with("## Last 1 log lines\n\n2023-11-20T11:59:59.000Z start Take started.\n"));
+    }
+}
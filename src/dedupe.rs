@@ -0,0 +1,60 @@
+use crate::types::Action;
+use std::time::{Duration, Instant};
+
+/// Window in which a repeated start or stop is dropped unless the configuration sets one.
+pub const DEFAULT_WINDOW: Duration = Duration::from_millis(300);
+
+/// Drops starts and stops which repeat the previous one within a window.
+///
+/// With a footswitch on MIDI and a tablet on OSC both wired to the transport one press may arrive twice,
+/// and a second start would end the take the first one started.
+pub struct Dedupe {
+    window: Duration,
+    /// Whether the last start or stop was a start, and when it arrived.
+    last: Option<(bool, Instant)>,
+}
+
+impl Dedupe {
+    pub const fn new(window: Duration) -> Self {
+        Self { window, last: None }
+    }
+
+    /// Whether an action repeats the last start or stop within the window, other actions never do.
+    pub fn is_repeat(&mut self, action: &Action, now: Instant) -> bool {
+        let start = match action {
+            Action::Start => true,
+            Action::Stop => false,
+            _ => return false,
+        };
+        if let Some((last, at)) = self.last {
+            // Counted from the first of them, so a stream of repeats can not hold the window open.
+            if last == start && now.checked_duration_since(at).unwrap_or_default() < self.window {
+                return true;
+            }
+        }
+        self.last = Some((start, now));
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats_within_the_window_are_dropped() {
+        let mut dedupe = Dedupe::new(Duration::from_millis(300));
+        let now = Instant::now();
+        let after = |millis| now + Duration::from_millis(millis);
+
+        assert!(!dedupe.is_repeat(&Action::Start, now));
+        assert!(dedupe.is_repeat(&Action::Start, after(40)));
+        assert!(!dedupe.is_repeat(&Action::Arm, after(50)));
+        assert!(!dedupe.is_repeat(&Action::Stop, after(100)));
+        assert!(dedupe.is_repeat(&Action::Stop, after(390)));
+        assert!(!dedupe.is_repeat(&Action::Stop, after(400)));
+        // A start well after the last one starts a new take as before.
+        assert!(!dedupe.is_repeat(&Action::Start, after(1000)));
+        assert!(!dedupe.is_repeat(&Action::Start, after(1300)));
+    }
+}
@@ -0,0 +1,242 @@
+// cpal does not expose channel names so they are queried from the backends directly where it is possible.
+// ASIO names channels only to the process which loaded the driver, and cpal keeps the driver to itself, so ASIO
+// channels are not named.
+
+#[cfg(target_os = "macos")]
+use cpal::traits::DeviceTrait;
+
+/// Queries the names of the first channels of the input of a device in channel order.
+///
+/// Returns `None` when the backend does not name its channels or they could not be queried.
+#[cfg_attr(not(target_os = "macos"), allow(unused_variables))]
+pub fn input_channel_names(
+    host: &cpal::Host,
+    device: &cpal::Device,
+    channels: usize,
+) -> Option<Vec<String>> {
+    match host.id().name() {
+        // The JACK host of cpal has a single device which is connected to the physical capture ports in order.
+        "JACK" => jack_capture_port_names(),
+        #[cfg(target_os = "macos")]
+        "CoreAudio" => coreaudio::input_channel_names(&device.name().ok()?, channels),
+        _ => None,
+    }
+}
+
+fn jack_capture_port_names() -> Option<Vec<String>> {
+    let output = std::process::Command::new("jack_lsp")
+        .args(["-A", "-p"])
+        .output()
+        .ok()?;
+    let names = parse_jack_lsp(&String::from_utf8_lossy(&output.stdout));
+    (!names.is_empty()).then_some(names)
+}
+
+/// Parses the output of `jack_lsp -A -p` to the names of the physical capture ports.
+///
+/// Ports are listed one per line, followed by indented lines of their aliases and properties.
+/// The last alias is preferred since it is usually the most descriptive one.
+fn parse_jack_lsp(output: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut port: Option<(String, bool)> = None;
+    let mut flush = |port: Option<(String, bool)>| {
+        if let Some((name, true)) = port {
+            names.push(name);
+        }
+    };
+
+    for line in output.lines() {
+        if line.starts_with(char::is_whitespace) {
+            let line = line.trim();
+            if let Some((name, is_capture)) = port.as_mut() {
+                if let Some(properties) = line.strip_prefix("properties:") {
+                    *is_capture = properties.contains("output") && properties.contains("physical");
+                } else if !line.is_empty() {
+                    *name = short_name(line);
+                }
+            }
+        } else {
+            flush(port.take());
+            port = Some((short_name(line), false));
+        }
+    }
+    flush(port);
+
+    names
+}
+
+/// Drops the client part of a port name and characters which do not belong in file names.
+fn short_name(port: &str) -> String {
+    file_name(port.rsplit(':').next().unwrap_or(port))
+}
+
+fn file_name(name: &str) -> String {
+    name.trim().replace(['/', '\\'], "_")
+}
+
+/// Core Audio names the elements of the input scope of a device, which are its channels counted from 1.
+#[cfg(target_os = "macos")]
+mod coreaudio {
+    use std::ffi::{c_char, c_void, CStr};
+
+    // Four character codes of the properties and scopes.
+    const DEVICES: u32 = u32::from_be_bytes(*b"dev#");
+    const NAME: u32 = u32::from_be_bytes(*b"lnam");
+    const ELEMENT_NAME: u32 = u32::from_be_bytes(*b"lchn");
+    const GLOBAL: u32 = u32::from_be_bytes(*b"glob");
+    const INPUT: u32 = u32::from_be_bytes(*b"inpt");
+    const SYSTEM_OBJECT: u32 = 1;
+    const UTF8: u32 = 0x0800_0100;
+
+    #[repr(C)]
+    struct Address {
+        selector: u32,
+        scope: u32,
+        element: u32,
+    }
+
+    #[link(name = "CoreAudio", kind = "framework")]
+    extern "C" {
+        fn AudioObjectGetPropertyDataSize(
+            object: u32,
+            address: *const Address,
+            qualifier_size: u32,
+            qualifier: *const c_void,
+            size: *mut u32,
+        ) -> i32;
+        fn AudioObjectGetPropertyData(
+            object: u32,
+            address: *const Address,
+            qualifier_size: u32,
+            qualifier: *const c_void,
+            size: *mut u32,
+            data: *mut c_void,
+        ) -> i32;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFStringGetCString(
+            string: *const c_void,
+            buffer: *mut c_char,
+            size: isize,
+            encoding: u32,
+        ) -> u8;
+        fn CFRelease(object: *const c_void);
+    }
+
+    /// The names of the channels of the device cpal gave the name, empty where a channel has none.
+    pub fn input_channel_names(device: &str, channels: usize) -> Option<Vec<String>> {
+        let name = Address {
+            selector: NAME,
+            scope: GLOBAL,
+            element: 0,
+        };
+        let id = devices()?
+            .into_iter()
+            .find(|id| string(*id, &name).as_deref() == Some(device))?;
+        let names = (1..=u32::try_from(channels).ok()?)
+            .map(|element| {
+                let address = Address {
+                    selector: ELEMENT_NAME,
+                    scope: INPUT,
+                    element,
+                };
+                string(id, &address)
+                    .map(|name| super::file_name(&name))
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+        names.iter().any(|name| !name.is_empty()).then_some(names)
+    }
+
+    fn devices() -> Option<Vec<u32>> {
+        let address = Address {
+            selector: DEVICES,
+            scope: GLOBAL,
+            element: 0,
+        };
+        let mut size = 0;
+        // SAFETY: The address and the size outlive the call.
+        let status = unsafe {
+            AudioObjectGetPropertyDataSize(
+                SYSTEM_OBJECT,
+                &raw const address,
+                0,
+                std::ptr::null(),
+                &raw mut size,
+            )
+        };
+        if status != 0 {
+            return None;
+        }
+        let mut devices = vec![0_u32; size as usize / std::mem::size_of::<u32>()];
+        // SAFETY: The devices have room for the size which is passed along.
+        let status = unsafe {
+            AudioObjectGetPropertyData(
+                SYSTEM_OBJECT,
+                &raw const address,
+                0,
+                std::ptr::null(),
+                &raw mut size,
+                devices.as_mut_ptr().cast(),
+            )
+        };
+        // Devices can go away between the calls.
+        devices.truncate(size as usize / std::mem::size_of::<u32>());
+        (status == 0).then_some(devices)
+    }
+
+    /// Reads a property which is a `CFString`.
+    fn string(object: u32, address: &Address) -> Option<String> {
+        let mut string: *const c_void = std::ptr::null();
+        #[allow(clippy::cast_possible_truncation)]
+        let mut size = std::mem::size_of::<*const c_void>() as u32;
+        // SAFETY: The property is a `CFStringRef` which fits in the pointer it is written to.
+        let status = unsafe {
+            AudioObjectGetPropertyData(
+                object,
+                address,
+                0,
+                std::ptr::null(),
+                &raw mut size,
+                (&raw mut string).cast(),
+            )
+        };
+        if status != 0 || string.is_null() {
+            return None;
+        }
+        let mut buffer = [0 as c_char; 256];
+        // SAFETY: The string was handed over to the caller, the buffer is as long as given and released after.
+        unsafe {
+            let converted = CFStringGetCString(string, buffer.as_mut_ptr(), 256, UTF8);
+            CFRelease(string);
+            (converted != 0).then(|| {
+                CStr::from_ptr(buffer.as_ptr())
+                    .to_string_lossy()
+                    .into_owned()
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn physical_capture_ports_from_jack_lsp() {
+        let output = "system:capture_1
+   alsa_pcm:Scarlett 2i2 USB:capture_FL
+	properties: output,physical,terminal,
+system:capture_2
+	properties: output,physical,terminal,
+system:playback_1
+   alsa_pcm:Scarlett 2i2 USB:playback_FL
+	properties: input,physical,terminal,
+smrec:in_1
+	properties: input,
+";
+        assert_eq!(parse_jack_lsp(output), vec!["capture_FL", "capture_2"]);
+    }
+}
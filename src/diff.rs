@@ -0,0 +1,215 @@
+use crate::{fingerprint, loudness, takes};
+use anyhow::Result;
+use camino::Utf8Path;
+use std::collections::BTreeMap;
+
+/// FNV-1a, small and good enough to tell whether two files hold the same samples.
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// What is compared of a file of a take.
+#[derive(Debug, Clone, PartialEq)]
+struct Summary {
+    frames: u32,
+    spec: hound::WavSpec,
+    /// Integrated loudness in LUFS, `None` when everything is below the gate.
+    loudness: Option<f64>,
+    /// Checksum of the samples only, headers and chunks differ between recorders of the same signal.
+    checksum: u64,
+}
+
+/// Compares two takes file by file and prints their differences, returns whether they match.
+pub fn run(a: &Utf8Path, b: &Utf8Path) -> Result<bool> {
+    let (name_a, name_b) = (
+        a.file_name().unwrap_or(a.as_str()),
+        b.file_name().unwrap_or(b.as_str()),
+    );
+    let differences = differences(name_a, &summarize(a)?, name_b, &summarize(b)?);
+    for difference in &differences {
+        println!("{difference}");
+    }
+    if differences.is_empty() {
+        println!("Takes {name_a} and {name_b} match.");
+    } else {
+        println!(
+            "Takes {name_a} and {name_b} differ in {} way(s).",
+            differences.len()
+        );
+    }
+    // Fingerprints recognize the same audio in takes which were renamed, converted or cut.
+    if let Some(similarity) = takes::take_fingerprint(a)
+        .zip(takes::take_fingerprint(b))
+        .and_then(|(a, b)| fingerprint::similarity(&a, &b))
+    {
+        println!(
+            "Fingerprints of {name_a} and {name_b} are {:.0}% alike.",
+            similarity * 100.0
+        );
+    }
+    Ok(differences.is_empty())
+}
+
+/// Summaries of the files of a take by their names.
+fn summarize(take: &Utf8Path) -> Result<BTreeMap<String, Summary>> {
+    let mut files = BTreeMap::new();
+    for entry in take.read_dir_utf8()? {
+        let path = entry?.into_path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("wav"))
+        {
+            let name = path.file_name().unwrap_or_default().to_string();
+            files.insert(name, summarize_file(&path)?);
+        }
+    }
+    Ok(files)
+}
+
+fn summarize_file(path: &Utf8Path) -> Result<Summary> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let mut checksum = FNV_OFFSET;
+    let mut hash = |bytes: [u8; 4]| {
+        for byte in bytes {
+            checksum = (checksum ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+        }
+    };
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .map(|sample| {
+                sample.map(|sample| {
+                    hash(sample.to_bits().to_le_bytes());
+                    f64::from(sample)
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        hound::SampleFormat::Int => {
+            let full_scale = f64::from(1_u32 << (spec.bits_per_sample - 1));
+            reader
+                .samples::<i32>()
+                .map(|sample| {
+                    sample.map(|sample| {
+                        hash(sample.to_le_bytes());
+                        f64::from(sample) / full_scale
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+    Ok(Summary {
+        frames: reader.duration(),
+        spec,
+        loudness: loudness::analyze(&samples, spec.sample_rate).loudness,
+        checksum,
+    })
+}
+
+/// Lines describing how two takes differ, empty when they match.
+fn differences(
+    name_a: &str,
+    a: &BTreeMap<String, Summary>,
+    name_b: &str,
+    b: &BTreeMap<String, Summary>,
+) -> Vec<String> {
+    let mut differences = Vec::new();
+    if a.len() != b.len() {
+        differences.push(format!("Channels: {} / {}", a.len(), b.len()));
+    }
+    for name in a.keys().filter(|name| !b.contains_key(*name)) {
+        differences.push(format!("{name}: only in {name_a}"));
+    }
+    for name in b.keys().filter(|name| !a.contains_key(*name)) {
+        differences.push(format!("{name}: only in {name_b}"));
+    }
+    for (name, a) in a {
+        let Some(b) = b.get(name) else {
+            continue;
+        };
+        if a.frames != b.frames {
+            differences.push(format!("{name}: length {} / {} frames", a.frames, b.frames));
+        }
+        if a.spec != b.spec {
+            differences.push(format!(
+                "{name}: format {} / {}",
+                format_spec(a.spec),
+                format_spec(b.spec)
+            ));
+        }
+        // Compared as printed, so differences below what is shown are not reported.
+        let (loudness_a, loudness_b) = (format_loudness(a.loudness), format_loudness(b.loudness));
+        if loudness_a != loudness_b {
+            differences.push(format!("{name}: loudness {loudness_a} / {loudness_b} LUFS"));
+        }
+        if a.checksum != b.checksum {
+            differences.push(format!(
+                "{name}: checksum {:016x} / {:016x}",
+                a.checksum, b.checksum
+            ));
+        }
+    }
+    differences
+}
+
+fn format_spec(spec: hound::WavSpec) -> String {
+    let format = match spec.sample_format {
+        hound::SampleFormat::Float => "float",
+        hound::SampleFormat::Int => "int",
+    };
+    format!(
+        "{} Hz {} bit {format}",
+        spec.sample_rate, spec.bits_per_sample
+    )
+}
+
+fn format_loudness(loudness: Option<f64>) -> String {
+    loudness.map_or_else(|| "-inf".to_string(), |loudness| format!("{loudness:.1}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn mirrored_takes_are_compared_by_their_samples() {
+        let dir = TempDir::new("diff");
+        let base = dir.path();
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let write = |take: &str, name: &str, samples: &[i16]| {
+            std::fs::create_dir_all(base.join(take)).unwrap();
+            let mut writer = hound::WavWriter::create(base.join(take).join(name), spec).unwrap();
+            for sample in samples {
+                writer.write_sample(*sample).unwrap();
+            }
+            writer.finalize().unwrap();
+        };
+        write("a", "Kick.wav", &[0, 1000, -1000, 0]);
+        write("a", "Snare.wav", &[0, 500]);
+        write("b", "Kick.wav", &[0, 1000, -1000, 0]);
+        write("b", "Snare.wav", &[0, 500, 0]);
+        write("b", "Room.wav", &[0]);
+
+        let (a, b) = (
+            summarize(&base.join("a")).unwrap(),
+            summarize(&base.join("b")).unwrap(),
+        );
+        assert!(differences("a", &a, "a", &a).is_empty());
+        let differences = differences("a", &a, "b", &b);
+        assert_eq!(
+            differences[..3],
+            [
+                "Channels: 2 / 3",
+                "Room.wav: only in b",
+                "Snare.wav: length 2 / 3 frames"
+            ]
+        );
+        assert!(differences[3].starts_with("Snare.wav: checksum "));
+        assert_eq!(differences.len(), 4);
+    }
+}
@@ -0,0 +1,343 @@
+mod hd44780;
+mod ssd1306;
+
+use crate::{
+    control::ControlSurface,
+    error::Code,
+    processing::Processing,
+    space,
+    types::{Action, Transport},
+};
+use anyhow::{bail, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use crossbeam::channel::{Receiver, RecvTimeoutError, Sender};
+use std::{
+    fs::File,
+    io::Write,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// How often the elapsed time and the levels are drawn again.
+const REFRESH: Duration = Duration::from_millis(250);
+/// The level at the bottom of a bar, in dBFS.
+const FLOOR_DB: f32 = -60.0;
+
+/// The displays which can be driven, all of them over I2C.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// A 128x64 OLED.
+    Ssd1306,
+    /// A character LCD with a PCF8574 backpack, columns and rows.
+    Hd44780(usize, usize),
+}
+
+/// Which display is used and where it is connected, e.g. `ssd1306`, `hd44780-20x4:/dev/i2c-0@0x3f`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spec {
+    kind: Kind,
+    bus: Utf8PathBuf,
+    address: u16,
+}
+
+impl FromStr for Spec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (rest, address) = match s.rsplit_once('@') {
+            Some((rest, address)) => {
+                let address = address.trim();
+                let parsed = address.strip_prefix("0x").map_or_else(
+                    || address.parse::<u16>(),
+                    |hex| u16::from_str_radix(hex, 16),
+                );
+                (
+                    rest,
+                    Some(parsed.with_context(|| format!("{address} is not an I2C address."))?),
+                )
+            }
+            None => (s, None),
+        };
+        let (kind, bus) = rest
+            .split_once(':')
+            .map_or((rest, None), |(kind, bus)| (kind, Some(bus)));
+        let kind = match kind.trim().to_ascii_lowercase().as_str() {
+            "ssd1306" => Kind::Ssd1306,
+            "hd44780" | "hd44780-16x2" => Kind::Hd44780(16, 2),
+            "hd44780-20x4" => Kind::Hd44780(20, 4),
+            kind => bail!("Unknown display {kind}, use ssd1306, hd44780-16x2 or hd44780-20x4."),
+        };
+        Ok(Self {
+            kind,
+            bus: Utf8PathBuf::from(bus.map_or("/dev/i2c-1", str::trim)),
+            // The addresses most modules are sold with.
+            address: address.unwrap_or(match kind {
+                Kind::Ssd1306 => 0x3C,
+                Kind::Hd44780(..) => 0x27,
+            }),
+        })
+    }
+}
+
+/// What is shown, drawn by every kind of display its own way.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Screen {
+    pub transport: Transport,
+    /// Of the running take.
+    pub elapsed: Option<Duration>,
+    /// Seconds of recording time left on the disk, once it was estimated.
+    pub remaining: Option<u64>,
+    /// The last error until the next take starts.
+    pub error: Option<Code>,
+    /// Peak of every recorded channel since the take started, in dBFS.
+    pub levels: Vec<f32>,
+}
+
+impl Screen {
+    /// e.g. `REC 00:12:34`.
+    pub fn status(&self) -> String {
+        match (self.transport, self.elapsed) {
+            (Transport::Recording, Some(elapsed)) => {
+                let secs = elapsed.as_secs();
+                format!(
+                    "REC {:02}:{:02}:{:02}",
+                    secs / 3600,
+                    secs % 3600 / 60,
+                    secs % 60
+                )
+            }
+            (Transport::Recording, None) => "REC".to_string(),
+            (Transport::Armed, _) => "ARMED".to_string(),
+            (Transport::Stopped, _) => "STOPPED".to_string(),
+        }
+    }
+
+    /// The time left on the disk, or the last error.
+    pub fn detail(&self) -> String {
+        match (self.error, self.remaining) {
+            (Some(code), _) => format!("ERROR {}", code.code()),
+            (None, Some(remaining)) => format!("DISK {}", space::format_duration(remaining)),
+            (None, None) => "DISK --".to_string(),
+        }
+    }
+}
+
+/// How many of `steps` a bar of a level in dBFS fills, silence fills none and 0 dBFS all of them.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+pub fn bar(level_db: f32, steps: usize) -> usize {
+    let fraction = ((level_db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+    ((fraction * steps as f32).ceil() as usize).min(steps)
+}
+
+/// Where the bytes to a display go.
+trait Bus: Send {
+    fn write(&mut self, bytes: &[u8]) -> Result<()>;
+}
+
+/// A display which draws screens, it is set up when it is created.
+trait Panel: Send {
+    fn show(&mut self, screen: &Screen) -> Result<()>;
+}
+
+/// A device on a Linux I2C bus, through `/dev/i2c-*`.
+struct I2c(File);
+
+impl I2c {
+    #[cfg(target_os = "linux")]
+    fn open(bus: &Utf8Path, address: u16) -> Result<Self> {
+        use std::os::fd::AsRawFd;
+
+        const I2C_SLAVE: libc::Ioctl = 0x0703;
+
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .open(bus)
+            .with_context(|| format!("Error opening {bus}."))?;
+        // SAFETY: The file is open and the address is passed by value.
+        if unsafe { libc::ioctl(file.as_raw_fd(), I2C_SLAVE, libc::c_ulong::from(address)) } < 0 {
+            bail!(
+                "Error selecting the device at {address:#04x} on {bus}: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(Self(file))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn open(bus: &Utf8Path, _address: u16) -> Result<Self> {
+        bail!("{bus} can not be opened, displays are only supported on Linux.")
+    }
+}
+
+impl Bus for I2c {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        // Every write is one transfer on the bus.
+        Ok(self.0.write_all(bytes)?)
+    }
+}
+
+/// Keeps every write, for the tests of the displays.
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct Recorder(Arc<std::sync::Mutex<Vec<Vec<u8>>>>);
+
+#[cfg(test)]
+impl Recorder {
+    fn take(&self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+#[cfg(test)]
+impl Bus for Recorder {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.0.lock().unwrap().push(bytes.to_vec());
+        Ok(())
+    }
+}
+
+/// Shows the transport, elapsed time, levels and disk space on a small display, for recorders without a monitor.
+pub struct Display {
+    panel: Option<Box<dyn Panel>>,
+    processing: Arc<Processing>,
+    channels: Vec<usize>,
+}
+
+impl Display {
+    /// Opens and sets up the display, which shows the levels of the given 0 indexed device channels.
+    pub fn new(spec: &Spec, processing: Arc<Processing>, channels: Vec<usize>) -> Result<Self> {
+        let bus = I2c::open(&spec.bus, spec.address)?;
+        let panel: Box<dyn Panel> = match spec.kind {
+            Kind::Ssd1306 => Box::new(ssd1306::Ssd1306::new(bus)?),
+            Kind::Hd44780(columns, rows) => Box::new(hd44780::Hd44780::new(bus, columns, rows)?),
+        };
+        Ok(Self {
+            panel: Some(panel),
+            processing,
+            channels,
+        })
+    }
+}
+
+impl ControlSurface for Display {
+    fn start(&mut self, _to_main_thread: Sender<Action>, events: Receiver<Action>) -> Result<()> {
+        let Some(panel) = self.panel.take() else {
+            bail!("The display is started already.");
+        };
+        let (processing, channels) = (self.processing.clone(), self.channels.clone());
+        std::thread::spawn(move || run(panel, &processing, &channels, &events));
+        Ok(())
+    }
+}
+
+/// Draws the screen after the events and every `REFRESH` until the session ends.
+fn run(
+    mut panel: Box<dyn Panel>,
+    processing: &Processing,
+    channels: &[usize],
+    events: &Receiver<Action>,
+) {
+    let mut screen = Screen {
+        transport: Transport::Stopped,
+        elapsed: None,
+        remaining: None,
+        error: None,
+        levels: Vec::new(),
+    };
+    let mut started = None;
+    loop {
+        match events.recv_timeout(REFRESH) {
+            Ok(Action::Start) => {
+                screen.transport = Transport::Recording;
+                screen.error = None;
+                started = Some(Instant::now());
+            }
+            Ok(Action::Stop) => {
+                if screen.transport == Transport::Recording {
+                    screen.transport = Transport::Armed;
+                }
+                started = None;
+            }
+            Ok(Action::Arm) if screen.transport == Transport::Stopped => {
+                screen.transport = Transport::Armed;
+            }
+            Ok(Action::Disarm) if screen.transport == Transport::Armed => {
+                screen.transport = Transport::Stopped;
+            }
+            Ok(Action::RemainingTime(secs)) => screen.remaining = Some(secs),
+            Ok(Action::Err(code, _)) => screen.error = Some(code),
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        screen.elapsed = started.map(|started| started.elapsed());
+        let meters = processing.meters().held();
+        screen.levels = channels
+            .iter()
+            .filter_map(|channel| meters.get(*channel))
+            .map(|meter| meter.peak_db)
+            .collect();
+        if let Err(err) = panel.show(&screen) {
+            eprintln!("Error updating the display, it is not updated anymore: {err}");
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_are_given_by_kind_bus_and_address() {
+        assert_eq!(
+            "ssd1306".parse::<Spec>().unwrap(),
+            Spec {
+                kind: Kind::Ssd1306,
+                bus: Utf8PathBuf::from("/dev/i2c-1"),
+                address: 0x3C,
+            }
+        );
+        assert_eq!(
+            "hd44780-20x4:/dev/i2c-0@0x3f".parse::<Spec>().unwrap(),
+            Spec {
+                kind: Kind::Hd44780(20, 4),
+                bus: Utf8PathBuf::from("/dev/i2c-0"),
+                address: 0x3F,
+            }
+        );
+        assert!("st7735".parse::<Spec>().is_err());
+        assert!("ssd1306@0xZZ".parse::<Spec>().is_err());
+    }
+
+    #[test]
+    fn screens_show_the_transport_and_the_disk() {
+        let mut screen = Screen {
+            transport: Transport::Recording,
+            elapsed: Some(Duration::from_secs(3754)),
+            remaining: Some(11520),
+            error: None,
+            levels: vec![],
+        };
+        assert_eq!(
+            (screen.status(), screen.detail()),
+            ("REC 01:02:34".to_string(), "DISK 3h12m".to_string())
+        );
+        screen.error = Some(Code::Storage);
+        screen.transport = Transport::Armed;
+        assert_eq!(
+            (screen.status(), screen.detail()),
+            ("ARMED".to_string(), "ERROR 40".to_string())
+        );
+
+        assert_eq!(bar(f32::NEG_INFINITY, 8), 0);
+        assert_eq!(bar(-30.0, 8), 4);
+        assert_eq!(bar(-0.1, 8), 8);
+        assert_eq!(bar(3.0, 8), 8);
+    }
+}
@@ -0,0 +1,192 @@
+use super::{bar, Bus, Panel, Screen};
+use anyhow::Result;
+use std::time::Duration;
+
+// The pins of a PCF8574 backpack, the data nibble is on the upper four.
+const REGISTER_SELECT: u8 = 0x01;
+const ENABLE: u8 = 0x04;
+const BACKLIGHT: u8 = 0x08;
+
+const CLEAR: u8 = 0x01;
+const ENTRY_LEFT_TO_RIGHT: u8 = 0x06;
+const DISPLAY_OFF: u8 = 0x08;
+const DISPLAY_ON: u8 = 0x0C;
+/// 4 bit interface, 2 lines and 5x8 dots, 4 line displays are 2 line displays folded.
+const FUNCTION_SET: u8 = 0x28;
+const SET_CGRAM_ADDRESS: u8 = 0x40;
+const SET_DDRAM_ADDRESS: u8 = 0x80;
+
+/// Custom characters of bars 1 to 8 dots high.
+const BARS: usize = 8;
+
+/// A character LCD driven through a PCF8574 I2C backpack.
+pub struct Hd44780<B> {
+    bus: B,
+    columns: usize,
+    rows: usize,
+    /// What the display shows, only rows which changed are written.
+    shown: Vec<Vec<u8>>,
+}
+
+impl<B: Bus> Hd44780<B> {
+    pub fn new(bus: B, columns: usize, rows: usize) -> Result<Self> {
+        let mut display = Self {
+            bus,
+            columns,
+            rows,
+            shown: vec![vec![b' '; columns]; rows],
+        };
+        // Switches to the 4 bit interface from whichever state it is in, as the datasheet describes.
+        std::thread::sleep(Duration::from_millis(50));
+        for (nibble, wait) in [(0x3, 5), (0x3, 1), (0x3, 1), (0x2, 1)] {
+            display.nibble(nibble, 0)?;
+            std::thread::sleep(Duration::from_millis(wait));
+        }
+        display.command(FUNCTION_SET)?;
+        display.command(DISPLAY_OFF)?;
+        display.command(CLEAR)?;
+        std::thread::sleep(Duration::from_millis(2));
+        display.command(ENTRY_LEFT_TO_RIGHT)?;
+        display.command(SET_CGRAM_ADDRESS)?;
+        for height in 1..=BARS {
+            for dot_row in 0..8 {
+                display.data(if dot_row >= 8 - height { 0x1F } else { 0 })?;
+            }
+        }
+        display.command(DISPLAY_ON)?;
+        Ok(display)
+    }
+
+    /// Latches a nibble on the falling edge of the enable pin, one I2C write takes longer than the pulse needs to be.
+    fn nibble(&mut self, nibble: u8, mode: u8) -> Result<()> {
+        let byte = (nibble << 4) | mode | BACKLIGHT;
+        self.bus.write(&[byte | ENABLE])?;
+        self.bus.write(&[byte])
+    }
+
+    fn command(&mut self, command: u8) -> Result<()> {
+        self.nibble(command >> 4, 0)?;
+        self.nibble(command & 0x0F, 0)
+    }
+
+    fn data(&mut self, data: u8) -> Result<()> {
+        self.nibble(data >> 4, REGISTER_SELECT)?;
+        self.nibble(data & 0x0F, REGISTER_SELECT)
+    }
+
+    /// The address of the first character of a row, rows 3 and 4 continue rows 1 and 2.
+    #[allow(clippy::cast_possible_truncation)]
+    const fn row_address(&self, row: usize) -> u8 {
+        let folded = (row / 2 * self.columns) as u8;
+        if row.is_multiple_of(2) {
+            folded
+        } else {
+            0x40 + folded
+        }
+    }
+
+    /// Rows with the status, the detail and a bar for every channel which fits.
+    ///
+    /// Bars follow the detail on 2 row displays and take up the rows below it on larger ones.
+    fn rows_of(&self, screen: &Screen) -> Vec<Vec<u8>> {
+        let mut rows = vec![vec![b' '; self.columns]; self.rows];
+        let (status, detail) = (screen.status(), screen.detail());
+        put(&mut rows[0], &status);
+        put(&mut rows[1], &detail);
+        let cells = if self.rows > 2 {
+            (2..self.rows)
+                .flat_map(|row| (0..self.columns).map(move |column| (row, column)))
+                .collect::<Vec<_>>()
+        } else {
+            (detail.len() + 1..self.columns)
+                .map(|column| (1, column))
+                .collect()
+        };
+        for (level, (row, column)) in screen.levels.iter().zip(cells) {
+            rows[row][column] = match bar(*level, BARS) {
+                0 => b' ',
+                #[allow(clippy::cast_possible_truncation)]
+                height => (height - 1) as u8,
+            };
+        }
+        rows
+    }
+}
+
+/// Writes text into a row, characters the display does not have are shown as `?`.
+fn put(row: &mut [u8], text: &str) {
+    for (cell, character) in row.iter_mut().zip(text.chars()) {
+        *cell = match character {
+            ' '..='}' => character as u8,
+            _ => b'?',
+        };
+    }
+}
+
+impl<B: Bus> Panel for Hd44780<B> {
+    fn show(&mut self, screen: &Screen) -> Result<()> {
+        let rows = self.rows_of(screen);
+        for (index, row) in rows.into_iter().enumerate() {
+            if self.shown[index] == row {
+                continue;
+            }
+            self.command(SET_DDRAM_ADDRESS | self.row_address(index))?;
+            for character in &row {
+                self.data(*character)?;
+            }
+            self.shown[index] = row;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{display::Recorder, types::Transport};
+
+    /// The bytes sent to the display, with whether they were characters, from the nibbles latched by the enable pin.
+    fn sent(writes: &[Vec<u8>]) -> Vec<(bool, u8)> {
+        let nibbles = writes
+            .iter()
+            .filter(|write| write[0] & ENABLE != 0)
+            .map(|write| (write[0] & REGISTER_SELECT != 0, write[0] >> 4))
+            .collect::<Vec<_>>();
+        nibbles
+            .chunks(2)
+            .map(|pair| (pair[0].0, pair[0].1 << 4 | pair[1].1))
+            .collect()
+    }
+
+    #[test]
+    fn rows_which_changed_are_written() {
+        let recorder = Recorder::default();
+        let mut display = Hd44780::new(recorder.clone(), 16, 2).unwrap();
+        let setup = recorder.take();
+        // The 4 nibbles which switch to the 4 bit interface come first.
+        assert_eq!(sent(&setup[8..])[0], (false, FUNCTION_SET));
+
+        let mut screen = Screen {
+            transport: Transport::Armed,
+            elapsed: None,
+            remaining: Some(300),
+            error: None,
+            levels: vec![f32::NEG_INFINITY, -30.0, 0.0],
+        };
+        display.show(&screen).unwrap();
+        let written = sent(&recorder.take());
+        assert_eq!(written[0], (false, SET_DDRAM_ADDRESS));
+        let row = |bytes: &[(bool, u8)]| bytes.iter().map(|(_, byte)| *byte).collect::<Vec<_>>();
+        assert_eq!(row(&written[1..17]), b"ARMED           ");
+        assert_eq!(written[17], (false, SET_DDRAM_ADDRESS | 0x40));
+        // Silence, half and full bars after the detail.
+        assert_eq!(row(&written[18..]), b"DISK 5m  \x03\x07     ");
+
+        // Only the status changed.
+        screen.transport = Transport::Stopped;
+        display.show(&screen).unwrap();
+        assert_eq!(sent(&recorder.take()).len(), 17);
+        display.show(&screen).unwrap();
+        assert!(recorder.take().is_empty());
+    }
+}
@@ -0,0 +1,237 @@
+use super::{bar, Bus, Panel, Screen};
+use anyhow::Result;
+
+const WIDTH: usize = 128;
+/// Rows of 8 pixels, each byte of a page is a column of them with the top one in the lowest bit.
+const PAGES: usize = 8;
+/// Pixels per character, a 5 pixel glyph and a gap.
+const CHARACTER_WIDTH: usize = 6;
+/// The pages the bars take up at the bottom.
+const BAR_PAGES: usize = 4;
+
+/// Follows a control byte to tell what comes after it apart.
+const COMMANDS: u8 = 0x00;
+const DATA: u8 = 0x40;
+/// Bytes of data sent at once, small I2C adapters can not send many more.
+const CHUNK: usize = 16;
+
+/// Sets up a 128x64 module with its charge pump, as in the application note of the controller.
+const SETUP: [u8; 25] = [
+    0xAE, // Display off
+    0xD5, 0x80, // Clock
+    0xA8, 0x3F, // 64 rows
+    0xD3, 0x00, // No offset
+    0x40, // Starts at row 0
+    0x8D, 0x14, // Charge pump on
+    0x20, 0x00, // Horizontal addressing
+    0xA1, // Column 127 is on the right
+    0xC8, // Row 63 is at the bottom
+    0xDA, 0x12, // Alternative row pins
+    0x81, 0xCF, // Contrast
+    0xD9, 0xF1, // Pre-charge
+    0xDB, 0x40, // Deselect voltage
+    0xA4, // Shows the memory
+    0xA6, // Not inverted
+    0xAF, // Display on
+];
+/// Addresses the whole memory, from the top left.
+const WHOLE_SCREEN: [u8; 6] = [0x21, 0, 127, 0x22, 0, 7];
+/// The glyph of `?`, drawn for characters the font does not have.
+const UNKNOWN: usize = b'?' as usize - 0x20;
+
+/// A 128x64 OLED which shows the status and the detail in text and a bar for every channel below them.
+pub struct Ssd1306<B> {
+    bus: B,
+    /// What the display shows, it is only written when it changed.
+    shown: Option<Vec<u8>>,
+}
+
+impl<B: Bus> Ssd1306<B> {
+    pub fn new(mut bus: B) -> Result<Self> {
+        bus.write(&[&[COMMANDS][..], &SETUP].concat())?;
+        Ok(Self { bus, shown: None })
+    }
+}
+
+/// Draws text into a page, characters the font does not have are drawn as `?`.
+fn text(frame: &mut [u8], page: usize, text: &str) {
+    let columns = frame[page * WIDTH..(page + 1) * WIDTH].chunks_mut(CHARACTER_WIDTH);
+    for (cells, character) in columns.zip(text.chars()) {
+        let glyph = (character as usize)
+            .checked_sub(0x20)
+            .and_then(|index| FONT.get(index))
+            .unwrap_or(&FONT[UNKNOWN]);
+        for (cell, column) in cells.iter_mut().zip(glyph) {
+            *cell = *column;
+        }
+    }
+}
+
+/// The frame of a screen, a byte per column of every page.
+fn frame(screen: &Screen) -> Vec<u8> {
+    let mut frame = vec![0; WIDTH * PAGES];
+    text(&mut frame, 0, &screen.status());
+    text(&mut frame, 2, &screen.detail());
+    // Bars with a pixel between them, as many as fit.
+    let width = (WIDTH / screen.levels.len().max(1)).clamp(2, 16);
+    let height = BAR_PAGES * 8;
+    for (index, level) in screen.levels.iter().take(WIDTH / width).enumerate() {
+        let filled = bar(*level, height);
+        for x in index * width..(index + 1) * width - 1 {
+            for y in PAGES * 8 - filled..PAGES * 8 {
+                frame[y / 8 * WIDTH + x] |= 1 << (y % 8);
+            }
+        }
+    }
+    frame
+}
+
+impl<B: Bus> Panel for Ssd1306<B> {
+    fn show(&mut self, screen: &Screen) -> Result<()> {
+        let frame = frame(screen);
+        if self.shown.as_ref() == Some(&frame) {
+            return Ok(());
+        }
+        self.bus.write(&[&[COMMANDS][..], &WHOLE_SCREEN].concat())?;
+        for chunk in frame.chunks(CHUNK) {
+            self.bus.write(&[&[DATA][..], chunk].concat())?;
+        }
+        self.shown = Some(frame);
+        Ok(())
+    }
+}
+
+/// 5x7 glyphs of the printable ASCII characters, a byte per column.
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // #
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // )
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08], // *
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // 0
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4B, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1E], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3E], // @
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], // A
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], // D
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7F, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3E, 0x41, 0x49, 0x49, 0x7A], // G
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // H
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // J
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F], // M
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // N
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // Q
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7F, 0x01, 0x01], // T
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // V
+    [0x3F, 0x40, 0x38, 0x40, 0x3F], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x07, 0x08, 0x70, 0x08, 0x07], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7F, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x7F, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7F, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7F], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7E, 0x09, 0x01, 0x02], // f
+    [0x0C, 0x52, 0x52, 0x52, 0x3E], // g
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3D, 0x00], // j
+    [0x7F, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // l
+    [0x7C, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7C, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7C], // q
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3F, 0x44, 0x40, 0x20], // t
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // u
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // v
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0C, 0x50, 0x50, 0x50, 0x3C], // y
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7F, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x08, 0x04, 0x08, 0x10, 0x08], // ~
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{display::Recorder, types::Transport};
+
+    #[test]
+    fn frames_are_drawn_when_they_changed() {
+        let recorder = Recorder::default();
+        let mut display = Ssd1306::new(recorder.clone()).unwrap();
+        assert_eq!(recorder.take()[0][..2], [COMMANDS, 0xAE]);
+
+        let screen = Screen {
+            transport: Transport::Recording,
+            elapsed: Some(std::time::Duration::from_secs(5)),
+            remaining: None,
+            error: None,
+            levels: vec![0.0, f32::NEG_INFINITY],
+        };
+        let frame = frame(&screen);
+        // `R` of `REC`.
+        assert_eq!(frame[..5], FONT[usize::from(b'R' - 0x20)]);
+        // Bars of 15 pixels and a gap, the first full and the second silent.
+        assert!(frame[4 * WIDTH..]
+            .chunks(WIDTH)
+            .all(|page| page[0] == 0xFF && page[14] == 0xFF && page[15] == 0 && page[16] == 0));
+        assert_eq!(frame[3 * WIDTH], 0);
+
+        display.show(&screen).unwrap();
+        let writes = recorder.take();
+        assert_eq!(writes.len(), 1 + WIDTH * PAGES / CHUNK);
+        assert_eq!(writes[1][0], DATA);
+        display.show(&screen).unwrap();
+        assert!(recorder.take().is_empty());
+    }
+}
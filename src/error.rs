@@ -0,0 +1,126 @@
+use std::fmt;
+
+/// What went wrong, sent with `/smrec/error` and used as the exit status of the process.
+///
+/// The numbers are kept stable so remote UIs can localize and react to them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    /// Anything without a more specific code.
+    Other = 1,
+    /// The configuration could not be loaded, changed or restored.
+    Config = 10,
+    /// A channel does not exist or can not be recorded.
+    Channel = 11,
+    /// The audio inputs could not be opened or deliver samples which can not be recorded.
+    Device = 20,
+    /// A recording could not be started, stopped or restarted.
+    Recording = 30,
+    /// A recording was started without arming first.
+    NotArmed = 31,
+    /// There is no running recording to act on.
+    NoRecording = 32,
+    /// The time given for an action has passed.
+    TimePassed = 33,
+    /// Files could not be written or do not hold what was written to them.
+    Storage = 40,
+    /// A take could not be found, read or locked.
+    Takes = 50,
+    /// Two takes which were compared differ.
+    TakesDiffer = 51,
+    /// Gain, gate, solo or mute could not be set.
+    Processing = 60,
+    /// The OSC listener could not be started.
+    Osc = 70,
+    /// The MIDI ports could not be opened.
+    Midi = 71,
+    /// The GPIO pins could not be opened.
+    #[cfg_attr(not(feature = "gpio"), allow(dead_code))]
+    Gpio = 72,
+    /// The display could not be opened.
+    #[cfg_attr(not(feature = "display"), allow(dead_code))]
+    Display = 73,
+    /// The port of the failover heartbeats could not be bound.
+    Failover = 74,
+}
+
+impl Code {
+    pub const fn code(self) -> i32 {
+        self as i32
+    }
+
+    /// The part of `smrec` the error comes from.
+    pub const fn subsystem(self) -> &'static str {
+        match self {
+            Self::Other => "general",
+            Self::Config | Self::Channel => "config",
+            Self::Device => "device",
+            Self::Recording | Self::NotArmed | Self::NoRecording | Self::TimePassed => "recording",
+            Self::Storage => "storage",
+            Self::Takes | Self::TakesDiffer => "takes",
+            Self::Processing => "processing",
+            Self::Osc => "osc",
+            Self::Midi => "midi",
+            Self::Gpio => "gpio",
+            Self::Display => "display",
+            Self::Failover => "failover",
+        }
+    }
+
+    /// The code an error was given with `.context(Code::...)`, `Other` when it was not given one.
+    pub fn of(err: &anyhow::Error) -> Self {
+        err.downcast_ref::<Self>().copied().unwrap_or(Self::Other)
+    }
+}
+
+// Shown above the error it was given to as context.
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            Self::Other => "Failed",
+            Self::Config => "Configuration error",
+            Self::Channel => "Channel error",
+            Self::Device => "Audio input error",
+            Self::Recording => "Recording error",
+            Self::NotArmed => "Not armed",
+            Self::NoRecording => "No running recording",
+            Self::TimePassed => "Time has passed",
+            Self::Storage => "Storage error",
+            Self::Takes => "Takes error",
+            Self::TakesDiffer => "Takes differ",
+            Self::Processing => "Processing error",
+            Self::Osc => "OSC error",
+            Self::Midi => "MIDI error",
+            Self::Gpio => "GPIO error",
+            Self::Display => "Display error",
+            Self::Failover => "Failover error",
+        };
+        write!(f, "{message} ({})", self.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn codes_are_found_in_the_context() {
+        let err = Err::<(), _>(anyhow::anyhow!("No such device."))
+            .context(Code::Device)
+            .unwrap_err();
+        assert_eq!(Code::of(&err), Code::Device);
+        assert_eq!(
+            format!("{err:#}"),
+            "Audio input error (20): No such device."
+        );
+        assert_eq!(Code::of(&anyhow::anyhow!("Anything.")), Code::Other);
+        assert_eq!(
+            (Code::Storage.code(), Code::Storage.subsystem()),
+            (40, "storage")
+        );
+        assert_eq!(
+            (Code::TakesDiffer.code(), Code::TakesDiffer.subsystem()),
+            (51, "takes")
+        );
+    }
+}
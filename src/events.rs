@@ -0,0 +1,80 @@
+use anyhow::Result;
+use camino::Utf8Path;
+use std::io::Write;
+
+/// Session-wide log of transport events in the output directory.
+const EVENTS_FILE: &str = "events.csv";
+const EVENTS_HEADER: &str = "wall_clock,session_sample,event,take";
+
+/// Transport events which are logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Start,
+    Stop,
+    Split,
+}
+
+impl Event {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Start => "start",
+            Self::Stop => "stop",
+            Self::Split => "split",
+        }
+    }
+}
+
+/// Appends an event to the log, writing the header first if the log is new.
+///
+/// `session_sample` counts frames from the start of the session at the stream sample rate.
+pub fn append(
+    base: &Utf8Path,
+    wall_clock: &str,
+    session_sample: u64,
+    event: Event,
+    take: &str,
+) -> Result<()> {
+    let path = base.join(EVENTS_FILE);
+    let is_new = !path.exists();
+    let mut log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    if is_new {
+        writeln!(log, "{EVENTS_HEADER}")?;
+    }
+    writeln!(log, "{}", row(wall_clock, session_sample, event, take))?;
+    Ok(())
+}
+
+fn row(wall_clock: &str, session_sample: u64, event: Event, take: &str) -> String {
+    // Take names may contain commas or quotes when they come from user configuration.
+    let take = if take.contains([',', '"']) {
+        format!("\"{}\"", take.replace('"', "\"\""))
+    } else {
+        take.to_string()
+    };
+    format!("{wall_clock},{session_sample},{},{take}", event.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_escaped() {
+        assert_eq!(
+            row(
+                "2023-11-20T12:00:00Z",
+                48000,
+                Event::Start,
+                "rec_20231120_120000"
+            ),
+            "2023-11-20T12:00:00Z,48000,start,rec_20231120_120000"
+        );
+        assert_eq!(
+            row("2023-11-20T12:00:00Z", 0, Event::Stop, "a \"b\", c"),
+            "2023-11-20T12:00:00Z,0,stop,\"a \"\"b\"\", c\""
+        );
+    }
+}
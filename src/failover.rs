@@ -0,0 +1,309 @@
+use crate::{
+    control::ControlSurface,
+    syslog,
+    types::{Action, Transport},
+};
+use anyhow::{Context, Result};
+use crossbeam::channel::{Receiver, RecvTimeoutError, Sender};
+use serde::Deserialize;
+use std::{
+    net::{SocketAddr, UdpSocket},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Heartbeats start with this, anything else arriving on the port is ignored.
+const HEARTBEAT: &str = "smrec-heartbeat";
+
+/// Which of the two recorders this one is.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Records the show and tells the standby that it does.
+    Primary,
+    /// Starts recording when the primary stops responding.
+    Standby,
+}
+
+/// What the primary was doing when it went silent for the standby to take over.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Takeover {
+    /// Only when it was recording.
+    #[default]
+    Recording,
+    /// Also when it was armed, e.g. waiting for the show to start.
+    Armed,
+}
+
+impl Takeover {
+    fn applies_to(self, transport: Transport) -> bool {
+        match self {
+            Self::Recording => transport == Transport::Recording,
+            Self::Armed => transport != Transport::Stopped,
+        }
+    }
+}
+
+/// Two recorders which exchange heartbeats, configured in `[failover]`.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct FailoverConfig {
+    role: Role,
+    /// Where the heartbeats of the peer arrive, e.g. `0.0.0.0:9100`.
+    listen: SocketAddr,
+    /// Where the heartbeats are sent, the address the peer listens on.
+    peer: SocketAddr,
+    /// Milliseconds between heartbeats.
+    #[serde(default = "FailoverConfig::default_interval_ms")]
+    interval_ms: u64,
+    /// Milliseconds without a heartbeat after which the peer is considered gone.
+    #[serde(default = "FailoverConfig::default_timeout_ms")]
+    timeout_ms: u64,
+    #[serde(default)]
+    takeover: Takeover,
+}
+
+impl FailoverConfig {
+    const fn default_interval_ms() -> u64 {
+        250
+    }
+
+    const fn default_timeout_ms() -> u64 {
+        2000
+    }
+}
+
+/// What a recorder knows about its peer from the heartbeats it heard.
+#[derive(Debug)]
+struct Watch {
+    timeout: Duration,
+    /// When the last heartbeat arrived and the transport it carried.
+    last: Option<(Instant, Transport)>,
+    /// Whether the peer is silent since the last heartbeat, so it is only acted on once.
+    lost: bool,
+}
+
+impl Watch {
+    const fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            last: None,
+            lost: false,
+        }
+    }
+
+    /// Keeps a heartbeat, true when the peer was lost before.
+    fn heard(&mut self, at: Instant, transport: Transport) -> bool {
+        self.last = Some((at, transport));
+        std::mem::take(&mut self.lost)
+    }
+
+    /// The transport the peer reported last, once it has been silent for the timeout.
+    ///
+    /// A peer which was never heard is not lost, the recorders may just be started one after the other.
+    fn lost(&mut self, now: Instant) -> Option<Transport> {
+        let (at, transport) = self.last?;
+        if self.lost || now.duration_since(at) < self.timeout {
+            return None;
+        }
+        self.lost = true;
+        Some(transport)
+    }
+}
+
+/// Watches a peer recorder and takes over the recording when it stops responding mid-show.
+pub struct Failover {
+    config: FailoverConfig,
+    socket: Option<UdpSocket>,
+}
+
+impl Failover {
+    /// Binds the port the heartbeats of the peer arrive on.
+    pub fn new(config: FailoverConfig) -> Result<Self> {
+        let socket = UdpSocket::bind(config.listen)
+            .with_context(|| format!("Error listening for heartbeats on {}.", config.listen))?;
+        socket.set_read_timeout(Some(Duration::from_millis(config.interval_ms.max(1))))?;
+        println!(
+            "Failover as the {} with {}.",
+            match config.role {
+                Role::Primary => "primary",
+                Role::Standby => "standby",
+            },
+            config.peer
+        );
+        Ok(Self {
+            config,
+            socket: Some(socket),
+        })
+    }
+}
+
+impl ControlSurface for Failover {
+    fn start(&mut self, to_main_thread: Sender<Action>, events: Receiver<Action>) -> Result<()> {
+        let Some(socket) = self.socket.take() else {
+            anyhow::bail!("Failover is started already.");
+        };
+        let transport = Arc::new(Mutex::new(Transport::Stopped));
+        let sender = socket.try_clone()?;
+        let (config, own) = (self.config, transport.clone());
+        std::thread::spawn(move || beat(&sender, &config, &own, &events));
+        let config = self.config;
+        std::thread::spawn(move || watch(&socket, &config, &transport, &to_main_thread));
+        Ok(())
+    }
+}
+
+/// Sends a heartbeat with the transport every interval until the session ends.
+fn beat(
+    socket: &UdpSocket,
+    config: &FailoverConfig,
+    transport: &Mutex<Transport>,
+    events: &Receiver<Action>,
+) {
+    let interval = Duration::from_millis(config.interval_ms);
+    let mut next = Instant::now();
+    loop {
+        let timeout = next.saturating_duration_since(Instant::now());
+        match events.recv_timeout(timeout) {
+            Ok(action) => {
+                let mut transport = transport.lock().unwrap();
+                *transport = match (action, *transport) {
+                    (Action::Start, _) => Transport::Recording,
+                    (Action::Stop, Transport::Recording) | (Action::Arm, Transport::Stopped) => {
+                        Transport::Armed
+                    }
+                    (Action::Disarm, Transport::Armed) => Transport::Stopped,
+                    (_, unchanged) => unchanged,
+                };
+                drop(transport);
+                continue;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        let heartbeat = format!("{HEARTBEAT} {}", transport_name(*transport.lock().unwrap()));
+        // A peer which is not there yet or anymore is noticed on its side.
+        socket.send_to(heartbeat.as_bytes(), config.peer).ok();
+        next = Instant::now() + interval;
+    }
+}
+
+/// Listens for the heartbeats of the peer, a standby starts recording when the primary is lost mid-show.
+fn watch(
+    socket: &UdpSocket,
+    config: &FailoverConfig,
+    transport: &Mutex<Transport>,
+    to_main_thread: &Sender<Action>,
+) {
+    let mut watch = Watch::new(Duration::from_millis(config.timeout_ms));
+    let mut datagram = [0; 64];
+    loop {
+        // Heartbeats from anywhere else are not from the peer.
+        if let Ok((length, from)) = socket.recv_from(&mut datagram) {
+            let heartbeat = std::str::from_utf8(&datagram[..length])
+                .ok()
+                .and_then(parse_heartbeat);
+            if let (Some(peer), true) = (heartbeat, from.ip() == config.peer.ip()) {
+                if watch.heard(Instant::now(), peer) {
+                    println!("The peer at {} is responding again.", config.peer);
+                }
+            }
+        }
+        let Some(peer) = watch.lost(Instant::now()) else {
+            continue;
+        };
+        let message = format!(
+            "The peer at {} stopped responding while {}.",
+            config.peer,
+            transport_name(peer)
+        );
+        println!("{message}");
+        syslog::log(
+            syslog::Severity::Warning,
+            "peer_lost",
+            &[("peer", &config.peer.to_string())],
+            &message,
+        );
+        let own = *transport.lock().unwrap();
+        if config.role == Role::Standby
+            && config.takeover.applies_to(peer)
+            && own != Transport::Recording
+        {
+            println!("Taking over the recording.");
+            syslog::log(
+                syslog::Severity::Warning,
+                "takeover",
+                &[("peer", &config.peer.to_string())],
+                "Taking over the recording.",
+            );
+            // The standby does not wait for an operator to arm it, nobody is watching it.
+            for action in [Action::Arm, Action::Start] {
+                if to_main_thread.send(action).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+const fn transport_name(transport: Transport) -> &'static str {
+    match transport {
+        Transport::Stopped => "stopped",
+        Transport::Armed => "armed",
+        Transport::Recording => "recording",
+    }
+}
+
+fn parse_heartbeat(datagram: &str) -> Option<Transport> {
+    match datagram.strip_prefix(HEARTBEAT)?.trim() {
+        "stopped" => Some(Transport::Stopped),
+        "armed" => Some(Transport::Armed),
+        "recording" => Some(Transport::Recording),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standbys_take_over_once_the_primary_is_lost() {
+        assert_eq!(
+            parse_heartbeat("smrec-heartbeat recording"),
+            Some(Transport::Recording)
+        );
+        assert_eq!(parse_heartbeat("hello recording"), None);
+
+        let start = Instant::now();
+        let mut watch = Watch::new(Duration::from_secs(2));
+        // Never heard, so never lost.
+        assert_eq!(watch.lost(start + Duration::from_secs(10)), None);
+
+        assert!(!watch.heard(start, Transport::Recording));
+        assert_eq!(watch.lost(start + Duration::from_secs(1)), None);
+        assert_eq!(
+            watch.lost(start + Duration::from_secs(3)),
+            Some(Transport::Recording)
+        );
+        // Lost once until it is heard again.
+        assert_eq!(watch.lost(start + Duration::from_secs(4)), None);
+        assert!(watch.heard(start + Duration::from_secs(5), Transport::Armed));
+        assert_eq!(
+            watch.lost(start + Duration::from_secs(8)),
+            Some(Transport::Armed)
+        );
+
+        assert!(Takeover::Recording.applies_to(Transport::Recording));
+        assert!(!Takeover::Recording.applies_to(Transport::Armed));
+        assert!(Takeover::Armed.applies_to(Transport::Armed));
+        assert!(!Takeover::Armed.applies_to(Transport::Stopped));
+
+        let config: FailoverConfig = toml::from_str(
+            "role = \"standby\"\nlisten = \"0.0.0.0:9100\"\npeer = \"10.0.0.2:9100\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.takeover, Takeover::Recording);
+        assert_eq!(config.timeout_ms, 2000);
+    }
+}
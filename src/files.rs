@@ -0,0 +1,210 @@
+// Every written channel keeps its file, its safety copy and its peaks open while a take is recorded. The limit of
+// open files the system gives a process is raised when smrec starts, a big rig which still does not fit has its files
+// opened only while they are written, so a take does not fail halfway with EMFILE.
+
+use crate::console;
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{
+    fs::OpenOptions,
+    io::{self, Seek, SeekFrom, Write},
+};
+
+/// Files a session needs besides the ones of the take, for devices, sockets, guard tracks, pipes and hooks.
+const HEADROOM: u64 = 64;
+/// Bytes a deferred file collects before it is opened and written.
+const BUFFER: usize = 64 * 1024;
+
+/// How many files are open while a take of the channels is recorded.
+pub fn needed(channels: usize, safety: bool, peaks: bool) -> u64 {
+    channels as u64 * (1 + u64::from(safety) + u64::from(peaks)) + HEADROOM
+}
+
+/// Raises the limit of open files to what is needed, as far as the system allows, and gives the limit.
+pub fn raise(needed: u64) -> Result<u64> {
+    let (soft, hard) = platform::limit()?;
+    console::trace(|| {
+        format!("The system allows {soft} open files and up to {hard}, takes need {needed}.")
+    });
+    if soft >= needed {
+        return Ok(soft);
+    }
+    let raised = needed.min(hard);
+    platform::set_limit(raised)?;
+    console::trace(|| format!("Raised the limit of open files from {soft} to {raised}."));
+    Ok(raised)
+}
+
+/// A file which is only open while what was written to it is written out.
+pub struct Deferred {
+    path: Utf8PathBuf,
+    buffer: Vec<u8>,
+    /// Where the buffer goes in the file.
+    start: u64,
+    length: u64,
+}
+
+impl Deferred {
+    /// Takes over a file which was created empty.
+    pub fn new(path: &Utf8Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            buffer: Vec::with_capacity(BUFFER),
+            start: 0,
+            length: 0,
+        }
+    }
+
+    fn write_out(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let mut file = OpenOptions::new().write(true).open(&self.path)?;
+        file.seek(SeekFrom::Start(self.start))?;
+        file.write_all(&self.buffer)?;
+        self.start += self.buffer.len() as u64;
+        self.length = self.length.max(self.start);
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl Write for Deferred {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= BUFFER {
+            self.write_out()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_out()
+    }
+}
+
+impl Seek for Deferred {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.write_out()?;
+        self.start = match pos {
+            SeekFrom::Start(at) => Some(at),
+            SeekFrom::Current(by) => self.start.checked_add_signed(by),
+            SeekFrom::End(by) => self.length.checked_add_signed(by),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start."))?;
+        Ok(self.start)
+    }
+}
+
+impl Drop for Deferred {
+    fn drop(&mut self) {
+        if let Err(err) = self.write_out() {
+            console::error(format!("Error writing {}: {err}", self.path));
+        }
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use anyhow::{bail, Result};
+
+    /// macOS refuses more than this even when the hard limit is unlimited.
+    #[cfg(target_os = "macos")]
+    const OPEN_MAX: u64 = 10240;
+
+    fn read() -> Result<libc::rlimit> {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: The limit outlives the call.
+        if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &raw mut limit) } != 0 {
+            bail!(
+                "Error reading the limit of open files: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(limit)
+    }
+
+    /// The soft and the hard limit of open files.
+    pub fn limit() -> Result<(u64, u64)> {
+        let limit = read()?;
+        #[allow(clippy::useless_conversion)]
+        let (soft, hard) = (u64::from(limit.rlim_cur), u64::from(limit.rlim_max));
+        #[cfg(target_os = "macos")]
+        let hard = hard.min(OPEN_MAX);
+        Ok((soft, hard))
+    }
+
+    /// Sets the soft limit, the hard limit stays as it is.
+    pub fn set_limit(soft: u64) -> Result<()> {
+        let mut limit = read()?;
+        limit.rlim_cur = libc::rlim_t::try_from(soft)?;
+        // SAFETY: The limit outlives the call.
+        if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &raw const limit) } != 0 {
+            bail!(
+                "Error raising the limit of open files to {soft}: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Windows has no limit of open files for a process.
+#[cfg(not(unix))]
+mod platform {
+    use anyhow::Result;
+
+    pub const fn limit() -> Result<(u64, u64)> {
+        Ok((u64::MAX, u64::MAX))
+    }
+
+    pub const fn set_limit(_soft: u64) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn deferred_files_are_written_like_open_ones() {
+        assert_eq!(needed(128, true, true), 128 * 3 + HEADROOM);
+        assert_eq!(needed(8, false, false), 8 + HEADROOM);
+        let (soft, _) = platform::limit().unwrap();
+        assert!(raise(16).unwrap() >= soft.min(16));
+
+        let dir = TempDir::new("files");
+        let path = dir.path().join("chn_1.wav");
+        std::fs::File::create(&path).unwrap();
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::new(Deferred::new(&path), spec).unwrap();
+        for sample in 0..BUFFER {
+            writer
+                .write_sample(i16::try_from(sample % 1000).unwrap())
+                .unwrap();
+        }
+        // The header is rewritten in the middle of the file while it is recorded.
+        writer.flush().unwrap();
+        writer.write_sample(7_i16).unwrap();
+        writer.finalize().unwrap();
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.duration() as usize, BUFFER + 1);
+        let samples = reader
+            .samples::<i16>()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(samples[1999], 999);
+        assert_eq!(samples[BUFFER], 7);
+    }
+}
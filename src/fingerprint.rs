@@ -0,0 +1,229 @@
+// The fingerprint is smrec's own, after the idea of Chromaprint: chroma features of the start of the audio, turned
+// into bits which survive gain changes, encoding and resampling, so the same recording is recognized on any drive.
+// It is not compatible with Chromaprint, `fpcalc` or AcoustID, fingerprints are only compared to each other.
+#![allow(clippy::suboptimal_flops)]
+
+use crate::wav;
+use anyhow::Result;
+use camino::Utf8Path;
+use rustfft::{num_complex::Complex, FftPlanner};
+use std::{f32::consts::PI, fmt::Write};
+
+/// Rate the mixdown is fingerprinted at, the chroma features do not need more.
+const SAMPLE_RATE: u32 = 11025;
+const FRAME: usize = 4096;
+/// Frames overlap by two thirds.
+const HOP: usize = FRAME / 3;
+/// Seconds from the start which are fingerprinted.
+const SECONDS: u32 = 120;
+/// Frequencies outside of this range do not make up the notes.
+const MIN_FREQUENCY: f32 = 28.0;
+const MAX_FREQUENCY: f32 = 3520.0;
+/// Smoothing of the chroma features over time.
+const SMOOTHING: [f32; 5] = [0.25, 0.75, 1.0, 0.75, 0.25];
+/// Frames two fingerprints may be shifted against each other by, about 10 seconds.
+const MAX_SHIFT: usize = 80;
+
+/// Fingerprints the mixdown of the first two minutes of the files of a take, as hex words.
+pub fn of_files(paths: &[impl AsRef<Utf8Path>]) -> Result<String> {
+    let (mixdown, sample_rate) = wav::mixdown(paths)?;
+    let samples = mixdown
+        .take((SECONDS * sample_rate) as usize)
+        .collect::<Vec<_>>();
+    Ok(encode(&fingerprint(&samples, sample_rate)))
+}
+
+/// A word of 32 bits for every frame of the audio.
+pub fn fingerprint(samples: &[f32], sample_rate: u32) -> Vec<u32> {
+    let samples = downsample(samples, sample_rate);
+    if samples.len() < FRAME {
+        return Vec::new();
+    }
+    let fft = FftPlanner::new().plan_fft_forward(FRAME);
+    #[allow(clippy::cast_precision_loss)]
+    let window = (0..FRAME)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / (FRAME - 1) as f32).cos())
+        .collect::<Vec<_>>();
+    let classes = pitch_classes();
+    let mut buffer = vec![Complex::default(); FRAME];
+    let chroma = samples
+        .windows(FRAME)
+        .step_by(HOP)
+        .map(|frame| {
+            for ((bin, sample), weight) in buffer.iter_mut().zip(frame).zip(&window) {
+                *bin = Complex::new(sample * weight, 0.0);
+            }
+            fft.process(&mut buffer);
+            let mut chroma = [0.0_f32; 12];
+            for (bin, class) in buffer.iter().zip(&classes) {
+                if let Some(class) = class {
+                    chroma[*class] += bin.norm_sqr();
+                }
+            }
+            normalize(&mut chroma);
+            chroma
+        })
+        .collect::<Vec<_>>();
+    let smoothed = (0..chroma.len())
+        .map(|at| {
+            let mut smoothed = [0.0; 12];
+            for (offset, weight) in SMOOTHING.iter().enumerate() {
+                let Some(frame) = (at + offset).checked_sub(2).and_then(|at| chroma.get(at)) else {
+                    continue;
+                };
+                for (smoothed, value) in smoothed.iter_mut().zip(frame) {
+                    *smoothed += weight * value;
+                }
+            }
+            smoothed
+        })
+        .collect::<Vec<_>>();
+    smoothed
+        .windows(2)
+        .map(|pair| word(&pair[0], &pair[1]))
+        .collect()
+}
+
+/// How alike two fingerprints are, from 0.5 for unrelated audio to 1 for the same, at the best shift.
+pub fn similarity(a: &[u32], b: &[u32]) -> Option<f64> {
+    let overlap = |shift: usize, a: &[u32], b: &[u32]| {
+        let pairs = a.iter().skip(shift).zip(b).collect::<Vec<_>>();
+        // Too short an overlap matches by chance.
+        if pairs.len() < a.len().min(b.len()) / 2 || pairs.is_empty() {
+            return None;
+        }
+        let differing = pairs
+            .iter()
+            .map(|(a, b)| (*a ^ *b).count_ones())
+            .sum::<u32>();
+        #[allow(clippy::cast_precision_loss)]
+        Some(1.0 - f64::from(differing) / (32 * pairs.len()) as f64)
+    };
+    (0..=MAX_SHIFT)
+        .flat_map(|shift| [overlap(shift, a, b), overlap(shift, b, a)])
+        .flatten()
+        .max_by(f64::total_cmp)
+}
+
+/// Hex words, 8 digits each.
+pub fn encode(fingerprint: &[u32]) -> String {
+    fingerprint.iter().fold(
+        String::with_capacity(fingerprint.len() * 8),
+        |mut hex, word| {
+            let _ = write!(hex, "{word:08x}");
+            hex
+        },
+    )
+}
+
+pub fn decode(hex: &str) -> Option<Vec<u32>> {
+    if !hex.len().is_multiple_of(8) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(8)
+        .map(|at| u32::from_str_radix(hex.get(at..at + 8)?, 16).ok())
+        .collect()
+}
+
+/// Averages the samples down to the rate of the fingerprint, which also keeps the higher frequencies out.
+fn downsample(samples: &[f32], sample_rate: u32) -> Vec<f32> {
+    if sample_rate <= SAMPLE_RATE {
+        return samples.to_vec();
+    }
+    let ratio = f64::from(sample_rate) / f64::from(SAMPLE_RATE);
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    (0..(samples.len() as f64 / ratio) as usize)
+        .map(|i| {
+            let start = (i as f64 * ratio) as usize;
+            let end = (((i + 1) as f64 * ratio) as usize).min(samples.len());
+            samples[start..end].iter().sum::<f32>() / (end - start).max(1) as f32
+        })
+        .collect()
+}
+
+/// The pitch class of every bin of a frame, none outside of the range of notes.
+fn pitch_classes() -> Vec<Option<usize>> {
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    (0..FRAME)
+        .map(|bin| {
+            let frequency = bin as f32 * SAMPLE_RATE as f32 / FRAME as f32;
+            (MIN_FREQUENCY..=MAX_FREQUENCY)
+                .contains(&frequency)
+                .then(|| {
+                    // MIDI note numbers, 0 is C.
+                    let note = 12.0 * (frequency / 440.0).log2() + 69.0;
+                    (note.round() as i64).rem_euclid(12) as usize
+                })
+        })
+        .collect()
+}
+
+fn normalize(chroma: &mut [f32; 12]) {
+    let norm = chroma.iter().map(|value| value * value).sum::<f32>().sqrt();
+    // Silence has no notes, noise of the converters should not make some up.
+    if norm < 1e-6 {
+        *chroma = [0.0; 12];
+    } else {
+        for value in chroma {
+            *value /= norm;
+        }
+    }
+}
+
+/// Bits of which notes are louder than their neighbours, their previous frame and the notes two above them.
+fn word(previous: &[f32; 12], chroma: &[f32; 12]) -> u32 {
+    let mut word = 0;
+    for i in 0..12 {
+        word |= u32::from(chroma[i] > chroma[(i + 1) % 12]) << i;
+        word |= u32::from(chroma[i] > previous[i]) << (12 + i);
+    }
+    for i in 0..8 {
+        word |= u32::from(chroma[i] > chroma[i + 2]) << (24 + i);
+    }
+    word
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Notes changing every half second, like a melody.
+    #[allow(clippy::cast_precision_loss)]
+    fn melody(notes: &[f32], sample_rate: u32, gain: f32) -> Vec<f32> {
+        (0..sample_rate * 20)
+            .map(|i| {
+                let note = notes[(i / (sample_rate / 2)) as usize % notes.len()];
+                let frequency = 440.0 * ((note - 69.0) / 12.0).exp2();
+                gain * (2.0 * PI * frequency * i as f32 / sample_rate as f32).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_same_audio_is_recognized() {
+        let notes = [60.0, 64.0, 67.0, 72.0, 65.0, 69.0, 62.0];
+        let original = fingerprint(&melody(&notes, 48000, 0.5), 48000);
+        // A copy at another level and rate, e.g. from the other recorder.
+        let copy = fingerprint(&melody(&notes, 44100, 0.1), 44100);
+        let other = fingerprint(&melody(&[61.0, 66.0, 70.0, 63.0, 68.0], 48000, 0.5), 48000);
+
+        assert!(!original.is_empty());
+        assert!(similarity(&original, &copy).unwrap() > 0.9);
+        assert!(similarity(&original, &original[20..]).unwrap() > 0.99);
+        assert!(similarity(&original, &other).unwrap() < 0.8);
+        assert_eq!(similarity(&original, &[]), None);
+
+        assert_eq!(decode(&encode(&original)).unwrap(), original);
+        assert_eq!(encode(&[0xdead_beef, 1]), "deadbeef00000001");
+        assert_eq!(decode("deadbee"), None);
+    }
+}
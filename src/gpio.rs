@@ -0,0 +1,280 @@
+use crate::{control::ControlSurface, types::Action};
+use anyhow::{bail, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use crossbeam::channel::{Receiver, Sender};
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+const SYSFS: &str = "/sys/class/gpio";
+/// How often the button is read.
+const POLL: Duration = Duration::from_millis(10);
+/// Changes of the button within this long after the last one are contacts bouncing.
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// Pins of a Raspberry Pi or another board with sysfs GPIO, numbered as in `/sys/class/gpio`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pins {
+    /// Lit while recording.
+    recording: Option<u32>,
+    /// Lit after an error until the next take starts.
+    error: Option<u32>,
+    /// Lit while armed.
+    armed: Option<u32>,
+    /// Starts a take when pressed, arming first, and stops the running one.
+    button: Option<u32>,
+}
+
+impl FromStr for Pins {
+    type Err = anyhow::Error;
+
+    /// Parses pins like `recording=17,error=27,armed=22,button=23`, every one is optional.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut pins = Self::default();
+        for assignment in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let Some((name, pin)) = assignment.split_once('=') else {
+                bail!("{assignment} is not a pin, use e.g. recording=17.");
+            };
+            let pin = pin
+                .trim()
+                .parse::<u32>()
+                .with_context(|| format!("{} is not a GPIO number.", pin.trim()))?;
+            let slot = match name.trim() {
+                "recording" => &mut pins.recording,
+                "error" => &mut pins.error,
+                "armed" => &mut pins.armed,
+                "button" => &mut pins.button,
+                name => bail!("Unknown GPIO pin {name}, use recording, error, armed or button."),
+            };
+            *slot = Some(pin);
+        }
+        if pins == Self::default() {
+            bail!("No GPIO pins are given, use e.g. recording=17,button=23.");
+        }
+        Ok(pins)
+    }
+}
+
+impl Pins {
+    fn leds(&self) -> impl Iterator<Item = u32> {
+        [self.recording, self.error, self.armed]
+            .into_iter()
+            .flatten()
+    }
+}
+
+/// Status LEDs and a transport button on GPIO pins, so a board with an interface records without a screen.
+pub struct Gpio {
+    pins: Pins,
+    root: Utf8PathBuf,
+}
+
+impl Gpio {
+    /// Exports the pins, the LEDs are switched off.
+    pub fn new(pins: Pins) -> Result<Self> {
+        Self::with_root(pins, Utf8PathBuf::from(SYSFS))
+    }
+
+    fn with_root(pins: Pins, root: Utf8PathBuf) -> Result<Self> {
+        let gpio = Self { pins, root };
+        for pin in gpio.pins.leds() {
+            gpio.open(pin, "out")?;
+            set(&gpio.root, pin, false);
+        }
+        if let Some(button) = gpio.pins.button {
+            gpio.open(button, "in")?;
+        }
+        Ok(gpio)
+    }
+
+    /// Exports a pin and sets its direction, `in` or `out`.
+    fn open(&self, pin: u32, direction: &str) -> Result<()> {
+        let dir = self.root.join(format!("gpio{pin}"));
+        if !dir.exists() {
+            std::fs::write(self.root.join("export"), pin.to_string())
+                .with_context(|| format!("Error exporting GPIO {pin}."))?;
+        }
+        // udev may give the files of a freshly exported pin their permissions a moment later.
+        let mut attempts = 0;
+        loop {
+            match std::fs::write(dir.join("direction"), direction) {
+                Ok(()) => return Ok(()),
+                Err(_) if attempts < 20 => {
+                    attempts += 1;
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+                Err(err) => bail!("Error setting the direction of GPIO {pin}: {err}"),
+            }
+        }
+    }
+}
+
+impl ControlSurface for Gpio {
+    fn start(&mut self, to_main_thread: Sender<Action>, events: Receiver<Action>) -> Result<()> {
+        // The button stops a take once it started, whichever surface started it.
+        let recording = Arc::new(AtomicBool::new(false));
+        let (root, pins) = (self.root.clone(), self.pins.clone());
+        let lit = recording.clone();
+        std::thread::spawn(move || light(&root, &pins, &events, &lit));
+        if let Some(button) = self.pins.button {
+            let root = self.root.clone();
+            std::thread::spawn(move || watch(&root, button, &to_main_thread, &recording));
+        }
+        println!("GPIO status and transport control is enabled.");
+        Ok(())
+    }
+}
+
+/// Sets the LEDs after the events until the session ends.
+fn light(root: &Utf8Path, pins: &Pins, events: &Receiver<Action>, recording: &AtomicBool) {
+    let led = |pin: Option<u32>, on| {
+        if let Some(pin) = pin {
+            set(root, pin, on);
+        }
+    };
+    while let Ok(event) = events.recv() {
+        match event {
+            Action::Start => {
+                recording.store(true, Ordering::Relaxed);
+                led(pins.recording, true);
+                led(pins.error, false);
+            }
+            Action::Stop => {
+                recording.store(false, Ordering::Relaxed);
+                led(pins.recording, false);
+            }
+            Action::Arm => led(pins.armed, true),
+            Action::Disarm => led(pins.armed, false),
+            Action::Err(..) => led(pins.error, true),
+            _ => {}
+        }
+    }
+    for pin in pins.leds() {
+        set(root, pin, false);
+    }
+}
+
+/// Reads the button, wired between the pin and ground with a pull-up, and starts or stops a take when it is pressed.
+fn watch(root: &Utf8Path, pin: u32, to_main_thread: &Sender<Action>, recording: &AtomicBool) {
+    let value = root.join(format!("gpio{pin}")).join("value");
+    let mut pressed = false;
+    let mut changed_at = Instant::now();
+    loop {
+        std::thread::sleep(POLL);
+        let Ok(read) = std::fs::read_to_string(&value) else {
+            continue;
+        };
+        let down = read.trim() == "0";
+        if down == pressed || changed_at.elapsed() < DEBOUNCE {
+            continue;
+        }
+        pressed = down;
+        changed_at = Instant::now();
+        if !pressed {
+            continue;
+        }
+        let actions: &[Action] = if recording.load(Ordering::Relaxed) {
+            &[Action::Stop]
+        } else {
+            // A button on the box is deliberate, it does not need to be armed separately.
+            &[Action::Arm, Action::Start]
+        };
+        for action in actions {
+            if to_main_thread.send(action.clone()).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn set(root: &Utf8Path, pin: u32, on: bool) {
+    let path = root.join(format!("gpio{pin}")).join("value");
+    if let Err(err) = std::fs::write(&path, if on { "1" } else { "0" }) {
+        eprintln!("Error setting GPIO {pin}: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn pins_are_parsed() {
+        let pins = "recording=17, button=23".parse::<Pins>().unwrap();
+        assert_eq!(
+            pins,
+            Pins {
+                recording: Some(17),
+                button: Some(23),
+                ..Pins::default()
+            }
+        );
+        assert!("".parse::<Pins>().is_err());
+        assert!("record=17".parse::<Pins>().is_err());
+        assert!("error=red".parse::<Pins>().is_err());
+    }
+
+    #[test]
+    fn leds_follow_the_transport_and_the_button_controls_it() {
+        let dir = TempDir::new("gpio");
+        let root = dir.path().to_path_buf();
+        // Pins which are exported already, as sysfs would have them.
+        for pin in [17, 27, 23] {
+            std::fs::create_dir_all(root.join(format!("gpio{pin}"))).unwrap();
+        }
+        std::fs::write(root.join("gpio23").join("value"), "1").unwrap();
+        let value =
+            |pin: u32| std::fs::read_to_string(root.join(format!("gpio{pin}/value"))).unwrap();
+
+        let mut gpio = Gpio::with_root(
+            "recording=17,error=27,button=23".parse().unwrap(),
+            root.clone(),
+        )
+        .unwrap();
+        let (to_main_thread, from_surface) = crossbeam::channel::unbounded();
+        let (to_surface, events) = crossbeam::channel::unbounded();
+        gpio.start(to_main_thread, events).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(root.join("gpio23/direction")).unwrap(),
+            "in"
+        );
+        assert_eq!(value(17), "0");
+
+        // Pressed, arms and starts.
+        std::fs::write(root.join("gpio23").join("value"), "0").unwrap();
+        let timeout = Duration::from_secs(1);
+        assert!(matches!(
+            from_surface.recv_timeout(timeout),
+            Ok(Action::Arm)
+        ));
+        assert!(matches!(
+            from_surface.recv_timeout(timeout),
+            Ok(Action::Start)
+        ));
+        std::fs::write(root.join("gpio23").join("value"), "1").unwrap();
+
+        to_surface
+            .send(Action::Err(crate::error::Code::Recording, String::new()))
+            .unwrap();
+        to_surface.send(Action::Start).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!((value(17), value(27)), ("1".to_string(), "0".to_string()));
+
+        // Pressed again while recording, stops.
+        std::fs::write(root.join("gpio23").join("value"), "0").unwrap();
+        assert!(matches!(
+            from_surface.recv_timeout(timeout),
+            Ok(Action::Stop)
+        ));
+
+        drop(to_surface);
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(value(17), "0");
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{
+    console,
+    stream::{self, Input},
+    tap::Taps,
+    wav::{create_writer, FileWriter},
+};
+use anyhow::{bail, Result};
+use camino::Utf8PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Guard files are split after this long so a session of days does not end up in one huge file.
+const SPLIT_SECS: u32 = 3600;
+/// How often the guard file is written out, an interrupted run loses at most this much of it.
+const FLUSH_SECS: u32 = 1;
+
+/// Records one channel for the whole session, whether a take is running or not.
+///
+/// It is written to `<stem>_1.wav`, `<stem>_2.wav`... one after another,
+/// so nothing said between takes is lost. The channel is tapped from the streams of the takes.
+pub struct Guard {
+    // Finalized when the session ends.
+    files: Arc<Mutex<Files>>,
+}
+
+impl Guard {
+    /// Starts recording a 1 indexed channel, numbered across the inputs like `--include`.
+    pub fn start(
+        taps: &mut Taps,
+        inputs: &[Input],
+        channel: usize,
+        stem: Utf8PathBuf,
+    ) -> Result<Self> {
+        let Some((input, _)) = channel
+            .checked_sub(1)
+            .and_then(|channel| stream::find_channel(inputs, channel))
+        else {
+            bail!(
+                "Channel {channel} can not be the guard track, the inputs have {} channels.",
+                inputs
+                    .iter()
+                    .map(|input| input.config.channels() as usize)
+                    .sum::<usize>()
+            );
+        };
+        let files = Arc::new(Mutex::new(Files::new(
+            stem,
+            input.config.sample_rate().0,
+            SPLIT_SECS,
+        )));
+        let tapped = Arc::clone(&files);
+        taps.add(
+            channel - 1,
+            Box::new(move |samples| {
+                tapped
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .write(samples);
+            }),
+        );
+        println!("Guard track records channel {channel} for the whole session.");
+        Ok(Self { files })
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        self.files
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .close();
+    }
+}
+
+/// The files of the guard track, a new one is started when the running one reached its length.
+struct Files {
+    stem: Utf8PathBuf,
+    spec: hound::WavSpec,
+    frames_per_file: u32,
+    part: usize,
+    writer: Option<FileWriter>,
+    frames_since_flush: u32,
+    /// Set after an error, so a full disk is reported once rather than on every buffer, and when it is closed.
+    failed: bool,
+}
+
+impl Files {
+    const fn new(stem: Utf8PathBuf, sample_rate: u32, split_secs: u32) -> Self {
+        Self {
+            stem,
+            // Written as float whatever the device delivers, so one format covers every device.
+            spec: hound::WavSpec {
+                channels: 1,
+                sample_rate,
+                bits_per_sample: 32,
+                sample_format: hound::SampleFormat::Float,
+            },
+            frames_per_file: sample_rate * split_secs,
+            part: 0,
+            writer: None,
+            frames_since_flush: 0,
+            failed: false,
+        }
+    }
+
+    fn write(&mut self, samples: &[f32]) {
+        if self.failed {
+            return;
+        }
+        if let Err(err) = self.try_write(samples) {
+            console::error(format!(
+                "Error writing the guard track, it is stopped: {err}"
+            ));
+            self.failed = true;
+        }
+    }
+
+    fn try_write(&mut self, samples: &[f32]) -> Result<()> {
+        for sample in samples {
+            let writer = match &mut self.writer {
+                Some(writer) if writer.duration() < self.frames_per_file => writer,
+                _ => self.next_file()?,
+            };
+            writer.write_sample(*sample)?;
+        }
+        self.frames_since_flush += u32::try_from(samples.len()).unwrap_or(u32::MAX);
+        if self.frames_since_flush >= self.spec.sample_rate * FLUSH_SECS {
+            self.frames_since_flush = 0;
+            if let Some(writer) = &mut self.writer {
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn next_file(&mut self) -> Result<&mut FileWriter> {
+        if let Some(writer) = self.writer.take() {
+            writer.finalize()?;
+        }
+        self.part += 1;
+        let path = self.stem.with_file_name(format!(
+            "{}_{}.wav",
+            self.stem.file_name().unwrap_or_default(),
+            self.part
+        ));
+        Ok(self
+            .writer
+            .insert(create_writer(&path, self.spec, None, false)?))
+    }
+}
+
+impl Files {
+    /// Finalizes the running file, nothing is written after it.
+    fn close(&mut self) {
+        self.failed = true;
+        if let Some(writer) = self.writer.take() {
+            writer.finalize().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn guard_files_are_split() {
+        let dir = TempDir::new("guard");
+        let base = dir.path().to_path_buf();
+
+        // 10 Hz and a second per file to keep it short.
+        let mut files = Files::new(base.join("guard_20231120_120000"), 10, 1);
+        files.write(&[0.5; 25]);
+        files.close();
+
+        let frames = |part| {
+            hound::WavReader::open(base.join(format!("guard_20231120_120000_{part}.wav")))
+                .unwrap()
+                .duration()
+        };
+        assert_eq!((frames(1), frames(2), frames(3)), (10, 10, 5));
+
+        // A second guard of the same session never overwrites the files of the first.
+        let mut files = Files::new(base.join("guard_20231120_120000"), 10, 1);
+        files.write(&[0.5]);
+        assert!(files.failed);
+    }
+}
@@ -0,0 +1,467 @@
+use crate::{clock::TimestampZone, meters::Meter, processing::Processing, takes::Take};
+use anyhow::{anyhow, bail, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use crossbeam::channel::{Receiver, Sender};
+#[cfg(feature = "midi")]
+use midir::MidiOutput;
+#[cfg(feature = "osc")]
+use mlua::Value;
+#[cfg(any(feature = "osc", feature = "midi"))]
+use mlua::Variadic;
+use mlua::{Function, Lua};
+#[cfg(feature = "osc")]
+use rosc::{encoder::encode, OscMessage, OscPacket, OscType};
+#[cfg(feature = "osc")]
+use std::net::UdpSocket;
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+#[cfg(feature = "http")]
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+};
+
+/// How long stopping waits for the `on_stop` hook so it also runs when smrec exits right after.
+const STOP_HOOK_TIMEOUT_SECS: u64 = 5;
+/// How often `on_meters` is called while a take is recorded.
+const METERS_HOOK_SECS: u64 = 1;
+#[cfg(feature = "http")]
+const HTTP_TIMEOUT_SECS: u64 = 5;
+
+/// A transport event handed to the hook script.
+struct HookEvent {
+    name: &'static str,
+    take: Option<Utf8PathBuf>,
+    wall_clock: String,
+    /// The meters of every device channel since the take started, only for `meters`.
+    meters: Vec<Meter>,
+    /// Signalled when the hook has run, with the name the take is to be renamed to.
+    done: Option<Sender<Option<String>>>,
+}
+
+/// Runs the functions of a `hooks.lua` script on its own thread as transport events happen.
+///
+/// A function named `on_<event>` is called with a table of the event if the script defines it,
+/// e.g. `on_start`, `on_stop`, `on_split`, `on_arm`, `on_disarm` or `on_meters`.
+#[derive(Clone, Debug)]
+pub struct Hooks {
+    events: Sender<HookEvent>,
+    /// Whether the script defines `on_meters`, meters are only sent to it then.
+    meters: bool,
+}
+
+impl Hooks {
+    /// Loads the script if it exists, errors in the script are reported before recording starts.
+    pub fn load(path: &Utf8Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let source = std::fs::read_to_string(path)?;
+        let name = path.to_string();
+        let (events, incoming) = crossbeam::channel::unbounded();
+        let (loaded, load_result) = crossbeam::channel::bounded(1);
+
+        // Lua states are not `Send`, the state lives on the thread which runs the hooks.
+        std::thread::spawn(move || match Engine::new(&source, &name) {
+            Ok(engine) => {
+                let _ = loaded.send(Ok(engine.defines("meters")));
+                engine.run(&incoming);
+            }
+            Err(err) => {
+                let _ = loaded.send(Err(err));
+            }
+        });
+
+        let meters = load_result
+            .recv()
+            .map_err(|_| anyhow!("Hooks thread exited unexpectedly."))??;
+        println!("Loaded hooks from {path}.");
+        Ok(Some(Self { events, meters }))
+    }
+
+    /// Runs the hook of an event without waiting for it, unless it is a stop.
+    ///
+    /// Gives the name a stopped take is to be renamed to when its hook asked for one, the caller renames it before
+    /// anything else reads the files of the take.
+    pub fn run(
+        &self,
+        name: &'static str,
+        take: Option<&Utf8Path>,
+        wall_clock: String,
+    ) -> Option<String> {
+        let (done, finished) = crossbeam::channel::bounded(1);
+        let wait = name == "stop";
+        let event = HookEvent {
+            name,
+            take: take.map(Utf8Path::to_path_buf),
+            wall_clock,
+            meters: Vec::new(),
+            done: wait.then_some(done),
+        };
+        if self.events.send(event).is_err() {
+            eprintln!("Hooks are not running anymore, {name} hook is skipped.");
+            return None;
+        }
+        if !wait {
+            return None;
+        }
+        finished
+            .recv_timeout(Duration::from_secs(STOP_HOOK_TIMEOUT_SECS))
+            .map_err(|_| {
+                eprintln!("The {name} hook did not finish in {STOP_HOOK_TIMEOUT_SECS} seconds.");
+            })
+            .ok()
+            .flatten()
+    }
+
+    /// Starts a thread which calls `on_meters` every second while a take is recorded, if the script defines it.
+    ///
+    /// A call is skipped while the hooks are still busy with earlier events.
+    pub fn watch_meters(
+        &self,
+        writers: Arc<Mutex<Option<Take>>>,
+        processing: Arc<Processing>,
+        timestamp: TimestampZone,
+    ) {
+        if !self.meters {
+            return;
+        }
+        let events = self.events.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(Duration::from_secs(METERS_HOOK_SECS));
+            let take = writers
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|take| take.directory.clone());
+            let Some(take) = take.filter(|_| events.is_empty()) else {
+                continue;
+            };
+            let event = HookEvent {
+                name: "meters",
+                take: Some(take),
+                wall_clock: timestamp.rfc3339(chrono::Utc::now()),
+                meters: processing.meters().held(),
+                done: None,
+            };
+            if events.send(event).is_err() {
+                return;
+            }
+        });
+    }
+}
+
+struct Engine {
+    lua: Lua,
+    /// New name of the take requested by the running hook.
+    rename: Rc<RefCell<Option<String>>>,
+}
+
+impl Engine {
+    fn new(source: &str, name: &str) -> Result<Self> {
+        let lua = Lua::new();
+        let rename = Rc::new(RefCell::new(None));
+        register_api(&lua, Rc::clone(&rename))
+            .map_err(|err| anyhow!("Error preparing hooks: {err}"))?;
+        lua.load(source)
+            .set_name(name)
+            .exec()
+            .map_err(|err| anyhow!("Error loading hooks: {err}"))?;
+        Ok(Self { lua, rename })
+    }
+
+    fn run(&self, incoming: &Receiver<HookEvent>) {
+        for event in incoming {
+            let rename = self.dispatch(&event).unwrap_or_else(|err| {
+                eprintln!("Error running {} hook: {err}", event.name);
+                None
+            });
+            if let Some(done) = event.done {
+                let _ = done.send(rename);
+            }
+        }
+    }
+
+    /// Whether the script defines the hook of an event.
+    fn defines(&self, event: &str) -> bool {
+        self.lua
+            .globals()
+            .get::<_, Option<Function>>(format!("on_{event}"))
+            .is_ok_and(|hook| hook.is_some())
+    }
+
+    /// Calls the hook of the event if the script defines one and gives the name the take is to be renamed to.
+    fn dispatch(&self, event: &HookEvent) -> Result<Option<String>> {
+        let hook: Option<Function> = self.lua.globals().get(format!("on_{}", event.name))?;
+        let Some(hook) = hook else {
+            return Ok(None);
+        };
+
+        let table = self.lua.create_table()?;
+        table.set("event", event.name)?;
+        table.set("time", event.wall_clock.as_str())?;
+        if let Some(take) = &event.take {
+            table.set("take", take.file_name().unwrap_or(take.as_str()))?;
+            table.set("path", take.as_str())?;
+        }
+        if !event.meters.is_empty() {
+            // Indexed by the 1 indexed device channels.
+            let meters = self.lua.create_table()?;
+            for meter in &event.meters {
+                let channel = self.lua.create_table()?;
+                channel.set("peak", meter.peak_db)?;
+                channel.set("rms", meter.rms_db)?;
+                channel.set("overs", meter.overs)?;
+                meters.push(channel)?;
+            }
+            table.set("meters", meters)?;
+        }
+
+        self.rename.borrow_mut().take();
+        hook.call::<_, ()>(table)?;
+
+        let rename = self.rename.borrow_mut().take();
+        if rename.is_some() && (event.name != "stop" || event.take.is_none()) {
+            bail!("Takes can only be renamed in on_stop, after they are finalized.");
+        }
+        Ok(rename)
+    }
+}
+
+/// Exposes the `smrec` table to scripts.
+fn register_api(lua: &Lua, rename: Rc<RefCell<Option<String>>>) -> mlua::Result<()> {
+    let api = lua.create_table()?;
+
+    // smrec.osc("127.0.0.1:9000", "/address", ...)
+    #[cfg(feature = "osc")]
+    api.set(
+        "osc",
+        lua.create_function(|_, (to, addr, args): (String, String, Variadic<Value>)| {
+            let args = args
+                .iter()
+                .map(osc_argument)
+                .collect::<mlua::Result<Vec<_>>>()?;
+            send_osc(&to, addr, args).map_err(mlua::Error::external)
+        })?,
+    )?;
+
+    // smrec.midi("port name pattern", 0xB0, 20, 127)
+    #[cfg(feature = "midi")]
+    api.set(
+        "midi",
+        lua.create_function(|_, (port, message): (String, Variadic<u8>)| {
+            send_midi(&port, &message).map_err(mlua::Error::external)
+        })?,
+    )?;
+
+    // smrec.http("POST", "http://host:port/path", body, content_type) returns the status code.
+    #[cfg(feature = "http")]
+    api.set(
+        "http",
+        lua.create_function(
+            |_,
+             (method, url, body, content_type): (
+                String,
+                String,
+                Option<String>,
+                Option<String>,
+            )| {
+                http(
+                    &method,
+                    &url,
+                    body.as_deref().unwrap_or_default(),
+                    content_type.as_deref().unwrap_or("application/json"),
+                )
+                .map_err(mlua::Error::external)
+            },
+        )?,
+    )?;
+
+    // smrec.rename_take("new name")
+    api.set(
+        "rename_take",
+        lua.create_function(move |_, name: String| {
+            rename.borrow_mut().replace(name);
+            Ok(())
+        })?,
+    )?;
+
+    lua.globals().set("smrec", api)
+}
+
+#[cfg(feature = "osc")]
+fn osc_argument(value: &Value) -> mlua::Result<OscType> {
+    Ok(match value {
+        Value::Boolean(value) => OscType::Bool(*value),
+        Value::Integer(value) => i32::try_from(*value).map_or(OscType::Long(*value), OscType::Int),
+        #[allow(clippy::cast_possible_truncation)]
+        Value::Number(value) => OscType::Float(*value as f32),
+        Value::String(value) => OscType::String(value.to_str()?.to_string()),
+        other => {
+            return Err(mlua::Error::RuntimeError(format!(
+                "A {} can not be sent as an OSC argument.",
+                other.type_name()
+            )))
+        }
+    })
+}
+
+#[cfg(feature = "osc")]
+fn send_osc(to: &str, addr: String, args: Vec<OscType>) -> Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    let packet = OscPacket::Message(OscMessage { addr, args });
+    socket.send_to(&encode(&packet)?, to)?;
+    Ok(())
+}
+
+#[cfg(feature = "midi")]
+/// Sends a raw message to every output port which matches the pattern.
+fn send_midi(pattern: &str, message: &[u8]) -> Result<()> {
+    let output = MidiOutput::new("smrec")?;
+    let port = output
+        .ports()
+        .into_iter()
+        .find(|port| {
+            output
+                .port_name(port)
+                .is_ok_and(|name| glob_match::glob_match(pattern, &name))
+        })
+        .ok_or_else(|| anyhow!("No MIDI output port matches {pattern}."))?;
+    let mut connection = output
+        .connect(&port, "smrec-hooks")
+        .map_err(|err| anyhow!("{err}"))?;
+    connection.send(message)?;
+    Ok(())
+}
+
+#[cfg(feature = "http")]
+/// Makes a plain HTTP/1.1 request and returns the status code.
+fn http(method: &str, url: &str, body: &str, content_type: &str) -> Result<u16> {
+    let (authority, path) = split_url(url)?;
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{authority}:80")
+    };
+
+    let mut stream = TcpStream::connect(address)?;
+    stream.set_read_timeout(Some(Duration::from_secs(HTTP_TIMEOUT_SECS)))?;
+    write!(
+        stream,
+        "{method} {path} HTTP/1.1\r\nHost: {authority}\r\nConnection: close\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\r\n{body}",
+        body.len()
+    )?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| anyhow!("Unexpected response from {url}: {}", status_line.trim()))
+}
+
+#[cfg(feature = "http")]
+/// Splits an `http://` URL into its authority and path.
+fn split_url(url: &str) -> Result<(&str, &str)> {
+    let Some(rest) = url.strip_prefix("http://") else {
+        bail!("Only http:// URLs are supported, {url} is not one.");
+    };
+    Ok(rest
+        .find('/')
+        .map_or((rest, "/"), |slash| (&rest[..slash], &rest[slash..])))
+}
+
+/// Renames the directory of a finalized take and gives where it is now.
+pub fn rename_take(take: &Utf8Path, name: &str) -> Result<Utf8PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+        bail!("{name} is not a valid take name.");
+    }
+    let renamed = take.with_file_name(name);
+    if renamed.exists() {
+        bail!("Can not rename {take} to {renamed}, it already exists.");
+    }
+    std::fs::rename(take, &renamed)?;
+    println!("Take {take} is renamed to {renamed}.");
+    Ok(renamed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    #[cfg(feature = "http")]
+    fn urls_are_split() {
+        assert_eq!(
+            split_url("http://localhost:8080/hooks/take").unwrap(),
+            ("localhost:8080", "/hooks/take")
+        );
+        assert_eq!(
+            split_url("http://example.com").unwrap(),
+            ("example.com", "/")
+        );
+        assert!(split_url("https://example.com/").is_err());
+    }
+
+    #[test]
+    fn stop_hook_renames_take() {
+        let dir = TempDir::new("hooks");
+        let base = dir.path();
+        let take = base.join("rec_20231120_120000");
+        std::fs::create_dir_all(&take).unwrap();
+
+        let engine = Engine::new(
+            r#"
+            function on_stop(event)
+                smrec.rename_take(event.take .. "_" .. event.event)
+            end
+            function on_start(event)
+                smrec.rename_take("too early")
+            end
+            function on_meters(event)
+                assert(event.meters[2].peak == -6 and event.meters[2].overs == 1)
+            end
+            "#,
+            "hooks.lua",
+        )
+        .unwrap();
+        let event = |name| HookEvent {
+            name,
+            take: Some(take.clone()),
+            wall_clock: "2023-11-20T12:00:00Z".to_string(),
+            meters: Vec::new(),
+            done: None,
+        };
+
+        assert!(engine.dispatch(&event("start")).is_err());
+        // Events without a hook are ignored.
+        assert_eq!(engine.dispatch(&event("split")).unwrap(), None);
+        assert!(engine.defines("meters"));
+        let meter = |peak_db, overs| Meter {
+            peak_db,
+            overs,
+            rms_db: f32::NEG_INFINITY,
+        };
+        engine
+            .dispatch(&HookEvent {
+                meters: vec![meter(f32::NEG_INFINITY, 0), meter(-6.0, 1)],
+                ..event("meters")
+            })
+            .unwrap();
+
+        // The take is renamed by the recorder, after it is finalized and before its files are analyzed.
+        let name = engine.dispatch(&event("stop")).unwrap().unwrap();
+        assert_eq!(
+            rename_take(&take, &name).unwrap(),
+            base.join("rec_20231120_120000_stop")
+        );
+        assert!(base.join("rec_20231120_120000_stop").exists());
+        assert!(!take.exists());
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::Result;
+use std::sync::Mutex;
+
+/// Keeps the system awake while it lives and lets it sleep again when dropped.
+struct Inhibitor {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    child: std::process::Child,
+}
+
+/// The inhibitor of the running recording, if any.
+static INHIBITOR: Mutex<Option<Inhibitor>> = Mutex::new(None);
+
+/// Prevents the system from sleeping until [`release`] is called.
+///
+/// Failing to inhibit sleep is not fatal, recording goes on with a warning.
+pub fn acquire() {
+    let mut inhibitor = INHIBITOR.lock().unwrap();
+    if inhibitor.is_some() {
+        return;
+    }
+    match Inhibitor::new() {
+        Ok(new) => *inhibitor = Some(new),
+        Err(err) => {
+            eprintln!("Could not prevent the system from sleeping while recording: {err}");
+        }
+    }
+}
+
+/// Lets the system sleep again.
+pub fn release() {
+    INHIBITOR.lock().unwrap().take();
+}
+
+// The inhibiting processes wait for smrec to exit, so they never outlive it even if it is killed.
+
+#[cfg(target_os = "linux")]
+impl Inhibitor {
+    fn new() -> Result<Self> {
+        let child = std::process::Command::new("systemd-inhibit")
+            .args([
+                "--what=sleep:idle",
+                "--who=smrec",
+                "--why=Recording",
+                "--mode=block",
+                "tail",
+                &format!("--pid={}", std::process::id()),
+                "-f",
+                "/dev/null",
+            ])
+            .spawn()?;
+        Ok(Self { child })
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl Inhibitor {
+    fn new() -> Result<Self> {
+        // Creates an IOKit assertion which prevents idle sleep.
+        let child = std::process::Command::new("caffeinate")
+            .args(["-i", "-w", &std::process::id().to_string()])
+            .spawn()?;
+        Ok(Self { child })
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+impl Drop for Inhibitor {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod execution_state {
+    pub const ES_CONTINUOUS: u32 = 0x8000_0000;
+    pub const ES_SYSTEM_REQUIRED: u32 = 0x0000_0001;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn SetThreadExecutionState(flags: u32) -> u32;
+    }
+}
+
+// The execution state belongs to the calling thread, recordings are always started and stopped on the main thread.
+
+#[cfg(target_os = "windows")]
+impl Inhibitor {
+    fn new() -> Result<Self> {
+        use execution_state::{SetThreadExecutionState, ES_CONTINUOUS, ES_SYSTEM_REQUIRED};
+        // SAFETY: Only sets a flag for the calling thread.
+        if unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) } == 0 {
+            anyhow::bail!("SetThreadExecutionState failed.");
+        }
+        Ok(Self {})
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl Drop for Inhibitor {
+    fn drop(&mut self) {
+        // SAFETY: Only clears the flag set in `new`.
+        unsafe { execution_state::SetThreadExecutionState(execution_state::ES_CONTINUOUS) };
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+impl Inhibitor {
+    fn new() -> Result<Self> {
+        anyhow::bail!("Inhibiting sleep is not supported on this platform.")
+    }
+}
@@ -0,0 +1,98 @@
+use anyhow::{anyhow, bail, Result};
+use cpal::{FromSample, Sample};
+use std::str::FromStr;
+
+/// Amplitude of generated signals, -12 dBFS.
+const AMPLITUDE: f32 = 0.25;
+
+/// A signal recorded instead of the input, given to `--inject`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Signal {
+    /// A sine at a frequency in Hz on every channel.
+    Sine(f32),
+    Silence,
+}
+
+impl FromStr for Signal {
+    type Err = anyhow::Error;
+
+    /// Parses `sine:<frequency>` with the frequency in Hz, e.g. `sine:440` or `sine:1k`, or `silence`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            Some(("sine", frequency)) => {
+                let (number, scale) = frequency
+                    .strip_suffix(['k', 'K'])
+                    .map_or((frequency, 1.0), |number| (number, 1000.0));
+                let frequency = number
+                    .parse::<f32>()
+                    .ok()
+                    .map(|number| number * scale)
+                    .filter(|frequency| frequency.is_finite() && *frequency > 0.0)
+                    .ok_or_else(|| anyhow!("Invalid frequency {frequency} in {s}."))?;
+                Ok(Self::Sine(frequency))
+            }
+            None if s == "silence" => Ok(Self::Silence),
+            _ => bail!("Unknown signal {s}, expected e.g. sine:1k or silence."),
+        }
+    }
+}
+
+/// Generates a signal continuously across the buffers of a stream.
+pub struct Generator {
+    signal: Signal,
+    sample_rate: u32,
+    /// Frames generated so far, wrapped at the sample rate so the phase stays precise.
+    frame: u32,
+}
+
+impl Generator {
+    pub const fn new(signal: Signal, sample_rate: u32) -> Self {
+        Self {
+            signal,
+            sample_rate,
+            frame: 0,
+        }
+    }
+
+    /// Replaces interleaved samples with the next frames of the signal on every channel.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn fill<T>(&mut self, data: &mut [T], channels: usize)
+    where
+        T: Sample + FromSample<f32>,
+    {
+        for frame in data.chunks_mut(channels) {
+            let value = match self.signal {
+                Signal::Sine(frequency) => {
+                    let t = self.frame as f32 / self.sample_rate as f32;
+                    (std::f32::consts::TAU * frequency * t).sin() * AMPLITUDE
+                }
+                Signal::Silence => 0.0,
+            };
+            frame.fill(T::from_sample(value));
+            // Whole seconds hold whole periods of integer frequencies.
+            self.frame = (self.frame + 1) % self.sample_rate;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signals_are_parsed_and_generated() {
+        assert_eq!("sine:1k".parse::<Signal>().unwrap(), Signal::Sine(1000.0));
+        assert_eq!("sine:440".parse::<Signal>().unwrap(), Signal::Sine(440.0));
+        assert_eq!("silence".parse::<Signal>().unwrap(), Signal::Silence);
+        assert!("sine:".parse::<Signal>().is_err());
+        assert!("sine:-5".parse::<Signal>().is_err());
+        assert!("noise".parse::<Signal>().is_err());
+
+        // A quarter period of 1 kHz at 8 kHz is 2 frames.
+        let mut generator = Generator::new(Signal::Sine(1000.0), 8000);
+        let mut data = [0.0_f32; 6];
+        generator.fill(&mut data, 2);
+        assert!(data[0].abs() < 1e-6 && data[1].abs() < 1e-6);
+        assert!((data[4] - AMPLITUDE).abs() < 1e-6 && (data[5] - AMPLITUDE).abs() < 1e-6);
+    }
+}
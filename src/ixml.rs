@@ -0,0 +1,146 @@
+use serde::Deserialize;
+use std::fmt::Write;
+
+/// Production metadata written into an iXML chunk of every file, configured in `[ixml]` or on the command line.
+#[derive(clap::Args, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Ixml {
+    /// Specify the project written into the iXML chunk of the files.
+    /// Example: smrec --project "Feature"
+    #[clap(long)]
+    #[serde(default)]
+    project: Option<String>,
+    /// Specify the scene written into the iXML chunk of the files, take numbers count up per scene.
+    /// Example: smrec --scene 12A
+    #[clap(long)]
+    #[serde(default)]
+    scene: Option<String>,
+    /// Specify the tape (sound roll) written into the iXML chunk of the files.
+    /// Example: smrec --tape SR001
+    #[clap(long)]
+    #[serde(default)]
+    tape: Option<String>,
+}
+
+/// A track of a file in the iXML track list.
+pub struct Track<'a> {
+    /// 1 indexed channel of the recorder.
+    pub channel: usize,
+    pub name: &'a str,
+}
+
+impl Ixml {
+    /// Fills the values which are not given on the command line from the configuration.
+    ///
+    /// Returns `None` when neither of them sets anything, iXML chunks are not written then.
+    pub fn or(self, config: Option<Self>) -> Option<Self> {
+        let config = config.unwrap_or_default();
+        let merged = Self {
+            project: self.project.or(config.project),
+            scene: self.scene.or(config.scene),
+            tape: self.tape.or(config.tape),
+        };
+        (merged != Self::default()).then_some(merged)
+    }
+
+    pub fn project(&self) -> Option<&str> {
+        self.project.as_deref()
+    }
+
+    pub fn scene(&self) -> &str {
+        self.scene.as_deref().unwrap_or_default()
+    }
+
+    /// Renders the iXML document of a mono file.
+    pub fn render(
+        &self,
+        take: u32,
+        track: &Track,
+        sample_rate: u32,
+        bits_per_sample: u16,
+    ) -> String {
+        let mut xml = String::new();
+        // Writing to a string does not fail.
+        let _ = writeln!(xml, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+        let _ = writeln!(xml, "<BWFXML>");
+        let _ = writeln!(xml, "  <IXML_VERSION>1.61</IXML_VERSION>");
+        for (tag, value) in [
+            ("PROJECT", &self.project),
+            ("SCENE", &self.scene),
+            ("TAPE", &self.tape),
+        ] {
+            if let Some(value) = value {
+                let _ = writeln!(xml, "  <{tag}>{}</{tag}>", escape(value));
+            }
+        }
+        let _ = writeln!(xml, "  <TAKE>{take}</TAKE>");
+        let _ = writeln!(xml, "  <SPEED>");
+        let _ = writeln!(
+            xml,
+            "    <FILE_SAMPLE_RATE>{sample_rate}</FILE_SAMPLE_RATE>"
+        );
+        let _ = writeln!(
+            xml,
+            "    <AUDIO_BIT_DEPTH>{bits_per_sample}</AUDIO_BIT_DEPTH>"
+        );
+        let _ = writeln!(xml, "  </SPEED>");
+        let _ = writeln!(xml, "  <TRACK_LIST>");
+        let _ = writeln!(xml, "    <TRACK_COUNT>1</TRACK_COUNT>");
+        let _ = writeln!(xml, "    <TRACK>");
+        let _ = writeln!(
+            xml,
+            "      <CHANNEL_INDEX>{}</CHANNEL_INDEX>",
+            track.channel
+        );
+        let _ = writeln!(xml, "      <INTERLEAVE_INDEX>1</INTERLEAVE_INDEX>");
+        let _ = writeln!(xml, "      <NAME>{}</NAME>", escape(track.name));
+        let _ = writeln!(xml, "    </TRACK>");
+        let _ = writeln!(xml, "  </TRACK_LIST>");
+        let _ = writeln!(xml, "</BWFXML>");
+        xml
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_line_wins_and_tracks_are_named() {
+        let cli = Ixml {
+            scene: Some("12A".to_string()),
+            ..Ixml::default()
+        };
+        let config = Ixml {
+            project: Some("Q&A".to_string()),
+            scene: Some("1".to_string()),
+            tape: None,
+        };
+        assert_eq!(Ixml::default().or(None), None);
+
+        let ixml = cli.or(Some(config)).unwrap();
+        assert_eq!(ixml.scene(), "12A");
+        let xml = ixml.render(
+            3,
+            &Track {
+                channel: 5,
+                name: "Boom",
+            },
+            48000,
+            24,
+        );
+        assert!(
+            xml.contains("  <PROJECT>Q&amp;A</PROJECT>\n  <SCENE>12A</SCENE>\n  <TAKE>3</TAKE>\n")
+        );
+        assert!(!xml.contains("<TAPE>"));
+        assert!(xml.contains("      <CHANNEL_INDEX>5</CHANNEL_INDEX>\n"));
+        assert!(xml.contains("      <NAME>Boom</NAME>\n"));
+    }
+}
@@ -0,0 +1,117 @@
+//! The recorder behind the `smrec` binary.
+//!
+//! Frontends which control it implement [`ControlSurface`], they are sent the [`Action`]s the recorder publishes
+//! and send it the ones they receive.
+
+// Most of the lints we deny here have a good chance to be relevant for our project.
+#![deny(clippy::all)]
+// We warn for all lints on the planet. Just to filter them later for customization.
+// It is impossible to remember all the lints so a subtractive approach keeps us updated, in control and knowledgeable.
+#![warn(clippy::pedantic, clippy::nursery, clippy::cargo)]
+// Then in the end we allow ridiculous or too restrictive lints that are not relevant for our project.
+// This list is dynamic and will grow in time which will define our style.
+#![allow(
+    clippy::multiple_crate_versions,
+    clippy::blanket_clippy_restriction_lints,
+    clippy::missing_docs_in_private_items,
+    clippy::pub_use,
+    clippy::std_instead_of_alloc,
+    clippy::std_instead_of_core,
+    clippy::implicit_return,
+    clippy::missing_inline_in_public_items,
+    clippy::similar_names,
+    clippy::question_mark_used,
+    clippy::expect_used,
+    clippy::missing_errors_doc,
+    clippy::pattern_type_mismatch,
+    clippy::module_name_repetitions,
+    clippy::empty_structs_with_brackets,
+    clippy::as_conversions,
+    clippy::self_named_module_files,
+    clippy::cargo_common_metadata,
+    clippy::exhaustive_structs,
+    // The library is the recorder of the binary, panicing is usually fine and what is returned is used.
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate
+)]
+
+pub mod apps;
+pub mod bagit;
+pub mod bench;
+pub mod clock;
+pub mod config;
+pub mod console;
+pub mod control;
+pub mod crash;
+pub mod dedupe;
+pub mod device_names;
+pub mod diff;
+#[cfg(feature = "display")]
+pub mod display;
+pub mod error;
+pub mod events;
+pub mod failover;
+pub mod files;
+pub mod fingerprint;
+#[cfg(feature = "gpio")]
+pub mod gpio;
+pub mod guard;
+#[cfg(feature = "hooks")]
+pub mod hooks;
+pub mod inhibit;
+pub mod inject;
+pub mod ixml;
+pub mod linecheck;
+pub mod list;
+pub mod loudness;
+pub mod meters;
+#[cfg(feature = "midi")]
+pub mod midi;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod monitor;
+pub mod ntp;
+#[cfg(feature = "osc")]
+pub mod osc;
+pub mod pairing;
+pub mod peaks;
+pub mod pipe;
+#[cfg(target_os = "linux")]
+pub mod pipewire;
+pub mod power;
+pub mod processing;
+#[cfg(feature = "osc")]
+pub mod qr;
+pub mod report;
+pub mod resample;
+pub mod schedule;
+pub mod selftest;
+pub mod session;
+pub mod setlist;
+pub mod space;
+pub mod spool;
+pub mod staging;
+pub mod state;
+pub mod stream;
+pub mod syslog;
+pub mod takes;
+pub mod tap;
+#[cfg(test)]
+mod testing;
+pub mod threads;
+pub mod timeline;
+pub mod title;
+pub mod transcribe;
+pub mod types;
+#[cfg(feature = "update")]
+pub mod update;
+pub mod verify;
+pub mod wav;
+
+pub use control::ControlSurface;
+pub use types::{Action, Transport};
+
+use std::sync::{Arc, Mutex};
+
+pub type WriterHandle = Arc<Mutex<Option<wav::FileWriter>>>;
+pub type WriterHandles = Arc<Vec<WriterHandle>>;
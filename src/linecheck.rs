@@ -0,0 +1,51 @@
+use crate::meters::Meter;
+
+/// Line checks are recorded into this directory of the output directory, apart from the takes of the show.
+pub const DIRECTORY: &str = "linecheck";
+
+/// Lines with the peak of every named channel of a line check, dead and clipping lines called out.
+pub fn report(channels: &[(String, Meter)]) -> Vec<String> {
+    let mut lines = vec!["Line check peaks:".to_string()];
+    lines.extend(channels.iter().map(|(name, meter)| {
+        if meter.peak_db == f32::NEG_INFINITY {
+            return format!("  {name}: silent, check the cable and the preamp.");
+        }
+        match meter.overs {
+            0 => format!("  {name}: peak {:.1} dBFS.", meter.peak_db),
+            1 => format!("  {name}: peak {:.1} dBFS, clipped once.", meter.peak_db),
+            overs => format!(
+                "  {name}: peak {:.1} dBFS, clipped {overs} times.",
+                meter.peak_db
+            ),
+        }
+    }));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_channel_is_reported_with_its_peak() {
+        let meter = |peak_db, overs| Meter {
+            peak_db,
+            overs,
+            rms_db: -30.0,
+        };
+        let lines = report(&[
+            ("Kick".to_string(), meter(-12.34, 0)),
+            ("Snare".to_string(), meter(0.0, 3)),
+            ("Vox".to_string(), meter(f32::NEG_INFINITY, 0)),
+        ]);
+        assert_eq!(
+            lines,
+            [
+                "Line check peaks:",
+                "  Kick: peak -12.3 dBFS.",
+                "  Snare: peak 0.0 dBFS, clipped 3 times.",
+                "  Vox: silent, check the cable and the preamp.",
+            ]
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use anyhow::Result;
 use cpal::traits::{DeviceTrait, HostTrait};
+#[cfg(feature = "midi")]
 use midir::{Ignore, MidiInput, MidiOutput};
 
 pub fn enumerate_audio() -> Result<()> {
@@ -96,6 +97,7 @@ pub fn enumerate_audio() -> Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "midi")]
 pub fn enumerate_midi() -> Result<()> {
     let mut midi_in = MidiInput::new("dummy input")?;
     midi_in.ignore(Ignore::None);
@@ -0,0 +1,217 @@
+// The filters are written as in BS.1770 rather than with fused multiply-adds.
+#![allow(clippy::suboptimal_flops)]
+
+use anyhow::Result;
+use camino::Utf8Path;
+use std::f64::consts::PI;
+
+/// Loudness which `ReplayGain` 2.0 normalizes to.
+const REPLAYGAIN_REFERENCE_LUFS: f64 = -18.0;
+/// Gating blocks are 400 ms long and overlap by 75%.
+const BLOCK_MILLIS: u32 = 400;
+const BLOCK_STEPS: usize = 4;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+/// Loudness and peak of a file.
+#[derive(Debug, Clone, Copy)]
+pub struct Analysis {
+    /// Integrated loudness in LUFS, `None` when everything is below the absolute gate.
+    pub loudness: Option<f64>,
+    /// Highest absolute sample, 1.0 is full scale.
+    pub peak: f64,
+}
+
+impl Analysis {
+    /// `ReplayGain` 2.0 track gain in dB.
+    pub fn track_gain(&self) -> Option<f64> {
+        self.loudness
+            .map(|loudness| REPLAYGAIN_REFERENCE_LUFS - loudness)
+    }
+}
+
+/// Measures the integrated loudness (ITU-R BS.1770, EBU R128) and the peak of a mono file.
+///
+/// The file is read through once, only the power of every step of the gating blocks is kept.
+pub fn analyze_file(path: &Utf8Path) -> Result<Analysis> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let mut meter = Meter::new(spec.sample_rate);
+    match spec.sample_format {
+        hound::SampleFormat::Float => {
+            for sample in reader.samples::<f32>() {
+                meter.push(f64::from(sample?));
+            }
+        }
+        hound::SampleFormat::Int => {
+            let full_scale = f64::from(1_u32 << (spec.bits_per_sample - 1));
+            for sample in reader.samples::<i32>() {
+                meter.push(f64::from(sample?) / full_scale);
+            }
+        }
+    }
+    Ok(meter.finish())
+}
+
+pub fn analyze(samples: &[f64], sample_rate: u32) -> Analysis {
+    let mut meter = Meter::new(sample_rate);
+    for sample in samples {
+        meter.push(*sample);
+    }
+    meter.finish()
+}
+
+/// Measures samples as they come.
+struct Meter {
+    filters: [Biquad; 2],
+    peak: f64,
+    /// Samples of a step, a quarter of a gating block.
+    step: usize,
+    /// Sum of the squared weighted samples of the step so far and how many there are.
+    sum: f64,
+    count: usize,
+    /// Sums of the finished steps.
+    steps: Vec<f64>,
+}
+
+impl Meter {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            filters: k_weighting(sample_rate),
+            peak: 0.0,
+            step: (sample_rate * BLOCK_MILLIS / 1000) as usize / BLOCK_STEPS,
+            sum: 0.0,
+            count: 0,
+            steps: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, sample: f64) {
+        self.peak = self.peak.max(sample.abs());
+        let weighted = self
+            .filters
+            .iter_mut()
+            .fold(sample, |sample, filter| filter.process(sample));
+        self.sum += weighted * weighted;
+        self.count += 1;
+        if self.count == self.step {
+            self.steps.push(self.sum);
+            self.sum = 0.0;
+            self.count = 0;
+        }
+    }
+
+    fn finish(self) -> Analysis {
+        Analysis {
+            loudness: gated_loudness(&self.steps, self.step),
+            peak: self.peak,
+        }
+    }
+}
+
+/// The gated loudness of the blocks made of consecutive steps, given the sums of their squared samples.
+#[allow(clippy::cast_precision_loss)]
+fn gated_loudness(steps: &[f64], step: usize) -> Option<f64> {
+    if step == 0 || steps.len() < BLOCK_STEPS {
+        return None;
+    }
+
+    let blocks = steps
+        .windows(BLOCK_STEPS)
+        .map(|block| block.iter().sum::<f64>() / (step * BLOCK_STEPS) as f64)
+        .filter(|power| loudness(*power) > ABSOLUTE_GATE_LUFS)
+        .collect::<Vec<_>>();
+    if blocks.is_empty() {
+        return None;
+    }
+
+    let relative_gate = loudness(mean(&blocks)) + RELATIVE_GATE_LU;
+    let gated = blocks
+        .into_iter()
+        .filter(|power| loudness(*power) > relative_gate)
+        .collect::<Vec<_>>();
+    (!gated.is_empty()).then(|| loudness(mean(&gated)))
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn mean(powers: &[f64]) -> f64 {
+    powers.iter().sum::<f64>() / powers.len() as f64
+}
+
+fn loudness(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+/// A biquad filter in direct form II transposed.
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.z[0];
+        self.z[0] = self.b[1] * input + self.z[1] - self.a[0] * output;
+        self.z[1] = self.b[2] * input - self.a[1] * output;
+        output
+    }
+}
+
+/// The pre-filter and the high pass of BS.1770 for any sample rate.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = f64::from(sample_rate);
+
+    // High shelf modelling the acoustic effect of the head.
+    let (f0, gain, q) = (
+        1_681.974_450_955_533,
+        3.999_843_853_973_347,
+        0.707_175_236_955_419_6,
+    );
+    let k = (PI * f0 / rate).tan();
+    let vh = 10.0_f64.powf(gain / 20.0);
+    let vb = vh.powf(0.499_666_774_154_541_6);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    // High pass removing the lowest frequencies.
+    let (f0, q) = (38.135_470_876_024_44, 0.500_327_037_323_877_3);
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    [shelf, high_pass]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine_loudness_and_replaygain() {
+        // 1 kHz at -20 dBFS peak for 3 seconds.
+        let samples = (0..3 * 48000)
+            .map(|i| (2.0 * PI * 1000.0 * f64::from(i) / 48000.0).sin() * 0.1)
+            .collect::<Vec<_>>();
+        let analysis = analyze(&samples, 48000);
+
+        let loudness = analysis.loudness.unwrap();
+        assert!((loudness + 23.0).abs() < 0.1, "{loudness}");
+        assert!((analysis.track_gain().unwrap() - 5.0).abs() < 0.1);
+        assert!((analysis.peak - 0.1).abs() < 1e-6);
+
+        assert!(analyze(&vec![0.0; 48000], 48000).loudness.is_none());
+    }
+}
@@ -29,32 +29,49 @@
     clippy::missing_panics_doc
 )]
 
-mod config;
-mod list;
-mod midi;
-mod osc;
-mod stream;
-mod types;
-mod wav;
-
-use crate::{
-    config::{choose_channels_to_record, SmrecConfig},
-    midi::Midi,
+#[cfg(feature = "display")]
+use smrec::display;
+#[cfg(feature = "gpio")]
+use smrec::gpio;
+#[cfg(feature = "midi")]
+use smrec::midi;
+#[cfg(feature = "mock")]
+use smrec::mock;
+#[cfg(feature = "osc")]
+use smrec::osc;
+#[cfg(target_os = "linux")]
+use smrec::pipewire;
+#[cfg(feature = "osc")]
+use smrec::qr;
+#[cfg(feature = "update")]
+use smrec::update;
+use smrec::{
+    apps, bagit, bench, clock, config, console, control, crash, dedupe, device_names, diff, error,
+    events, failover, guard, inhibit, inject, ixml, linecheck, list, meters, monitor, pairing,
+    pipe, power, report, resample, selftest, setlist, space, spool, staging, state, stream, syslog,
+    takes, tap, threads, title, types, verify, wav,
 };
-use anyhow::{bail, Result};
+
+use anyhow::{anyhow, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
 use clap::{Parser, Subcommand};
-use config::{choose_device, choose_host};
-use cpal::traits::{DeviceTrait, StreamTrait};
-use hound::WavWriter;
+use clock::TimestampZone;
+use config::SmrecConfig;
+use config::{choose_device, choose_host, choose_output_device};
+use error::Code;
+use events::Event;
+#[cfg(feature = "midi")]
+use midi::Midi;
+#[cfg(feature = "osc")]
 use osc::Osc;
 use std::{
     cell::RefCell,
-    fs::File,
-    io::BufWriter,
     rc::Rc,
     sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
-use types::Action;
+use takes::{Finished, Take};
+use types::{Action, Transport};
 
 #[derive(Parser)]
 #[command(
@@ -63,15 +80,40 @@ use types::Action;
     about = "Minimalist multi-track audio recorder which may be controlled via OSC or MIDI.
 You may visit <https://github.com/alisomay/smrec/blob/main/README.md> for a detailed tutorial."
 )]
+#[allow(clippy::struct_excessive_bools)]
 struct Cli {
     /// Specify audio host.
     /// Example: smrec --host "Asio"
-    #[clap(long)]
+    #[clap(long, value_parser = config::HostParser)]
     host: Option<String>,
-    /// Specify audio device.
+    /// Specify audio device, repeat it to record several devices together.
+    /// A list of preferences separated by commas uses the first connected one, `*` matches any text.
     /// Example: smrec --device "MacBook Pro Microphone"
+    /// Example: smrec --device "UMC1820*,Scarlett*,default"
+    #[clap(long)]
+    device: Vec<String>,
+    /// Resample devices which can not run at the sample rate and format of the first device to it: fast, good or best.
+    /// Better quality adds latency, 1, 8 or 32 frames of the device, and load.
+    /// Example: smrec --device "UMC1820" --device "USB Mic" --resample good
     #[clap(long)]
-    device: Option<String>,
+    resample: Option<resample::Quality>,
+    /// Record a `PipeWire` node through the `pipewire` ALSA device, `smrec nodes` lists them.
+    /// A sink name with a `.monitor` suffix records what is played through it.
+    /// Example: smrec --node "alsa_output.pci-0000_00_1f.3.analog-stereo.monitor"
+    #[cfg(target_os = "linux")]
+    #[clap(long)]
+    node: Option<String>,
+    /// Record the audio an application plays to its own channels, repeat it for several applications.
+    /// Its 2 channels come after the channels of the devices. Needs `parec` of `PulseAudio` or `PipeWire`.
+    /// Example: smrec --source app:Firefox
+    #[clap(long, value_parser = apps::parse_source)]
+    source: Vec<String>,
+    /// Record what the default output device plays through WASAPI loopback next to the input devices, e.g. a call next to the microphone.
+    /// Its channels come after the channels of the devices, in the same take.
+    /// Example: smrec --loopback
+    #[cfg(windows)]
+    #[clap(long)]
+    loopback: bool,
     /// Include specified channels in recording.
     /// Example: smrec --include 1,2
     #[clap(long, value_delimiter = ',', num_args = 1..)]
@@ -88,18 +130,154 @@ struct Cli {
     /// Example: smrec --out ~/Music
     #[clap(long)]
     out: Option<String>,
+    /// Continue the most recent take with the first take of the run instead of creating a new one.
+    /// Example: smrec --append
+    #[clap(long)]
+    append: bool,
+    /// Specify the time zone used for take names and metadata timestamps.
+    /// Example: smrec --timestamp local, smrec --timestamp +02:00
+    #[clap(long, default_value = "utc")]
+    timestamp: TimestampZone,
+    /// Start takes on the next transient reaching the threshold in dBFS (-30 by default).
+    /// Example: smrec --start-on-transient, smrec --start-on-transient -20
+    #[clap(long, num_args = 0..=1, default_missing_value = "-30", allow_negative_numbers = true)]
+    start_on_transient: Option<f32>,
+    /// Also record every channel attenuated by the given gain into a paired `_safety` file.
+    /// Example: smrec --safety -12dB
+    #[clap(long, allow_negative_numbers = true, value_parser = config::parse_safety_db)]
+    safety: Option<f32>,
+    /// Place all takes of the run on one sample timeline, written to `timeline_<session start>.toml`.
+    /// Example: smrec --session-timeline
+    #[clap(long)]
+    session_timeline: bool,
+    /// Keep up to this much audio in memory and only write it to disk when it fills up or the take stops.
+    /// Example: smrec --ram-buffer 2G
+    #[clap(long, value_parser = config::parse_size)]
+    ram_buffer: Option<u64>,
+    /// What to do when the RAM buffer is full because the disk fell behind: block, drop-oldest or flush.
+    /// Example: smrec --ram-buffer 256M --ram-buffer-policy drop-oldest
+    #[clap(long, default_value = "block")]
+    ram_buffer_policy: spool::Policy,
+    /// Stream the recorded channels to a `smrec listen` receiver for monitoring, of the first device with several.
+    /// Example: `smrec --monitor udp://192.168.1.20:18100`
+    #[clap(long, value_parser = monitor::parse_url)]
+    monitor: Option<std::net::SocketAddr>,
+    /// Start a new take at the new sample rate when the device changes it while recording, instead of only stopping.
+    /// Example: smrec --restart-on-rate-change
+    #[clap(long)]
+    restart_on_rate_change: bool,
+    /// Start recording again when the previous run was interrupted while recording, e.g. by a crash or a power loss.
+    /// Example: smrec --osc --resume
+    #[clap(long)]
+    resume: bool,
+    /// Record a generated signal instead of the inputs, to verify the pipeline without sound sources.
+    /// Example: smrec --inject sine:1k
+    #[clap(long)]
+    inject: Option<inject::Signal>,
+    /// Print every configuration the devices offer, why the ones which are not used are rejected and the ones which are.
+    /// Example: smrec --verbose
+    #[clap(long)]
+    verbose: bool,
+    /// Refuse to record devices whose samples would lose precision to be written, instead of converting them with a warning.
+    /// Example: smrec --strict
+    #[clap(long)]
+    strict: bool,
+    /// Record a channel for the whole session, across takes and while stopped, to `guard_<session start>_N.wav` files.
+    /// Example: smrec --osc --guard-track 1
+    #[clap(long)]
+    guard_track: Option<usize>,
+    /// Read back the end of every file once a second while recording and warn when it does not hold what was written.
+    /// Example: smrec --verify
+    #[clap(long)]
+    verify: bool,
+    /// Split the take after every channel stayed below -50 dBFS this long, the next take starts with the next sound.
+    /// Example: smrec --split-on-silence 3s
+    #[clap(long, value_parser = config::parse_duration)]
+    split_on_silence: Option<Duration>,
+    /// Add the ULID of the take to the names of its files, which is always written to take.toml.
+    /// Example: smrec --id-in-file-names
+    #[clap(long)]
+    id_in_file_names: bool,
+    /// Transcribe every take with a speech-to-text command once it is finalized, into `transcript.txt` in the take.
+    /// The path of a 16 kHz mono WAV of the mixdown is appended to the command, what it prints is the transcript.
+    /// Example: smrec --transcribe "whisper-cli -m ggml-base.en.bin --no-timestamps --no-prints -f"
+    #[clap(long)]
+    transcribe: Option<String>,
+    /// Run the audio threads with this real-time priority, 1 to 99, and the writer threads of --ram-buffer one below it.
+    /// Keeps dropouts away on machines busy with a DAW, the user needs to be allowed to, e.g. with rtprio on Linux.
+    /// Example: smrec --realtime-priority 70
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..=99))]
+    realtime_priority: Option<u8>,
+    /// Pin the audio threads to these cores, numbered from 0. Not supported on macOS.
+    /// Example: smrec --audio-cores 2,3
+    #[clap(long, value_delimiter = ',', num_args = 1..)]
+    audio_cores: Vec<usize>,
+    /// Pin the writer threads of --ram-buffer to these cores, numbered from 0. Not supported on macOS.
+    /// Example: smrec --ram-buffer 2G --writer-cores 1
+    #[clap(long, value_delimiter = ',', num_args = 1..)]
+    writer_cores: Vec<usize>,
+    /// Write a crash report with a backtrace, the configuration and the last log messages into .smrec/crash when smrec panics,
+    /// after finalizing the running take. Nothing is sent anywhere.
+    /// Example: smrec --crash-reports
+    #[clap(long)]
+    crash_reports: bool,
+    /// Ship log messages to a syslog server over UDP, e.g. for the monitoring of permanently installed recorders.
+    /// Example: smrec --log-syslog logs.venue.lan:514
+    #[clap(long, value_parser = syslog::parse_address)]
+    log_syslog: Option<std::net::SocketAddr>,
+    /// When a take stops, compare the average level and the peak of every channel to a level in dBFS RMS, -18 unless given,
+    /// and suggest how to change the gains for the next take.
+    /// Example: smrec --gain-report -20
+    #[clap(long, num_args = 0..=1, default_missing_value = "-18", allow_negative_numbers = true)]
+    gain_report: Option<f32>,
+    /// Label new takes with the lines of a text file in order, one song or scene per line.
+    /// Example: smrec --osc --setlist setlist.txt
+    #[clap(long)]
+    setlist: Option<Utf8PathBuf>,
+    /// Stamp the files with this sample rate instead of the one they are recorded at, e.g. for tape transfers played back at another speed.
+    /// Example: smrec --playback-rate 44100
+    #[clap(long)]
+    playback_rate: Option<u32>,
+    /// Name of this recorder, prefixed to take names so recorders can share an output directory.
+    /// Example: smrec --instance stage-left
+    #[clap(long)]
+    instance: Option<String>,
+    #[clap(flatten)]
+    ixml: ixml::Ixml,
     /// Specify recording duration in seconds.
     /// Example: smrec --duration 10
     #[clap(long)]
     duration: Option<String>,
     /// Configure OSC control.
     /// Example: smrec --osc "0.0.0.0:18000;255.255.255.255:18001"
+    #[cfg(feature = "osc")]
     #[clap(long, value_delimiter = ';', num_args = 0..2, default_value = "EMPTY_HACK", hide_default_value = true)]
     osc: Vec<String>,
+    /// Also listen for OSC messages on this address, may be repeated, e.g. to accept local scripts on localhost only.
+    /// Example: smrec --osc "0.0.0.0:18000" --osc-listen 127.0.0.1:18002
+    #[cfg(feature = "osc")]
+    #[clap(long)]
+    osc_listen: Vec<std::net::SocketAddr>,
+    /// Print a QR code of the OSC address and the instance name for a controller app to connect with, enables OSC control.
+    /// Example: smrec --pair --instance stage-left
+    #[cfg(feature = "osc")]
+    #[clap(long)]
+    pair: bool,
     /// Configure MIDI control.
     /// Example: smrec --midi my first port[(1,2,3), (15, 127, 126), (12,4,5)], my second port[(1,2,3)]
+    #[cfg(feature = "midi")]
     #[clap(long, value_delimiter = ';', num_args = 0..2, default_value = "EMPTY_HACK", hide_default_value = true)]
     midi: Vec<String>,
+    /// Light LEDs on GPIO pins while recording, armed and after an error and start or stop with a button.
+    /// Example: smrec --gpio recording=17,error=27,armed=22,button=23
+    #[cfg(feature = "gpio")]
+    #[clap(long)]
+    gpio: Option<gpio::Pins>,
+    /// Show the transport, elapsed time, levels and disk space on a display connected over I2C.
+    /// Example: smrec --display hd44780-20x4
+    #[cfg(feature = "display")]
+    #[clap(long)]
+    display: Option<display::Spec>,
 
     #[clap(subcommand)]
     command: Option<Commands>,
@@ -110,12 +288,58 @@ enum Commands {
     /// Lists hosts, devices and configs.
     #[clap(about = "Lists hosts, devices and configs.")]
     List(List),
+    /// Plays a chirp through an output device and verifies it is recorded back.
+    #[clap(about = "Plays a chirp through an output device and verifies it is recorded back.")]
+    Selftest(Selftest),
+    /// Records a short clip of every recorded channel into `linecheck/` and prints their peaks.
+    #[clap(
+        about = "Records a short clip of every recorded channel into `linecheck/` and prints their peaks."
+    )]
+    Linecheck(Linecheck),
+    /// Manages recorded takes.
+    #[clap(about = "Manages recorded takes.")]
+    Takes(Takes),
+    /// Listens to all channels of a device and suggests stereo pairs of correlated channels.
+    #[clap(
+        about = "Listens to all channels of a device and suggests stereo pairs of correlated channels."
+    )]
+    Pairs(Pairs),
+    /// Plays the monitor stream another smrec sends with `--monitor`.
+    #[clap(about = "Plays the monitor stream another smrec sends with `--monitor`.")]
+    Listen(Listen),
+    /// Lists the `PipeWire` nodes which can be recorded with `--node`.
+    #[cfg(target_os = "linux")]
+    #[clap(about = "Lists the `PipeWire` nodes which can be recorded with `--node`.")]
+    Nodes,
+    /// Checks the configuration file.
+    #[clap(about = "Checks the configuration file.")]
+    Config(ConfigCommand),
+    /// Writes synthetic audio in real time to measure whether a disk keeps up with a session.
+    #[clap(
+        about = "Writes synthetic audio in real time to measure whether a disk keeps up with a session."
+    )]
+    Bench(Bench),
+    /// Packages takes into a `BagIt` bag with checksums and metadata for the ingest of archives.
+    #[clap(
+        about = "Packages takes into a BagIt bag with checksums and metadata for the ingest of archives."
+    )]
+    Export(Export),
+    /// Replaces this binary with the latest signed release.
+    #[cfg(feature = "update")]
+    #[clap(about = "Replaces this binary with the latest signed release.")]
+    SelfUpdate {
+        /// Only tell whether there is a newer release.
+        /// Example: smrec self-update --check
+        #[clap(long)]
+        check: bool,
+    },
 }
 
 #[derive(Parser)]
 struct List {
     /// List MIDI configurations.
     /// Example: smrec list --midi
+    #[cfg(feature = "midi")]
     #[clap(long)]
     midi: bool,
     /// List audio configurations.
@@ -124,18 +348,201 @@ struct List {
     audio: bool,
 }
 
-pub type WriterHandle = Arc<Mutex<Option<WavWriter<BufWriter<File>>>>>;
-pub type WriterHandles = Arc<Vec<WriterHandle>>;
+#[derive(Parser)]
+struct Listen {
+    /// Address to receive the monitor stream on.
+    /// Example: `smrec listen udp://0.0.0.0:18100`
+    url: String,
+    /// Specify the output device to play the monitor stream through.
+    /// Example: `smrec listen udp://0.0.0.0:18100 --out "Headphones"`
+    #[clap(long = "out")]
+    output: Option<String>,
+}
+
+#[derive(Parser)]
+struct Selftest {
+    /// Specify the input device to record the chirp from.
+    /// Example: smrec selftest --in "XR18"
+    #[clap(long = "in")]
+    input: Option<String>,
+    /// Specify the output device to play the chirp through.
+    /// Example: smrec selftest --out "XR18"
+    #[clap(long = "out")]
+    output: Option<String>,
+}
+
+#[derive(Parser)]
+struct Pairs {
+    /// How many seconds to listen for.
+    /// Example: smrec pairs --seconds 10
+    #[clap(long, default_value_t = 5)]
+    seconds: u64,
+    /// Correlation from 0 to 1 which channels need to reach to be suggested as a pair.
+    /// Example: smrec pairs --threshold 0.9
+    #[clap(long, default_value_t = 0.8)]
+    threshold: f64,
+}
+
+#[derive(Parser)]
+struct Linecheck {
+    /// How many seconds to record for.
+    /// Example: smrec linecheck --seconds 5
+    #[clap(long, default_value_t = 5)]
+    seconds: u64,
+}
+
+#[derive(Parser)]
+struct Bench {
+    /// How many channels to write.
+    /// Example: smrec bench --channels 32
+    #[clap(long, default_value_t = 32)]
+    channels: usize,
+    /// Sample rate to write at.
+    /// Example: smrec bench --rate 96000
+    #[clap(long, default_value_t = 48000)]
+    rate: u32,
+    /// How many seconds to write for.
+    /// Example: smrec bench --seconds 30
+    #[clap(long, default_value_t = 30)]
+    seconds: u64,
+    /// Directory on the disk to measure, the files are removed afterwards.
+    /// Example: smrec bench --out /mnt/disk
+    #[clap(long, default_value = ".")]
+    out: String,
+}
+
+#[derive(Parser)]
+struct Export {
+    /// Directory to write the bag into, it needs to be new or empty.
+    /// Example: `smrec export --bagit ./bags/interview_03`
+    #[clap(long)]
+    bagit: Utf8PathBuf,
+    /// Names of the take directories in the output directory or paths to them, all takes when none are given.
+    /// Example: `smrec export --bagit ./bag rec_20231120_120000 rec_20231120_130000`
+    takes: Vec<String>,
+    /// More lines of `bag-info.txt`, may be repeated.
+    /// Example: `smrec export --bagit ./bag --info "Source-Organization: City Archive"`
+    #[clap(long)]
+    info: Vec<String>,
+}
+
+#[derive(Parser)]
+struct ConfigCommand {
+    #[clap(subcommand)]
+    command: ConfigCommands,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Reports unknown keys, invalid values and channels the device does not have.
+    #[clap(about = "Reports unknown keys, invalid values and channels the device does not have.")]
+    Validate,
+    /// Writes a commented configuration with an entry for every channel of the device.
+    #[clap(
+        about = "Writes a commented configuration with an entry for every channel of the device."
+    )]
+    Init {
+        /// Specify the input device to write the configuration for, may be repeated.
+        /// Example: smrec config init --device "XR18"
+        #[clap(long)]
+        device: Vec<String>,
+        /// Specify where to write the configuration, `./.smrec/config.toml` by default.
+        /// Example: smrec config init --out ./live.toml
+        #[clap(long)]
+        out: Option<String>,
+    },
+}
+
+#[derive(Parser)]
+struct Takes {
+    #[clap(subcommand)]
+    command: TakesCommands,
+}
+
+#[derive(Subcommand)]
+enum TakesCommands {
+    /// Marks a take read-only and exempts it from rotation and retention.
+    #[clap(about = "Marks a take read-only and exempts it from rotation and retention.")]
+    Lock {
+        /// Name of the take directory in the output directory, e.g. `rec_20231120_120000`, or a path to it.
+        name: String,
+    },
+    /// Writes a sound report of the takes in the output directory.
+    #[clap(about = "Writes a sound report of the takes in the output directory.")]
+    Report {
+        /// Also write the report as a PDF.
+        /// Example: smrec takes report --pdf
+        #[clap(long)]
+        pdf: bool,
+    },
+    /// Compares the length, channels, loudness and checksums of the files of two takes.
+    #[clap(
+        about = "Compares the length, channels, loudness and checksums of the files of two takes."
+    )]
+    Diff {
+        /// Name of the first take directory in the output directory, or a path to it.
+        a: String,
+        /// Name of the second take directory in the output directory, or a path to it.
+        b: String,
+    },
+}
+
+fn main() {
+    let result = run();
+    takes::wait_for_measurements();
+    if let Err(err) = result {
+        eprintln!("Error: {err:?}");
+        syslog::log(
+            syslog::Severity::Error,
+            "exit",
+            &[
+                ("code", &Code::of(&err).code().to_string()),
+                ("subsystem", Code::of(&err).subsystem()),
+            ],
+            &format!("{err:#}"),
+        );
+        // Scripts can tell failures apart by the same codes OSC clients receive.
+        std::process::exit(Code::of(&err).code());
+    }
+}
 
 #[allow(clippy::too_many_lines)]
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+fn run() -> Result<()> {
+    #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+    let mut cli = Cli::parse();
+    console::set_verbose(cli.verbose);
+    // Read before the process title is written over it.
+    title::command_line();
 
+    #[cfg(windows)]
+    let loopback = cli.loopback;
+    #[cfg(not(windows))]
+    let loopback = false;
+
+    #[cfg(target_os = "linux")]
+    if let Some(node) = &cli.node {
+        pipewire::target(node);
+        if cli.device.is_empty() {
+            cli.device.push(pipewire::ALSA_DEVICE.to_string());
+        }
+    }
+
+    if let Some(to) = cli.log_syslog {
+        syslog::start(to)?;
+    }
+    threads::configure(threads::Tuning {
+        priority: cli.realtime_priority,
+        audio_cores: cli.audio_cores,
+        writer_cores: cli.writer_cores,
+    })?;
+
+    let mock = cli.host.as_deref() == Some("mock");
     let host = choose_host(cli.host)?;
 
     if let Some(command) = cli.command {
         match command {
             // Enumerate and exit.
+            #[cfg(feature = "midi")]
             Commands::List(list) => {
                 if list.midi {
                     list::enumerate_midi()?;
@@ -149,147 +556,929 @@ fn main() -> Result<()> {
                     list::enumerate_midi()?;
                 }
             }
+            // There is only audio to list without MIDI.
+            #[cfg(not(feature = "midi"))]
+            Commands::List(_) => list::enumerate_audio()?,
+            Commands::Selftest(selftest) => {
+                let input = choose_device(&host, selftest.input)?;
+                let output = choose_output_device(&host, selftest.output)?;
+                selftest::run(&input, &output)?;
+            }
+            Commands::Linecheck(linecheck) => {
+                let inputs =
+                    open_inputs(&host, mock, cli.device, loopback, cli.source, cli.resample)
+                        .context(Code::Device)?;
+                let out =
+                    Utf8PathBuf::from(cli.out.as_deref().unwrap_or(".")).join(linecheck::DIRECTORY);
+                let mut smrec_config = SmrecConfig::new(
+                    cli.config,
+                    Some(out.to_string()),
+                    cli.include,
+                    cli.exclude,
+                    device_channel_names(&host, &inputs),
+                    stream::Input::combined_config(&inputs),
+                )
+                .context(Code::Config)?;
+                smrec_config.set_timestamp(cli.timestamp);
+                smrec_config
+                    .set_instance(cli.instance)
+                    .context(Code::Config)?;
+                smrec_config.set_ixml(cli.ixml);
+                smrec_config.fit_open_files().context(Code::Storage)?;
+                run_linecheck(&inputs, &mut smrec_config, linecheck.seconds)
+                    .context(Code::Recording)?;
+            }
+            #[cfg(target_os = "linux")]
+            Commands::Nodes => pipewire::enumerate()?,
+            Commands::Listen(listen) => {
+                let output = choose_output_device(&host, listen.output)?;
+                monitor::listen(&listen.url, &output)?;
+            }
+            Commands::Bench(bench) => {
+                bench::run(
+                    &Utf8PathBuf::from(bench.out),
+                    bench.channels,
+                    bench.rate,
+                    bench.seconds,
+                )?;
+            }
+            Commands::Pairs(pairs) => {
+                let device = choose_device(&host, cli.device.into_iter().next())?;
+                pairing::run(&device, pairs.seconds, pairs.threshold)?;
+            }
+            Commands::Config(config) => match config.command {
+                ConfigCommands::Validate => {
+                    // Channel numbers are checked when the devices can be opened.
+                    let channels =
+                        open_inputs(&host, mock, cli.device, loopback, cli.source, cli.resample)
+                            .ok()
+                            .map(|inputs| {
+                                stream::Input::combined_config(&inputs).channels() as usize
+                            });
+                    config::validate(&SmrecConfig::path(cli.config)?, channels)?;
+                }
+                ConfigCommands::Init { device, out } => {
+                    let inputs =
+                        open_inputs(&host, mock, device, loopback, Vec::new(), cli.resample)?;
+                    let names = inputs.iter().map(stream::Input::name).collect::<Vec<_>>();
+                    let path = Utf8PathBuf::from(out.as_deref().unwrap_or("./.smrec/config.toml"));
+                    config::init(
+                        &path,
+                        &config::starter(&names, &device_channel_names(&host, &inputs)),
+                    )?;
+                    println!("Wrote a configuration for {} to {path}.", names.join(", "));
+                }
+            },
+            Commands::Takes(takes) => match takes.command {
+                TakesCommands::Lock { name } => {
+                    let base = Utf8PathBuf::from(cli.out.as_deref().unwrap_or("."));
+                    takes::lock(&takes::resolve(&base, &name)?)?;
+                }
+                TakesCommands::Report { pdf } => {
+                    report::run(&Utf8PathBuf::from(cli.out.as_deref().unwrap_or(".")), pdf)?;
+                }
+                TakesCommands::Diff { a, b } => {
+                    let base = Utf8PathBuf::from(cli.out.as_deref().unwrap_or("."));
+                    let matching = diff::run(
+                        &takes::resolve(&base, &a).context(Code::Takes)?,
+                        &takes::resolve(&base, &b).context(Code::Takes)?,
+                    )
+                    .context(Code::Takes)?;
+                    if !matching {
+                        // Lets scripts check mirrored recordings, apart from takes which could not be compared.
+                        std::process::exit(Code::TakesDiffer.code());
+                    }
+                }
+            },
+            Commands::Export(export) => {
+                let base = Utf8PathBuf::from(cli.out.as_deref().unwrap_or("."));
+                let take_dirs = if export.takes.is_empty() {
+                    takes::all(&base)?
+                } else {
+                    export
+                        .takes
+                        .iter()
+                        .map(|name| takes::resolve(&base, name))
+                        .collect::<Result<Vec<_>>>()?
+                };
+                bagit::export(&take_dirs, &export.bagit, &export.info)?;
+            }
+            #[cfg(feature = "update")]
+            Commands::SelfUpdate { check } => update::run(check)?,
         };
         return Ok(());
     }
 
-    let device = choose_device(&host, cli.device)?;
-    let writers_container: Arc<Mutex<Option<WriterHandles>>> = Arc::new(Mutex::new(None));
-    let stream_container: Rc<RefCell<Option<cpal::Stream>>> = Rc::new(RefCell::new(None));
+    let mut inputs = open_inputs(&host, mock, cli.device, loopback, cli.source, cli.resample)
+        .context(Code::Device)?;
+    wav::check_narrowing(
+        stream::Input::combined_config(&inputs).sample_format(),
+        cli.strict,
+    )
+    .context(Code::Device)?;
+    if cli.monitor.is_some() && inputs.len() > 1 {
+        println!(
+            "Warning: --monitor streams only the channels of the first device which records any."
+        );
+    }
+    let writers_container: Arc<Mutex<Option<Take>>> = Arc::new(Mutex::new(None));
+    let stream_container: Rc<RefCell<Vec<stream::Stream>>> = Rc::new(RefCell::new(Vec::new()));
 
-    if let Ok(config) = device.default_input_config() {
-        let smrec_config = Arc::new(SmrecConfig::new(
-            cli.config,
-            cli.out,
-            choose_channels_to_record(cli.include, cli.exclude, &config)?,
-            config.clone(),
-        )?);
+    let config_path = SmrecConfig::path(cli.config.clone()).context(Code::Config)?;
+    let state_path = config_path.with_file_name(state::STATE_FILE);
+    let mut smrec_config = SmrecConfig::new(
+        cli.config,
+        cli.out,
+        cli.include,
+        cli.exclude,
+        device_channel_names(&host, &inputs),
+        stream::Input::combined_config(&inputs),
+    )
+    .context(Code::Config)?;
+    smrec_config.set_append(cli.append);
+    smrec_config.set_timestamp(cli.timestamp);
+    smrec_config.set_start_on_transient(cli.start_on_transient);
+    smrec_config.set_safety(cli.safety);
+    smrec_config.set_session_timeline(cli.session_timeline);
+    smrec_config.set_ram_buffer(cli.ram_buffer);
+    smrec_config.set_monitor(cli.monitor);
+    smrec_config.set_restart_on_rate_change(cli.restart_on_rate_change);
+    smrec_config.set_inject(cli.inject);
+    smrec_config
+        .set_instance(cli.instance)
+        .context(Code::Config)?;
+    smrec_config.set_ixml(cli.ixml);
 
-        let (to_main_thread, from_listener_thread) = crossbeam::channel::unbounded::<Action>();
-        let (to_listener_thread, from_main_thread) = crossbeam::channel::unbounded::<Action>();
+    if cli.crash_reports {
+        crash::enable(
+            config_path.with_file_name("crash"),
+            crash::snapshot(&config_path, &smrec_config.dump()?),
+            writers_container.clone(),
+        );
+    }
 
-        let cli_osc = if cli.osc == vec!["EMPTY_HACK"] {
-            None
-        } else if cli.osc.is_empty() {
-            Some(vec![])
-        } else {
-            Some(cli.osc)
-        };
+    // Lives as long as the session.
+    let mut taps = tap::Taps::default();
+    let _guard = cli
+        .guard_track
+        .map(|channel| {
+            guard::Guard::start(&mut taps, &inputs, channel, smrec_config.guard_path_stem()?)
+        })
+        .transpose()
+        .context(Code::Storage)?;
+    for (channel, path) in smrec_config.pipes() {
+        pipe::start(&mut taps, &inputs, channel, path).context(Code::Storage)?;
+    }
+    smrec_config.set_taps(taps);
+    tap_until_first_take(&inputs, &writers_container, &smrec_config).context(Code::Recording)?;
 
-        let cli_midi = if cli.midi == vec!["EMPTY_HACK"] {
-            None
-        } else if cli.midi.is_empty() {
-            Some(vec![])
-        } else {
-            Some(cli.midi)
-        };
+    let previous = state::open(
+        state_path,
+        smrec_config
+            .channels_to_record()
+            .iter()
+            .map(|channel| channel + 1)
+            .collect(),
+    );
 
-        let osc = if let Some(osc_config) = cli_osc {
-            if osc_config.len() > 2 {
-                bail!("Too many arguments for --osc");
-            }
-            let mut osc = Osc::new(
-                &osc_config,
-                to_main_thread.clone(),
-                from_main_thread.clone(),
-            )?;
-            osc.listen();
-            Some(osc)
-        } else {
-            None
-        };
+    let (to_main_thread, from_listener_thread) = crossbeam::channel::unbounded::<Action>();
+    smrec_config.set_to_main_thread(to_main_thread.clone());
+    // The main thread finalizes the take with the configuration as it is when Ctrl-C is pressed.
+    let to_main_thread_in_ctrlc = to_main_thread.clone();
+    ctrlc::set_handler(move || {
+        to_main_thread_in_ctrlc.send(Action::Quit).ok();
+    })?;
+    let (to_listener_thread, from_main_thread) = crossbeam::channel::unbounded::<Action>();
 
-        let midi = if let Some(midi) = cli_midi {
-            let mut midi = Midi::new(to_main_thread, from_main_thread, &midi)?;
-            midi.listen()?;
-            Some(midi)
-        } else {
-            None
-        };
+    // Lives as long as the session.
+    let mut surfaces = control::Surfaces::default();
+    #[cfg(feature = "osc")]
+    if let Some(osc) =
+        osc_surface(cli.osc, &cli.osc_listen, cli.pair, &smrec_config).context(Code::Osc)?
+    {
+        if cli.pair {
+            qr::print(&osc.listen_addrs(), smrec_config.instance()).context(Code::Osc)?;
+        }
+        surfaces.add(osc);
+    }
+    #[cfg(feature = "midi")]
+    if let Some(midi) = midi_surface(cli.midi, &smrec_config).context(Code::Midi)? {
+        surfaces.add(midi);
+    }
+    #[cfg(feature = "gpio")]
+    if let Some(pins) = cli.gpio {
+        surfaces.add(gpio::Gpio::new(pins).context(Code::Gpio)?);
+    }
+    #[cfg(feature = "display")]
+    if let Some(spec) = &cli.display {
+        surfaces.add(
+            display::Display::new(
+                spec,
+                smrec_config.processing().clone(),
+                smrec_config.channels_to_record().to_vec(),
+            )
+            .context(Code::Display)?,
+        );
+    }
+    if let Some(failover) = smrec_config.failover() {
+        surfaces.add(failover::Failover::new(failover).context(Code::Failover)?);
+    }
+    surfaces.start(&to_main_thread, from_main_thread)?;
 
-        match (midi, osc) {
-            (None, None) => {
-                // Pass
-            }
-            _ => listen_and_block_main_thread(
-                &from_listener_thread,
-                &to_listener_thread,
-                &device,
-                &stream_container,
-                &writers_container,
-                &smrec_config,
-            ),
+    // Recording windows from the configuration, acted on like any other control input.
+    let scheduled = !smrec_config.schedule().is_empty();
+    if scheduled {
+        smrec_config
+            .schedule()
+            .clone()
+            .spawn(to_main_thread.clone());
+    }
+
+    if let Some(previous) = previous {
+        if let (Transport::Recording, Some(take)) = (previous.transport, &previous.take) {
+            let take = Utf8Path::new(take).file_name().unwrap_or(take).to_string();
+            println!("Warning: the previous run was interrupted while recording {take}.");
+            // Nobody hears it without listeners.
+            to_listener_thread.send(Action::Interrupted(take)).ok();
         }
+        // Picks up where the previous run was, a listener starts recording like it was told to.
+        if cli.resume && previous.transport != Transport::Stopped {
+            to_main_thread
+                .send(Action::Arm)
+                .expect("Internal thread error.");
+            if previous.transport == Transport::Recording {
+                to_main_thread
+                    .send(Action::Start)
+                    .expect("Internal thread error.");
+            }
+        }
+    }
 
-        // No listeners, just start recording, for ever or for a certain duration.
+    smrec_config.set_split_on_silence(cli.split_on_silence);
+    smrec_config.set_id_in_file_names(cli.id_in_file_names);
+    smrec_config.set_transcribe(cli.transcribe);
+    smrec_config.set_gain_target(cli.gain_report);
+    smrec_config.set_ram_buffer_policy(cli.ram_buffer_policy);
+    smrec_config.fit_open_files().context(Code::Storage)?;
+    if let Some(path) = &cli.setlist {
+        smrec_config.set_setlist(Some(setlist::Setlist::load(path).context(Code::Config)?));
+    }
+    smrec_config
+        .set_playback_rate(cli.playback_rate)
+        .context(Code::Config)?;
+    if cli.verify {
+        // The files are not on the disk before the take ends with a RAM buffer, there is nothing to read back.
+        if cli.ram_buffer.is_some() {
+            return Err(anyhow!(
+                "--verify reads back what is written while recording, with --ram-buffer nothing is written then."
+            ))
+            .context(Code::Config);
+        }
+        smrec_config.set_verifier(verify::start(to_listener_thread.clone()));
+    }
 
-        new_recording(
-            &device,
+    // Splits takes before the battery dies and finalizes them before the system sleeps.
+    power::watch(to_main_thread);
+    space::watch(writers_container.clone(), to_listener_thread.clone());
+    smrec_config.watch_meters(&writers_container);
+
+    if !surfaces.is_empty() || scheduled {
+        // One press reaches the recorder twice when it is wired to both.
+        let dedupe = if surfaces.len() > 1 {
+            smrec_config.dedupe_window().map(dedupe::Dedupe::new)
+        } else {
+            None
+        };
+        listen_and_block_main_thread(
+            dedupe,
+            &from_listener_thread,
+            &to_listener_thread,
+            &mut inputs,
             &stream_container,
             &writers_container,
-            &smrec_config,
-        )?;
+            &mut smrec_config,
+        );
+    }
 
-        cli.duration.map_or_else(
-            || {
-                std::thread::park();
-            },
-            |dur| {
-                let secs = dur
-                    .parse::<u64>()
-                    .expect("--duration must be a positive integer.");
-                std::thread::park_timeout(std::time::Duration::from_secs(secs));
-            },
+    // No listeners, just start recording, for ever or for a certain duration.
+
+    new_recording(
+        &inputs,
+        &stream_container,
+        &writers_container,
+        &mut smrec_config,
+        None,
+    )
+    .context(Code::Recording)?;
+
+    let deadline = cli.duration.map(|dur| {
+        let secs = dur
+            .parse::<u64>()
+            .expect("--duration must be a positive integer.");
+        Instant::now() + Duration::from_secs(secs)
+    });
+
+    // Wait for the duration to pass, only splitting the take when asked to.
+    loop {
+        let action = deadline.map_or_else(
+            || from_listener_thread.recv().map_err(|_| ()),
+            |deadline| from_listener_thread.recv_deadline(deadline).map_err(|_| ()),
         );
+        match action {
+            Ok(Action::Quit) => quit(&stream_container, &writers_container, &smrec_config),
+            Ok(Action::Sleep) => {
+                finalize_for_sleep(&stream_container, &writers_container, &smrec_config);
+            }
+            Ok(Action::Split | Action::Wake) => {
+                if let Err(err) = new_recording(
+                    &inputs,
+                    &stream_container,
+                    &writers_container,
+                    &mut smrec_config,
+                    None,
+                ) {
+                    println!("Error splitting recording: {err}");
+                }
+            }
+            Ok(Action::SampleRateChanged(measured))
+                if stream_container.borrow().is_empty()
+                    || !rate_change_confirmed(measured, &inputs) => {}
+            Ok(Action::SampleRateChanged(measured)) => {
+                match sample_rate_changed(
+                    measured,
+                    &mut inputs,
+                    &stream_container,
+                    &writers_container,
+                    &mut smrec_config,
+                ) {
+                    Ok(true) => {}
+                    Ok(false) => return Ok(()),
+                    Err(err) => {
+                        println!("Error restarting recording: {err}");
+                        return Ok(());
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(()) => break,
+        }
+    }
+
+    stop_recording(&stream_container, &writers_container, &smrec_config)
+        .context(Code::Recording)?;
+    println!("Recording complete!");
 
-        stop_recording(&stream_container, &writers_container)?;
-        println!("Recording complete!");
+    Ok(())
+}
+
+/// Opens the input devices by name, or the default input device when there are none, and the applications after them.
+///
+/// With `--host mock` the devices are generated ones.
+#[cfg_attr(not(feature = "mock"), allow(unused_variables))]
+fn open_inputs(
+    host: &cpal::Host,
+    mock: bool,
+    devices: Vec<String>,
+    loopback: bool,
+    apps: Vec<String>,
+    resample: Option<resample::Quality>,
+) -> Result<Vec<stream::Input>> {
+    #[cfg(feature = "mock")]
+    if mock {
+        return mock::inputs(&devices, resample);
+    }
+    let devices = if devices.is_empty() {
+        vec![choose_device(host, None)?]
+    } else {
+        devices
+            .into_iter()
+            .map(|device| choose_device(host, Some(device)))
+            .collect::<Result<Vec<_>>>()?
+    };
+    let loopback = if loopback {
+        vec![choose_output_device(host, None)?]
     } else {
-        bail!("No default input config found for device.");
+        Vec::new()
+    };
+    stream::Input::open(devices, loopback, apps, resample)
+}
+
+/// Channel names reported by the devices, numbered across all of them.
+fn device_channel_names(host: &cpal::Host, inputs: &[stream::Input]) -> Vec<String> {
+    inputs
+        .iter()
+        .flat_map(|input| {
+            let mut names = match &input.source {
+                stream::Source::Device(_) if input.loopback => {
+                    vec!["Loopback L".to_string(), "Loopback R".to_string()]
+                }
+                stream::Source::Device(device) => device_names::input_channel_names(
+                    host,
+                    device,
+                    input.config.channels() as usize,
+                )
+                .unwrap_or_default(),
+                stream::Source::App(app) => vec![format!("{app} L"), format!("{app} R")],
+                #[cfg(feature = "mock")]
+                stream::Source::Mock(_) => Vec::new(),
+            };
+            // Keeps the names of the next device at its channels.
+            names.resize(input.config.channels() as usize, String::new());
+            names
+        })
+        .collect()
+}
+
+#[cfg(feature = "osc")]
+fn osc_surface(
+    cli_osc: Vec<String>,
+    cli_osc_listen: &[std::net::SocketAddr],
+    pair: bool,
+    smrec_config: &SmrecConfig,
+) -> Result<Option<Osc>> {
+    let osc_config = if cli_osc == vec!["EMPTY_HACK"] {
+        // Additional listen addresses and pairing also enable OSC control.
+        if cli_osc_listen.is_empty() && !pair {
+            return Ok(None);
+        }
+        vec![]
+    } else if cli_osc.is_empty() {
+        vec![]
+    } else {
+        cli_osc
+    };
+
+    if osc_config.len() > 2 {
+        anyhow::bail!("Too many arguments for --osc");
     }
+    Ok(Some(Osc::new(
+        &osc_config,
+        cli_osc_listen,
+        smrec_config.osc_feedback(),
+        smrec_config.processing().clone(),
+    )?))
+}
 
-    Ok(())
+#[cfg(feature = "midi")]
+fn midi_surface(cli_midi: Vec<String>, smrec_config: &SmrecConfig) -> Result<Option<Midi>> {
+    let midi_config = if cli_midi == vec!["EMPTY_HACK"] {
+        return Ok(None);
+    } else if cli_midi.is_empty() {
+        vec![]
+    } else {
+        cli_midi
+    };
+
+    Ok(Some(Midi::new(
+        &midi_config,
+        smrec_config.midi_arming(),
+        smrec_config.midi_monitor().clone(),
+    )?))
 }
 
+#[allow(clippy::too_many_lines)]
 pub fn listen_and_block_main_thread(
+    mut dedupe: Option<dedupe::Dedupe>,
     from_listener_thread: &crossbeam::channel::Receiver<Action>,
     to_listener_thread: &crossbeam::channel::Sender<Action>,
-    device: &cpal::Device,
-    stream_container: &Rc<RefCell<Option<cpal::Stream>>>,
-    writers_container: &Arc<Mutex<Option<WriterHandles>>>,
-    smrec_config: &SmrecConfig,
+    inputs: &mut [stream::Input],
+    stream_container: &Rc<RefCell<Vec<stream::Stream>>>,
+    writers_container: &Arc<Mutex<Option<Take>>>,
+    smrec_config: &mut SmrecConfig,
 ) {
+    // Recordings can only be started after arming, so a stray message can not start one.
+    let mut transport = Transport::Stopped;
+    let mut saved = transport;
+    // Whether a take was finalized for sleep and goes on in a new one after the system wakes up.
+    let mut resume = false;
     loop {
-        match from_listener_thread.recv() {
-            Ok(Action::Start) => {
-                if let Err(err) =
-                    new_recording(device, stream_container, writers_container, smrec_config)
-                {
+        if transport != saved {
+            state::transport(transport);
+            saved = transport;
+        }
+        let received = from_listener_thread.recv();
+        if let (Ok(action), Some(dedupe)) = (&received, dedupe.as_mut()) {
+            if dedupe.is_repeat(action, Instant::now()) {
+                println!("Ignoring a repeated start or stop, MIDI and OSC may both have sent it.");
+                continue;
+            }
+        }
+        let received = match received {
+            Ok(Action::Wake) if std::mem::take(&mut resume) => Ok(Action::Start),
+            received => received,
+        };
+        match received {
+            Ok(Action::Quit) => quit(stream_container, writers_container, smrec_config),
+            Ok(Action::Sleep) => {
+                if finalize_for_sleep(stream_container, writers_container, smrec_config) {
+                    transport = Transport::Armed;
+                    resume = true;
+                    to_listener_thread
+                        .send(Action::Stop)
+                        .expect("Internal thread error.");
+                }
+            }
+            Ok(action @ (Action::Start | Action::Split)) => {
+                // Splitting only applies to a running recording.
+                if matches!(action, Action::Split) && stream_container.borrow().is_empty() {
+                    continue;
+                }
+                if transport == Transport::Stopped {
+                    println!("Not armed, arm before starting a recording.");
+                    to_listener_thread
+                        .send(Action::Err(
+                            Code::NotArmed,
+                            "Not armed, arm before starting a recording.".to_string(),
+                        ))
+                        .expect("Internal thread error.");
+                    continue;
+                }
+                if let Err(err) = new_recording(
+                    inputs,
+                    stream_container,
+                    writers_container,
+                    smrec_config,
+                    Some(to_listener_thread),
+                ) {
                     println!("Error starting recording: {err}");
 
                     to_listener_thread
-                        .send(Action::Err(format!("Error starting recording: {err}")))
+                        .send(Action::Err(
+                            Code::Recording,
+                            format!("Error starting recording: {err}"),
+                        ))
                         .expect("Internal thread error.");
                 } else {
+                    transport = Transport::Recording;
+                    take_started(smrec_config, writers_container);
                     to_listener_thread
                         .send(Action::Start)
                         .expect("Internal thread error.");
                 }
             }
             Ok(Action::Stop) => {
-                if let Err(err) = stop_recording(stream_container, writers_container) {
+                if let Err(err) = stop_recording(stream_container, writers_container, smrec_config)
+                {
                     println!("Error stopping recording: {err}");
                     to_listener_thread
-                        .send(Action::Err(format!("Error starting recording: {err}")))
+                        .send(Action::Err(
+                            Code::Recording,
+                            format!("Error starting recording: {err}"),
+                        ))
                         .expect("Internal thread error.");
                 } else {
+                    // Stays ready for the next take.
+                    if transport == Transport::Recording {
+                        transport = Transport::Armed;
+                    }
                     to_listener_thread
                         .send(Action::Stop)
                         .expect("Internal thread error.");
                 }
             }
+            Ok(Action::Arm) => {
+                if transport == Transport::Stopped {
+                    transport = Transport::Armed;
+                    println!("Armed.");
+                    smrec_config.run_hook("arm");
+                }
+                to_listener_thread
+                    .send(Action::Arm)
+                    .expect("Internal thread error.");
+            }
+            Ok(Action::Disarm) => {
+                if transport == Transport::Recording {
+                    println!("Can not disarm while recording, stop the recording first.");
+                    to_listener_thread
+                        .send(Action::Err(
+                            Code::Recording,
+                            "Can not disarm while recording, stop the recording first.".to_string(),
+                        ))
+                        .expect("Internal thread error.");
+                } else {
+                    if transport == Transport::Armed {
+                        smrec_config.run_hook("disarm");
+                    }
+                    transport = Transport::Stopped;
+                    println!("Disarmed.");
+                    to_listener_thread
+                        .send(Action::Disarm)
+                        .expect("Internal thread error.");
+                }
+            }
+            Ok(Action::Marker(label)) => {
+                if let Some(take) = writers_container.lock().unwrap().as_mut() {
+                    let frame = take.mark(label);
+                    println!("Marker {} at frame {frame}.", take.markers.len());
+                } else {
+                    println!("There is no running recording to mark.");
+                }
+            }
+            Ok(Action::Cue(cue)) => match smrec_config.cue(cue) {
+                Some(label) => {
+                    if let Some(take) = writers_container.lock().unwrap().as_mut() {
+                        let frame = take.mark(Some(label.to_string()));
+                        println!("Cue {cue} ({label}) at frame {frame}.");
+                    } else {
+                        println!("There is no running recording to mark with cue {cue}.");
+                    }
+                }
+                None => println!("There is no cue {cue}, add it to [cues] in the configuration."),
+            },
+            Ok(Action::NextTakeName(name)) => {
+                println!("The next take will be labelled {name}.");
+                smrec_config.set_next_take_name(Some(name));
+            }
+            #[cfg(feature = "midi")]
+            Ok(Action::SongSelect(song)) => {
+                let title = smrec_config.song_title(song);
+                println!("Song {song} selected, the next take will be labelled {title}.");
+                smrec_config.set_next_take_name(Some(title));
+            }
+            #[cfg(not(feature = "midi"))]
+            Ok(Action::SongSelect(_)) => {}
+            Ok(action @ (Action::SetlistNext | Action::SetlistPrev)) => {
+                if let Some(setlist) = smrec_config.setlist_mut() {
+                    if matches!(action, Action::SetlistNext) {
+                        setlist.next();
+                    } else {
+                        setlist.prev();
+                    }
+                    println!("The setlist is at {}.", setlist.describe());
+                } else {
+                    println!("There is no setlist, give one with --setlist.");
+                }
+            }
+            Ok(Action::Beat(at)) => {
+                let at = smrec_config.clock().at(at);
+                smrec_config.processing().beat_clock().set(at);
+            }
+            Ok(Action::SetOut(path)) => {
+                if let Err(err) = smrec_config.set_out(path) {
+                    println!("Error setting the output directory: {err}");
+                    to_listener_thread
+                        .send(Action::Err(
+                            Code::Config,
+                            format!("Error setting the output directory: {err}"),
+                        ))
+                        .expect("Internal thread error.");
+                } else {
+                    println!("Output directory changed, it applies from the next take on.");
+                }
+            }
+            Ok(Action::PlaybackRate(rate)) => {
+                if let Err(err) = smrec_config.set_playback_rate(rate) {
+                    println!("Error setting the playback rate: {err}");
+                    to_listener_thread
+                        .send(Action::Err(
+                            Code::Config,
+                            format!("Error setting the playback rate: {err}"),
+                        ))
+                        .expect("Internal thread error.");
+                } else if let Some(rate) = rate {
+                    println!("Files are stamped with {rate} Hz from the next take on.");
+                } else {
+                    println!("Files are stamped with the recorded rate from the next take on.");
+                }
+            }
+            Ok(Action::IncludeChannel(channel)) => {
+                if let Err(err) = smrec_config.include_channel(channel) {
+                    println!("Error including channel {}: {err}", channel + 1);
+                    to_listener_thread
+                        .send(Action::Err(
+                            Code::Channel,
+                            format!("Error including channel {}: {err}", channel + 1),
+                        ))
+                        .expect("Internal thread error.");
+                } else {
+                    println!(
+                        "Channel {} is included, it applies from the next take on.",
+                        channel + 1
+                    );
+                }
+            }
+            Ok(Action::ExcludeChannel(channel)) => {
+                if let Err(err) = smrec_config.exclude_channel(channel) {
+                    println!("Error excluding channel {}: {err}", channel + 1);
+                    to_listener_thread
+                        .send(Action::Err(
+                            Code::Channel,
+                            format!("Error excluding channel {}: {err}", channel + 1),
+                        ))
+                        .expect("Internal thread error.");
+                } else {
+                    println!(
+                        "Channel {} is excluded, it applies from the next take on.",
+                        channel + 1
+                    );
+                }
+            }
+            Ok(Action::LockLastTake) => match lock_last_take(smrec_config) {
+                Ok(take) => {
+                    to_listener_thread
+                        .send(Action::TakeLocked(take))
+                        .expect("Internal thread error.");
+                }
+                Err(err) => {
+                    println!("Error locking take: {err}");
+                    to_listener_thread
+                        .send(Action::Err(
+                            Code::Takes,
+                            format!("Error locking take: {err}"),
+                        ))
+                        .expect("Internal thread error.");
+                }
+            },
+            Ok(Action::DumpConfig) => match smrec_config.dump() {
+                Ok(dump) => {
+                    to_listener_thread
+                        .send(Action::ConfigDump(dump))
+                        .expect("Internal thread error.");
+                }
+                Err(err) => {
+                    println!("Error dumping configuration: {err}");
+                    to_listener_thread
+                        .send(Action::Err(
+                            Code::Config,
+                            format!("Error dumping configuration: {err}"),
+                        ))
+                        .expect("Internal thread error.");
+                }
+            },
+            Ok(Action::Status) => {
+                to_listener_thread
+                    .send(Action::StatusReport(
+                        transport,
+                        smrec_config.processing().meters().held(),
+                    ))
+                    .expect("Internal thread error.");
+                let pressure = writers_container
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .and_then(|take| take.budget.as_ref().map(|budget| budget.pressure()));
+                if let Some(pressure) = pressure {
+                    to_listener_thread
+                        .send(Action::RamBuffer(pressure))
+                        .expect("Internal thread error.");
+                }
+            }
+            Ok(Action::RestoreConfig(dump)) => {
+                if let Err(err) = smrec_config.restore(&dump) {
+                    println!("Error restoring configuration: {err}");
+                    to_listener_thread
+                        .send(Action::Err(
+                            Code::Config,
+                            format!("Error restoring configuration: {err}"),
+                        ))
+                        .expect("Internal thread error.");
+                } else {
+                    println!("Configuration restored, it applies from the next take on.");
+                }
+            }
+            Ok(Action::SampleRateChanged(measured))
+                if stream_container.borrow().is_empty()
+                    || !rate_change_confirmed(measured, inputs) => {}
+            Ok(Action::SampleRateChanged(measured)) => {
+                to_listener_thread
+                    .send(Action::SampleRateChanged(measured))
+                    .expect("Internal thread error.");
+                match sample_rate_changed(
+                    measured,
+                    inputs,
+                    stream_container,
+                    writers_container,
+                    smrec_config,
+                ) {
+                    Ok(true) => {
+                        take_started(smrec_config, writers_container);
+                        to_listener_thread
+                            .send(Action::Start)
+                            .expect("Internal thread error.");
+                    }
+                    Ok(false) => {
+                        transport = Transport::Armed;
+                        to_listener_thread
+                            .send(Action::Stop)
+                            .expect("Internal thread error.");
+                    }
+                    Err(err) => {
+                        transport = Transport::Armed;
+                        println!("Error restarting recording: {err}");
+                        to_listener_thread
+                            .send(Action::Err(
+                                Code::Recording,
+                                format!("Error restarting recording: {err}"),
+                            ))
+                            .expect("Internal thread error.");
+                    }
+                }
+            }
+            Ok(Action::StopAt(time)) => {
+                let mut writers = writers_container.lock().unwrap();
+                let result = match writers.as_mut() {
+                    None => Err((
+                        Code::NoRecording,
+                        "There is no running recording to stop.".to_string(),
+                    )),
+                    Some(_) if time <= chrono::Utc::now() => Err((
+                        Code::TimePassed,
+                        format!("Can not stop at {time}, it has passed."),
+                    )),
+                    Some(take) => {
+                        take.stop_at = Some(time);
+                        Ok(())
+                    }
+                };
+                drop(writers);
+                match result {
+                    Ok(()) => println!("Stopping at {time}."),
+                    Err((code, err)) => {
+                        println!("{err}");
+                        to_listener_thread
+                            .send(Action::Err(code, err))
+                            .expect("Internal thread error.");
+                    }
+                }
+            }
+            Ok(Action::SetGain(channel, db)) => {
+                match smrec_config.processing().set_gain(channel, db) {
+                    Ok(()) => println!("Gain of channel {} set to {db} dB.", channel + 1),
+                    Err(err) => {
+                        println!("Error setting gain: {err}");
+                        to_listener_thread
+                            .send(Action::Err(
+                                Code::Processing,
+                                format!("Error setting gain: {err}"),
+                            ))
+                            .expect("Internal thread error.");
+                    }
+                }
+            }
+            Ok(Action::SetGate(channel, threshold)) => {
+                match smrec_config.processing().set_gate(channel, threshold) {
+                    Ok(()) => match threshold {
+                        Some(db) => println!("Gate of channel {} set to {db} dBFS.", channel + 1),
+                        None => println!("Gate of channel {} turned off.", channel + 1),
+                    },
+                    Err(err) => {
+                        println!("Error setting gate: {err}");
+                        to_listener_thread
+                            .send(Action::Err(
+                                Code::Processing,
+                                format!("Error setting gate: {err}"),
+                            ))
+                            .expect("Internal thread error.");
+                    }
+                }
+            }
+            Ok(Action::MonitorSolo(channel, solo)) => {
+                match smrec_config.processing().set_solo(channel, solo) {
+                    Ok(()) if solo => println!("Channel {} soloed in the monitor.", channel + 1),
+                    Ok(()) => println!("Channel {} unsoloed in the monitor.", channel + 1),
+                    Err(err) => {
+                        println!("Error soloing: {err}");
+                        to_listener_thread
+                            .send(Action::Err(
+                                Code::Processing,
+                                format!("Error soloing: {err}"),
+                            ))
+                            .expect("Internal thread error.");
+                    }
+                }
+            }
+            Ok(Action::MonitorMute(channel, mute)) => {
+                match smrec_config.processing().set_mute(channel, mute) {
+                    Ok(()) if mute => println!("Channel {} muted in the monitor.", channel + 1),
+                    Ok(()) => println!("Channel {} unmuted in the monitor.", channel + 1),
+                    Err(err) => {
+                        println!("Error muting: {err}");
+                        to_listener_thread
+                            .send(Action::Err(
+                                Code::Processing,
+                                format!("Error muting: {err}"),
+                            ))
+                            .expect("Internal thread error.");
+                    }
+                }
+            }
+            // Nothing is resumed, guard tracks and pipes are fed again until the next take.
+            Ok(Action::Wake) => {
+                if let Err(err) = tap_until_first_take(inputs, writers_container, smrec_config) {
+                    console::error(format!("Error tapping channels after waking up: {err:#}"));
+                }
+            }
+            // Should not be used here though, no user facing api anyway.
+            Ok(
+                Action::TakeLocked(_)
+                | Action::ConfigDump(_)
+                | Action::Interrupted(_)
+                | Action::RemainingTime(_)
+                | Action::StatusReport(..)
+                | Action::RamBuffer(_),
+            ) => {}
             // Should not be used here though, no user facing api anyway.
-            Ok(Action::Err(err)) => {
+            Ok(Action::Err(_, err)) => {
                 println!("Error: {err}");
             }
             Err(_) => {
@@ -299,64 +1488,322 @@ pub fn listen_and_block_main_thread(
     }
 }
 
+/// Clears the label of the take which just started and moves the setlist on when it is a new take.
+fn take_started(smrec_config: &mut SmrecConfig, writers_container: &Arc<Mutex<Option<Take>>>) {
+    // Labels only apply to the take they were given for.
+    smrec_config.set_next_take_name(None);
+    let new_take = writers_container
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|take| take.part == 1);
+    if let (true, Some(setlist)) = (new_take, smrec_config.setlist_mut()) {
+        setlist.next();
+        println!("The setlist is at {}.", setlist.describe());
+    }
+}
+
+/// Whether the devices report another sample rate than they run at, after audio arrived at the measured rate.
+///
+/// A take is only stopped when they do, or when there is no device to ask.
+pub fn rate_change_confirmed(measured: u32, inputs: &[stream::Input]) -> bool {
+    let mut asked = false;
+    for input in inputs {
+        match input.current_sample_rate() {
+            Ok(Some(rate)) if rate != input.running_sample_rate() => return true,
+            Ok(Some(_)) => asked = true,
+            Ok(None) => {}
+            Err(err) => {
+                console::error(format!("{err:#}"));
+                return true;
+            }
+        }
+    }
+    if asked {
+        println!(
+            "The input arrived at about {measured} Hz but the devices still report the rate they run at, the take goes on."
+        );
+    }
+    !asked
+}
+
+/// Stops the running take when the devices changed their sample rate, so its files keep the rate they were recorded at.
+///
+/// With `--restart-on-rate-change` the devices are opened again and a new take is started at their new rate,
+/// returns whether it was.
+pub fn sample_rate_changed(
+    measured: u32,
+    inputs: &mut [stream::Input],
+    stream_container: &Rc<RefCell<Vec<stream::Stream>>>,
+    writers_container: &Arc<Mutex<Option<Take>>>,
+    smrec_config: &mut SmrecConfig,
+) -> Result<bool> {
+    let expected = smrec_config.supported_cpal_stream_config().sample_rate().0;
+    println!("The input runs at about {measured} Hz instead of {expected} Hz, stopping the take.");
+    stop_recording(stream_container, writers_container, smrec_config)?;
+    if !smrec_config.restart_on_rate_change() {
+        return Ok(false);
+    }
+
+    stream::Input::reopen(inputs)?;
+    let config = stream::Input::combined_config(inputs);
+    if config.sample_rate().0 == expected {
+        anyhow::bail!("The devices still report {expected} Hz, not starting a new take.");
+    }
+    println!("Starting a new take at {} Hz.", config.sample_rate().0);
+    smrec_config.set_stream_config(config);
+    new_recording(
+        inputs,
+        stream_container,
+        writers_container,
+        smrec_config,
+        None,
+    )?;
+    Ok(true)
+}
+
+pub fn lock_last_take(smrec_config: &SmrecConfig) -> Result<String> {
+    let base = smrec_config.out_base()?;
+    let take = takes::last_take_directory(&base, &smrec_config.take_prefix())?
+        .ok_or_else(|| anyhow!("There is no take to lock."))?;
+    takes::lock(&take)?;
+    Ok(take.file_name().unwrap_or(take.as_str()).to_string())
+}
+
+/// Starts a take, after finalizing the running one when it is split.
+///
+/// A take which can not be finalized is reported, to the listeners when there are any, and the next take starts anyway.
 pub fn new_recording(
-    device: &cpal::Device,
-    stream_container: &Rc<RefCell<Option<cpal::Stream>>>,
-    writer_handles: &Arc<Mutex<Option<WriterHandles>>>,
-    smrec_config: &SmrecConfig,
+    inputs: &[stream::Input],
+    stream_container: &Rc<RefCell<Vec<stream::Stream>>>,
+    writer_handles: &Arc<Mutex<Option<Take>>>,
+    smrec_config: &mut SmrecConfig,
+    to_listener_thread: Option<&crossbeam::channel::Sender<Action>>,
 ) -> Result<()> {
     // If there's an active stream, pause it and finalize the writers
-    if let Some(stream) = stream_container.borrow_mut().as_mut() {
-        stream.pause()?;
-        finalize_writers_if_some(writer_handles).unwrap();
-        println!("Restarting new recording...");
-    } else {
+    let event = if stream_container.borrow().is_empty() {
         println!("Starting recording...");
-    }
+        Event::Start
+    } else {
+        let streams = std::mem::take(&mut *stream_container.borrow_mut());
+        release_streams(streams, smrec_config)?;
+        if let Err(err) = finalize_writers_if_some(writer_handles) {
+            let message = format!("Error finalizing the previous take: {err:#}");
+            console::error(message.clone());
+            if let Some(to_listener_thread) = to_listener_thread {
+                to_listener_thread
+                    .send(Action::Err(Code::Storage, message))
+                    .ok();
+            }
+        }
+        println!("Restarting new recording...");
+        Event::Split
+    };
+    check_channel_counts(inputs, smrec_config)?;
 
+    // The take is stamped with the system time it starts at.
+    smrec_config.reanchor_clock();
     // Make new writers
     let writers = smrec_config.writers()?;
+    // Meters report the take from its start.
+    smrec_config.processing().meters().reset();
+    let directory = writers.directory.clone();
+
+    // Create and start the new streams
+    let latencies = inputs
+        .iter()
+        .map(|input| smrec_config.latency_frames(&input.name(), input.config.sample_rate().0))
+        .collect::<Vec<_>>();
+    let build = || {
+        stream::build_all(
+            inputs,
+            &latencies,
+            &smrec_config.channels_to_write(),
+            *smrec_config.clock(),
+            writer_handles,
+            smrec_config.monitor(),
+            smrec_config.to_main_thread(),
+            smrec_config.processing(),
+            smrec_config.taps(),
+            smrec_config.inject(),
+        )
+    };
+    // The streams kept running for the taps feed guard tracks and pipes until the ones of the take are there, only
+    // devices which can not be opened twice are opened again after they stop.
+    let new_streams = match build() {
+        Err(_) if tap::is_running() => {
+            tap::stop();
+            build()
+        }
+        built => built,
+    };
+    let new_streams = match new_streams {
+        Ok(new_streams) => new_streams,
+        Err(err) => {
+            // The take never started.
+            if let Err(err) = writers.finish() {
+                console::error(format!(
+                    "Error finalizing the take which did not start: {err:#}"
+                ));
+            }
+            return Err(err);
+        }
+    };
+    tap::stop();
     // Replace the old ones.
     writer_handles.lock().unwrap().replace(writers);
 
-    // Errors when ctrl+c handler is already set. We ignore this error since we have no intention of a reset.
-    let writer_handles_in_ctrlc = Arc::clone(writer_handles);
-    let _ = ctrlc::try_set_handler(move || {
-        // TODO: Necessary to drop stream?
+    for stream in &new_streams {
+        stream.play()?;
+    }
+    inhibit::acquire();
+    #[cfg(feature = "tui")]
+    title::recording(smrec_config.channels_to_write().len());
+    state::recording(&directory);
+    smrec_config.log_event(event, &directory);
+    if smrec_config.onset_threshold_db().is_some() {
+        println!("Waiting for a transient to start recording...");
+    } else {
+        println!("Recording started.");
+    }
+    *stream_container.borrow_mut() = new_streams;
 
-        // TODO: Maybe inform user in unsuccessful operation?
-        finalize_writers_if_some(&writer_handles_in_ctrlc).unwrap();
+    Ok(())
+}
 
-        // TODO: Better message, differentiate if the recording was stopped or interrupted.
-        println!("\rRecording interrupted thus stopped.");
-        std::process::exit(0);
-    });
+/// Pauses the streams of a take which ends, or keeps them running for the taps until the next take starts.
+///
+/// Nothing is written by them anymore once the take is finalized.
+fn release_streams(streams: Vec<stream::Stream>, smrec_config: &SmrecConfig) -> Result<()> {
+    if smrec_config.taps().is_empty() {
+        for stream in streams {
+            stream.pause()?;
+        }
+    } else {
+        tap::keep_running(streams);
+    }
+    Ok(())
+}
 
-    // Create and start a new stream
-    let new_stream = stream::build(
-        device,
-        smrec_config.supported_cpal_stream_config(),
-        smrec_config.channels_to_record(),
-        Arc::clone(writer_handles),
+/// Starts the streams of the tapped channels before the first take or after waking up, they record nothing themselves.
+fn tap_until_first_take(
+    inputs: &[stream::Input],
+    writer_handles: &Arc<Mutex<Option<Take>>>,
+    smrec_config: &SmrecConfig,
+) -> Result<()> {
+    if smrec_config.taps().is_empty() {
+        return Ok(());
+    }
+    let streams = stream::build_all(
+        inputs,
+        &vec![0; inputs.len()],
+        &[],
+        *smrec_config.clock(),
+        writer_handles,
+        None,
+        None,
+        smrec_config.processing(),
+        smrec_config.taps(),
+        None,
     )?;
+    for stream in &streams {
+        stream.play()?;
+    }
+    tap::keep_running(streams);
+    Ok(())
+}
 
-    new_stream.play()?;
-    println!("Recording started.");
-    stream_container.borrow_mut().replace(new_stream);
+/// Checks that the devices still have the channels they were opened with before a take is started.
+///
+/// An interface which is switched to another mode changes its channel count, recording it with the old mapping
+/// would write the channels into the wrong files, so the take is not started and the change is reported instead.
+fn check_channel_counts(inputs: &[stream::Input], smrec_config: &SmrecConfig) -> Result<()> {
+    let mut changes = Vec::new();
+    let mut available = 0;
+    for input in inputs {
+        let opened = input.config.channels();
+        let current = input.current_channels()?.unwrap_or(opened);
+        if current != opened {
+            changes.push(format!(
+                "{} has {current} channels instead of {opened}",
+                input.name()
+            ));
+        }
+        available += usize::from(current);
+    }
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let mapping = smrec_config.validate_channel_count(available).map_or_else(
+        |err| err.to_string(),
+        |()| "the channels would be recorded into the files of others".to_string(),
+    );
+    anyhow::bail!(
+        "{}, {mapping}. Restart smrec to record with the new channels.",
+        changes.join(", ")
+    )
+}
 
-    Ok(())
+/// Finalizes the running take before the system goes to sleep and lets it sleep, returns whether a take was running.
+fn finalize_for_sleep(
+    stream_container: &Rc<RefCell<Vec<stream::Stream>>>,
+    writers_container: &Arc<Mutex<Option<Take>>>,
+    smrec_config: &SmrecConfig,
+) -> bool {
+    let recording = !stream_container.borrow().is_empty();
+    if recording {
+        if let Err(err) = stop_recording(stream_container, writers_container, smrec_config) {
+            console::error(format!("Error finalizing the take before sleeping: {err}"));
+        }
+    }
+    // Guard tracks and pipes are fed again when the system wakes up.
+    tap::stop();
+    power::ready_to_sleep();
+    recording
+}
+
+/// Finalizes the running take when Ctrl-C is pressed and exits.
+fn quit(
+    stream_container: &Rc<RefCell<Vec<stream::Stream>>>,
+    writers_container: &Arc<Mutex<Option<Take>>>,
+    smrec_config: &SmrecConfig,
+) -> ! {
+    if !stream_container.borrow().is_empty() {
+        if let Err(err) = stop_recording(stream_container, writers_container, smrec_config) {
+            // Printed right away, the console thread would not get to it before the process exits.
+            eprintln!("Error finalizing the take: {err:#}");
+            takes::wait_for_measurements();
+            std::process::exit(Code::Storage.code());
+        }
+        println!("\rRecording interrupted thus stopped.");
+    }
+    takes::wait_for_measurements();
+    std::process::exit(0);
 }
 
 pub fn stop_recording(
-    stream_container: &Rc<RefCell<Option<cpal::Stream>>>,
-    writer_handles: &Arc<Mutex<Option<WriterHandles>>>,
+    stream_container: &Rc<RefCell<Vec<stream::Stream>>>,
+    writer_handles: &Arc<Mutex<Option<Take>>>,
+    smrec_config: &SmrecConfig,
 ) -> Result<()> {
     println!("Stopping recording...");
 
-    if let Some(stream) = stream_container.borrow_mut().take() {
-        stream.pause()?;
-        finalize_writers_if_some(writer_handles)?;
+    let streams = std::mem::take(&mut *stream_container.borrow_mut());
+    if !streams.is_empty() {
+        release_streams(streams, smrec_config)?;
+        if let Some(mut finished) = finish_writers_if_some(writer_handles)? {
+            // The stop hook can rename the take, its files are analyzed where they end up.
+            finished.directory = smrec_config.log_event(Event::Stop, &finished.directory);
+            finished.analyze();
+        }
+        inhibit::release();
+        #[cfg(feature = "tui")]
+        title::stopped();
+        state::stopped();
         println!("Recording stopped.");
+        if let Some(target_db) = smrec_config.gain_target() {
+            report_gain_staging(smrec_config, target_db);
+        }
         return Ok(());
     }
     println!("There is no running recording to stop.");
@@ -364,14 +1811,69 @@ pub fn stop_recording(
     Ok(())
 }
 
-pub fn finalize_writers_if_some(writers: &Arc<Mutex<Option<WriterHandles>>>) -> Result<()> {
-    let writers = writers.lock().unwrap().take();
-    if let Some(writers) = writers {
-        for writer in writers.iter() {
-            if let Some(writer) = writer.lock().unwrap().take() {
-                writer.finalize().unwrap();
-            }
-        }
+/// Prints how the average level and the peak of every written channel of the take compare to a target.
+fn report_gain_staging(smrec_config: &SmrecConfig, target_db: f32) {
+    for line in staging::report(&written_meters(smrec_config), target_db) {
+        println!("{line}");
+    }
+}
+
+/// The meters of the take for every written channel, with its name.
+fn written_meters(smrec_config: &SmrecConfig) -> Vec<(String, meters::Meter)> {
+    let meters = smrec_config.processing().meters().held();
+    smrec_config
+        .channels_to_write()
+        .into_iter()
+        .filter_map(|channel| {
+            let name = smrec_config
+                .get_channel_name_from_0_indexed_channel_num(channel)
+                .ok()?;
+            Some((name, *meters.get(channel)?))
+        })
+        .collect()
+}
+
+/// Records a take of a few seconds, which the output directory of the configuration keeps as proof of the line check,
+/// and prints the peak of every channel.
+fn run_linecheck(
+    inputs: &[stream::Input],
+    smrec_config: &mut SmrecConfig,
+    seconds: u64,
+) -> Result<()> {
+    let stream_container = Rc::new(RefCell::new(Vec::new()));
+    let writers_container = Arc::new(Mutex::new(None));
+    new_recording(
+        inputs,
+        &stream_container,
+        &writers_container,
+        smrec_config,
+        None,
+    )?;
+    let directory = writers_container
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|take: &Take| take.directory.clone());
+    std::thread::sleep(Duration::from_secs(seconds));
+    stop_recording(&stream_container, &writers_container, smrec_config)?;
+    for line in linecheck::report(&written_meters(smrec_config)) {
+        println!("{line}");
+    }
+    if let Some(directory) = directory {
+        println!("Line check is written to {directory}.");
+    }
+    Ok(())
+}
+
+pub fn finalize_writers_if_some(writers: &Arc<Mutex<Option<Take>>>) -> Result<()> {
+    if let Some(finished) = finish_writers_if_some(writers)? {
+        finished.analyze();
     }
     Ok(())
 }
+
+/// Finalizes the running take, if there is one, and leaves the analysis of its files to the caller.
+pub fn finish_writers_if_some(writers: &Arc<Mutex<Option<Take>>>) -> Result<Option<Finished>> {
+    let take = writers.lock().unwrap().take();
+    take.map(Take::finish).transpose()
+}
@@ -0,0 +1,305 @@
+use crate::processing::{db_to_linear, linear_to_db};
+use anyhow::{bail, Result};
+use cpal::{FromSample, Sample};
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+};
+
+/// Integration time of the quasi-peak programme meter, as in IEC 60268-10 type I.
+const PPM_INTEGRATION_SECS: f32 = 0.005;
+/// Peak meters fall back by 20 dB in 1.7 s, as in IEC 60268-10 and IEC 60268-18.
+const RELEASE_DB: f32 = 20.0;
+const RELEASE_SECS: f32 = 1.7;
+/// Time constant of the VU meter, a step reaches 99% of its level in 300 ms.
+const VU_SECS: f32 = 0.065;
+/// Average of a rectified sine relative to its RMS, VU meters read the RMS of a sine.
+const VU_SINE: f32 = std::f32::consts::FRAC_PI_2 / std::f32::consts::SQRT_2;
+/// Time constant of the running RMS of the live meters.
+const RMS_SECS: f32 = 0.3;
+/// Live levels below about -200 dBFS are silence, so decaying levels do not end up as slow subnormal floats.
+const SILENT: f32 = 1e-10;
+
+/// Peak, overs and average level of every device channel since the take started, for status requests
+/// and the gain staging report, as well as the live levels of the meter stream.
+///
+/// Levels are atomics so the audio callbacks never wait for them.
+#[derive(Debug, Default)]
+pub struct Meters {
+    channels: Vec<ChannelMeter>,
+}
+
+/// Levels of a channel, stored as the bits of linear `f32` values.
+#[derive(Debug, Default)]
+struct ChannelMeter {
+    /// Highest absolute sample since the take started.
+    peak: AtomicU32,
+    /// Runs of samples at full scale since the take started.
+    overs: AtomicU32,
+    /// Whether the last sample was at full scale, so a run is counted once.
+    over: AtomicBool,
+    /// Sum of the squared samples since the take started, the bits of an `f64`.
+    energy: AtomicU64,
+    /// Samples since the take started.
+    samples: AtomicU64,
+    /// Live levels with the ballistics of a programme meter, a digital peak meter and a VU meter.
+    ppm: AtomicU32,
+    digital: AtomicU32,
+    vu: AtomicU32,
+    /// Running average of the squared samples.
+    mean_square: AtomicU32,
+}
+
+/// Peak hold, overs and average level of a channel since the take started.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Meter {
+    /// In dBFS, negative infinity for silence.
+    pub peak_db: f32,
+    pub overs: u32,
+    /// RMS in dBFS, negative infinity for silence.
+    pub rms_db: f32,
+}
+
+/// How the live level of a channel rises and falls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ballistics {
+    /// Digital peak meter, every sample is seen.
+    Peak,
+    /// Quasi-peak programme meter, short transients read lower.
+    Ppm,
+    /// Volume unit meter, the average level.
+    Vu,
+}
+
+impl Ballistics {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Peak => "peak",
+            Self::Ppm => "ppm",
+            Self::Vu => "vu",
+        }
+    }
+}
+
+impl FromStr for Ballistics {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "peak" | "digital" => Ok(Self::Peak),
+            "ppm" => Ok(Self::Ppm),
+            "vu" => Ok(Self::Vu),
+            _ => bail!("Ballistics must be peak, ppm or vu but it is {s}."),
+        }
+    }
+}
+
+/// Live level of a channel with the chosen ballistics and its running RMS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Level {
+    /// In dBFS, negative infinity for silence.
+    pub level_db: f32,
+    /// In dBFS, negative infinity for silence.
+    pub rms_db: f32,
+}
+
+/// Per sample coefficients of the live meters at the sample rate of a stream.
+pub struct MeterTimes {
+    ppm_attack: f32,
+    release: f32,
+    vu: f32,
+    rms: f32,
+}
+
+impl MeterTimes {
+    #[allow(clippy::cast_precision_loss)]
+    pub fn new(sample_rate: u32) -> Self {
+        let rate = sample_rate.max(1) as f32;
+        let coefficient = |secs: f32| 1.0 - (-1.0 / (secs * rate)).exp();
+        Self {
+            ppm_attack: coefficient(PPM_INTEGRATION_SECS),
+            release: db_to_linear(-RELEASE_DB / (RELEASE_SECS * rate)),
+            vu: coefficient(VU_SECS),
+            rms: coefficient(RMS_SECS),
+        }
+    }
+}
+
+impl Meters {
+    pub fn new(channels: usize) -> Self {
+        Self {
+            channels: (0..channels).map(|_| ChannelMeter::default()).collect(),
+        }
+    }
+
+    /// Peak hold, overs and average level of every device channel, channels which are not recorded stay silent.
+    pub fn held(&self) -> Vec<Meter> {
+        self.channels
+            .iter()
+            .map(|meter| {
+                let energy = f64::from_bits(meter.energy.load(Ordering::Relaxed));
+                #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+                let rms = match meter.samples.load(Ordering::Relaxed) {
+                    0 => 0.0,
+                    samples => (energy / samples as f64).sqrt() as f32,
+                };
+                Meter {
+                    peak_db: linear_to_db(f32::from_bits(meter.peak.load(Ordering::Relaxed))),
+                    overs: meter.overs.load(Ordering::Relaxed),
+                    rms_db: linear_to_db(rms),
+                }
+            })
+            .collect()
+    }
+
+    /// Live level of every device channel with the given ballistics, for the meter stream.
+    pub fn levels(&self, ballistics: Ballistics) -> Vec<Level> {
+        self.channels
+            .iter()
+            .map(|meter| {
+                let load = |level: &AtomicU32| f32::from_bits(level.load(Ordering::Relaxed));
+                let level = match ballistics {
+                    Ballistics::Peak => load(&meter.digital),
+                    Ballistics::Ppm => load(&meter.ppm),
+                    Ballistics::Vu => load(&meter.vu) * VU_SINE,
+                };
+                Level {
+                    level_db: linear_to_db(level),
+                    rms_db: linear_to_db(load(&meter.mean_square).sqrt()),
+                }
+            })
+            .collect()
+    }
+
+    /// Starts the peak hold and overs again, when a take starts.
+    pub fn reset(&self) {
+        for meter in &self.channels {
+            meter.peak.store(0.0_f32.to_bits(), Ordering::Relaxed);
+            meter.overs.store(0, Ordering::Relaxed);
+            meter.over.store(false, Ordering::Relaxed);
+            meter.energy.store(0.0_f64.to_bits(), Ordering::Relaxed);
+            meter.samples.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Updates the peak hold, overs, average and live levels of a 0 indexed channel with samples as they are recorded.
+    pub fn meter<T>(&self, channel: usize, samples: &[T], times: &MeterTimes)
+    where
+        T: Sample,
+        f32: FromSample<T>,
+    {
+        let Some(meter) = self.channels.get(channel) else {
+            return;
+        };
+        // Only the stream recording the channel writes these, loading and storing is enough.
+        let mut peak = f32::from_bits(meter.peak.load(Ordering::Relaxed));
+        let mut overs = meter.overs.load(Ordering::Relaxed);
+        let mut over = meter.over.load(Ordering::Relaxed);
+        let mut energy = f64::from_bits(meter.energy.load(Ordering::Relaxed));
+        let load = |level: &AtomicU32| f32::from_bits(level.load(Ordering::Relaxed));
+        let (mut ppm, mut digital) = (load(&meter.ppm), load(&meter.digital));
+        let (mut vu, mut mean_square) = (load(&meter.vu), load(&meter.mean_square));
+        for sample in samples {
+            let value = f32::from_sample(*sample).abs();
+            peak = peak.max(value);
+            energy += f64::from(value) * f64::from(value);
+            let full_scale = value >= 1.0;
+            if full_scale && !over {
+                overs = overs.saturating_add(1);
+            }
+            over = full_scale;
+
+            ppm = if value > ppm {
+                (value - ppm).mul_add(times.ppm_attack, ppm)
+            } else {
+                ppm * times.release
+            };
+            digital = (digital * times.release).max(value);
+            vu = (value - vu).mul_add(times.vu, vu);
+            mean_square = value
+                .mul_add(value, -mean_square)
+                .mul_add(times.rms, mean_square);
+        }
+        let store = |level: &AtomicU32, value: f32| {
+            let value = if value < SILENT { 0.0 } else { value };
+            level.store(value.to_bits(), Ordering::Relaxed);
+        };
+        store(&meter.ppm, ppm);
+        store(&meter.digital, digital);
+        store(&meter.vu, vu);
+        store(&meter.mean_square, mean_square);
+        meter.peak.store(peak.to_bits(), Ordering::Relaxed);
+        meter.overs.store(overs, Ordering::Relaxed);
+        meter.over.store(over, Ordering::Relaxed);
+        meter.energy.store(energy.to_bits(), Ordering::Relaxed);
+        meter
+            .samples
+            .fetch_add(samples.len() as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn peaks_are_held_and_overs_counted_until_the_next_take() {
+        let meters = Meters::new(2);
+        let times = MeterTimes::new(48000);
+        meters.meter(0, &[0.25_f32, -0.5, 0.1], &times);
+        meters.meter(0, &[1.0_f32, -1.0, 0.0, 1.0], &times);
+        // A run continuing into the next buffer is still one over.
+        meters.meter(0, &[1.0_f32, 0.0], &times);
+        let held = meters.held();
+        assert!(held[0].peak_db.abs() < 0.01);
+        assert_eq!(held[0].overs, 2);
+        assert_eq!(held[1].peak_db, f32::NEG_INFINITY);
+
+        meters.meter(1, &[0.5_f32], &times);
+        assert!((meters.held()[1].peak_db + 6.02).abs() < 0.01);
+        // A square wave averages its peak.
+        meters.meter(1, &[-0.5_f32, 0.5, -0.5], &times);
+        assert!((meters.held()[1].rms_db + 6.02).abs() < 0.01);
+
+        meters.reset();
+        assert!(meters
+            .held()
+            .iter()
+            .all(|meter| meter.peak_db == f32::NEG_INFINITY
+                && meter.overs == 0
+                && meter.rms_db == f32::NEG_INFINITY));
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    fn live_levels_follow_their_ballistics() {
+        let meters = Meters::new(1);
+        let times = MeterTimes::new(48000);
+        let level = |ballistics| meters.levels(ballistics)[0].level_db;
+
+        // A burst of 10 ms reads about 1 dB low on a programme meter.
+        meters.meter(0, &[1.0_f32; 480], &times);
+        assert!(level(Ballistics::Peak).abs() < 0.01);
+        assert!((-1.5..-1.0).contains(&level(Ballistics::Ppm)));
+        assert!(level(Ballistics::Vu) < -10.0);
+
+        // A steady sine reads about its peak on peak meters and its RMS on the VU meter.
+        let sine = (0..96000)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 48000.0).sin())
+            .collect::<Vec<_>>();
+        meters.meter(0, &sine, &times);
+        assert!((level(Ballistics::Peak) + 6.02).abs() < 0.1);
+        assert!((level(Ballistics::Ppm) + 6.02).abs() < 0.5);
+        assert!((level(Ballistics::Vu) + 9.03).abs() < 0.1);
+        assert!((meters.levels(Ballistics::Vu)[0].rms_db + 9.03).abs() < 0.1);
+
+        // Peak meters fall back 20 dB in 1.7 s.
+        meters.meter(0, &vec![0.0_f32; 81600], &times);
+        assert!((level(Ballistics::Peak) + 26.02).abs() < 0.1);
+        meters.reset();
+        assert!(level(Ballistics::Peak) > f32::NEG_INFINITY);
+        assert_eq!("VU".parse::<Ballistics>().unwrap(), Ballistics::Vu);
+        assert!("needle".parse::<Ballistics>().is_err());
+    }
+}
@@ -1,19 +1,31 @@
 mod parse;
+mod sysex;
 
 const CHANNEL_MASK: u8 = 0b0000_1111;
 const ANY_CHANNEL_INTERNAL: u8 = 0xFF;
-
-use crate::types::Action;
-use anyhow::{bail, Result};
+const SYSEX_START: u8 = 0xF0;
+const SONG_SELECT: u8 = 0xF3;
+const TIMING_CLOCK: u8 = 0xF8;
+const CLOCK_START: u8 = 0xFA;
+const CLOCK_CONTINUE: u8 = 0xFB;
+const CLOCK_STOP: u8 = 0xFC;
+/// Timing clock messages per quarter note.
+const CLOCKS_PER_BEAT: u8 = 24;
+
+use crate::{console, control::ControlSurface, error::Code, types::Action};
+use anyhow::{bail, Context, Result};
+use crossbeam::channel::{Receiver, Sender};
 use midir::{
     MidiInput, MidiInputConnection, MidiInputPort, MidiOutput, MidiOutputConnection, MidiOutputPort,
 };
+use serde::Deserialize;
 use std::{
     collections::HashMap,
     ops::Deref,
     str::FromStr,
     sync::{Arc, Mutex},
 };
+use sysex::SysexMessage;
 
 enum MessageType {
     NoteOff,
@@ -39,6 +51,50 @@ const fn get_message_type(message: &[u8]) -> MessageType {
     }
 }
 
+/// The song number of a Song Select message.
+fn song_select(message: &[u8]) -> Option<u8> {
+    match message {
+        [SONG_SELECT, song] => Some(*song),
+        _ => None,
+    }
+}
+
+/// The cue a Program Change message drops, programs are numbered from 1 like on most devices.
+fn program_change_cue(message: &[u8]) -> Option<u8> {
+    match (get_message_type(message), message) {
+        (MessageType::ProgramChange, [_, program @ 0..=127]) => Some(program + 1),
+        _ => None,
+    }
+}
+
+/// Counts the timing clock messages of an external MIDI clock into beats while its transport runs.
+#[derive(Debug, Default)]
+struct BeatClock {
+    /// Clocks since the last beat, `None` while stopped.
+    clocks: Option<u8>,
+}
+
+impl BeatClock {
+    /// Whether a real-time message is a clock which falls on a beat.
+    fn is_beat(&mut self, message: &[u8]) -> bool {
+        match message {
+            // The first clock after a start is the first beat.
+            [CLOCK_START] => self.clocks = Some(0),
+            [CLOCK_CONTINUE] => self.clocks = Some(self.clocks.unwrap_or_default()),
+            [CLOCK_STOP] => self.clocks = None,
+            [TIMING_CLOCK] => {
+                if let Some(clocks) = self.clocks.as_mut() {
+                    let beat = *clocks == 0;
+                    *clocks = (*clocks + 1) % CLOCKS_PER_BEAT;
+                    return beat;
+                }
+            }
+            _ => {}
+        }
+        false
+    }
+}
+
 const fn get_channel(message: &[u8]) -> u8 {
     message[0] & CHANNEL_MASK
 }
@@ -67,14 +123,74 @@ impl FromStr for MidiConfig {
     }
 }
 
+/// CC numbers which arm and disarm, configured in `[midi_arming]`.
+///
+/// They are listened for and sent on the ports and channels of the start and stop configuration.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct MidiArming {
+    #[serde(default = "MidiArming::default_arm")]
+    arm: u8,
+    #[serde(default = "MidiArming::default_disarm")]
+    disarm: u8,
+}
+
+impl Default for MidiArming {
+    fn default() -> Self {
+        Self {
+            arm: Self::default_arm(),
+            disarm: Self::default_disarm(),
+        }
+    }
+}
+
+impl MidiArming {
+    const fn default_arm() -> u8 {
+        18
+    }
+
+    const fn default_disarm() -> u8 {
+        19
+    }
+}
+
+/// CC numbers which solo and mute channels in the monitor stream, configured in `[midi_monitor]`.
+///
+/// The nth CC number of a list belongs to channel n, a value of 64 or above switches on and a lower one off.
+/// They are listened for on the ports and channels of the start and stop configuration.
+///
+/// With `click = true` the beats of a MIDI clock received on the ports are clicked in the monitor stream.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct MidiMonitor {
+    #[serde(default)]
+    solo: Vec<u8>,
+    #[serde(default)]
+    mute: Vec<u8>,
+    #[serde(default)]
+    click: bool,
+}
+
+impl MidiMonitor {
+    /// The solo or mute a CC message asks for, if its number is configured.
+    fn action(&self, cc_number: u8, value: u8) -> Option<Action> {
+        let on = value >= 64;
+        if let Some(channel) = self.solo.iter().position(|cc| *cc == cc_number) {
+            return Some(Action::MonitorSolo(channel, on));
+        }
+        self.mute
+            .iter()
+            .position(|cc| *cc == cc_number)
+            .map(|channel| Action::MonitorMute(channel, on))
+    }
+}
+
 #[allow(clippy::type_complexity)]
 pub struct Midi {
     input: MidiInput,
     output: Option<MidiOutput>,
     input_config: MidiConfig,
     output_config: Option<MidiConfig>,
-    sender_channel: crossbeam::channel::Sender<Action>,
-    receiver_channel: crossbeam::channel::Receiver<Action>,
+    arming: MidiArming,
+    monitor: MidiMonitor,
     input_connections: HashMap<String, MidiInputConnection<Vec<(u8, u8, u8)>>>,
     output_thread: Option<std::thread::JoinHandle<()>>,
 }
@@ -127,11 +243,7 @@ impl Midi {
         }
     }
 
-    pub fn new(
-        sender_channel: crossbeam::channel::Sender<Action>,
-        receiver_channel: crossbeam::channel::Receiver<Action>,
-        cli_config: &[String],
-    ) -> Result<Self> {
+    pub fn new(cli_config: &[String], arming: MidiArming, monitor: MidiMonitor) -> Result<Self> {
         let input = MidiInput::new("smrec")?;
 
         let input_config = if let Some(input_config) = cli_config.get(0) {
@@ -155,8 +267,8 @@ impl Midi {
             },
             input_config,
             output_config,
-            sender_channel,
-            receiver_channel,
+            arming,
+            monitor,
             input_connections: HashMap::new(),
             output_thread: None,
         })
@@ -181,12 +293,16 @@ impl Midi {
             .collect::<Result<Vec<(String, MidiInputPort, Vec<(u8, u8, u8)>)>, anyhow::Error>>()
     }
 
-    fn register_midi_input_hooks(&mut self) -> Result<()> {
+    #[allow(clippy::too_many_lines)]
+    fn register_midi_input_hooks(&mut self, to_main_thread: &Sender<Action>) -> Result<()> {
         let input_ports = self.input_ports_from_configs()?;
 
         // Start listening for MIDI messages on all configured ports and channels.
         for (port_name, port, configs) in input_ports {
-            let to_main_thread = self.sender_channel.clone();
+            let to_main_thread = to_main_thread.clone();
+            let arming = self.arming;
+            let monitor = self.monitor.clone();
+            let mut beat_clock = BeatClock::default();
 
             let input = MidiInput::new("smrec")?;
             self.input_connections.insert(
@@ -196,7 +312,44 @@ impl Midi {
                         &port,
                         &port_name,
                         move |_stamp, message, configs| {
+                            if monitor.click && beat_clock.is_beat(message) {
+                                to_main_thread
+                                    .send(Action::Beat(std::time::Instant::now()))
+                                    .unwrap();
+                                return;
+                            }
+                            if message.first() == Some(&SYSEX_START) {
+                                match sysex::parse(message) {
+                                    Ok(Some(SysexMessage::DumpRequest)) => {
+                                        to_main_thread.send(Action::DumpConfig).unwrap();
+                                    }
+                                    Ok(Some(SysexMessage::Dump(dump))) => {
+                                        to_main_thread.send(Action::RestoreConfig(dump)).unwrap();
+                                    }
+                                    Ok(None) => {
+                                        // Not meant for us.
+                                    }
+                                    Err(err) => {
+                                        console::error(format!("Invalid SysEx message: {err}"));
+                                    }
+                                }
+                                return;
+                            }
+                            if let Some(song) = song_select(message) {
+                                to_main_thread.send(Action::SongSelect(song)).unwrap();
+                                return;
+                            }
+
                             let channel = get_channel(message);
+                            if let Some(cue) = program_change_cue(message) {
+                                // Cues listen on the channels of the start and stop configuration like arming.
+                                if configs.iter().any(|(chn, _, _)| {
+                                    *chn == channel || *chn == ANY_CHANNEL_INTERNAL
+                                }) {
+                                    to_main_thread.send(Action::Cue(cue)).unwrap();
+                                }
+                                return;
+                            }
                             let message_type = get_message_type(message);
                             if matches!(message_type, MessageType::ControlChange) {
                                 if let (Some(cc_number), Some(value)) =
@@ -250,8 +403,26 @@ impl Midi {
                                             to_main_thread.send(Action::Stop).unwrap();
                                         }
                                     }
+
+                                    // Arming listens on the channels of the start and stop configuration.
+                                    let on_configured_channel =
+                                        configs.iter().any(|(chn, _, _)| {
+                                            *chn == channel || *chn == ANY_CHANNEL_INTERNAL
+                                        });
+                                    if on_configured_channel && *value == 127 {
+                                        if *cc_number == arming.arm {
+                                            to_main_thread.send(Action::Arm).unwrap();
+                                        } else if *cc_number == arming.disarm {
+                                            to_main_thread.send(Action::Disarm).unwrap();
+                                        }
+                                    }
+                                    if on_configured_channel {
+                                        if let Some(action) = monitor.action(*cc_number, *value) {
+                                            to_main_thread.send(action).unwrap();
+                                        }
+                                    }
                                 } else {
-                                    println!("Invalid CC message: {message:?}");
+                                    console::error(format!("Invalid CC message: {message:?}"));
                                 }
                             }
                         },
@@ -309,79 +480,72 @@ impl Midi {
         Ok(None)
     }
 
-    fn spin_midi_output_thread_if_necessary(&mut self) -> Result<()> {
+    fn spin_midi_output_thread_if_necessary(&mut self, events: Receiver<Action>) -> Result<()> {
         let output_connections = self.output_connections_from_config()?;
-        let receiver_channel = self.receiver_channel.clone();
+        let arming = self.arming;
 
         if let Some(output_connections) = output_connections {
             self.output_thread = Some(std::thread::spawn(move || {
                 loop {
-                    if let Ok(action) = receiver_channel.recv() {
+                    if let Ok(action) = events.recv() {
                         match action {
                             Action::Start => {
-                                for (port_name, connection, configs) in &output_connections {
-                                    for (channel, start_cc_num, _) in configs {
-                                        // Send to all channels if channel is 255.
-                                        if *channel == ANY_CHANNEL_INTERNAL {
-                                            for chn in 0..15 {
-                                                if let Err(err) = connection
-                                                    .lock()
-                                                    .unwrap()
-                                                    .send(&make_cc_message(chn, *start_cc_num, 127))
-                                                {
-                                                    println!(
-                                                "Error sending CC message to {port_name}: {err} ",
-                                            );
-                                                }
-                                            }
-                                            continue;
-                                        }
-
-                                        if let Err(err) = connection
-                                            .lock()
-                                            .unwrap()
-                                            .send(&make_cc_message(*channel, *start_cc_num, 127))
-                                        {
-                                            println!(
-                                                "Error sending CC message to {port_name}: {err} ",
-                                            );
-                                        }
-                                    }
-                                }
+                                send_cc_to_all(&output_connections, |(_, start_cc_num, _)| {
+                                    *start_cc_num
+                                });
                             }
                             Action::Stop => {
-                                for (port_name, connection, configs) in &output_connections {
-                                    for (channel, _, stop_cc_num) in configs {
-                                        // Send to all channels if channel is 255.
-                                        if *channel == ANY_CHANNEL_INTERNAL {
-                                            for chn in 0..15 {
-                                                if let Err(err) = connection
-                                                    .lock()
-                                                    .unwrap()
-                                                    .send(&make_cc_message(chn, *stop_cc_num, 127))
-                                                {
-                                                    println!(
-                                                "Error sending CC message to {port_name}: {err} ",
-                                            );
-                                                }
-                                            }
-                                            continue;
-                                        }
-
-                                        if let Err(err) = connection
-                                            .lock()
-                                            .unwrap()
-                                            .send(&make_cc_message(*channel, *stop_cc_num, 127))
-                                        {
-                                            println!(
-                                                "Error sending CC message to {port_name}: {err} ",
-                                            );
-                                        }
+                                send_cc_to_all(&output_connections, |(_, _, stop_cc_num)| {
+                                    *stop_cc_num
+                                });
+                            }
+                            Action::Arm => {
+                                send_cc_to_all(&output_connections, |_| arming.arm);
+                            }
+                            Action::Disarm => {
+                                send_cc_to_all(&output_connections, |_| arming.disarm);
+                            }
+                            Action::ConfigDump(dump) => {
+                                let message = sysex::encode_dump(&dump);
+                                for (port_name, connection, _) in &output_connections {
+                                    let result = connection.lock().unwrap().send(&message);
+                                    if let Err(err) = result {
+                                        println!("Error sending SysEx dump to {port_name}: {err} ");
                                     }
                                 }
                             }
-                            Action::Err(_) => {
-                                // Ignore, we don't send midi messages when errors occur.
+                            Action::Err(..)
+                            | Action::Split
+                            | Action::Sleep
+                            | Action::Wake
+                            | Action::Quit
+                            | Action::SampleRateChanged(_)
+                            | Action::StopAt(_)
+                            | Action::Interrupted(_)
+                            | Action::RemainingTime(_)
+                            | Action::SetGain(..)
+                            | Action::SetGate(..)
+                            | Action::MonitorSolo(..)
+                            | Action::MonitorMute(..)
+                            | Action::Marker(_)
+                            | Action::Cue(_)
+                            | Action::NextTakeName(_)
+                            | Action::SongSelect(_)
+                            | Action::SetlistNext
+                            | Action::RamBuffer(_)
+                            | Action::SetlistPrev
+                            | Action::Beat(_)
+                            | Action::SetOut(_)
+                            | Action::PlaybackRate(_)
+                            | Action::IncludeChannel(_)
+                            | Action::ExcludeChannel(_)
+                            | Action::LockLastTake
+                            | Action::TakeLocked(_)
+                            | Action::DumpConfig
+                            | Action::RestoreConfig(_)
+                            | Action::Status
+                            | Action::StatusReport(..) => {
+                                // Ignore, we don't send midi messages when errors occur or for take management.
                             }
                         }
                     }
@@ -391,11 +555,102 @@ impl Midi {
 
         Ok(())
     }
+}
 
-    pub fn listen(&mut self) -> Result<()> {
-        self.register_midi_input_hooks()?;
-        self.spin_midi_output_thread_if_necessary()?;
+impl ControlSurface for Midi {
+    fn start(&mut self, to_main_thread: Sender<Action>, events: Receiver<Action>) -> Result<()> {
+        self.register_midi_input_hooks(&to_main_thread)
+            .context(Code::Midi)?;
+        self.spin_midi_output_thread_if_necessary(events)
+            .context(Code::Midi)?;
 
         Ok(())
     }
 }
+
+/// Sends a CC message with a value of 127 on every configured port and channel.
+///
+/// The CC number is picked from each channel configuration.
+#[allow(clippy::type_complexity)]
+fn send_cc_to_all(
+    output_connections: &[(String, Arc<Mutex<MidiOutputConnection>>, Vec<(u8, u8, u8)>)],
+    cc_num: impl Fn(&(u8, u8, u8)) -> u8,
+) {
+    for (port_name, connection, configs) in output_connections {
+        for config in configs {
+            let (channel, _, _) = config;
+            // Send to all channels if channel is 255.
+            let channels = if *channel == ANY_CHANNEL_INTERNAL {
+                0..15
+            } else {
+                *channel..*channel + 1
+            };
+            for chn in channels {
+                if let Err(err) =
+                    connection
+                        .lock()
+                        .unwrap()
+                        .send(&make_cc_message(chn, cc_num(config), 127))
+                {
+                    println!("Error sending CC message to {port_name}: {err} ");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monitor_ccs_solo_and_mute_channels() {
+        let monitor = MidiMonitor {
+            solo: vec![20, 21],
+            mute: vec![52, 53],
+            click: false,
+        };
+        assert!(matches!(
+            monitor.action(21, 127),
+            Some(Action::MonitorSolo(1, true))
+        ));
+        assert!(matches!(
+            monitor.action(52, 0),
+            Some(Action::MonitorMute(0, false))
+        ));
+        assert!(monitor.action(16, 127).is_none());
+    }
+
+    #[test]
+    fn song_select_messages() {
+        assert_eq!(song_select(&[0xF3, 4]), Some(4));
+        assert_eq!(song_select(&[0xF3]), None);
+        assert_eq!(song_select(&[0xB0, 16, 127]), None);
+    }
+
+    #[test]
+    fn program_changes_drop_cues() {
+        assert_eq!(program_change_cue(&[0xC3, 0]), Some(1));
+        assert_eq!(program_change_cue(&[0xC0, 127]), Some(128));
+        assert_eq!(program_change_cue(&[0xB0, 16]), None);
+        assert_eq!(program_change_cue(&[0xC0]), None);
+    }
+
+    #[test]
+    fn beats_are_counted_from_the_start_of_the_clock() {
+        let mut beat_clock = BeatClock::default();
+        let mut beats = |message: &[u8], clocks: usize| {
+            (0..clocks).filter(|_| beat_clock.is_beat(message)).count()
+        };
+        // Clocks of a stopped transport are not beats.
+        assert_eq!(beats(&[TIMING_CLOCK], 48), 0);
+        assert_eq!(beats(&[CLOCK_START], 1), 0);
+        assert_eq!(beats(&[TIMING_CLOCK], 1), 1);
+        assert_eq!(beats(&[TIMING_CLOCK], 47), 1);
+        assert_eq!(beats(&[CLOCK_STOP], 1), 0);
+        assert_eq!(beats(&[TIMING_CLOCK], 24), 0);
+        // Continuing picks up the count where it stopped.
+        assert_eq!(beats(&[CLOCK_CONTINUE], 1), 0);
+        assert_eq!(beats(&[TIMING_CLOCK], 1), 1);
+    }
+}
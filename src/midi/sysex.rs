@@ -0,0 +1,116 @@
+use anyhow::{anyhow, bail, Result};
+
+const SYSEX_START: u8 = 0xF0;
+const SYSEX_END: u8 = 0xF7;
+/// The manufacturer id reserved for non-commercial use.
+const MANUFACTURER_ID: u8 = 0x7D;
+/// Follows the manufacturer id to tell smrec messages apart from other non-commercial ones.
+const SMREC_ID: [u8; 2] = [b's', b'm'];
+
+const DUMP_REQUEST: u8 = 0x01;
+const DUMP: u8 = 0x02;
+
+/// System exclusive messages which smrec understands.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SysexMessage {
+    /// Asks smrec to send its current configuration.
+    DumpRequest,
+    /// A configuration dump, either sent by smrec or sent to smrec to restore it.
+    Dump(String),
+}
+
+/// Parses a sysex message, returns `None` if it is not meant for smrec.
+pub fn parse(message: &[u8]) -> Result<Option<SysexMessage>> {
+    let Some(body) = message
+        .strip_prefix(&[SYSEX_START, MANUFACTURER_ID])
+        .and_then(|body| body.strip_prefix(&SMREC_ID))
+    else {
+        return Ok(None);
+    };
+    let body = body
+        .strip_suffix(&[SYSEX_END])
+        .ok_or_else(|| anyhow!("SysEx message is not terminated."))?;
+
+    match body.split_first() {
+        Some((&DUMP_REQUEST, _)) => Ok(Some(SysexMessage::DumpRequest)),
+        Some((&DUMP, payload)) => Ok(Some(SysexMessage::Dump(String::from_utf8(unpack_7bit(
+            payload,
+        )?)?))),
+        Some((command, _)) => bail!("Unknown SysEx command {command:#04x}."),
+        None => bail!("SysEx message has no command."),
+    }
+}
+
+/// Encodes a configuration dump as a sysex message.
+pub fn encode_dump(config: &str) -> Vec<u8> {
+    let mut message = vec![SYSEX_START, MANUFACTURER_ID];
+    message.extend_from_slice(&SMREC_ID);
+    message.push(DUMP);
+    message.extend(pack_7bit(config.as_bytes()));
+    message.push(SYSEX_END);
+    message
+}
+
+/// Packs 8 bit data to 7 bit sysex data.
+///
+/// Every group of up to 7 bytes is preceded by a byte carrying their most significant bits.
+fn pack_7bit(data: &[u8]) -> Vec<u8> {
+    let mut packed = Vec::with_capacity(data.len() + data.len() / 7 + 1);
+    for group in data.chunks(7) {
+        let msbs = group
+            .iter()
+            .enumerate()
+            .fold(0, |msbs, (i, byte)| msbs | ((byte >> 7) << i));
+        packed.push(msbs);
+        packed.extend(group.iter().map(|byte| byte & 0x7F));
+    }
+    packed
+}
+
+/// Reverses [`pack_7bit`].
+fn unpack_7bit(packed: &[u8]) -> Result<Vec<u8>> {
+    if packed.iter().any(|byte| byte & 0x80 != 0) {
+        bail!("SysEx data bytes can not have their most significant bit set.");
+    }
+    let mut data = Vec::with_capacity(packed.len());
+    for group in packed.chunks(8) {
+        let Some((msbs, bytes)) = group.split_first() else {
+            continue;
+        };
+        data.extend(
+            bytes
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| byte | (((msbs >> i) & 1) << 7)),
+        );
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_round_trip() {
+        let config = "channels = [1, 2]\n\n[channel_names]\n1 = \"Kick.wav\"\n2 = \"Sänger.wav\"\n";
+        let message = encode_dump(config);
+        assert!(message[1..message.len() - 1]
+            .iter()
+            .all(|byte| byte & 0x80 == 0));
+        assert_eq!(
+            parse(&message).unwrap(),
+            Some(SysexMessage::Dump(config.to_string()))
+        );
+    }
+
+    #[test]
+    fn dump_request_and_foreign_messages() {
+        assert_eq!(
+            parse(&[0xF0, 0x7D, b's', b'm', 0x01, 0xF7]).unwrap(),
+            Some(SysexMessage::DumpRequest)
+        );
+        assert_eq!(parse(&[0xF0, 0x43, 0x10, 0xF7]).unwrap(), None);
+        assert!(parse(&[0xF0, 0x7D, b's', b'm', 0x7F, 0xF7]).is_err());
+    }
+}
@@ -0,0 +1,304 @@
+// Devices of `--host mock` generate their input instead of capturing it, so whole recordings run without hardware.
+
+use crate::{
+    clock::Clock,
+    resample::Quality,
+    stream::{Callback, Input, Source},
+    threads,
+};
+use anyhow::{anyhow, Result};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// Name of the host given to `--host`.
+pub const HOST: &str = "mock";
+/// The device used when none is given.
+const DEFAULT_DEVICE: &str = "Mock 2ch";
+const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+/// Frames delivered at a time.
+const BUFFER_FRAMES: usize = 512;
+/// Frequency step between the channels, channel n plays a sine at n times this.
+const CHANNEL_STEP_HZ: f32 = 100.0;
+const AMPLITUDE: f32 = 0.25;
+
+/// A generated device, named like `Mock 8ch` or `Mock 8ch 96000Hz`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    pub name: String,
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+impl std::str::FromStr for Device {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        let invalid =
+            || anyhow!("Mock device {name} should be named like Mock 8ch or Mock 8ch 96000Hz.");
+        let mut words = name.split_whitespace();
+        if words.next() != Some("Mock") {
+            return Err(invalid());
+        }
+        let channels = words
+            .next()
+            .and_then(|word| word.strip_suffix("ch"))
+            .and_then(|channels| channels.parse::<u16>().ok())
+            .filter(|channels| *channels > 0)
+            .ok_or_else(invalid)?;
+        let sample_rate = match words.next() {
+            None => DEFAULT_SAMPLE_RATE,
+            Some(word) => word
+                .strip_suffix("Hz")
+                .and_then(|rate| rate.parse::<u32>().ok())
+                .filter(|rate| *rate > 0)
+                .ok_or_else(invalid)?,
+        };
+        if words.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            name: name.to_string(),
+            channels,
+            sample_rate,
+        })
+    }
+}
+
+/// Opens mock devices by name, `Mock 2ch` when there are none.
+///
+/// Like real devices they are recorded at the sample rate of the first one,
+/// the ones running at another rate are generated at it unless they are resampled.
+pub fn inputs(devices: &[String], resample: Option<Quality>) -> Result<Vec<Input>> {
+    let devices = if devices.is_empty() {
+        vec![DEFAULT_DEVICE.parse::<Device>()?]
+    } else {
+        devices
+            .iter()
+            .map(|device| device.parse())
+            .collect::<Result<Vec<Device>>>()?
+    };
+    let sample_rate = devices[0].sample_rate;
+    Ok(devices
+        .into_iter()
+        .map(|device| {
+            let config = |sample_rate| {
+                cpal::SupportedStreamConfig::new(
+                    device.channels,
+                    cpal::SampleRate(sample_rate),
+                    cpal::SupportedBufferSize::Unknown,
+                    cpal::SampleFormat::F32,
+                )
+            };
+            Input {
+                config: config(sample_rate),
+                native: (resample.is_some() && device.sample_rate != sample_rate)
+                    .then(|| config(device.sample_rate)),
+                resample,
+                source: Source::Mock(device),
+                loopback: false,
+            }
+        })
+        .collect())
+}
+
+/// Delivers the generated input of a mock device in real time while it plays.
+pub struct Stream {
+    playing: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Stream {
+    /// Starts a thread which delivers interleaved buffers, channel n carries a sine at n times 100 Hz.
+    pub fn start(
+        channels: u16,
+        sample_rate: u32,
+        clock: Clock,
+        mut callback: Callback<f32>,
+    ) -> Self {
+        let playing = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let (playing_in_thread, stopped_in_thread) = (Arc::clone(&playing), Arc::clone(&stopped));
+
+        let thread = std::thread::spawn(move || {
+            threads::tune(threads::Kind::Audio);
+            let channels = channels as usize;
+            let mut data = vec![0.0_f32; BUFFER_FRAMES * channels];
+            #[allow(clippy::cast_precision_loss)]
+            let budget = Duration::from_secs_f64(BUFFER_FRAMES as f64 / f64::from(sample_rate));
+            let (start, started_at) = (Instant::now(), clock.now());
+            // Frames delivered so far, the capture times advance with them like those of a device.
+            let mut frames: usize = 0;
+            let mut buffers: u32 = 0;
+            while !stopped_in_thread.load(Ordering::Relaxed) {
+                buffers += 1;
+                if let Some(wait) =
+                    (start + budget * buffers).checked_duration_since(Instant::now())
+                {
+                    std::thread::sleep(wait);
+                }
+                if !playing_in_thread.load(Ordering::Relaxed) {
+                    continue;
+                }
+                fill(&mut data, channels, frames, sample_rate);
+                #[allow(clippy::cast_possible_wrap)]
+                let captured_at = Clock::offset_by_frames(started_at, frames as isize, sample_rate);
+                callback(&data, captured_at);
+                frames += BUFFER_FRAMES;
+            }
+        });
+
+        Self {
+            playing,
+            stopped,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn play(&self) {
+        self.playing.store(true, Ordering::Relaxed);
+    }
+
+    pub fn pause(&self) {
+        self.playing.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.pause();
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+/// Fills interleaved frames starting at a frame of the signal.
+#[allow(clippy::cast_precision_loss)]
+fn fill(data: &mut [f32], channels: usize, first_frame: usize, sample_rate: u32) {
+    for (offset, frame) in data.chunks_mut(channels).enumerate() {
+        // Whole seconds hold whole periods, which keeps the phase precise.
+        let t = ((first_frame + offset) % sample_rate as usize) as f32 / sample_rate as f32;
+        for (channel, sample) in frame.iter_mut().enumerate() {
+            let frequency = CHANNEL_STEP_HZ * (channel + 1) as f32;
+            *sample = (std::f32::consts::TAU * frequency * t).sin() * AMPLITUDE;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::SmrecConfig, processing::Processing, stream, testing::TempDir};
+    use std::sync::Mutex;
+
+    #[test]
+    fn mock_device_names() {
+        assert_eq!(
+            "Mock 8ch 96000Hz".parse::<Device>().unwrap(),
+            Device {
+                name: "Mock 8ch 96000Hz".to_string(),
+                channels: 8,
+                sample_rate: 96_000
+            }
+        );
+        assert_eq!("Mock 2ch".parse::<Device>().unwrap().sample_rate, 48_000);
+        assert!("Mock".parse::<Device>().is_err());
+        assert!("Mock 0ch".parse::<Device>().is_err());
+        assert!("XR18".parse::<Device>().is_err());
+    }
+
+    /// Records two mock devices end to end and checks every file got the channel it was mapped to.
+    ///
+    /// The second device runs at another sample rate and is resampled to the rate of the first.
+    #[test]
+    fn mock_devices_record_end_to_end() {
+        let dir = TempDir::new("mock");
+        let out = dir.path();
+        let config_path = out.join("config.toml");
+        // Swaps the tracks of channel 1 and channel 5, the third of the second device, and leaves channel 2 out.
+        std::fs::write(
+            &config_path,
+            "exclude = [2]\n\n[map]\n1 = 5\n5 = 1\n\n[channel_names]\n1 = \"one\"\n5 = \"five\"\n",
+        )
+        .unwrap();
+        let inputs = inputs(
+            &[
+                "Mock 2ch 8000Hz".to_string(),
+                "Mock 3ch 16000Hz".to_string(),
+            ],
+            Some(Quality::Good),
+        )
+        .unwrap();
+        assert!(inputs[0].native.is_none());
+        assert_eq!(inputs[1].native.as_ref().unwrap().sample_rate().0, 16000);
+        let config = stream::Input::combined_config(&inputs);
+        assert_eq!((config.channels(), config.sample_rate().0), (5, 8000));
+
+        let mut smrec_config = SmrecConfig::new(
+            Some(config_path.to_string()),
+            Some(out.to_string()),
+            None,
+            None,
+            Vec::new(),
+            config,
+        )
+        .unwrap();
+        let take = smrec_config.writers().unwrap();
+        let directory = take.directory.clone();
+        let writers = Arc::new(Mutex::new(Some(take)));
+        let streams = stream::build_all(
+            &inputs,
+            &[0, 0],
+            &smrec_config.channels_to_write(),
+            *smrec_config.clock(),
+            &writers,
+            None,
+            None,
+            &Arc::new(Processing::new(5)),
+            &Arc::default(),
+            None,
+        )
+        .unwrap();
+        for stream in &streams {
+            stream.play().unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(300));
+        drop(streams);
+        writers.lock().unwrap().take().unwrap().finalize().unwrap();
+
+        // The dominant frequency of a file is found by counting rising zero crossings.
+        let frequency = |name: &str| {
+            let mut reader = hound::WavReader::open(directory.join(name)).unwrap();
+            let samples = reader
+                .samples::<f32>()
+                .map(Result::unwrap)
+                .collect::<Vec<_>>();
+            assert!(samples.len() >= 1024, "{name} has {} frames", samples.len());
+            let crossings = samples
+                .windows(2)
+                .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+                .count();
+            #[allow(clippy::cast_precision_loss)]
+            let frequency = crossings as f32 * 8000.0 / samples.len() as f32;
+            frequency
+        };
+        let mut files = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "wav"))
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(files.len(), 4, "{files:?}");
+        // Names follow the tracks, channel 5 plays 300 Hz on track 1.
+        assert!((frequency("one.wav") - 300.0).abs() < 20.0, "{files:?}");
+        assert!((frequency("five.wav") - 100.0).abs() < 20.0);
+    }
+}
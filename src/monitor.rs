@@ -0,0 +1,518 @@
+use crate::{clock::Clock, console};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use cpal::{
+    traits::{DeviceTrait, StreamTrait},
+    FromSample, Sample, SizedSample,
+};
+use std::{
+    collections::VecDeque,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{Arc, Mutex},
+};
+
+/// Starts every monitor packet, the last byte is the version of the format.
+const MAGIC: [u8; 4] = *b"SMR1";
+/// Magic, sequence number, sample rate and channel count.
+const HEADER_LEN: usize = 14;
+/// Keeps packets below the usual MTU so they are not fragmented.
+const MAX_PACKET_LEN: usize = 1400;
+/// Audio buffered before playback starts and after running dry.
+const LATENCY_SECS: f64 = 0.05;
+/// Buffered audio above this is dropped so clock drift between the machines does not pile up.
+const MAX_LATENCY_SECS: f64 = 0.2;
+/// The click on the beats of a MIDI clock, a decaying sine burst.
+const CLICK_HZ: f32 = 1000.0;
+const CLICK_SECS: f32 = 0.02;
+const CLICK_LEVEL: f32 = 0.5;
+
+/// Parses a `udp://host:port` address of a monitor stream.
+pub fn parse_url(url: &str) -> Result<SocketAddr> {
+    let address = url.strip_prefix("udp://").ok_or_else(|| {
+        anyhow!("Monitor address {url} should start with udp://, e.g. udp://192.168.1.20:18100.")
+    })?;
+    address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("Monitor address {url} could not be resolved."))
+}
+
+/// Sends the recorded channels as 16 bit PCM to a `smrec listen` receiver.
+pub struct Sender {
+    socket: UdpSocket,
+    target: SocketAddr,
+    sequence: u32,
+    sample_rate: u32,
+    channels: u16,
+    packet: Vec<u8>,
+    click: Click,
+}
+
+impl Sender {
+    pub fn new(target: SocketAddr, sample_rate: u32, channels: usize) -> Result<Self> {
+        let socket = UdpSocket::bind(if target.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        })?;
+        // Sending must never hold up the audio callback.
+        socket.set_nonblocking(true)?;
+        socket.set_broadcast(true)?;
+        Ok(Self {
+            socket,
+            target,
+            sequence: 0,
+            sample_rate,
+            channels: u16::try_from(channels)?,
+            packet: Vec::with_capacity(MAX_PACKET_LEN),
+            click: Click::new(sample_rate),
+        })
+    }
+
+    /// Renders the click of the next buffer, which was captured at a time, for the last beat of a MIDI clock.
+    pub fn click(
+        &mut self,
+        beat: Option<DateTime<Utc>>,
+        captured_at: DateTime<Utc>,
+        frames: usize,
+    ) {
+        self.click.render(beat, captured_at, frames);
+    }
+
+    const fn frames_per_packet(&self) -> usize {
+        let frame_len = 2 * self.channels as usize;
+        if frame_len == 0 {
+            return 1;
+        }
+        (MAX_PACKET_LEN - HEADER_LEN) / frame_len
+    }
+
+    /// Interleaves the de-interleaved channels into packets and sends them, channels which are not monitored are silent.
+    ///
+    /// The click rendered for the buffer is mixed into every channel.
+    pub fn send<T>(&mut self, channel_buffer: &[Vec<T>], monitored: &[bool])
+    where
+        T: Sample,
+        i16: FromSample<T>,
+    {
+        let frames = channel_buffer.first().map_or(0, Vec::len);
+        let frames_per_packet = self.frames_per_packet();
+
+        for first_frame in (0..frames).step_by(frames_per_packet) {
+            self.packet.clear();
+            self.packet.extend_from_slice(&MAGIC);
+            self.packet.extend_from_slice(&self.sequence.to_le_bytes());
+            self.packet
+                .extend_from_slice(&self.sample_rate.to_le_bytes());
+            self.packet.extend_from_slice(&self.channels.to_le_bytes());
+            for frame in first_frame..frames.min(first_frame + frames_per_packet) {
+                let click = self
+                    .click
+                    .samples
+                    .get(frame)
+                    .copied()
+                    .unwrap_or(0.0)
+                    .to_sample::<i16>();
+                for (channel, monitored) in channel_buffer.iter().zip(monitored) {
+                    let sample = if *monitored {
+                        i16::from_sample(channel[frame])
+                    } else {
+                        0
+                    }
+                    .saturating_add(click);
+                    self.packet.extend_from_slice(&sample.to_le_bytes());
+                }
+            }
+            // Monitoring is best effort, the recording goes on when the network does not keep up.
+            let _ = self.socket.send_to(&self.packet, self.target);
+            self.sequence = self.sequence.wrapping_add(1);
+        }
+    }
+}
+
+/// Synthesizes a click at the frames beats of an external MIDI clock fall on.
+struct Click {
+    sample_rate: u32,
+    length: usize,
+    /// The beat which the last click was started for.
+    last_beat: Option<DateTime<Utc>>,
+    /// Frames into the running click.
+    position: Option<usize>,
+    /// The click of the current buffer, empty when there is none.
+    samples: Vec<f32>,
+}
+
+impl Click {
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            length: (sample_rate as f32 * CLICK_SECS) as usize,
+            last_beat: None,
+            position: None,
+            samples: Vec::new(),
+        }
+    }
+
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss,
+        clippy::cast_possible_wrap
+    )]
+    fn render(&mut self, beat: Option<DateTime<Utc>>, captured_at: DateTime<Utc>, frames: usize) {
+        self.samples.clear();
+        let mut start = 0;
+        if let Some(beat) = beat.filter(|beat| Some(*beat) != self.last_beat) {
+            let offset = Clock::frames_between(captured_at, beat, self.sample_rate);
+            // A beat in a later buffer waits for it.
+            if offset < frames as isize {
+                self.last_beat = Some(beat);
+                // The message of a beat may arrive after the buffer it falls into, it is clicked late then
+                // unless the click would be over already.
+                if offset > -(self.length as isize) {
+                    start = offset.max(0) as usize;
+                    self.position = Some(0);
+                }
+            }
+        }
+        let Some(mut position) = self.position else {
+            return;
+        };
+        self.samples.resize(frames, 0.0);
+        for sample in &mut self.samples[start..] {
+            if position >= self.length {
+                self.position = None;
+                return;
+            }
+            let t = position as f32 / self.sample_rate as f32;
+            let decay = 1.0 - position as f32 / self.length as f32;
+            *sample = CLICK_LEVEL * decay * (std::f32::consts::TAU * CLICK_HZ * t).sin();
+            position += 1;
+        }
+        self.position = Some(position);
+    }
+}
+
+#[derive(Debug)]
+struct Packet {
+    sequence: u32,
+    sample_rate: u32,
+    channels: usize,
+    /// Interleaved samples.
+    samples: Vec<f32>,
+}
+
+impl Packet {
+    fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.len() < HEADER_LEN || packet[..4] != MAGIC {
+            return None;
+        }
+        let channels = usize::from(u16::from_le_bytes(packet[12..14].try_into().ok()?));
+        if channels == 0 {
+            return None;
+        }
+        Some(Self {
+            sequence: u32::from_le_bytes(packet[4..8].try_into().ok()?),
+            sample_rate: u32::from_le_bytes(packet[8..12].try_into().ok()?),
+            channels,
+            samples: packet[HEADER_LEN..]
+                .chunks_exact(2)
+                .map(|sample| f32::from_sample(i16::from_le_bytes([sample[0], sample[1]])))
+                .collect(),
+        })
+    }
+}
+
+/// Received audio waiting to be played.
+struct Playback {
+    channels: usize,
+    queue: VecDeque<f32>,
+    /// Samples to buffer before playing.
+    latency: usize,
+    /// Samples to buffer at most.
+    max_latency: usize,
+    buffering: bool,
+}
+
+impl Playback {
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn new(channels: usize, sample_rate: u32) -> Self {
+        let frames = |secs: f64| (secs * f64::from(sample_rate)) as usize;
+        Self {
+            channels,
+            queue: VecDeque::new(),
+            latency: frames(LATENCY_SECS) * channels,
+            max_latency: frames(MAX_LATENCY_SECS) * channels,
+            buffering: true,
+        }
+    }
+
+    fn push(&mut self, samples: &[f32]) {
+        self.queue.extend(samples);
+        if self.queue.len() > self.max_latency {
+            let excess = self.queue.len() - self.latency;
+            self.queue.drain(..excess - excess % self.channels);
+        }
+    }
+
+    /// Takes the next frame, `None` while buffering.
+    fn pull(&mut self, frame: &mut [f32]) -> Option<()> {
+        if self.buffering && self.queue.len() < self.latency.max(self.channels) {
+            return None;
+        }
+        self.buffering = self.queue.len() < self.channels;
+        if self.buffering {
+            return None;
+        }
+        for sample in frame.iter_mut() {
+            *sample = self.queue.pop_front()?;
+        }
+        Some(())
+    }
+}
+
+/// Receives a monitor stream sent with `--monitor` and plays it through the output device.
+pub fn listen(url: &str, device: &cpal::Device) -> Result<()> {
+    let socket = UdpSocket::bind(parse_url(url)?)?;
+    println!("Listening for a monitor stream on {url}...");
+
+    let mut buffer = vec![0_u8; u16::MAX as usize];
+    let mut receive = || -> Result<Packet> {
+        loop {
+            let (len, _) = socket.recv_from(&mut buffer)?;
+            if let Some(packet) = Packet::parse(&buffer[..len]) {
+                return Ok(packet);
+            }
+        }
+    };
+
+    let mut packet = receive()?;
+    let (sample_rate, channels) = (packet.sample_rate, packet.channels);
+    let config = output_config(device, sample_rate)?;
+    println!(
+        "Receiving {channels} channel(s) at {sample_rate} Hz, playing through \"{}\".",
+        device.name()?
+    );
+
+    let playback = Arc::new(Mutex::new(Playback::new(channels, sample_rate)));
+    let stream = build_output(device, &config, Arc::clone(&playback))?;
+    stream.play()?;
+
+    let mut next_sequence = packet.sequence;
+    loop {
+        if packet.sample_rate != sample_rate || packet.channels != channels {
+            bail!(
+                "The monitor stream changed to {} channel(s) at {} Hz, listen again to follow it.",
+                packet.channels,
+                packet.sample_rate
+            );
+        }
+        // A lower sequence number is a new take on the sending side.
+        if packet.sequence > next_sequence {
+            eprintln!(
+                "Lost {} monitor packet(s).",
+                packet.sequence - next_sequence
+            );
+        }
+        next_sequence = packet.sequence.wrapping_add(1);
+        playback.lock().unwrap().push(&packet.samples);
+        packet = receive()?;
+    }
+}
+
+fn output_config(device: &cpal::Device, sample_rate: u32) -> Result<cpal::SupportedStreamConfig> {
+    let default = device.default_output_config()?;
+    if default.sample_rate().0 == sample_rate {
+        return Ok(default);
+    }
+    let mut supported = device.supported_output_configs()?.collect::<Vec<_>>();
+    // Prefer the channel count of the default configuration.
+    supported.sort_by_key(|config| config.channels() != default.channels());
+    supported
+        .into_iter()
+        .find(|config| {
+            (config.min_sample_rate().0..=config.max_sample_rate().0).contains(&sample_rate)
+        })
+        .map(|config| config.with_sample_rate(cpal::SampleRate(sample_rate)))
+        .ok_or_else(|| {
+            anyhow!("The output device does not support {sample_rate} Hz of the monitor stream.")
+        })
+}
+
+fn build_output(
+    device: &cpal::Device,
+    config: &cpal::SupportedStreamConfig,
+    playback: Arc<Mutex<Playback>>,
+) -> Result<cpal::Stream> {
+    match config.sample_format() {
+        cpal::SampleFormat::I8 => output::<i8>(device, config, playback),
+        cpal::SampleFormat::I16 => output::<i16>(device, config, playback),
+        cpal::SampleFormat::I32 => output::<i32>(device, config, playback),
+        cpal::SampleFormat::F32 => output::<f32>(device, config, playback),
+        sample_format => bail!("Sample format {sample_format:?} is not supported by this program."),
+    }
+}
+
+fn output<T>(
+    device: &cpal::Device,
+    config: &cpal::SupportedStreamConfig,
+    playback: Arc<Mutex<Playback>>,
+) -> Result<cpal::Stream>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let output_channels = config.channels() as usize;
+    let received_channels = playback.lock().unwrap().channels;
+    let mut received = vec![0.0; received_channels];
+    let mut mixed = vec![0.0; output_channels];
+
+    Ok(device.build_output_stream(
+        &config.clone().into(),
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            let mut playback = playback.lock().unwrap();
+            for frame in data.chunks_mut(output_channels) {
+                if playback.pull(&mut received).is_some() {
+                    mix(&received, &mut mixed);
+                } else {
+                    mixed.fill(0.0);
+                }
+                for (sample, value) in frame.iter_mut().zip(&mixed) {
+                    *sample = T::from_sample(*value);
+                }
+            }
+            drop(playback);
+        },
+        move |err| {
+            console::error(format!("An error occurred on the output stream: {err}"));
+        },
+        None,
+    )?)
+}
+
+/// Spreads the received channels over the output channels, wrapping around whichever has fewer.
+///
+/// Received channels sharing an output channel are mixed down so they do not clip.
+#[allow(clippy::cast_precision_loss)]
+fn mix(received: &[f32], output: &mut [f32]) {
+    if received.is_empty() || output.is_empty() {
+        return;
+    }
+    if output.len() >= received.len() {
+        for (channel, sample) in output.iter_mut().enumerate() {
+            *sample = received[channel % received.len()];
+        }
+    } else {
+        output.fill(0.0);
+        for (channel, sample) in received.iter().enumerate() {
+            output[channel % output.len()] += sample;
+        }
+        let gain = output.len() as f32 / received.len() as f32;
+        for sample in output {
+            *sample *= gain;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn packets_reach_the_receiver() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let url = format!("udp://{}", receiver.local_addr().unwrap());
+        let mut sender = Sender::new(parse_url(&url).unwrap(), 48000, 2).unwrap();
+        assert!(parse_url("127.0.0.1:18100").is_err());
+
+        let left = (0..1000_i16).collect::<Vec<_>>();
+        let right = left.iter().map(|sample| -sample).collect::<Vec<_>>();
+        sender.send(&[left.clone(), right.clone()], &[true, true]);
+
+        let mut buffer = [0_u8; MAX_PACKET_LEN];
+        let mut frames = 0;
+        for sequence in 0..3 {
+            let len = receiver.recv(&mut buffer).unwrap();
+            assert!(len <= MAX_PACKET_LEN);
+            let packet = Packet::parse(&buffer[..len]).unwrap();
+            assert_eq!(packet.sequence, sequence);
+            assert_eq!((packet.sample_rate, packet.channels), (48000, 2));
+            assert_eq!(
+                packet.samples[2],
+                f32::from_sample(i16::try_from(frames).unwrap() + 1)
+            );
+            assert_eq!(packet.samples[3], -packet.samples[2]);
+            frames += packet.samples.len() / 2;
+        }
+        assert_eq!(frames, 1000);
+        assert!(Packet::parse(b"OSC ").is_none());
+
+        // A muted channel is sent as silence.
+        sender.send(&[left, right], &[false, true]);
+        let len = receiver.recv(&mut buffer).unwrap();
+        let packet = Packet::parse(&buffer[..len]).unwrap();
+        assert_eq!(packet.samples[2], 0.0);
+        assert_eq!(packet.samples[3], f32::from_sample(-1_i16));
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn channels_wrap_around_the_output() {
+        let mut stereo = [0.0; 2];
+        mix(&[0.5], &mut stereo);
+        assert_eq!(stereo, [0.5, 0.5]);
+        mix(&[0.25, 0.5, 0.75, 1.0], &mut stereo);
+        assert_eq!(stereo, [0.5, 0.75]);
+
+        let mut playback = Playback::new(2, 1000);
+        let mut frame = [0.0; 2];
+        playback.push(&[1.0; 98]);
+        assert!(playback.pull(&mut frame).is_none());
+        playback.push(&[1.0; 2]);
+        assert!(playback.pull(&mut frame).is_some());
+        playback.push(&[0.0; 1000]);
+        assert_eq!(playback.queue.len(), 100);
+    }
+
+    #[test]
+    fn clicks_start_at_the_frame_of_the_beat() {
+        // 1 kHz so a frame is a millisecond, a click is 20 frames long.
+        let mut click = Click::new(1000);
+        let captured_at = Clock::new().started();
+        let at = |frames| Clock::offset_by_frames(captured_at, frames, 1000);
+
+        click.render(None, captured_at, 10);
+        assert!(click.samples.is_empty());
+        // A beat in a later buffer waits for it.
+        click.render(Some(at(15)), captured_at, 10);
+        assert!(click.samples.is_empty());
+        click.render(Some(at(15)), at(10), 10);
+        assert_eq!(click.samples.len(), 10);
+        assert!(click.samples[..5].iter().all(|sample| *sample == 0.0));
+        assert!(click.samples[6] != 0.0);
+        // It goes on into the next buffers until it is over.
+        click.render(Some(at(15)), at(20), 10);
+        assert!(click.samples.iter().all(|sample| *sample != 0.0));
+        click.render(Some(at(15)), at(30), 10);
+        assert!(click.samples[..5].iter().any(|sample| *sample != 0.0));
+        assert!(click.samples[5..].iter().all(|sample| *sample == 0.0));
+        click.render(Some(at(15)), at(40), 10);
+        assert!(click.samples.is_empty());
+
+        // A beat which arrived late is clicked at the start of the buffer, one long gone is not clicked.
+        click.render(Some(at(45)), at(50), 10);
+        assert!(click.samples[1] != 0.0);
+        let mut click = Click::new(1000);
+        click.render(Some(at(0)), at(100), 10);
+        assert!(click.samples.is_empty());
+    }
+}
@@ -0,0 +1,166 @@
+// Simple NTP (RFC 4330): the request and the reply are timestamped on both ends, which gives the offset of the
+// system clock against the server without trusting either clock for the round trip.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::{
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    thread::JoinHandle,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const PORT: u16 = 123;
+/// How long the server is waited for, a take does not need its offset before it is finalized.
+const TIMEOUT: Duration = Duration::from_secs(2);
+/// Seconds from the NTP epoch in 1900 to the Unix epoch.
+const NTP_UNIX_OFFSET_SECS: u64 = 2_208_988_800;
+const PACKET: usize = 48;
+/// Version 4 in client mode.
+const CLIENT: u8 = (4 << 3) | 3;
+const SERVER_MODE: u8 = 4;
+
+/// How far the system clock was from the time of an NTP server when a take started.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+    pub server: String,
+    /// Milliseconds to add to the system clock to get the time of the server.
+    pub offset_ms: f64,
+    /// Round trip of the request in milliseconds, the offset is accurate to about half of it.
+    pub delay_ms: f64,
+}
+
+/// A query which runs in the background while the take is recorded.
+pub struct Query(JoinHandle<Result<Measurement>>);
+
+impl Query {
+    pub fn start(server: &str) -> Self {
+        let server = server.to_string();
+        Self(std::thread::spawn(move || query(&server)))
+    }
+
+    pub fn wait(self) -> Result<Measurement> {
+        self.0
+            .join()
+            .map_err(|_| anyhow!("The NTP query panicked."))?
+    }
+}
+
+/// Asks a server, given as `host` or `host:port`, for its time.
+pub fn query(server: &str) -> Result<Measurement> {
+    let address = server
+        .to_socket_addrs()
+        .or_else(|_| (server, PORT).to_socket_addrs())
+        .with_context(|| format!("Error resolving NTP server {server}."))?
+        .next()
+        .ok_or_else(|| anyhow!("NTP server {server} has no address."))?;
+    let local = match address {
+        SocketAddr::V4(_) => SocketAddr::from(([0, 0, 0, 0], 0)),
+        SocketAddr::V6(_) => SocketAddr::from(([0; 16], 0)),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+
+    let mut request = [0; PACKET];
+    request[0] = CLIENT;
+    let sent = now();
+    request[40..48].copy_from_slice(&sent.to_be_bytes());
+    socket.send_to(&request, address)?;
+
+    let mut reply = [0; PACKET];
+    let received = loop {
+        let (length, from) = socket
+            .recv_from(&mut reply)
+            .with_context(|| format!("NTP server {server} did not answer."))?;
+        // Anyone can send to the socket, only the answer to the request counts.
+        if from == address && length >= PACKET && reply[24..32] == sent.to_be_bytes() {
+            break now();
+        }
+    };
+    if reply[0] & 0b111 != SERVER_MODE {
+        bail!("{server} did not answer as an NTP server.");
+    }
+    // Kiss-o'-Death, the server asks to be left alone or has no time itself.
+    if reply[1] == 0 {
+        bail!(
+            "NTP server {server} refused the request with {}.",
+            String::from_utf8_lossy(&reply[12..16])
+        );
+    }
+    let timestamp =
+        |at: usize| u64::from_be_bytes(reply[at..at + 8].try_into().unwrap_or_default());
+    let (offset, delay) = offset(sent, timestamp(32), timestamp(40), received);
+    Ok(Measurement {
+        server: server.to_string(),
+        offset_ms: offset * 1000.0,
+        delay_ms: delay * 1000.0,
+    })
+}
+
+/// The offset and the round trip in seconds from when the request was sent, received by the server, answered
+/// and the answer received.
+fn offset(sent: u64, received: u64, answered: u64, arrived: u64) -> (f64, f64) {
+    let offset = f64::midpoint(seconds(received, sent), seconds(answered, arrived));
+    let delay = seconds(arrived, sent) - seconds(answered, received);
+    (offset, delay)
+}
+
+/// Seconds from `b` to `a`, which are close enough to each other to cross the end of an NTP era.
+#[allow(clippy::cast_possible_wrap, clippy::cast_precision_loss)]
+fn seconds(a: u64, b: u64) -> f64 {
+    a.wrapping_sub(b) as i64 as f64 / 4_294_967_296.0
+}
+
+/// The system clock as an NTP timestamp, seconds since 1900 and their fraction in 32 bits each.
+fn now() -> u64 {
+    to_timestamp(SystemTime::now())
+}
+
+fn to_timestamp(time: SystemTime) -> u64 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = (since_epoch.as_secs() + NTP_UNIX_OFFSET_SECS) & 0xFFFF_FFFF;
+    let fraction = (u64::from(since_epoch.subsec_nanos()) << 32) / 1_000_000_000;
+    (seconds << 32) | fraction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers one request with a clock which is ahead of the system clock.
+    fn serve(ahead: Duration, stratum: u8) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut request = [0; PACKET];
+            let (_, from) = socket.recv_from(&mut request).unwrap();
+            let mut reply = [0; PACKET];
+            reply[0] = (4 << 3) | SERVER_MODE;
+            reply[1] = stratum;
+            reply[12..16].copy_from_slice(b"RATE");
+            reply[24..32].copy_from_slice(&request[40..48]);
+            let time = to_timestamp(SystemTime::now() + ahead).to_be_bytes();
+            reply[32..40].copy_from_slice(&time);
+            reply[40..48].copy_from_slice(&time);
+            socket.send_to(&reply, from).unwrap();
+        });
+        address
+    }
+
+    #[test]
+    fn the_offset_of_the_system_clock_is_measured() {
+        // A server 1 s ahead, answering after 0.5 s from a 0.2 s round trip.
+        let at = |secs: f64| to_timestamp(UNIX_EPOCH + Duration::from_secs_f64(1.7e9 + secs));
+        let (offset, delay) = offset(at(0.0), at(1.1), at(1.6), at(0.7));
+        assert!((offset - 1.0).abs() < 1e-6);
+        assert!((delay - 0.2).abs() < 1e-6);
+
+        let server = serve(Duration::from_millis(1500), 2).to_string();
+        let measured = query(&server).unwrap();
+        assert_eq!(measured.server, server);
+        assert!((measured.offset_ms - 1500.0).abs() < 100.0);
+        assert!(measured.delay_ms < 100.0);
+
+        let refusing = serve(Duration::ZERO, 0).to_string();
+        let err = Query::start(&refusing).wait().unwrap_err();
+        assert!(err.to_string().contains("RATE"));
+    }
+}
@@ -1,194 +1,1131 @@
-use crate::types::Action;
-use anyhow::Result;
-use rosc::{encoder::encode, OscMessage, OscPacket, OscType};
+use crate::{
+    control::ControlSurface,
+    meters::{Ballistics, Level, Meter},
+    processing::Processing,
+    types::{Action, Transport},
+};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use crossbeam::channel::{Receiver, Sender};
+use rosc::{encoder::encode, OscBundle, OscMessage, OscPacket, OscTime, OscType};
+use serde::Deserialize;
 use std::{
-    net::{SocketAddr, UdpSocket},
+    collections::{HashMap, VecDeque},
+    net::{IpAddr, SocketAddr, UdpSocket},
     str::FromStr,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
+/// How long a subscription lives unless the client subscribes again.
+const SUBSCRIPTION_TIMEOUT_SECS: u64 = 300;
+const SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(SUBSCRIPTION_TIMEOUT_SECS);
+
+/// Rate of the live meters unless a client asks for another one, and the highest one.
+const METER_RATE: f32 = 30.0;
+const MAX_METER_RATE: f32 = 60.0;
+
+/// The OSC time tag which means "immediately".
+const IMMEDIATELY: (u32, u32) = (0, 1);
+/// Seconds from the NTP epoch in 1900 which OSC time tags count from to the Unix epoch.
+const NTP_UNIX_OFFSET_SECS: i64 = 2_208_988_800;
+
+/// Limits of continuous feedback such as the elapsed time, configured in `[osc_feedback]`.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct OscFeedback {
+    /// Bundles of continuous messages sent per second.
+    #[serde(default = "OscFeedback::default_rate")]
+    rate: f32,
+    /// How many continuous messages may wait to be sent, the oldest are dropped first.
+    #[serde(default = "OscFeedback::default_queue")]
+    queue: usize,
+}
+
+impl Default for OscFeedback {
+    fn default() -> Self {
+        Self {
+            rate: Self::default_rate(),
+            queue: Self::default_queue(),
+        }
+    }
+}
+
+impl OscFeedback {
+    const fn default_rate() -> f32 {
+        10.0
+    }
+
+    const fn default_queue() -> usize {
+        32
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / self.rate.max(0.1))
+    }
+}
+
+/// Queue of continuous messages which are sent in bundles at a limited rate.
+///
+/// A newer message replaces a waiting one with the same address and the oldest message is dropped when the queue is full,
+/// so the notification path never falls behind.
+struct Throttle {
+    queue: VecDeque<OscMessage>,
+    capacity: usize,
+}
+
+impl Throttle {
+    fn new(capacity: usize) -> Self {
+        Self {
+            queue: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+        }
+    }
+
+    fn push(&mut self, message: OscMessage) {
+        self.queue.retain(|waiting| waiting.addr != message.addr);
+        if self.queue.len() == self.capacity {
+            self.queue.pop_front();
+        }
+        self.queue.push_back(message);
+    }
+
+    /// Takes everything waiting as a single bundle.
+    fn flush(&mut self) -> Option<OscPacket> {
+        if self.queue.is_empty() {
+            return None;
+        }
+        Some(OscPacket::Bundle(OscBundle {
+            timetag: IMMEDIATELY.into(),
+            content: self.queue.drain(..).map(OscPacket::Message).collect(),
+        }))
+    }
+}
+
+/// Changes to the subscriber list, sent from the UDP thread to the messaging thread.
+enum Subscription {
+    Subscribe(SocketAddr),
+    Unsubscribe(SocketAddr),
+    SubscribeMeters(SocketAddr, MeterSubscription),
+    UnsubscribeMeters(SocketAddr),
+}
+
+/// What a client of the live meters asked for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MeterSubscription {
+    ballistics: Ballistics,
+    interval: Duration,
+}
+
+/// Clients of the live meters, each with its subscription, the time it last subscribed and when it is sent meters next.
+#[derive(Default)]
+struct MeterSubscribers {
+    clients: HashMap<SocketAddr, (MeterSubscription, Instant, Instant)>,
+}
+
+impl MeterSubscribers {
+    fn subscribe(&mut self, addr: SocketAddr, subscription: MeterSubscription, now: Instant) {
+        self.clients.insert(addr, (subscription, now, now));
+    }
+
+    fn unsubscribe(&mut self, addr: SocketAddr) {
+        self.clients.remove(&addr);
+    }
+
+    /// Drops the expired subscriptions and returns the clients which are due with their ballistics.
+    ///
+    /// A client which was not served in time is sent meters once and then at its rate again, it does not get a burst.
+    fn due(&mut self, now: Instant) -> Vec<(SocketAddr, Ballistics)> {
+        self.clients.retain(|addr, (_, subscribed_at, _)| {
+            let alive = now.duration_since(*subscribed_at) < SUBSCRIPTION_TIMEOUT;
+            if !alive {
+                println!("OSC meter subscription of {addr} timed out.");
+            }
+            alive
+        });
+        let mut due = Vec::new();
+        for (addr, (subscription, _, next)) in &mut self.clients {
+            if *next <= now {
+                due.push((*addr, subscription.ballistics));
+                *next += subscription.interval;
+                if *next <= now {
+                    *next = now + subscription.interval;
+                }
+            }
+        }
+        due
+    }
+
+    /// How long until a client is due, the subscription timeout when there are none.
+    fn wait(&self, now: Instant) -> Duration {
+        self.clients
+            .values()
+            .map(|(_, _, next)| next.saturating_duration_since(now))
+            .min()
+            .unwrap_or(SUBSCRIPTION_TIMEOUT)
+    }
+}
+
+/// Everyone who receives event notifications.
+struct Subscribers {
+    /// The send address from the cli, it never expires.
+    permanent: Option<SocketAddr>,
+    /// Dynamically subscribed clients with the time they last subscribed.
+    dynamic: HashMap<SocketAddr, Instant>,
+}
+
+impl Subscribers {
+    fn new(permanent: Option<SocketAddr>) -> Self {
+        Self {
+            permanent,
+            dynamic: HashMap::new(),
+        }
+    }
+
+    fn subscribe(&mut self, addr: SocketAddr, now: Instant) {
+        self.dynamic.insert(addr, now);
+    }
+
+    fn unsubscribe(&mut self, addr: SocketAddr) {
+        self.dynamic.remove(&addr);
+    }
+
+    /// Drops the expired subscriptions and returns the addresses to notify.
+    fn addresses(&mut self, now: Instant) -> Vec<SocketAddr> {
+        self.dynamic.retain(|addr, subscribed_at| {
+            let alive = now.duration_since(*subscribed_at) < SUBSCRIPTION_TIMEOUT;
+            if !alive {
+                println!("OSC subscription of {addr} timed out.");
+            }
+            alive
+        });
+
+        let mut addresses = self.permanent.into_iter().collect::<Vec<_>>();
+        for addr in self.dynamic.keys() {
+            if !addresses.contains(addr) {
+                addresses.push(*addr);
+            }
+        }
+        addresses
+    }
+}
+
 pub struct Osc {
     sender_socket: Arc<UdpSocket>,
-    receiver_socket: Arc<UdpSocket>,
-    sender_channel: crossbeam::channel::Sender<Action>,
-    receiver_channel: crossbeam::channel::Receiver<Action>,
-    udp_thread: Option<std::thread::JoinHandle<()>>,
+    receiver_sockets: Vec<Arc<UdpSocket>>,
+    send_addr: Option<SocketAddr>,
+    feedback: OscFeedback,
+    processing: Arc<Processing>,
+    udp_threads: Vec<std::thread::JoinHandle<()>>,
     messaging_thread: Option<std::thread::JoinHandle<()>>,
 }
 
 impl Osc {
+    /// Binds a receive socket to the listen address of the configuration and to every additional one.
     pub fn new(
         osc_config: &[String],
-        sender_channel: crossbeam::channel::Sender<Action>,
-        receiver_channel: crossbeam::channel::Receiver<Action>,
+        listen: &[SocketAddr],
+        feedback: OscFeedback,
+        processing: Arc<Processing>,
     ) -> Result<Self> {
-        let recv_addr = if let Some(addr) = osc_config.get(0) {
-            SocketAddr::from_str(addr)?
-        } else {
+        let mut recv_addrs = osc_config
+            .first()
+            .map(|addr| SocketAddr::from_str(addr))
+            .transpose()?
+            .into_iter()
+            .chain(listen.iter().copied())
+            .collect::<Vec<_>>();
+        if recv_addrs.is_empty() {
             // Listen to all network and a random port by default.
-            SocketAddr::from(([0, 0, 0, 0], 0))
-        };
+            recv_addrs.push(SocketAddr::from(([0, 0, 0, 0], 0)));
+        }
 
+        // Without a send address only subscribed clients are notified.
         let send_addr = if let Some(addr) = osc_config.get(1) {
-            SocketAddr::from_str(addr)?
+            Some(SocketAddr::from_str(addr)?)
         } else {
-            SocketAddr::from(([127, 0, 0, 1], 0))
+            None
         };
 
         let sender_socket = Arc::new(
             // We're binding to build the socket, we don't care about the address because we're not going to listen.
             UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], 0)))
-                .unwrap_or_else(|_| panic!("Failed to bind socket to address {send_addr}")),
+                .unwrap_or_else(|_| panic!("Failed to bind socket for sending OSC messages")),
         );
 
-        // The address we're going to send to.
-        sender_socket
-            .connect(send_addr)
-            .unwrap_or_else(|_| panic!("Failed to connect socket to address {send_addr}"));
-
-        match send_addr.ip() {
-            std::net::IpAddr::V4(addr) => {
-                if addr.is_broadcast() {
-                    if let Err(err) = sender_socket.set_broadcast(true) {
-                        eprintln!("Error setting socket to broadcast: {err}");
+        if let Some(send_addr) = send_addr {
+            match send_addr.ip() {
+                IpAddr::V4(addr) => {
+                    if addr.is_broadcast() {
+                        if let Err(err) = sender_socket.set_broadcast(true) {
+                            eprintln!("Error setting socket to broadcast: {err}");
+                        }
                     }
                 }
-            }
-            std::net::IpAddr::V6(_) => {
-                panic!("IPv6 is not supported yet.")
-            }
-        }
-
-        match send_addr.ip() {
-            std::net::IpAddr::V4(addr) => {
-                if addr.is_broadcast() {
-                    if let Err(err) = sender_socket.set_broadcast(true) {
-                        eprintln!("Error setting socket to broadcast: {err}");
-                    }
+                IpAddr::V6(_) => {
+                    panic!("IPv6 is not supported yet.")
                 }
             }
-            std::net::IpAddr::V6(_) => {
-                panic!("IPv6 is not supported yet.")
-            }
         }
 
-        let receiver_socket = Arc::new(
-            UdpSocket::bind(recv_addr)
-                .unwrap_or_else(|_| panic!("Failed to bind socket to address {recv_addr}")),
-        );
+        let receiver_sockets = recv_addrs
+            .into_iter()
+            .map(|recv_addr| {
+                UdpSocket::bind(recv_addr)
+                    .map(Arc::new)
+                    .map_err(|err| anyhow!("Failed to bind socket to address {recv_addr}: {err}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
 
-        println!("Will be sending OSC messages to {send_addr}");
-        println!(
-            "Listening for OSC messages on {}",
-            receiver_socket.local_addr()?
-        );
+        if let Some(send_addr) = send_addr {
+            println!("Will be sending OSC messages to {send_addr} and subscribers");
+        } else {
+            println!("Will be sending OSC messages to subscribers");
+        }
+        for receiver_socket in &receiver_sockets {
+            println!(
+                "Listening for OSC messages on {}",
+                receiver_socket.local_addr()?
+            );
+        }
 
         Ok(Self {
             sender_socket,
-            receiver_socket,
-            sender_channel,
-            receiver_channel,
-            udp_thread: None,
+            receiver_sockets,
+            send_addr,
+            feedback,
+            processing,
+            udp_threads: Vec::new(),
             messaging_thread: None,
         })
     }
 
-    pub fn listen(&mut self) {
+    /// The addresses OSC messages are received on, with the ports the system picked.
+    pub fn listen_addrs(&self) -> Vec<SocketAddr> {
+        self.receiver_sockets
+            .iter()
+            .filter_map(|socket| socket.local_addr().ok())
+            .collect()
+    }
+}
+
+impl ControlSurface for Osc {
+    #[allow(clippy::too_many_lines)]
+    fn start(&mut self, to_main_thread: Sender<Action>, events: Receiver<Action>) -> Result<()> {
+        let (subscription_sender, subscription_receiver) =
+            crossbeam::channel::unbounded::<Subscription>();
+
         if self.messaging_thread.is_none() {
             let socket = self.sender_socket.clone();
-            let receiver_channel = self.receiver_channel.clone();
+            let mut subscribers = Subscribers::new(self.send_addr);
+            let mut meter_subscribers = MeterSubscribers::default();
+            let processing = self.processing.clone();
+            let mut throttle = Throttle::new(self.feedback.queue);
+            let ticker = crossbeam::channel::tick(self.feedback.interval());
+            // When the running take started, to report the elapsed time.
+            let mut recording_since: Option<Instant> = None;
             self.messaging_thread = Some(std::thread::spawn(move || loop {
-                match receiver_channel.recv() {
-                    Ok(Action::Start) => {
-                        if let Err(err) = socket.send(
-                            &encode(&OscPacket::Message(OscMessage {
-                                addr: "/smrec/start".to_string(),
-                                args: Vec::new(),
-                            }))
-                            .expect("OSC packet should encode."),
-                        ) {
-                            eprintln!("Error sending OSC packet: {err}");
-                        };
-                    }
-                    Ok(Action::Stop) => {
-                        if let Err(err) = socket.send(
-                            &encode(&OscPacket::Message(OscMessage {
-                                addr: "/smrec/stop".to_string(),
-                                args: Vec::new(),
-                            }))
-                            .expect("OSC packet should encode."),
-                        ) {
-                            eprintln!("Error sending OSC packet: {err}");
-                        };
-                    }
-                    Ok(Action::Err(err)) => {
-                        if let Err(err) = socket.send(
-                            &encode(&OscPacket::Message(OscMessage {
-                                addr: "/smrec/error".to_string(),
-                                args: vec![OscType::String(err)],
-                            }))
-                            .expect("OSC packet should encode."),
-                        ) {
-                            eprintln!("Error sending OSC packet: {err}");
+                let now = Instant::now();
+                for (to, ballistics) in meter_subscribers.due(now) {
+                    send(
+                        &socket,
+                        to,
+                        "/smrec/meters",
+                        meter_args(&processing.meters().levels(ballistics)),
+                    );
+                }
+                let wait = meter_subscribers.wait(now);
+                crossbeam::channel::select! {
+                    recv(ticker) -> _ => {
+                        if let Some(since) = recording_since {
+                            throttle.push(OscMessage {
+                                addr: "/smrec/time".to_string(),
+                                args: vec![OscType::Float(since.elapsed().as_secs_f32())],
+                            });
+                        }
+                        if let Some(bundle) = throttle.flush() {
+                            for to in subscribers.addresses(Instant::now()) {
+                                send_packet(&socket, to, &bundle);
+                            }
+                        }
+                    },
+                    recv(subscription_receiver) -> subscription => match subscription {
+                        Ok(Subscription::Subscribe(addr)) => {
+                            println!("OSC client {addr} subscribed.");
+                            subscribers.subscribe(addr, Instant::now());
+                            send(&socket, addr, "/smrec/subscribed", Vec::new());
+                        }
+                        Ok(Subscription::Unsubscribe(addr)) => {
+                            println!("OSC client {addr} unsubscribed.");
+                            subscribers.unsubscribe(addr);
+                        }
+                        Ok(Subscription::SubscribeMeters(addr, subscription)) => {
+                            println!(
+                                "OSC client {addr} subscribed to {} meters every {} ms.",
+                                subscription.ballistics.as_str(),
+                                subscription.interval.as_millis()
+                            );
+                            meter_subscribers.subscribe(addr, subscription, Instant::now());
+                        }
+                        Ok(Subscription::UnsubscribeMeters(addr)) => {
+                            println!("OSC client {addr} unsubscribed from meters.");
+                            meter_subscribers.unsubscribe(addr);
+                        }
+                        Err(err) => {
+                            eprintln!("Error receiving from channel: {err}");
+                        }
+                    },
+                    recv(events) -> action => {
+                        let (addr, args) = match action {
+                            Ok(Action::Start) => {
+                                recording_since = Some(Instant::now());
+                                ("/smrec/start", Vec::new())
+                            }
+                            Ok(Action::Stop) => {
+                                recording_since = None;
+                                ("/smrec/stop", Vec::new())
+                            }
+                            Ok(Action::Arm) => ("/smrec/armed", Vec::new()),
+                            Ok(Action::Disarm) => ("/smrec/disarmed", Vec::new()),
+                            Ok(Action::TakeLocked(take)) => {
+                                ("/smrec/takes/locked", vec![OscType::String(take)])
+                            }
+                            Ok(Action::Interrupted(take)) => {
+                                ("/smrec/interrupted", vec![OscType::String(take)])
+                            }
+                            Ok(Action::RemainingTime(secs)) => (
+                                "/smrec/remaining",
+                                vec![OscType::Int(i32::try_from(secs).unwrap_or(i32::MAX))],
+                            ),
+                            Ok(Action::Err(code, err)) => (
+                                "/smrec/error",
+                                vec![
+                                    OscType::Int(code.code()),
+                                    OscType::String(code.subsystem().to_string()),
+                                    OscType::String(err),
+                                ],
+                            ),
+                            Ok(Action::SampleRateChanged(rate)) => (
+                                "/smrec/sample_rate_changed",
+                                vec![OscType::Int(i32::try_from(rate).unwrap_or(i32::MAX))],
+                            ),
+                            Ok(Action::StatusReport(transport, meters)) => {
+                                ("/smrec/status", status_args(transport, &meters))
+                            }
+                            Ok(Action::RamBuffer(pressure)) => (
+                                "/smrec/ram_buffer",
+                                [
+                                    pressure.queued,
+                                    pressure.peak,
+                                    pressure.capacity,
+                                    pressure.dropped,
+                                    pressure.stalls,
+                                ]
+                                .into_iter()
+                                .map(|value| OscType::Long(i64::try_from(value).unwrap_or(i64::MAX)))
+                                .collect(),
+                            ),
+                            // Only sent to the main thread or only meant for MIDI.
+                            Ok(
+                                Action::Split
+                                | Action::Sleep
+                                | Action::Wake
+                                | Action::Quit
+                                | Action::StopAt(_)
+                                | Action::SetGain(..)
+                                | Action::SetGate(..)
+                                | Action::MonitorSolo(..)
+                                | Action::MonitorMute(..)
+                                | Action::Marker(_)
+                                | Action::Cue(_)
+                                | Action::NextTakeName(_)
+                                | Action::SongSelect(_)
+                                | Action::SetlistNext
+                                | Action::SetlistPrev
+                                | Action::Beat(_)
+                                | Action::SetOut(_)
+                                | Action::PlaybackRate(_)
+                                | Action::IncludeChannel(_)
+                                | Action::ExcludeChannel(_)
+                                | Action::LockLastTake
+                                | Action::DumpConfig
+                                | Action::ConfigDump(_)
+                                | Action::RestoreConfig(_)
+                                | Action::Status,
+                            ) => continue,
+                            Err(err) => {
+                                eprintln!("Error receiving from channel: {err}");
+                                continue;
+                            }
                         };
-                    }
-                    Err(err) => {
-                        eprintln!("Error receiving from channel: {err}");
-                    }
+                        for to in subscribers.addresses(Instant::now()) {
+                            send(&socket, to, addr, args.clone());
+                        }
+                    },
+                    // Meters are due.
+                    default(wait) => {}
                 }
             }));
         }
 
-        if self.udp_thread.is_none() {
-            let socket = self.receiver_socket.clone();
-            let sender_channel = self.sender_channel.clone();
-            self.udp_thread = Some(std::thread::spawn(move || {
-                let mut buf = [0u8; rosc::decoder::MTU];
+        // Every socket feeds the same channels, so it does not matter which one a message arrives on.
+        if self.udp_threads.is_empty() {
+            for socket in &self.receiver_sockets {
+                let socket = socket.clone();
+                let to_main_thread = to_main_thread.clone();
+                let subscription_sender = subscription_sender.clone();
+                self.udp_threads.push(std::thread::spawn(move || {
+                    let mut buf = [0u8; rosc::decoder::MTU];
 
-                loop {
-                    match socket.recv_from(&mut buf) {
-                        Ok((size, _addr)) => match rosc::decoder::decode_udp(&buf[..size]) {
-                            Ok((_, osc_packet)) => {
-                                handle_packet(&osc_packet, &sender_channel);
-                            }
+                    loop {
+                        match socket.recv_from(&mut buf) {
+                            Ok((size, from)) => match rosc::decoder::decode_udp(&buf[..size]) {
+                                Ok((_, osc_packet)) => {
+                                    handle_packet(
+                                        &osc_packet,
+                                        from,
+                                        &to_main_thread,
+                                        &subscription_sender,
+                                    );
+                                }
+                                Err(err) => {
+                                    eprintln!("Error decoding UDP packet: {err}");
+                                }
+                            },
                             Err(err) => {
-                                eprintln!("Error decoding UDP packet: {err}");
+                                eprintln!("Error receiving from socket: {err}");
                             }
-                        },
-                        Err(err) => {
-                            eprintln!("Error receiving from socket: {err}");
                         }
                     }
-                }
-            }));
+                }));
+            }
         }
+        Ok(())
+    }
+}
+
+fn send(socket: &UdpSocket, to: SocketAddr, addr: &str, args: Vec<OscType>) {
+    send_packet(
+        socket,
+        to,
+        &OscPacket::Message(OscMessage {
+            addr: addr.to_string(),
+            args,
+        }),
+    );
+}
+
+fn send_packet(socket: &UdpSocket, to: SocketAddr, packet: &OscPacket) {
+    if let Err(err) = socket.send_to(&encode(packet).expect("OSC packet should encode."), to) {
+        eprintln!("Error sending OSC packet to {to}: {err}");
     }
 }
 
-fn handle_packet(packet: &OscPacket, channel: &crossbeam::channel::Sender<Action>) {
+fn handle_packet(
+    packet: &OscPacket,
+    from: SocketAddr,
+    channel: &crossbeam::channel::Sender<Action>,
+    subscriptions: &crossbeam::channel::Sender<Subscription>,
+) {
     match packet {
         OscPacket::Message(message) => {
-            handle_message(message, channel);
+            handle_message(message, from, channel, subscriptions);
         }
         OscPacket::Bundle(bundle) => {
             bundle
                 .content
                 .iter()
-                .for_each(|packet| handle_packet(packet, channel));
+                .for_each(|packet| handle_packet(packet, from, channel, subscriptions));
         }
     }
 }
 
-fn handle_message(message: &OscMessage, channel: &crossbeam::channel::Sender<Action>) {
+#[allow(clippy::too_many_lines)]
+fn handle_message(
+    message: &OscMessage,
+    from: SocketAddr,
+    channel: &crossbeam::channel::Sender<Action>,
+    subscriptions: &crossbeam::channel::Sender<Subscription>,
+) {
     match message.addr.as_str() {
-        "/smrec/start" => {
-            channel.send(Action::Start).unwrap();
-        }
+        // Toggle buttons of control apps send 1 and 0 to one address.
+        "/smrec/start" => match switched_on(&message.addr, message.args.first()) {
+            Some(true) => channel.send(Action::Start).unwrap(),
+            Some(false) => channel.send(Action::Stop).unwrap(),
+            None => {}
+        },
         "/smrec/stop" => {
             channel.send(Action::Stop).unwrap();
         }
+        "/smrec/stop_at" => match message.args.first() {
+            Some(OscType::Time(time)) => {
+                channel.send(Action::StopAt(timetag_to_utc(*time))).unwrap();
+            }
+            _ => eprintln!("/smrec/stop_at expects an OSC time tag."),
+        },
+        "/smrec/arm" => {
+            channel.send(Action::Arm).unwrap();
+        }
+        "/smrec/disarm" => {
+            channel.send(Action::Disarm).unwrap();
+        }
+        "/smrec/marker" => {
+            let label = match message.args.first() {
+                Some(OscType::String(label)) => Some(label.clone()),
+                _ => None,
+            };
+            channel.send(Action::Marker(label)).unwrap();
+        }
+        "/smrec/cue" => match message.args.first() {
+            Some(OscType::Int(cue)) => match u8::try_from(*cue) {
+                Ok(cue @ 1..=128) => channel.send(Action::Cue(cue)).unwrap(),
+                _ => eprintln!("{} expects a cue from 1 to 128.", message.addr),
+            },
+            _ => eprintln!("{} expects the number of a cue.", message.addr),
+        },
+        "/smrec/next_take_name" => match message.args.first() {
+            Some(OscType::String(name)) => {
+                channel.send(Action::NextTakeName(name.clone())).unwrap();
+            }
+            _ => eprintln!("{} expects the name of the next take.", message.addr),
+        },
+        "/smrec/setlist/next" => {
+            channel.send(Action::SetlistNext).unwrap();
+        }
+        "/smrec/setlist/prev" => {
+            channel.send(Action::SetlistPrev).unwrap();
+        }
+        "/smrec/song" => match message.args.first() {
+            Some(OscType::String(title)) => {
+                channel.send(Action::NextTakeName(title.clone())).unwrap();
+            }
+            _ => eprintln!("{} expects the title of a song.", message.addr),
+        },
+        "/smrec/playback_rate" => match message.args.first() {
+            None => channel.send(Action::PlaybackRate(None)).unwrap(),
+            Some(OscType::Int(rate)) => match u32::try_from(*rate) {
+                Ok(rate) => channel.send(Action::PlaybackRate(Some(rate))).unwrap(),
+                Err(_) => eprintln!("{} expects a positive sample rate.", message.addr),
+            },
+            _ => eprintln!("{} expects a sample rate in Hz.", message.addr),
+        },
+        "/smrec/set_out" => match message.args.first() {
+            Some(OscType::String(path)) => {
+                channel.send(Action::SetOut(path.clone())).unwrap();
+            }
+            _ => eprintln!("{} expects a path.", message.addr),
+        },
+        "/smrec/include" => {
+            if let Some(device_channel) = channel_arg(message) {
+                channel
+                    .send(Action::IncludeChannel(device_channel))
+                    .unwrap();
+            }
+        }
+        "/smrec/exclude" => {
+            if let Some(device_channel) = channel_arg(message) {
+                channel
+                    .send(Action::ExcludeChannel(device_channel))
+                    .unwrap();
+            }
+        }
+        "/smrec/takes/lock_last" => {
+            channel.send(Action::LockLastTake).unwrap();
+        }
+        "/smrec/status" => {
+            channel.send(Action::Status).unwrap();
+        }
+        "/smrec/subscribe" => {
+            if let Some(addr) = return_address(message, from) {
+                subscriptions.send(Subscription::Subscribe(addr)).unwrap();
+            }
+        }
+        "/smrec/unsubscribe" => {
+            if let Some(addr) = return_address(message, from) {
+                subscriptions.send(Subscription::Unsubscribe(addr)).unwrap();
+            }
+        }
+        "/smrec/meters/subscribe" => {
+            if let Some((addr, subscription)) = meter_subscription(message, from) {
+                subscriptions
+                    .send(Subscription::SubscribeMeters(addr, subscription))
+                    .unwrap();
+            }
+        }
+        "/smrec/meters/unsubscribe" => {
+            if let Some(addr) = return_address(message, from) {
+                subscriptions
+                    .send(Subscription::UnsubscribeMeters(addr))
+                    .unwrap();
+            }
+        }
+        "/smrec/monitor/solo" | "/smrec/monitor/mute" => {
+            if let Some(action) = monitor_switch(message) {
+                channel.send(action).unwrap();
+            }
+        }
+        addr if addr.starts_with("/smrec/chan/") => {
+            if let Some(action) = channel_parameter(message) {
+                channel.send(action).unwrap();
+            }
+        }
         _ => {
             // Ignore
         }
     }
 }
+
+/// Whether an argument of a message switches on, no argument does and so does a number or bool which is not zero.
+fn switched_on(addr: &str, arg: Option<&OscType>) -> Option<bool> {
+    match arg {
+        None => Some(true),
+        Some(OscType::Float(value)) => Some(*value != 0.0),
+        Some(OscType::Double(value)) => Some(*value != 0.0),
+        Some(OscType::Int(value)) => Some(*value != 0),
+        Some(OscType::Long(value)) => Some(*value != 0),
+        Some(OscType::Bool(value)) => Some(*value),
+        Some(arg) => {
+            eprintln!("Unexpected argument {arg:?} in {addr}");
+            None
+        }
+    }
+}
+
+/// Parses `/smrec/monitor/solo <channel>` and `/smrec/monitor/mute <channel>`, a value of 0 after the channel takes it back.
+fn monitor_switch(message: &OscMessage) -> Option<Action> {
+    let channel = channel_arg(message)?;
+    let on = switched_on(&message.addr, message.args.get(1))?;
+    if message.addr.ends_with("solo") {
+        Some(Action::MonitorSolo(channel, on))
+    } else {
+        Some(Action::MonitorMute(channel, on))
+    }
+}
+
+/// Arguments of `/smrec/status`, the transport followed by the peak hold in dBFS and the overs of every device channel.
+fn status_args(transport: Transport, meters: &[Meter]) -> Vec<OscType> {
+    let transport = match transport {
+        Transport::Stopped => "stopped",
+        Transport::Armed => "armed",
+        Transport::Recording => "recording",
+    };
+    let mut args = vec![OscType::String(transport.to_string())];
+    for meter in meters {
+        args.push(OscType::Float(meter.peak_db));
+        args.push(OscType::Int(i32::try_from(meter.overs).unwrap_or(i32::MAX)));
+    }
+    args
+}
+
+/// Arguments of `/smrec/meters`, the live level in dBFS and the RMS in dBFS of every device channel.
+fn meter_args(levels: &[Level]) -> Vec<OscType> {
+    levels
+        .iter()
+        .flat_map(|level| [OscType::Float(level.level_db), OscType::Float(level.rms_db)])
+        .collect()
+}
+
+/// Parses `/smrec/meters/subscribe [<ballistics>] [<rate>] [<port> or <address>]`.
+fn meter_subscription(
+    message: &OscMessage,
+    from: SocketAddr,
+) -> Option<(SocketAddr, MeterSubscription)> {
+    let ballistics = match message.args.first() {
+        None => Ballistics::Peak,
+        Some(OscType::String(ballistics)) => match ballistics.parse() {
+            Ok(ballistics) => ballistics,
+            Err(err) => {
+                eprintln!("{err}");
+                return None;
+            }
+        },
+        Some(arg) => {
+            eprintln!("Unexpected argument {arg:?} in {}", message.addr);
+            return None;
+        }
+    };
+    #[allow(clippy::cast_precision_loss)]
+    let rate = match message.args.get(1) {
+        None => METER_RATE,
+        Some(OscType::Float(rate)) => *rate,
+        Some(OscType::Int(rate)) => *rate as f32,
+        Some(arg) => {
+            eprintln!("Unexpected argument {arg:?} in {}", message.addr);
+            return None;
+        }
+    };
+    if !(rate > 0.0 && rate <= MAX_METER_RATE) {
+        eprintln!(
+            "{} expects a rate above 0 and up to {MAX_METER_RATE} Hz.",
+            message.addr
+        );
+        return None;
+    }
+    let addr = return_address_arg(&message.addr, message.args.get(2), from)?;
+    Some((
+        addr,
+        MeterSubscription {
+            ballistics,
+            interval: Duration::from_secs_f64(1.0 / f64::from(rate)),
+        },
+    ))
+}
+
+/// The 0 indexed channel given as the first argument, 1 indexed in the message.
+fn channel_arg(message: &OscMessage) -> Option<usize> {
+    let channel = match message.args.first() {
+        Some(OscType::Int(channel)) => usize::try_from(*channel).ok(),
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        Some(OscType::Float(channel)) if channel.fract() == 0.0 => Some(*channel as usize),
+        _ => None,
+    }
+    .filter(|channel| *channel > 0);
+    if channel.is_none() {
+        eprintln!("{} expects a channel, channels start from 1.", message.addr);
+    }
+    channel.map(|channel| channel - 1)
+}
+
+/// Parses `/smrec/chan/<channel>/gain <dB>` and `/smrec/chan/<channel>/gate <dBFS>`, the gate is turned off without an argument.
+fn channel_parameter(message: &OscMessage) -> Option<Action> {
+    let (channel, parameter) = message
+        .addr
+        .strip_prefix("/smrec/chan/")
+        .and_then(|rest| rest.split_once('/'))?;
+    let Some(channel) = channel.parse::<usize>().ok().filter(|channel| *channel > 0) else {
+        eprintln!(
+            "Invalid channel {channel} in {}, channels start from 1.",
+            message.addr
+        );
+        return None;
+    };
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    let value = match message.args.first() {
+        None => None,
+        Some(OscType::Float(value)) => Some(*value),
+        Some(OscType::Double(value)) => Some(*value as f32),
+        Some(OscType::Int(value)) => Some(*value as f32),
+        Some(arg) => {
+            eprintln!("Unexpected argument {arg:?} in {}", message.addr);
+            return None;
+        }
+    };
+    match (parameter, value) {
+        ("gain", Some(db)) => Some(Action::SetGain(channel - 1, db)),
+        ("gate", threshold) => Some(Action::SetGate(channel - 1, threshold)),
+        ("gain", None) => {
+            eprintln!("{} expects a gain in dB.", message.addr);
+            None
+        }
+        _ => {
+            eprintln!("Unknown channel parameter {parameter} in {}", message.addr);
+            None
+        }
+    }
+}
+
+/// Converts an OSC time tag, NTP seconds since 1900 and a binary fraction, to a date.
+fn timetag_to_utc(time: OscTime) -> DateTime<Utc> {
+    let unix = i64::from(time.seconds) - NTP_UNIX_OFFSET_SECS;
+    let nanos = (u64::from(time.fractional) * 1_000_000_000) >> 32;
+    #[allow(clippy::cast_possible_truncation)]
+    DateTime::from_timestamp(unix, nanos as u32).unwrap_or_default()
+}
+
+/// The address a client wants to be notified on.
+///
+/// It is the sender of the message unless a port (int) or an address (string) is given as an argument.
+fn return_address(message: &OscMessage, from: SocketAddr) -> Option<SocketAddr> {
+    return_address_arg(&message.addr, message.args.first(), from)
+}
+
+fn return_address_arg(
+    message_addr: &str,
+    arg: Option<&OscType>,
+    from: SocketAddr,
+) -> Option<SocketAddr> {
+    match arg {
+        None => Some(from),
+        Some(OscType::Int(port)) => u16::try_from(*port).map_or_else(
+            |_| {
+                eprintln!("Invalid port {port} in {message_addr}");
+                None
+            },
+            |port| Some(SocketAddr::new(from.ip(), port)),
+        ),
+        Some(OscType::String(addr)) => SocketAddr::from_str(addr).map_or_else(
+            |err| {
+                eprintln!("Invalid address {addr} in {message_addr}: {err}");
+                None
+            },
+            Some,
+        ),
+        Some(arg) => {
+            eprintln!("Unexpected argument {arg:?} in {message_addr}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscriptions_expire_and_keep_the_permanent_address() {
+        let permanent = SocketAddr::from(([127, 0, 0, 1], 18001));
+        let client = SocketAddr::from(([192, 168, 1, 10], 9000));
+        let start = Instant::now();
+
+        let mut subscribers = Subscribers::new(Some(permanent));
+        subscribers.subscribe(client, start);
+        assert_eq!(subscribers.addresses(start), vec![permanent, client]);
+
+        subscribers.unsubscribe(client);
+        assert_eq!(subscribers.addresses(start), vec![permanent]);
+
+        subscribers.subscribe(client, start);
+        assert_eq!(
+            subscribers.addresses(start + SUBSCRIPTION_TIMEOUT),
+            vec![permanent]
+        );
+    }
+
+    #[test]
+    fn throttle_coalesces_and_drops_the_oldest() {
+        let message = |addr: &str, value| OscMessage {
+            addr: addr.to_string(),
+            args: vec![OscType::Float(value)],
+        };
+
+        let mut throttle = Throttle::new(2);
+        assert!(throttle.flush().is_none());
+
+        throttle.push(message("/smrec/time", 1.0));
+        throttle.push(message("/smrec/meters/1", 0.5));
+        throttle.push(message("/smrec/time", 2.0));
+        throttle.push(message("/smrec/meters/2", 0.25));
+
+        let Some(OscPacket::Bundle(bundle)) = throttle.flush() else {
+            panic!("Expected a bundle.");
+        };
+        assert_eq!(
+            bundle.content,
+            vec![
+                OscPacket::Message(message("/smrec/time", 2.0)),
+                OscPacket::Message(message("/smrec/meters/2", 0.25)),
+            ]
+        );
+        assert!(throttle.flush().is_none());
+    }
+
+    #[test]
+    fn return_address_from_arguments() {
+        let from = SocketAddr::from(([10, 0, 0, 2], 50000));
+        let message = |args| OscMessage {
+            addr: "/smrec/subscribe".to_string(),
+            args,
+        };
+
+        assert_eq!(return_address(&message(vec![]), from), Some(from));
+        assert_eq!(
+            return_address(&message(vec![OscType::Int(9000)]), from),
+            Some(SocketAddr::from(([10, 0, 0, 2], 9000)))
+        );
+        assert_eq!(
+            return_address(
+                &message(vec![OscType::String("10.0.0.3:9001".to_string())]),
+                from
+            ),
+            Some(SocketAddr::from(([10, 0, 0, 3], 9001)))
+        );
+        assert_eq!(return_address(&message(vec![OscType::Int(-1)]), from), None);
+    }
+
+    #[test]
+    fn meter_subscriptions_are_sent_at_their_rate() {
+        let from = SocketAddr::from(([10, 0, 0, 2], 50000));
+        let message = |args| OscMessage {
+            addr: "/smrec/meters/subscribe".to_string(),
+            args,
+        };
+        let (addr, subscription) = meter_subscription(
+            &message(vec![
+                OscType::String("vu".to_string()),
+                OscType::Int(10),
+                OscType::Int(9000),
+            ]),
+            from,
+        )
+        .unwrap();
+        assert_eq!(addr, SocketAddr::from(([10, 0, 0, 2], 9000)));
+        assert_eq!(subscription.ballistics, Ballistics::Vu);
+        assert_eq!(subscription.interval, Duration::from_millis(100));
+        assert_eq!(
+            meter_subscription(&message(vec![]), from)
+                .unwrap()
+                .1
+                .ballistics,
+            Ballistics::Peak
+        );
+        assert!(meter_subscription(
+            &message(vec![
+                OscType::String("ppm".to_string()),
+                OscType::Float(0.0)
+            ]),
+            from
+        )
+        .is_none());
+
+        let start = Instant::now();
+        let mut subscribers = MeterSubscribers::default();
+        assert_eq!(subscribers.wait(start), SUBSCRIPTION_TIMEOUT);
+        subscribers.subscribe(addr, subscription, start);
+        assert_eq!(subscribers.due(start), vec![(addr, Ballistics::Vu)]);
+        assert!(subscribers
+            .due(start + Duration::from_millis(50))
+            .is_empty());
+        assert_eq!(
+            subscribers.wait(start + Duration::from_millis(50)),
+            Duration::from_millis(50)
+        );
+        // Late clients are not sent the meters they missed.
+        assert_eq!(subscribers.due(start + Duration::from_millis(350)).len(), 1);
+        assert!(subscribers
+            .due(start + Duration::from_millis(400))
+            .is_empty());
+        assert!(subscribers.due(start + SUBSCRIPTION_TIMEOUT).is_empty());
+        assert!(subscribers.clients.is_empty());
+    }
+
+    #[test]
+    fn every_listen_address_feeds_the_same_channel() {
+        let (sender, from_osc) = crossbeam::channel::unbounded();
+        let (_to_osc, receiver) = crossbeam::channel::unbounded();
+        let localhost = SocketAddr::from(([127, 0, 0, 1], 0));
+        let mut osc = Osc::new(
+            &["127.0.0.1:0".to_string()],
+            &[localhost],
+            OscFeedback::default(),
+            Arc::new(Processing::new(0)),
+        )
+        .unwrap();
+        osc.start(sender, receiver).unwrap();
+
+        let client = UdpSocket::bind(localhost).unwrap();
+        for (socket, addr) in osc
+            .receiver_sockets
+            .iter()
+            .zip(["/smrec/arm", "/smrec/disarm"])
+        {
+            send(&client, socket.local_addr().unwrap(), addr, Vec::new());
+        }
+        let timeout = Duration::from_secs(5);
+        let mut actions = [
+            from_osc.recv_timeout(timeout).unwrap(),
+            from_osc.recv_timeout(timeout).unwrap(),
+        ]
+        .map(|action| matches!(action, Action::Arm));
+        actions.sort_unstable();
+        assert_eq!(actions, [false, true]);
+    }
+
+    #[test]
+    fn channel_parameters_from_addresses() {
+        let message = |addr: &str, args: Vec<OscType>| OscMessage {
+            addr: addr.to_string(),
+            args,
+        };
+        assert!(matches!(
+            channel_parameter(&message("/smrec/chan/3/gain", vec![OscType::Float(-6.0)])),
+            Some(Action::SetGain(2, db)) if (db + 6.0).abs() < f32::EPSILON
+        ));
+        assert!(matches!(
+            channel_parameter(&message("/smrec/chan/1/gate", vec![OscType::Int(-50)])),
+            Some(Action::SetGate(0, Some(_)))
+        ));
+        assert!(matches!(
+            channel_parameter(&message("/smrec/chan/1/gate", Vec::new())),
+            Some(Action::SetGate(0, None))
+        ));
+        assert!(
+            channel_parameter(&message("/smrec/chan/0/gain", vec![OscType::Float(0.0)])).is_none()
+        );
+        assert!(channel_parameter(&message("/smrec/chan/1/gain", Vec::new())).is_none());
+        assert!(
+            channel_parameter(&message("/smrec/chan/1/eq", vec![OscType::Float(0.0)])).is_none()
+        );
+    }
+
+    #[test]
+    fn start_with_a_value_toggles() {
+        let start = |arg: OscType| switched_on("/smrec/start", Some(&arg));
+        assert_eq!(switched_on("/smrec/start", None), Some(true));
+        assert_eq!(start(OscType::Float(1.0)), Some(true));
+        assert_eq!(start(OscType::Float(0.63)), Some(true));
+        assert_eq!(start(OscType::Float(0.0)), Some(false));
+        assert_eq!(start(OscType::Int(127)), Some(true));
+        assert_eq!(start(OscType::Int(0)), Some(false));
+        assert_eq!(start(OscType::Bool(false)), Some(false));
+        assert_eq!(start(OscType::String("on".to_string())), None);
+    }
+
+    #[test]
+    fn monitor_solo_and_mute_of_channels() {
+        let message = |addr: &str, args: Vec<OscType>| OscMessage {
+            addr: addr.to_string(),
+            args,
+        };
+        assert!(matches!(
+            monitor_switch(&message("/smrec/monitor/solo", vec![OscType::Int(3)])),
+            Some(Action::MonitorSolo(2, true))
+        ));
+        assert!(matches!(
+            monitor_switch(&message(
+                "/smrec/monitor/mute",
+                vec![OscType::Float(1.0), OscType::Int(0)]
+            )),
+            Some(Action::MonitorMute(0, false))
+        ));
+        assert!(monitor_switch(&message("/smrec/monitor/solo", Vec::new())).is_none());
+        assert!(monitor_switch(&message("/smrec/monitor/solo", vec![OscType::Int(0)])).is_none());
+    }
+
+    #[test]
+    fn status_lists_the_meters_after_the_transport() {
+        let meters = [
+            Meter {
+                peak_db: -6.0,
+                overs: 0,
+                rms_db: -18.0,
+            },
+            Meter {
+                peak_db: 0.0,
+                overs: 3,
+                rms_db: -9.0,
+            },
+        ];
+        assert_eq!(
+            status_args(Transport::Recording, &meters),
+            [
+                OscType::String("recording".to_string()),
+                OscType::Float(-6.0),
+                OscType::Int(0),
+                OscType::Float(0.0),
+                OscType::Int(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn time_tags_to_dates() {
+        // 2024-01-01T00:00:00.5Z
+        let time = OscTime {
+            seconds: 3_913_056_000,
+            fractional: 1 << 31,
+        };
+        assert_eq!(
+            timetag_to_utc(time).to_rfc3339(),
+            "2024-01-01T00:00:00.500+00:00"
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use crate::selftest::{self, Capture};
+use anyhow::Result;
+use cpal::traits::{DeviceTrait, StreamTrait};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Channels quieter than this (RMS) are left out since their correlation is meaningless.
+const SILENCE_RMS: f64 = 1e-4;
+
+/// Listens to all channels of a device for a while and suggests stereo pairs of strongly correlated channels.
+pub fn run(device: &cpal::Device, seconds: u64, threshold: f64) -> Result<()> {
+    let config = device.default_input_config()?;
+    let capture = Arc::new(Mutex::new(Capture {
+        start: None,
+        channels: vec![Vec::new(); config.channels() as usize],
+    }));
+
+    let stream = selftest::build_input(device, &config, Arc::clone(&capture))?;
+    println!(
+        "Listening to {} channels for {seconds} seconds...",
+        config.channels()
+    );
+    stream.play()?;
+    std::thread::sleep(Duration::from_secs(seconds));
+    drop(stream);
+
+    let channels = std::mem::take(&mut capture.lock().unwrap().channels);
+    let pairs = correlated_pairs(&channels, threshold);
+    if pairs.is_empty() {
+        println!("No channels are correlated strongly enough to be a stereo pair.");
+    }
+    for (left, right, correlation) in pairs {
+        println!(
+            "Channels {} and {} look like a stereo pair (correlation {correlation:.2}).",
+            left + 1,
+            right + 1
+        );
+    }
+
+    Ok(())
+}
+
+/// Finds pairs of channels whose correlation is at least the threshold, strongest first.
+///
+/// Every channel is part of at most one pair.
+pub fn correlated_pairs(channels: &[Vec<f32>], threshold: f64) -> Vec<(usize, usize, f64)> {
+    let mut candidates = Vec::new();
+    for left in 0..channels.len() {
+        for right in left + 1..channels.len() {
+            if let Some(correlation) = correlation(&channels[left], &channels[right]) {
+                if correlation >= threshold {
+                    candidates.push((left, right, correlation));
+                }
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+    let mut paired = vec![false; channels.len()];
+    candidates
+        .into_iter()
+        .filter(|(left, right, _)| {
+            if paired[*left] || paired[*right] {
+                return false;
+            }
+            paired[*left] = true;
+            paired[*right] = true;
+            true
+        })
+        .collect()
+}
+
+/// Pearson correlation of two signals, `None` if either of them is silent.
+#[allow(clippy::cast_precision_loss)]
+fn correlation(a: &[f32], b: &[f32]) -> Option<f64> {
+    let len = a.len().min(b.len());
+    if len == 0 {
+        return None;
+    }
+    let mean =
+        |signal: &[f32]| signal[..len].iter().map(|s| f64::from(*s)).sum::<f64>() / len as f64;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (a, b) in a[..len].iter().zip(&b[..len]) {
+        let (a, b) = (f64::from(*a) - mean_a, f64::from(*b) - mean_b);
+        covariance += a * b;
+        variance_a += a * a;
+        variance_b += b * b;
+    }
+
+    let silent = SILENCE_RMS * SILENCE_RMS * len as f64;
+    if variance_a < silent || variance_b < silent {
+        return None;
+    }
+    Some(covariance / (variance_a * variance_b).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_stereo_pairs() {
+        let signal = (0..4800)
+            .map(|i| (f32::from(u16::try_from(i).unwrap()) * 0.05).sin() * 0.5)
+            .collect::<Vec<_>>();
+        let other = (0..4800)
+            .map(|i| (f32::from(u16::try_from(i).unwrap()) * 0.31).cos() * 0.5)
+            .collect::<Vec<_>>();
+        let channels = vec![
+            other.clone(),
+            signal.clone(),
+            vec![0.0; 4800],
+            signal.iter().map(|s| s * 0.7).collect(),
+            other.iter().map(|s| s * 0.9 + 0.01).collect(),
+        ];
+
+        let pairs = correlated_pairs(&channels, 0.8);
+        let mut found = pairs.iter().map(|(l, r, _)| (*l, *r)).collect::<Vec<_>>();
+        found.sort_unstable();
+        assert_eq!(found, vec![(0, 4), (1, 3)]);
+        assert!(pairs.iter().all(|(_, _, c)| *c > 0.99));
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{files::Deferred, wav::Sink};
+use anyhow::Result;
+use camino::Utf8Path;
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+};
+
+/// How many samples are summarized by one min and max pair.
+const SAMPLES_PER_PIXEL: u32 = 256;
+/// The header is rewritten with the current length after this many pairs so in-progress files are readable.
+const PAIRS_PER_HEADER_UPDATE: u32 = 64;
+/// Offset of the length field in the header.
+const LENGTH_OFFSET: u64 = 16;
+
+/// Writes a peaks file in the binary (version 1) format of BBC audiowaveform while a channel is recorded.
+pub struct Peaks {
+    file: Sink,
+    count: u32,
+    min: i16,
+    max: i16,
+    length: u32,
+    /// Pairs written since the header was last updated.
+    pending: u32,
+}
+
+impl Peaks {
+    pub fn create(path: &Utf8Path, sample_rate: u32, deferred: bool) -> Result<Self> {
+        let file = File::create(path)?;
+        let mut file = if deferred {
+            Sink::Deferred(Deferred::new(path))
+        } else {
+            Sink::File(BufWriter::new(file))
+        };
+        // Version
+        file.write_all(&1_i32.to_le_bytes())?;
+        // Flags, 0 for 16 bit resolution.
+        file.write_all(&0_u32.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&SAMPLES_PER_PIXEL.to_le_bytes())?;
+        // Length in pairs, updated as they are written.
+        file.write_all(&0_u32.to_le_bytes())?;
+
+        Ok(Self {
+            file,
+            count: 0,
+            min: i16::MAX,
+            max: i16::MIN,
+            length: 0,
+            pending: 0,
+        })
+    }
+
+    pub fn push(&mut self, sample: i16) -> Result<()> {
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+        self.count += 1;
+
+        if self.count == SAMPLES_PER_PIXEL {
+            self.write_pair()?;
+            self.pending += 1;
+            if self.pending == PAIRS_PER_HEADER_UPDATE {
+                self.update_length()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the last partial pair and the final length.
+    pub fn finalize(mut self) -> Result<()> {
+        if self.count > 0 {
+            self.write_pair()?;
+        }
+        self.update_length()
+    }
+
+    fn write_pair(&mut self) -> Result<()> {
+        self.file.write_all(&self.min.to_le_bytes())?;
+        self.file.write_all(&self.max.to_le_bytes())?;
+        self.length += 1;
+        self.count = 0;
+        self.min = i16::MAX;
+        self.max = i16::MIN;
+        Ok(())
+    }
+
+    fn update_length(&mut self) -> Result<()> {
+        self.file.seek(SeekFrom::Start(LENGTH_OFFSET))?;
+        self.file.write_all(&self.length.to_le_bytes())?;
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.flush()?;
+        self.pending = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn writes_audiowaveform_data() {
+        let dir = TempDir::new("peaks");
+        let path = dir.path().join("chn_1.dat");
+
+        let mut peaks = Peaks::create(&path, 48000, false).unwrap();
+        for i in 0..SAMPLES_PER_PIXEL + 2 {
+            peaks.push(i16::try_from(i).unwrap() - 10).unwrap();
+        }
+        peaks.finalize().unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        let word = |at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+        assert_eq!(word(8), 48000);
+        assert_eq!(word(12), SAMPLES_PER_PIXEL);
+        assert_eq!(word(16), 2);
+        let pairs = data[20..]
+            .chunks(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, vec![-10, 245, 246, 247]);
+    }
+}
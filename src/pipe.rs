@@ -0,0 +1,173 @@
+use crate::{
+    stream::{self, Input},
+    tap::Taps,
+};
+use anyhow::{bail, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use crossbeam::channel::{Receiver, Sender};
+use std::{fs::File, io::Write};
+
+/// Buffers waiting to be written to a pipe, more are dropped rather than holding up the audio callback.
+const QUEUE: usize = 64;
+/// Samples a buffer of the queue holds before it has to grow, more than devices deliver at a time.
+const BUFFER: usize = 8192;
+
+/// Whether a path is a named pipe (FIFO) which exists.
+pub fn is_fifo(path: &Utf8Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// Streams one channel into a named pipe for the whole session, so live consumers can tap it.
+///
+/// Pipes ending in `.wav` get a WAV header whose lengths are left open, others get the bare samples,
+/// both as 32 bit float. A reader which goes away is waited for again.
+///
+/// The channel is tapped from the streams of the takes. Buffers are allocated once and passed back and forth
+/// between the audio callback and the thread writing the pipe.
+pub fn start(taps: &mut Taps, inputs: &[Input], channel: usize, path: Utf8PathBuf) -> Result<()> {
+    let Some((input, _)) = stream::find_channel(inputs, channel) else {
+        bail!(
+            "Channel {} can not be streamed into {path}, it does not exist.",
+            channel + 1
+        );
+    };
+    let sample_rate = input.config.sample_rate().0;
+    let (buffers, received) = crossbeam::channel::bounded::<Vec<f32>>(QUEUE);
+    let (to_reuse, reused) = crossbeam::channel::bounded::<Vec<f32>>(QUEUE);
+    for _ in 0..QUEUE {
+        to_reuse
+            .send(Vec::with_capacity(BUFFER))
+            .expect("Internal thread error.");
+    }
+    taps.add(
+        channel,
+        Box::new(move |samples| {
+            // Without a free buffer the writer fell behind and the samples are dropped.
+            if let Ok(mut buffer) = reused.try_recv() {
+                buffer.clear();
+                buffer.extend_from_slice(samples);
+                buffers.try_send(buffer).ok();
+            }
+        }),
+    );
+    println!("Streaming channel {} into {path}.", channel + 1);
+    std::thread::spawn(move || feed(&path, sample_rate, &received, &to_reuse));
+    Ok(())
+}
+
+/// Writes the buffers into the pipe whenever it has a reader and hands them back, until the stream is gone.
+fn feed(path: &Utf8Path, sample_rate: u32, buffers: &Receiver<Vec<f32>>, reuse: &Sender<Vec<f32>>) {
+    let wav = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("wav"));
+    loop {
+        // Blocks until a reader opens the pipe.
+        let mut pipe = match File::options().write(true).open(path) {
+            Ok(pipe) => pipe,
+            Err(err) => {
+                eprintln!("Error opening {path}, it is not streamed to anymore: {err}");
+                return;
+            }
+        };
+        // Readers start from what is captured now, not from what piled up while nobody listened.
+        for buffer in buffers.try_iter() {
+            reuse.try_send(buffer).ok();
+        }
+        if wav && pipe.write_all(&streaming_header(sample_rate)).is_err() {
+            continue;
+        }
+        let mut bytes = Vec::with_capacity(BUFFER * 4);
+        loop {
+            let Ok(buffer) = buffers.recv() else {
+                return;
+            };
+            bytes.clear();
+            bytes.extend(buffer.iter().flat_map(|sample| sample.to_le_bytes()));
+            reuse.try_send(buffer).ok();
+            if pipe.write_all(&bytes).is_err() {
+                println!("The reader of {path} went away, waiting for the next one.");
+                break;
+            }
+        }
+    }
+}
+
+/// Header of a mono 32 bit float WAV stream, its lengths are the largest possible since they are not known.
+fn streaming_header(sample_rate: u32) -> Vec<u8> {
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16_u32.to_le_bytes());
+    // WAVE_FORMAT_IEEE_FLOAT
+    header.extend_from_slice(&3_u16.to_le_bytes());
+    // Channels
+    header.extend_from_slice(&1_u16.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    // Bytes per second and per frame.
+    header.extend_from_slice(&(sample_rate * 4).to_le_bytes());
+    header.extend_from_slice(&4_u16.to_le_bytes());
+    header.extend_from_slice(&32_u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use std::io::Read;
+
+    #[test]
+    #[cfg(unix)]
+    fn channels_are_streamed_into_pipes() {
+        let dir = TempDir::new("pipe");
+        let base = dir.path().to_path_buf();
+        let path = base.join("Kick.wav");
+        assert!(std::process::Command::new("mkfifo")
+            .arg(&path)
+            .status()
+            .unwrap()
+            .success());
+        assert!(is_fifo(&path));
+        assert!(!is_fifo(&base));
+
+        let (buffers, received) = crossbeam::channel::unbounded();
+        let (to_reuse, reused) = crossbeam::channel::unbounded();
+        let feeding = path.clone();
+        let feeder = std::thread::spawn(move || feed(&feeding, 48000, &received, &to_reuse));
+        let mut pipe_reader = File::open(&path).unwrap();
+        let mut header = [0; 44];
+        pipe_reader.read_exact(&mut header).unwrap();
+        assert_eq!(header[..], streaming_header(48000));
+        assert_eq!(&header[..4], b"RIFF");
+        // 32 bit float at 48 kHz.
+        assert_eq!(header[20..22], 3_u16.to_le_bytes());
+        assert_eq!(header[24..28], 48000_u32.to_le_bytes());
+
+        buffers.send(vec![0.5_f32, -0.25]).unwrap();
+        let mut samples = [0; 8];
+        pipe_reader.read_exact(&mut samples).unwrap();
+        assert_eq!(samples[..4], 0.5_f32.to_le_bytes());
+        assert_eq!(samples[4..], (-0.25_f32).to_le_bytes());
+        // The buffer is handed back to be filled again.
+        assert_eq!(
+            reused.recv_timeout(std::time::Duration::from_secs(1)),
+            Ok(vec![0.5, -0.25])
+        );
+
+        // The stream is gone.
+        drop(buffers);
+        feeder.join().unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+// cpal has no PipeWire host, nodes are recorded through the ALSA plugin of PipeWire which can be pointed at any node.
+
+use anyhow::{bail, Result};
+
+/// Name of the ALSA device of the `PipeWire` plugin.
+pub const ALSA_DEVICE: &str = "pipewire";
+/// Suffix which selects the monitor of a sink, as in `PulseAudio`.
+const MONITOR_SUFFIX: &str = ".monitor";
+
+/// An audio node of the `PipeWire` graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub name: String,
+    pub description: Option<String>,
+    /// Media class such as `Audio/Source`, `Audio/Sink` or `Stream/Output/Audio`.
+    pub class: String,
+}
+
+impl Node {
+    /// The name to give to `--node`, the monitor of a sink for sinks.
+    pub fn target(&self) -> String {
+        if self.class == "Audio/Sink" {
+            format!("{}{MONITOR_SUFFIX}", self.name)
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+/// Points the ALSA plugin of `PipeWire` at a node, it must be called before the device is opened.
+///
+/// A `.monitor` suffix records what is played through a sink, application streams record what the application plays.
+pub fn target(node: &str) {
+    if let Some(sink) = node.strip_suffix(MONITOR_SUFFIX) {
+        std::env::set_var("PIPEWIRE_NODE", sink);
+        std::env::set_var("PIPEWIRE_PROPS", "{ stream.capture.sink = true }");
+    } else {
+        std::env::set_var("PIPEWIRE_NODE", node);
+    }
+}
+
+/// Lists the nodes which can be recorded.
+pub fn nodes() -> Result<Vec<Node>> {
+    let output = std::process::Command::new("pw-cli")
+        .args(["ls", "Node"])
+        .output()
+        .map_err(|err| anyhow::anyhow!("Can not run pw-cli, is PipeWire installed? {err}"))?;
+    if !output.status.success() {
+        bail!(
+            "pw-cli failed, is PipeWire running? {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(parse_pw_cli(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Prints the nodes which can be recorded with `--node`.
+pub fn enumerate() -> Result<()> {
+    println!("PipeWire nodes:");
+    for node in nodes()? {
+        match &node.description {
+            Some(description) => println!("  {} ({description}, {})", node.target(), node.class),
+            None => println!("  {} ({})", node.target(), node.class),
+        }
+    }
+    Ok(())
+}
+
+/// Parses the output of `pw-cli ls Node` to the audio nodes which can be recorded.
+///
+/// Objects start with an `id` line followed by indented `key = "value"` lines of their properties.
+fn parse_pw_cli(output: &str) -> Vec<Node> {
+    let mut nodes = Vec::new();
+    let mut properties: Vec<(String, String)> = Vec::new();
+    let mut flush = |properties: &mut Vec<(String, String)>| {
+        let get = |key: &str| {
+            properties
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.clone())
+        };
+        if let (Some(name), Some(class)) = (get("node.name"), get("media.class")) {
+            if matches!(
+                class.as_str(),
+                "Audio/Source" | "Audio/Sink" | "Audio/Duplex" | "Stream/Output/Audio"
+            ) {
+                nodes.push(Node {
+                    name,
+                    description: get("node.description").or_else(|| get("application.name")),
+                    class,
+                });
+            }
+        }
+        properties.clear();
+    };
+
+    for line in output.lines() {
+        let line = line.trim();
+        if line.starts_with("id ") {
+            flush(&mut properties);
+        } else if let Some((key, value)) = line.split_once(" = ") {
+            properties.push((
+                key.trim_start_matches('*').trim().to_string(),
+                value.trim().trim_matches('"').to_string(),
+            ));
+        }
+    }
+    flush(&mut properties);
+
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audio_nodes_from_pw_cli() {
+        let output = r#"	id 31, type PipeWire:Interface:Node/3
+ 		object.serial = "31"
+ 		factory.id = "10"
+ 		node.name = "Dummy-Driver"
+ 		priority.driver = "20000"
+	id 46, type PipeWire:Interface:Node/3
+ 		object.serial = "46"
+ 		node.description = "Built-in Audio Analog Stereo"
+ 		node.name = "alsa_output.pci-0000_00_1f.3.analog-stereo"
+ 		media.class = "Audio/Sink"
+	id 47, type PipeWire:Interface:Node/3
+ 		node.description = "Built-in Audio Analog Stereo"
+ 		node.name = "alsa_input.pci-0000_00_1f.3.analog-stereo"
+ 		media.class = "Audio/Source"
+	id 80, type PipeWire:Interface:Node/3
+ 		application.name = "Firefox"
+ 		node.name = "Firefox"
+ 		media.class = "Stream/Output/Audio"
+	id 81, type PipeWire:Interface:Node/3
+ 		node.name = "v4l2_input.pci-0000_00_14.0-usb-0_6_1.0"
+ 		media.class = "Video/Source"
+"#;
+        let targets = parse_pw_cli(output)
+            .iter()
+            .map(Node::target)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            targets,
+            [
+                "alsa_output.pci-0000_00_1f.3.analog-stereo.monitor",
+                "alsa_input.pci-0000_00_1f.3.analog-stereo",
+                "Firefox"
+            ]
+        );
+    }
+}
@@ -0,0 +1,431 @@
+use crate::{console, types::Action};
+use crossbeam::channel::{Receiver, Sender};
+use std::{sync::Mutex, time::Duration};
+
+/// How often the battery is checked.
+const POWER_POLL_SECS: u64 = 5;
+/// Takes are split when the battery drops to this level while discharging.
+const LOW_BATTERY_PERCENT: u8 = 10;
+/// How long sleep is held back at most while the take is finalized, logind gives delay locks 5 seconds by default.
+const SLEEP_DELAY_SECS: u64 = 5;
+
+/// Tells the thread holding sleep back that the take was finalized.
+static FINALIZED: Mutex<Option<Sender<()>>> = Mutex::new(None);
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Battery {
+    percent: u8,
+    discharging: bool,
+}
+
+impl Battery {
+    const fn is_low(self) -> bool {
+        self.discharging && self.percent <= LOW_BATTERY_PERCENT
+    }
+}
+
+/// Starts threads which ask for the running take to be split when the battery runs low and to be finalized before
+/// the system goes to sleep.
+///
+/// A dying battery or a closed lid leaves valid files behind, recording goes on in a new take after a wake up.
+pub fn watch(to_main_thread: Sender<Action>) {
+    let (finalized, waiting) = crossbeam::channel::bounded(1);
+    *FINALIZED.lock().unwrap() = Some(finalized);
+    let sleep = Sleep {
+        to_main_thread: to_main_thread.clone(),
+        finalized: waiting,
+    };
+    std::thread::spawn(move || {
+        if let Err(err) = platform::watch_sleep(sleep) {
+            console::error(format!(
+                "Takes are not finalized before the system goes to sleep: {err}"
+            ));
+        }
+    });
+
+    std::thread::spawn(move || {
+        let mut was_low = false;
+        loop {
+            std::thread::sleep(Duration::from_secs(POWER_POLL_SECS));
+            let is_low = battery().is_some_and(Battery::is_low);
+            if is_low && !was_low {
+                println!("Battery is low, splitting the take to finalize the recorded files.");
+                to_main_thread
+                    .send(Action::Split)
+                    .expect("Internal thread error.");
+            }
+            was_low = is_low;
+        }
+    });
+}
+
+/// Lets the system go to sleep once the take was finalized for it.
+pub fn ready_to_sleep() {
+    if let Some(finalized) = FINALIZED.lock().unwrap().as_ref() {
+        finalized.try_send(()).ok();
+    }
+}
+
+/// What the thread watching for sleep does when the system is about to sleep and when it woke up.
+struct Sleep {
+    to_main_thread: Sender<Action>,
+    finalized: Receiver<()>,
+}
+
+impl Sleep {
+    /// Asks for the take to be finalized and waits until it was, the system sleeps when this returns.
+    fn going(&self) {
+        // A take which was finalized too late for the last sleep left its notice behind.
+        while self.finalized.try_recv().is_ok() {}
+        println!("System is going to sleep, finalizing the take.");
+        self.to_main_thread
+            .send(Action::Sleep)
+            .expect("Internal thread error.");
+        if self
+            .finalized
+            .recv_timeout(Duration::from_secs(SLEEP_DELAY_SECS))
+            .is_err()
+        {
+            console::error(
+                "The take was not finalized in time, the system goes to sleep anyway.".to_string(),
+            );
+        }
+    }
+
+    fn woke_up(&self) {
+        self.to_main_thread
+            .send(Action::Wake)
+            .expect("Internal thread error.");
+    }
+}
+
+/// logind announces sleep with `PrepareForSleep(true)` and holds it back while delay locks are taken.
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::Sleep;
+    use anyhow::{bail, Context, Result};
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+    };
+
+    /// Holds sleep back until it is killed, it waits for smrec to exit so it never outlives it.
+    fn delay_lock() -> Result<Child> {
+        Command::new("systemd-inhibit")
+            .args([
+                "--what=sleep",
+                "--who=smrec",
+                "--why=Finalizing the take",
+                "--mode=delay",
+                "tail",
+                &format!("--pid={}", std::process::id()),
+                "-f",
+                "/dev/null",
+            ])
+            .spawn()
+            .context("Error taking a delay lock with systemd-inhibit")
+    }
+
+    fn release(lock: Option<Child>) {
+        if let Some(mut lock) = lock {
+            lock.kill().ok();
+            lock.wait().ok();
+        }
+    }
+
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn watch_sleep(sleep: Sleep) -> Result<()> {
+        let mut monitor = Command::new("gdbus")
+            .args([
+                "monitor",
+                "--system",
+                "--dest",
+                "org.freedesktop.login1",
+                "--object-path",
+                "/org/freedesktop/login1",
+            ])
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Error watching logind with gdbus")?;
+        let signals = monitor.stdout.take().context("gdbus has no output")?;
+        let mut lock = Some(delay_lock()?);
+        for line in BufReader::new(signals).lines().map_while(Result::ok) {
+            match super::prepare_for_sleep(&line) {
+                Some(true) => {
+                    sleep.going();
+                    release(lock.take());
+                }
+                Some(false) => {
+                    lock = delay_lock()
+                        .map_err(|err| crate::console::error(format!("{err:#}")))
+                        .ok();
+                    sleep.woke_up();
+                }
+                None => {}
+            }
+        }
+        release(lock);
+        monitor.wait().ok();
+        bail!("gdbus stopped watching logind.")
+    }
+}
+
+/// IOKit asks whether the system may sleep and waits for the answer before it does.
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::Sleep;
+    use anyhow::{bail, Result};
+    use std::{ffi::c_void, sync::OnceLock};
+
+    const CAN_SYSTEM_SLEEP: u32 = 0xE000_0270;
+    const SYSTEM_WILL_SLEEP: u32 = 0xE000_0280;
+    const SYSTEM_HAS_POWERED_ON: u32 = 0xE000_0300;
+
+    type PowerCallback = extern "C" fn(*mut c_void, u32, u32, *mut c_void);
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IORegisterForSystemPower(
+            refcon: *mut c_void,
+            port: *mut *mut c_void,
+            callback: PowerCallback,
+            notifier: *mut u32,
+        ) -> u32;
+        fn IONotificationPortGetRunLoopSource(port: *mut c_void) -> *mut c_void;
+        fn IOAllowPowerChange(root_port: u32, notification: isize) -> i32;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        static kCFRunLoopDefaultMode: *const c_void;
+        fn CFRunLoopGetCurrent() -> *mut c_void;
+        fn CFRunLoopAddSource(run_loop: *mut c_void, source: *mut c_void, mode: *const c_void);
+        fn CFRunLoopRun();
+    }
+
+    /// What to do and the connection sleep is answered on, set before the run loop calls back.
+    static WATCH: OnceLock<(Sleep, u32)> = OnceLock::new();
+
+    extern "C" fn on_power(
+        _refcon: *mut c_void,
+        _service: u32,
+        message: u32,
+        argument: *mut c_void,
+    ) {
+        let Some((sleep, root_port)) = WATCH.get() else {
+            return;
+        };
+        match message {
+            CAN_SYSTEM_SLEEP => {}
+            SYSTEM_WILL_SLEEP => sleep.going(),
+            SYSTEM_HAS_POWERED_ON => {
+                sleep.woke_up();
+                return;
+            }
+            _ => return,
+        }
+        // SAFETY: The argument of the message is the notification to answer on the connection it came on.
+        unsafe { IOAllowPowerChange(*root_port, argument as isize) };
+    }
+
+    pub fn watch_sleep(sleep: Sleep) -> Result<()> {
+        let (mut port, mut notifier) = (std::ptr::null_mut(), 0);
+        // SAFETY: The port and the notifier outlive the call, the callback is a function of the program.
+        let root_port = unsafe {
+            IORegisterForSystemPower(
+                std::ptr::null_mut(),
+                &raw mut port,
+                on_power,
+                &raw mut notifier,
+            )
+        };
+        if root_port == 0 {
+            bail!("IORegisterForSystemPower failed.");
+        }
+        WATCH.get_or_init(|| (sleep, root_port));
+        // SAFETY: The port was just created and the run loop is the one of this thread, which runs it for ever.
+        unsafe {
+            CFRunLoopAddSource(
+                CFRunLoopGetCurrent(),
+                IONotificationPortGetRunLoopSource(port),
+                kCFRunLoopDefaultMode,
+            );
+            CFRunLoopRun();
+        }
+        bail!("The run loop watching for sleep stopped.")
+    }
+}
+
+/// The `WM_POWERBROADCAST` events, delivered to a callback instead of a window. Windows sleeps once it returns.
+#[cfg(windows)]
+mod platform {
+    use super::Sleep;
+    use anyhow::{bail, Result};
+    use std::{ffi::c_void, sync::OnceLock};
+
+    const DEVICE_NOTIFY_CALLBACK: u32 = 2;
+    const PBT_APMSUSPEND: u32 = 0x4;
+    const PBT_APMRESUMEAUTOMATIC: u32 = 0x12;
+
+    #[repr(C)]
+    struct Subscription {
+        callback: extern "system" fn(*mut c_void, u32, *mut c_void) -> u32,
+        context: *mut c_void,
+    }
+
+    #[link(name = "powrprof")]
+    extern "system" {
+        fn PowerRegisterSuspendResumeNotification(
+            flags: u32,
+            recipient: *mut c_void,
+            registration: *mut *mut c_void,
+        ) -> u32;
+    }
+
+    static SLEEP: OnceLock<Sleep> = OnceLock::new();
+
+    extern "system" fn on_power(_context: *mut c_void, event: u32, _setting: *mut c_void) -> u32 {
+        if let Some(sleep) = SLEEP.get() {
+            match event {
+                PBT_APMSUSPEND => sleep.going(),
+                PBT_APMRESUMEAUTOMATIC => sleep.woke_up(),
+                _ => {}
+            }
+        }
+        0
+    }
+
+    pub fn watch_sleep(sleep: Sleep) -> Result<()> {
+        SLEEP.get_or_init(|| sleep);
+        // The registration calls back for the rest of the run, it is never taken back.
+        let subscription = Box::leak(Box::new(Subscription {
+            callback: on_power,
+            context: std::ptr::null_mut(),
+        }));
+        let mut registration = std::ptr::null_mut();
+        // SAFETY: The subscription lives as long as the program and the registration outlives the call.
+        let err = unsafe {
+            PowerRegisterSuspendResumeNotification(
+                DEVICE_NOTIFY_CALLBACK,
+                std::ptr::from_mut(subscription).cast(),
+                &raw mut registration,
+            )
+        };
+        if err != 0 {
+            #[allow(clippy::cast_possible_wrap)]
+            let err = std::io::Error::from_raw_os_error(err as i32);
+            bail!("{err}");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    use super::Sleep;
+    use anyhow::{bail, Result};
+
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn watch_sleep(_sleep: Sleep) -> Result<()> {
+        bail!("Watching for sleep is not supported on this platform.")
+    }
+}
+
+/// Parses a line of `gdbus monitor`, e.g. `/org/freedesktop/login1: org.freedesktop.login1.Manager.PrepareForSleep (true,)`,
+/// whether the system is going to sleep or woke up.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn prepare_for_sleep(line: &str) -> Option<bool> {
+    match line.split_once(".Manager.PrepareForSleep ")?.1.trim() {
+        "(true,)" => Some(true),
+        "(false,)" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn battery() -> Option<Battery> {
+    std::fs::read_dir("/sys/class/power_supply")
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("BAT"))
+        .find_map(|entry| {
+            let read = |file| std::fs::read_to_string(entry.path().join(file)).ok();
+            parse_sysfs(&read("capacity")?, &read("status")?)
+        })
+}
+
+#[cfg(target_os = "macos")]
+fn battery() -> Option<Battery> {
+    let output = std::process::Command::new("pmset")
+        .args(["-g", "batt"])
+        .output()
+        .ok()?;
+    parse_pmset(&String::from_utf8_lossy(&output.stdout))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+const fn battery() -> Option<Battery> {
+    None
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_sysfs(capacity: &str, status: &str) -> Option<Battery> {
+    Some(Battery {
+        percent: capacity.trim().parse().ok()?,
+        discharging: status.trim() == "Discharging",
+    })
+}
+
+/// Parses the output of `pmset -g batt`, e.g. `-InternalBattery-0 (id=1234) 8%; discharging; 0:12 remaining`.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_pmset(output: &str) -> Option<Battery> {
+    let line = output
+        .lines()
+        .find(|line| line.contains("InternalBattery"))?;
+    let mut fields = line.split(';');
+    let percent = fields.next()?.rsplit(char::is_whitespace).next()?;
+    Some(Battery {
+        percent: percent.trim_end_matches('%').parse().ok()?,
+        discharging: fields.next()?.trim() == "discharging",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_power_events() {
+        let signal =
+            "/org/freedesktop/login1: org.freedesktop.login1.Manager.PrepareForSleep (true,)";
+        assert_eq!(prepare_for_sleep(signal), Some(true));
+        assert_eq!(
+            prepare_for_sleep(&signal.replace("true", "false")),
+            Some(false)
+        );
+        assert_eq!(
+            prepare_for_sleep("/org/freedesktop/login1: org.freedesktop.login1.Manager.SessionNew ('3', objectpath '/org/freedesktop/login1/session/_33')"),
+            None
+        );
+
+        assert_eq!(
+            parse_sysfs("8\n", "Discharging\n"),
+            Some(Battery {
+                percent: 8,
+                discharging: true
+            })
+        );
+        assert!(!parse_sysfs("8\n", "Charging\n").unwrap().is_low());
+
+        let pmset = "Now drawing from 'Battery Power'\n -InternalBattery-0 (id=4653155)\t9%; discharging; 0:14 remaining present: true\n";
+        assert!(parse_pmset(pmset).unwrap().is_low());
+        let pmset = "Now drawing from 'AC Power'\n -InternalBattery-0 (id=4653155)\t100%; charged; 0:00 remaining present: true\n";
+        assert_eq!(
+            parse_pmset(pmset),
+            Some(Battery {
+                percent: 100,
+                discharging: false
+            })
+        );
+    }
+}
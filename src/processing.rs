@@ -0,0 +1,238 @@
+use crate::{clock::BeatClock, meters::Meters};
+use anyhow::{anyhow, bail, Result};
+use cpal::{FromSample, Sample};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// How long a gate stays open after the channel drops below its threshold.
+const GATE_HOLD_SECS: f32 = 0.05;
+
+/// Gain and gate of every device channel, changed remotely while recording and applied from the next buffer on.
+///
+/// Solo and mute only change what the monitor stream carries, the recording keeps every channel.
+///
+/// The meters of the channels and the beat clock of the monitor stream are shared with the streams along with it.
+///
+/// Parameters are atomics so the audio callbacks never wait for them.
+#[derive(Debug, Default)]
+pub struct Processing {
+    channels: Vec<Parameters>,
+    meters: Meters,
+    beat_clock: BeatClock,
+}
+
+/// Parameters of a channel, stored as the bits of linear `f32` values.
+#[derive(Debug)]
+struct Parameters {
+    gain: AtomicU32,
+    /// Threshold of the gate, 0 when the gate is off.
+    gate: AtomicU32,
+    solo: AtomicBool,
+    mute: AtomicBool,
+}
+
+impl Processing {
+    pub fn new(channels: usize) -> Self {
+        Self {
+            channels: (0..channels)
+                .map(|_| Parameters {
+                    gain: AtomicU32::new(1.0_f32.to_bits()),
+                    gate: AtomicU32::new(0.0_f32.to_bits()),
+                    solo: AtomicBool::new(false),
+                    mute: AtomicBool::new(false),
+                })
+                .collect(),
+            meters: Meters::new(channels),
+            beat_clock: BeatClock::default(),
+        }
+    }
+
+    /// Peak hold, overs, average and live levels of every device channel.
+    pub const fn meters(&self) -> &Meters {
+        &self.meters
+    }
+
+    /// The last beat of an external MIDI clock, for the click of the monitor stream.
+    pub const fn beat_clock(&self) -> &BeatClock {
+        &self.beat_clock
+    }
+
+    /// Sets the gain of a 0 indexed channel in dB.
+    pub fn set_gain(&self, channel: usize, db: f32) -> Result<()> {
+        if !db.is_finite() {
+            bail!("Invalid gain {db} dB.");
+        }
+        self.parameters(channel)?
+            .gain
+            .store(db_to_linear(db).to_bits(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Sets the gate threshold of a 0 indexed channel in dBFS, `None` turns the gate off.
+    pub fn set_gate(&self, channel: usize, threshold_db: Option<f32>) -> Result<()> {
+        let threshold = match threshold_db {
+            Some(db) if db.is_finite() => db_to_linear(db),
+            Some(db) => bail!("Invalid gate threshold {db} dBFS."),
+            None => 0.0,
+        };
+        self.parameters(channel)?
+            .gate
+            .store(threshold.to_bits(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Solos a 0 indexed channel in the monitor stream or takes the solo back.
+    pub fn set_solo(&self, channel: usize, solo: bool) -> Result<()> {
+        self.parameters(channel)?
+            .solo
+            .store(solo, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Mutes a 0 indexed channel in the monitor stream or unmutes it.
+    pub fn set_mute(&self, channel: usize, mute: bool) -> Result<()> {
+        self.parameters(channel)?
+            .mute
+            .store(mute, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Whether a 0 indexed channel is heard in the monitor stream, while any channel is soloed only soloed ones are.
+    pub fn monitored(&self, channel: usize) -> bool {
+        let Some(parameters) = self.channels.get(channel) else {
+            return true;
+        };
+        if self
+            .channels
+            .iter()
+            .any(|parameters| parameters.solo.load(Ordering::Relaxed))
+        {
+            parameters.solo.load(Ordering::Relaxed)
+        } else {
+            !parameters.mute.load(Ordering::Relaxed)
+        }
+    }
+
+    fn parameters(&self, channel: usize) -> Result<&Parameters> {
+        self.channels.get(channel).ok_or_else(|| {
+            anyhow!(
+                "Channel {} does not exist, the device has {} channels.",
+                channel + 1,
+                self.channels.len()
+            )
+        })
+    }
+
+    /// Applies the gate and then the gain of a 0 indexed channel to its samples.
+    pub fn apply<T>(&self, channel: usize, samples: &mut [T], gate: &mut Gate)
+    where
+        T: Sample + FromSample<f32>,
+        f32: FromSample<T>,
+    {
+        let Some(parameters) = self.channels.get(channel) else {
+            return;
+        };
+        let gain = f32::from_bits(parameters.gain.load(Ordering::Relaxed));
+        let threshold = f32::from_bits(parameters.gate.load(Ordering::Relaxed));
+        // Leaves the samples untouched unless something is set.
+        #[allow(clippy::float_cmp)]
+        if gain == 1.0 && threshold == 0.0 {
+            return;
+        }
+        for sample in samples {
+            let value = f32::from_sample(*sample);
+            let open = threshold == 0.0 || gate.open(value.abs() >= threshold);
+            *sample = T::from_sample(if open { value * gain } else { 0.0 });
+        }
+    }
+}
+
+/// State of the gate of a channel, kept by the stream which records it.
+pub struct Gate {
+    hold: usize,
+    open_for: usize,
+}
+
+impl Gate {
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            hold: (sample_rate as f32 * GATE_HOLD_SECS) as usize,
+            open_for: 0,
+        }
+    }
+
+    /// Whether the gate is open for a sample, it stays open for the hold time after the last one above the threshold.
+    const fn open(&mut self, above: bool) -> bool {
+        if above {
+            self.open_for = self.hold;
+            return true;
+        }
+        self.open_for = self.open_for.saturating_sub(1);
+        self.open_for > 0
+    }
+}
+
+pub fn db_to_linear(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+pub fn linear_to_db(linear: f32) -> f32 {
+    20.0 * linear.log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn gain_and_gate_apply_from_the_next_buffer() {
+        let processing = Processing::new(2);
+        let mut gate = Gate::new(100);
+        let mut samples = [0.5_f32, -0.01, 0.0];
+
+        processing.apply(0, &mut samples, &mut gate);
+        assert_eq!(samples, [0.5, -0.01, 0.0]);
+
+        processing.set_gain(0, -6.0).unwrap();
+        processing.apply(0, &mut samples, &mut gate);
+        assert!((samples[0] - 0.25).abs() < 0.01);
+
+        // 5 samples of hold at 100 Hz.
+        processing.set_gain(0, 0.0).unwrap();
+        processing.set_gate(0, Some(-20.0)).unwrap();
+        let mut samples = [0.5_f32, 0.01, 0.01, 0.01, 0.01, 0.01, 0.01];
+        processing.apply(0, &mut samples, &mut gate);
+        assert_eq!(samples, [0.5, 0.01, 0.01, 0.01, 0.01, 0.0, 0.0]);
+
+        processing.set_gate(0, None).unwrap();
+        let mut samples = [0.01_f32; 2];
+        processing.apply(0, &mut samples, &mut gate);
+        assert!(samples.iter().all(|sample| *sample != 0.0));
+
+        assert!(processing.set_gain(2, 0.0).is_err());
+    }
+
+    #[test]
+    fn solo_wins_over_mute_in_the_monitor() {
+        let processing = Processing::new(3);
+        assert!((0..3).all(|channel| processing.monitored(channel)));
+
+        processing.set_mute(0, true).unwrap();
+        assert!(!processing.monitored(0) && processing.monitored(1));
+
+        processing.set_solo(0, true).unwrap();
+        processing.set_solo(2, true).unwrap();
+        assert!(processing.monitored(0) && !processing.monitored(1) && processing.monitored(2));
+
+        processing.set_solo(0, false).unwrap();
+        processing.set_solo(2, false).unwrap();
+        assert!(!processing.monitored(0) && processing.monitored(1));
+
+        assert!(processing.set_solo(3, true).is_err());
+    }
+}
@@ -0,0 +1,90 @@
+// Pairing prints where the recorder listens for OSC as a QR code, a controller app on a phone scans it instead
+// of the address being typed in on a headless machine.
+
+use crate::syslog;
+use anyhow::Result;
+use qrcode::{render::unicode::Dense1x2, QrCode};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+
+/// Prints a QR code of the first address OSC is listened on and the name of the recorder.
+pub fn print(listen: &[SocketAddr], instance: Option<&str>) -> Result<()> {
+    let Some(address) = listen.first() else {
+        return Ok(());
+    };
+    let instance = instance.map_or_else(syslog::hostname, ToString::to_string);
+    let endpoint = endpoint(reachable(*address), &instance);
+    let code = QrCode::new(endpoint.as_bytes())?;
+    // Light modules on a dark terminal, which is how most terminals look.
+    let image = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build();
+    println!("Scan to pair a controller with {instance}:\n{image}\n{endpoint}");
+    Ok(())
+}
+
+/// The endpoint as an OSC URL as liblo writes them, the instance name in the query.
+fn endpoint(address: SocketAddr, instance: &str) -> String {
+    let host = match address.ip() {
+        IpAddr::V6(ip) => format!("[{ip}]"),
+        IpAddr::V4(ip) => ip.to_string(),
+    };
+    format!(
+        "osc.udp://{host}:{}/?instance={}",
+        address.port(),
+        encode(instance)
+    )
+}
+
+/// A phone can not reach `0.0.0.0`, the address of the interface the default route leaves from is given then.
+fn reachable(address: SocketAddr) -> SocketAddr {
+    if !address.ip().is_unspecified() {
+        return address;
+    }
+    // Connecting a UDP socket sends nothing, it only picks the interface.
+    let ip = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], 0)))
+        .and_then(|socket| {
+            socket.connect(SocketAddr::from(([192, 0, 2, 1], 9)))?;
+            socket.local_addr()
+        })
+        .map_or(IpAddr::V4(Ipv4Addr::LOCALHOST), |local| local.ip());
+    SocketAddr::new(ip, address.port())
+}
+
+/// Percent encoding of everything but the unreserved characters of URLs.
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+                char::from(byte).to_string()
+            } else {
+                format!("%{byte:02X}")
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoints_can_be_reached_from_other_machines() {
+        assert_eq!(
+            endpoint("192.168.1.20:18000".parse().unwrap(), "stage left"),
+            "osc.udp://192.168.1.20:18000/?instance=stage%20left"
+        );
+        assert_eq!(
+            endpoint("[fe80::1]:18000".parse().unwrap(), "Bühne"),
+            "osc.udp://[fe80::1]:18000/?instance=B%C3%BChne"
+        );
+
+        let local = "127.0.0.1:18000".parse().unwrap();
+        assert_eq!(reachable(local), local);
+        let any = reachable("0.0.0.0:18000".parse().unwrap());
+        assert!(!any.ip().is_unspecified());
+        assert_eq!(any.port(), 18000);
+    }
+}
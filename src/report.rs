@@ -0,0 +1,369 @@
+use crate::takes;
+use anyhow::Result;
+use camino::Utf8Path;
+use chrono::DateTime;
+use std::fmt::Write;
+
+const CSV_FILE: &str = "sound_report.csv";
+const PDF_FILE: &str = "sound_report.pdf";
+const CSV_HEADER: &str =
+    "take,id,date,start,duration,scene,take_number,sample_rate,tracks,peaks_dbfs,notes";
+
+/// Landscape A4 in points.
+const PAGE_WIDTH: u32 = 842;
+const PAGE_HEIGHT: u32 = 595;
+const MARGIN: u32 = 40;
+const FONT_SIZE: u32 = 8;
+const LEADING: u32 = 11;
+
+/// A take as it appears in the report.
+#[derive(Debug, Default)]
+struct Entry {
+    take: String,
+    /// ULID of the take, empty for takes recorded before takes had one.
+    id: String,
+    date: String,
+    start: String,
+    frames: u64,
+    sample_rate: u32,
+    scene: String,
+    take_number: Option<i64>,
+    notes: String,
+    /// File names and their peak levels in dBFS, `None` when silent.
+    tracks: Vec<(String, Option<f64>)>,
+}
+
+impl Entry {
+    /// Duration as `HH:MM:SS`.
+    fn duration(&self) -> String {
+        let seconds = self.frames / u64::from(self.sample_rate.max(1));
+        format!(
+            "{:02}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+}
+
+/// Writes a sound report of all takes in the output directory as CSV and optionally as PDF.
+pub fn run(base: &Utf8Path, pdf: bool) -> Result<()> {
+    let entries = collect(base)?;
+
+    let path = base.join(CSV_FILE);
+    std::fs::write(&path, csv(&entries))?;
+    println!(
+        "Sound report of {} takes is written to {path}.",
+        entries.len()
+    );
+
+    if pdf {
+        let path = base.join(PDF_FILE);
+        std::fs::write(&path, pdf_document(&text_lines(base, &entries)))?;
+        println!("Sound report is written to {path}.");
+    }
+
+    Ok(())
+}
+
+/// Reads the takes in the output directory in the order they were recorded.
+fn collect(base: &Utf8Path) -> Result<Vec<Entry>> {
+    takes::all(base)?.iter().map(|take| entry(take)).collect()
+}
+
+fn entry(take: &Utf8Path) -> Result<Entry> {
+    let mut entry = Entry {
+        take: take.file_name().unwrap_or(take.as_str()).to_string(),
+        ..Entry::default()
+    };
+    let mut files = Vec::new();
+    for file in take.read_dir_utf8()? {
+        let path = file?.into_path();
+        let name = path.file_name().unwrap_or_default();
+        match path.extension() {
+            Some(extension)
+                if extension.eq_ignore_ascii_case("toml") && name.starts_with("take") =>
+            {
+                add_sidecar(&mut entry, &path, name == "take.toml")?;
+            }
+            Some(extension) if extension.eq_ignore_ascii_case("wav") => files.push(path),
+            _ => {}
+        }
+    }
+    files.sort();
+    entry.tracks = files
+        .into_iter()
+        .map(|file| {
+            let peak = peak_dbfs(&file).unwrap_or_else(|err| {
+                eprintln!("Error reading {file}: {err}");
+                None
+            });
+            (file.file_name().unwrap_or_default().to_string(), peak)
+        })
+        .collect();
+    Ok(entry)
+}
+
+/// Adds the metadata of a take sidecar, frames of continuation parts are summed up.
+fn add_sidecar(entry: &mut Entry, path: &Utf8Path, is_first_part: bool) -> Result<()> {
+    let sidecar = std::fs::read_to_string(path)?.parse::<toml::Table>()?;
+    let string = |key: &str| {
+        sidecar
+            .get(key)
+            .and_then(toml::Value::as_str)
+            .map(str::to_string)
+    };
+
+    entry.frames += sidecar
+        .get("frames")
+        .and_then(toml::Value::as_integer)
+        .and_then(|frames| u64::try_from(frames).ok())
+        .unwrap_or_default();
+    if let Some(sample_rate) = sidecar
+        .get("sample_rate")
+        .and_then(toml::Value::as_integer)
+        .and_then(|rate| u32::try_from(rate).ok())
+    {
+        entry.sample_rate = sample_rate;
+    }
+    if is_first_part {
+        if let Some(started_at) =
+            string("started_at").and_then(|date| DateTime::parse_from_rfc3339(&date).ok())
+        {
+            entry.date = started_at.format("%Y-%m-%d").to_string();
+            entry.start = started_at.format("%H:%M:%S").to_string();
+        }
+        entry.id = string("id").unwrap_or_default();
+        entry.scene = string("scene").unwrap_or_default();
+        entry.take_number = sidecar.get("take_number").and_then(toml::Value::as_integer);
+        entry.notes = string("notes").unwrap_or_default();
+    }
+    Ok(())
+}
+
+/// The highest absolute sample of a file in dBFS, `None` when it is silent.
+fn peak_dbfs(path: &Utf8Path) -> Result<Option<f64>> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let peak = match spec.sample_format {
+        hound::SampleFormat::Float => {
+            reader.samples::<f32>().try_fold(0.0_f64, |peak, sample| {
+                sample.map(|sample| peak.max(f64::from(sample.abs())))
+            })?
+        }
+        hound::SampleFormat::Int => {
+            let full_scale = f64::from(1_u32 << (spec.bits_per_sample - 1));
+            reader.samples::<i32>().try_fold(0.0_f64, |peak, sample| {
+                sample.map(|sample| peak.max(f64::from(sample.unsigned_abs())))
+            })? / full_scale
+        }
+    };
+    Ok((peak > 0.0).then(|| 20.0 * peak.log10()))
+}
+
+fn format_peak(peak: Option<f64>) -> String {
+    peak.map_or_else(|| "-inf".to_string(), |peak| format!("{peak:.1}"))
+}
+
+fn csv(entries: &[Entry]) -> String {
+    let mut csv = String::new();
+    // Writing to a string does not fail.
+    let _ = writeln!(csv, "{CSV_HEADER}");
+    for entry in entries {
+        let tracks = entry
+            .tracks
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+        let peaks = entry
+            .tracks
+            .iter()
+            .map(|(_, peak)| format_peak(*peak))
+            .collect::<Vec<_>>()
+            .join(";");
+        let fields = [
+            entry.take.clone(),
+            entry.id.clone(),
+            entry.date.clone(),
+            entry.start.clone(),
+            entry.duration(),
+            entry.scene.clone(),
+            entry
+                .take_number
+                .map(|take| take.to_string())
+                .unwrap_or_default(),
+            entry.sample_rate.to_string(),
+            tracks,
+            peaks,
+            entry.notes.clone(),
+        ];
+        let _ = writeln!(
+            csv,
+            "{}",
+            fields
+                .iter()
+                .map(|field| csv_field(field))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+    }
+    csv
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// The report as a monospaced table, a line for each take followed by its tracks.
+fn text_lines(base: &Utf8Path, entries: &[Entry]) -> Vec<String> {
+    let mut lines = vec![
+        format!("Sound report of {base}"),
+        String::new(),
+        format!(
+            "{:<22} {:<10} {:<8} {:<8} {:<10} {:>4} {:>6}  {}",
+            "Take", "Date", "Start", "Duration", "Scene", "Take", "Rate", "Notes"
+        ),
+    ];
+    for entry in entries {
+        lines.push(format!(
+            "{:<22} {:<10} {:<8} {:<8} {:<10} {:>4} {:>6}  {}",
+            entry.take,
+            entry.date,
+            entry.start,
+            entry.duration(),
+            entry.scene,
+            entry
+                .take_number
+                .map(|take| take.to_string())
+                .unwrap_or_default(),
+            entry.sample_rate,
+            entry.notes
+        ));
+        for (name, peak) in &entry.tracks {
+            lines.push(format!("    {name:<40} {:>6} dBFS", format_peak(*peak)));
+        }
+    }
+    lines
+}
+
+/// A minimal PDF of text lines in Courier, split into pages.
+fn pdf_document(lines: &[String]) -> Vec<u8> {
+    let lines_per_page = ((PAGE_HEIGHT - 2 * MARGIN) / LEADING) as usize;
+    let pages = lines.chunks(lines_per_page.max(1)).collect::<Vec<_>>();
+
+    // Catalog, page tree and font come first, then a page and its content for every page.
+    let page_ids = (0..pages.len())
+        .map(|page| 4 + 2 * page)
+        .collect::<Vec<_>>();
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            page_ids
+                .iter()
+                .map(|id| format!("{id} 0 R"))
+                .collect::<Vec<_>>()
+                .join(" "),
+            pages.len()
+        ),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Courier >>".to_string(),
+    ];
+    for (page, id) in pages.iter().zip(&page_ids) {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+            id + 1
+        ));
+        let mut content = format!(
+            "BT /F1 {FONT_SIZE} Tf {LEADING} TL {MARGIN} {} Td\n",
+            PAGE_HEIGHT - MARGIN
+        );
+        for line in *page {
+            let _ = writeln!(content, "({}) '", pdf_text(line));
+        }
+        content.push_str("ET");
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{content}\nendstream",
+            content.len()
+        ));
+    }
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (id, object) in (1..).zip(&objects) {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{id} 0 obj\n{object}\nendobj\n").as_bytes());
+    }
+    let xref = pdf.len();
+    let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(trailer, "{offset:010} 00000 n ");
+    }
+    let _ = write!(
+        trailer,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+        objects.len() + 1
+    );
+    pdf.extend_from_slice(trailer.as_bytes());
+    pdf
+}
+
+/// Escapes a line for a PDF string, characters the standard fonts can not show are replaced.
+fn pdf_text(line: &str) -> String {
+    line.chars()
+        .map(|c| match c {
+            '(' | ')' | '\\' => format!("\\{c}"),
+            ' '..='~' => c.to_string(),
+            _ => "?".to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn reports_takes_with_peaks() {
+        let dir = TempDir::new("report");
+        let base = dir.path();
+        let take = base.join("rec_20231120_120000");
+        std::fs::create_dir_all(&take).unwrap();
+        std::fs::write(
+            take.join("take.toml"),
+            "id = \"01HFKQ7Z6S3M0V4W5X6Y7Z8A9B\"\nstarted_at = \"2023-11-20T12:00:00+00:00\"\nsample_rate = 48000\nframes = 96000\nchannels = 1\nscene = \"12A\"\ntake_number = 3\nnotes = \"mic hit, redo\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            take.join("take_part2.toml"),
+            "sample_rate = 48000\nframes = 48000\n",
+        )
+        .unwrap();
+
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(take.join("Boom.wav"), spec).unwrap();
+        writer.write_sample(16384_i16).unwrap();
+        writer.write_sample(-8192_i16).unwrap();
+        writer.finalize().unwrap();
+
+        let entries = collect(base).unwrap();
+        assert_eq!(
+            csv(&entries),
+            format!("{CSV_HEADER}\nrec_20231120_120000,01HFKQ7Z6S3M0V4W5X6Y7Z8A9B,2023-11-20,12:00:00,00:00:03,12A,3,48000,Boom.wav,-6.0,\"mic hit, redo\"\n")
+        );
+
+        let pdf = pdf_document(&text_lines(base, &entries));
+        assert!(pdf.starts_with(b"%PDF-1.4\n"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+    }
+}
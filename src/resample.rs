@@ -0,0 +1,295 @@
+use anyhow::{bail, Result};
+use std::{
+    f64::consts::PI,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Steps between two fractional positions the filter is computed for.
+const PHASES: usize = 256;
+/// How long frames are counted before the rate they arrive at is followed.
+const PACE_SECS: u64 = 10;
+/// How far measured rates may be off the ratio of the sample rates, the clocks of devices are off by far less.
+const MAX_DRIFT: f64 = 0.001;
+
+/// How devices are resampled, trading latency and load for quality.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quality {
+    /// Linear interpolation, 1 frame of latency, aliases audibly when the rates are far apart.
+    Fast,
+    /// A windowed sinc of 16 taps, 8 frames of latency.
+    Good,
+    /// A windowed sinc of 64 taps, 32 frames of latency.
+    Best,
+}
+
+impl FromStr for Quality {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "fast" => Ok(Self::Fast),
+            "good" => Ok(Self::Good),
+            "best" => Ok(Self::Best),
+            _ => bail!("Unknown resampling quality {s}, use one of fast, good or best."),
+        }
+    }
+}
+
+impl Quality {
+    const fn taps(self) -> usize {
+        match self {
+            Self::Fast => 2,
+            Self::Good => 16,
+            Self::Best => 64,
+        }
+    }
+}
+
+/// Counts the frames a device delivers against the clock of the computer, to measure the rate it really runs at.
+#[derive(Default)]
+pub struct Pace(Mutex<Option<Count>>);
+
+#[derive(Clone, Copy)]
+struct Count {
+    /// When counting started, after the first buffer since its frames were captured before.
+    since: Instant,
+    frames: u64,
+    last: Instant,
+}
+
+impl Pace {
+    /// Counts the frames of a buffer which arrived now.
+    pub fn count(&self, frames: usize, now: Instant) {
+        let Ok(mut count) = self.0.try_lock() else {
+            return;
+        };
+        match count.as_mut() {
+            Some(count) => {
+                count.frames += frames as u64;
+                count.last = now;
+            }
+            None => {
+                *count = Some(Count {
+                    since: now,
+                    frames: 0,
+                    last: now,
+                });
+            }
+        }
+    }
+
+    /// Frames per second, none until they were counted long enough.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn rate(&self) -> Option<f64> {
+        let count = (*self.0.try_lock().ok()?)?;
+        let elapsed = count.last.duration_since(count.since);
+        (elapsed >= Duration::from_secs(PACE_SECS))
+            .then(|| count.frames as f64 / elapsed.as_secs_f64())
+    }
+}
+
+/// Converts interleaved audio from one sample rate to another as it arrives.
+pub struct Resampler {
+    channels: usize,
+    /// Input frames per output frame.
+    step: f64,
+    /// The step of the nominal sample rates.
+    nominal: f64,
+    taps: usize,
+    /// The filter for `PHASES + 1` fractional positions, `taps` coefficients each.
+    table: Vec<f32>,
+    /// Input frames which are still needed, interleaved.
+    history: Vec<f32>,
+    /// Position of the next output frame in `history`, in input frames.
+    position: f64,
+}
+
+impl Resampler {
+    #[allow(clippy::cast_precision_loss)]
+    pub fn new(channels: usize, from: u32, to: u32, quality: Quality) -> Self {
+        let taps = quality.taps();
+        let step = f64::from(from) / f64::from(to);
+        // Frequencies above the lower of the two Nyquist frequencies are filtered out.
+        let cutoff = (1.0 / step).min(1.0);
+        let half = (taps / 2) as f64;
+        let mut table = Vec::with_capacity((PHASES + 1) * taps);
+        for phase in 0..=PHASES {
+            let fraction = phase as f64 / PHASES as f64;
+            let row = (0..taps)
+                .map(|tap| {
+                    // Distance of the tap from the output position.
+                    let distance = fraction + half - 1.0 - tap as f64;
+                    match quality {
+                        Quality::Fast => (1.0 - distance.abs()).max(0.0),
+                        Quality::Good | Quality::Best => {
+                            cutoff * sinc(cutoff * distance) * blackman(distance / half)
+                        }
+                    }
+                })
+                .collect::<Vec<_>>();
+            // Every row passes DC unchanged.
+            let sum = row.iter().sum::<f64>();
+            #[allow(clippy::cast_possible_truncation)]
+            table.extend(row.iter().map(|coefficient| (coefficient / sum) as f32));
+        }
+        Self {
+            channels,
+            step,
+            nominal: step,
+            taps,
+            table,
+            // Output starts right away, as if silence came before the input.
+            history: vec![0.0; (taps / 2 - 1) * channels],
+            position: half - 1.0,
+        }
+    }
+
+    pub const fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Follows the rates the devices really run at, measured against the same clock, instead of their nominal ones.
+    ///
+    /// Without a common word clock the devices drift apart, the resampled one keeps pace with the one it is resampled to.
+    /// Rates further off than clocks drift are measurement errors and ignored.
+    pub fn follow(&mut self, from: f64, to: f64) {
+        let step = from / to;
+        if (step / self.nominal - 1.0).abs() <= MAX_DRIFT {
+            self.step = step;
+        }
+    }
+
+    /// Resamples interleaved input into `output`.
+    ///
+    /// Returns where the first output frame is relative to the first input frame, in input frames.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) -> f64 {
+        let start = (self.history.len() / self.channels) as f64;
+        let first = self.position - start;
+        self.history.extend_from_slice(input);
+        let frames = self.history.len() / self.channels;
+        let half = self.taps / 2;
+        output.clear();
+        while (self.position as usize) + half < frames {
+            let index = self.position as usize;
+            let phase = self.position.fract() * PHASES as f64;
+            let (row, blend) = (phase as usize, phase.fract() as f32);
+            let (this, next) = (
+                &self.table[row * self.taps..(row + 1) * self.taps],
+                &self.table[(row + 1) * self.taps..(row + 2) * self.taps],
+            );
+            let first_frame = index + 1 - half;
+            for channel in 0..self.channels {
+                let mut sample = 0.0;
+                for tap in 0..self.taps {
+                    let coefficient = (next[tap] - this[tap]).mul_add(blend, this[tap]);
+                    sample +=
+                        coefficient * self.history[(first_frame + tap) * self.channels + channel];
+                }
+                output.push(sample);
+            }
+            self.position += self.step;
+        }
+        // Frames before the ones the next output frame needs are not needed anymore.
+        let consumed = (self.position as usize + 1)
+            .saturating_sub(half)
+            .min(frames);
+        self.history.drain(..consumed * self.channels);
+        self.position -= consumed as f64;
+        first
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// A Blackman window over -1 to 1.
+fn blackman(x: f64) -> f64 {
+    if x.abs() >= 1.0 {
+        return 0.0;
+    }
+    0.08f64.mul_add((2.0 * PI * x).cos(), 0.5f64.mul_add((PI * x).cos(), 0.42))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Resamples a sine in buffers and returns its peak once the filter settled.
+    fn peak_of_resampled_sine(from: u32, to: u32, frequency: f64, quality: Quality) -> f32 {
+        let mut resampler = Resampler::new(2, from, to, quality);
+        let mut output = Vec::new();
+        let mut resampled = Vec::new();
+        #[allow(clippy::cast_possible_truncation)]
+        let sine = (0..from)
+            .flat_map(|frame| {
+                let sample =
+                    (2.0 * PI * frequency * f64::from(frame) / f64::from(from)).sin() as f32;
+                [sample, sample]
+            })
+            .collect::<Vec<_>>();
+        for buffer in sine.chunks(2 * 441) {
+            resampler.process(buffer, &mut output);
+            resampled.extend_from_slice(&output);
+        }
+        // A second of input is about a second of output.
+        let frames = resampled.len() / 2;
+        assert!(frames.abs_diff(to as usize) <= quality.taps());
+        resampled[1000..]
+            .iter()
+            .fold(0.0, |peak, sample| f32::max(peak, sample.abs()))
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn rates_are_converted_without_losing_the_signal() {
+        for quality in [Quality::Fast, Quality::Good, Quality::Best] {
+            let peak = peak_of_resampled_sine(44100, 48000, 1000.0, quality);
+            assert!((0.95..1.05).contains(&peak), "{quality:?} {peak}");
+        }
+        // Above the Nyquist frequency of the output, filtered out.
+        let peak = peak_of_resampled_sine(96000, 48000, 30000.0, Quality::Best);
+        assert!(peak < 0.05, "{peak}");
+
+        let mut resampler = Resampler::new(1, 48000, 48000, Quality::Good);
+        let mut output = Vec::new();
+        let first = resampler.process(&[1.0; 64], &mut output);
+        // It lags half of its taps behind.
+        assert_eq!(output.len(), 64 - 8);
+        assert!(output.iter().all(|sample| (sample - 1.0).abs() < 1e-6));
+        // Its first frame is the first frame of the input.
+        assert_eq!(first, 0.0);
+        assert!("linear".parse::<Quality>().is_err());
+
+        // The device runs 100 ppm fast against the one it is resampled to, which runs at its nominal rate.
+        let (pace, reference) = (Pace::default(), Pace::default());
+        let start = Instant::now();
+        for buffer in 0..=PACE_SECS * 100 {
+            let now = start + Duration::from_millis(buffer * 10);
+            pace.count(441, now);
+            reference.count(480, now);
+            if buffer == 100 {
+                assert!(pace.rate().is_none());
+            }
+        }
+        let (from, to) = (pace.rate().unwrap() * 1.0001, reference.rate().unwrap());
+        assert!((to - 48000.0).abs() < 1e-6);
+        let mut resampler = Resampler::new(1, 44100, 48000, Quality::Good);
+        resampler.follow(from, to);
+        assert!((resampler.step * 48000.0 / 44100.0 - 1.0001).abs() < 1e-9);
+        // A device which seems to run 1 % fast was measured wrong.
+        resampler.follow(from * 1.01, to);
+        assert!((resampler.step * 48000.0 / 44100.0 - 1.0001).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::types::Action;
+use anyhow::{anyhow, bail, Result};
+use chrono::{Local, NaiveTime};
+use serde::Deserialize;
+use std::{str::FromStr, time::Duration};
+
+/// How often the scheduler checks whether a window opened or closed.
+const SCHEDULE_POLL_SECS: u64 = 1;
+
+/// Daily time windows to record in, configured in the `[schedule]` section.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct Schedule {
+    #[serde(default)]
+    windows: Vec<Window>,
+}
+
+/// A daily window in local time, e.g. `22:00-06:00`, which may span midnight.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct Window {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl Window {
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            // Spans midnight.
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+impl FromStr for Window {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| anyhow!("Schedule window {s} should look like 22:00-06:00."))?;
+        let parse = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|err| anyhow!("Invalid time {time} in schedule window {s}: {err}"))
+        };
+        let window = Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        };
+        if window.start == window.end {
+            bail!("Schedule window {s} is empty.");
+        }
+        Ok(window)
+    }
+}
+
+impl TryFrom<String> for Window {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl Schedule {
+    pub const fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    fn is_open(&self, time: NaiveTime) -> bool {
+        self.windows.iter().any(|window| window.contains(time))
+    }
+
+    /// Starts a thread which starts recording when a window opens and stops it when it closes.
+    ///
+    /// Only the edges are acted on, so recordings can still be started and stopped via OSC or MIDI in between.
+    pub fn spawn(self, to_main_thread: crossbeam::channel::Sender<Action>) {
+        println!("Recording on schedule: {self}");
+        std::thread::spawn(move || {
+            let mut was_open = false;
+            loop {
+                let is_open = self.is_open(Local::now().time());
+                if is_open != was_open {
+                    println!(
+                        "Schedule window {}.",
+                        if is_open { "opened" } else { "closed" }
+                    );
+                    if is_open {
+                        // Scheduled windows are meant to record, they arm on their own.
+                        to_main_thread
+                            .send(Action::Arm)
+                            .expect("Internal thread error.");
+                    }
+                    to_main_thread
+                        .send(if is_open { Action::Start } else { Action::Stop })
+                        .expect("Internal thread error.");
+                    was_open = is_open;
+                }
+                std::thread::sleep(Duration::from_secs(SCHEDULE_POLL_SECS));
+            }
+        });
+    }
+}
+
+impl std::fmt::Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let windows = self
+            .windows
+            .iter()
+            .map(|window| {
+                format!(
+                    "{}-{}",
+                    window.start.format("%H:%M"),
+                    window.end.format("%H:%M")
+                )
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", windows.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn windows_within_a_day_and_across_midnight() {
+        let day: Window = "09:30-17:00".parse().unwrap();
+        assert!(day.contains(time(9, 30)));
+        assert!(day.contains(time(12, 0)));
+        assert!(!day.contains(time(17, 0)));
+        assert!(!day.contains(time(23, 0)));
+
+        let night: Window = "22:00-06:00".parse().unwrap();
+        assert!(night.contains(time(23, 59)));
+        assert!(night.contains(time(0, 0)));
+        assert!(night.contains(time(5, 59)));
+        assert!(!night.contains(time(6, 0)));
+        assert!(!night.contains(time(12, 0)));
+
+        assert!("22:00".parse::<Window>().is_err());
+        assert!("25:00-06:00".parse::<Window>().is_err());
+        assert!("06:00-06:00".parse::<Window>().is_err());
+    }
+
+    #[test]
+    fn deserialize_schedule() {
+        let schedule: Schedule =
+            toml::from_str(r#"windows = ["22:00-06:00", "12:00 - 12:30"]"#).unwrap();
+        assert!(schedule.is_open(time(12, 15)));
+        assert!(schedule.is_open(time(3, 0)));
+        assert!(!schedule.is_open(time(18, 0)));
+        assert_eq!(schedule.to_string(), "22:00-06:00, 12:00-12:30");
+    }
+}
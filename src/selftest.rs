@@ -0,0 +1,381 @@
+use anyhow::{bail, Result};
+use cpal::{
+    traits::{DeviceTrait, StreamTrait},
+    FromSample, SizedSample, StreamInstant,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Silence before the chirp in every channel slot.
+const PRE_ROLL_SECS: f64 = 0.25;
+/// Length of the chirp itself.
+const CHIRP_SECS: f64 = 0.25;
+/// Silence after the chirp so the round trip fits in the slot.
+const TAIL_SECS: f64 = 0.5;
+/// Anything quieter than this in a slot is considered "no signal".
+const DETECTION_THRESHOLD_DBFS: f64 = -60.0;
+
+const CHIRP_START_HZ: f64 = 100.0;
+const CHIRP_END_HZ: f64 = 8000.0;
+const CHIRP_AMPLITUDE: f64 = 0.5;
+
+/// The result of testing a single output channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelReport {
+    /// 0 indexed output channel which played the chirp.
+    pub output_channel: usize,
+    /// 0 indexed input channel which received the chirp the loudest, if any.
+    pub input_channel: Option<usize>,
+    pub latency_ms: f64,
+    pub peak_dbfs: f64,
+}
+
+struct Playback {
+    frame: usize,
+    emitted: Vec<Option<StreamInstant>>,
+}
+
+/// What an input stream captured, the samples of every channel on their own.
+pub struct Capture {
+    /// When the first samples were captured.
+    pub start: Option<StreamInstant>,
+    pub channels: Vec<Vec<f32>>,
+}
+
+/// Plays a chirp on every channel of the output device one after another,
+/// records them back from the input device and reports what arrived where.
+#[allow(clippy::cast_precision_loss)]
+pub fn run(input_device: &cpal::Device, output_device: &cpal::Device) -> Result<()> {
+    let input_config = input_device.default_input_config()?;
+    let output_config = output_device.default_output_config()?;
+
+    if input_config.sample_rate() != output_config.sample_rate() {
+        bail!(
+            "Input and output sample rates differ ({} and {}), self test needs them to match.",
+            input_config.sample_rate().0,
+            output_config.sample_rate().0
+        );
+    }
+
+    let sample_rate = f64::from(output_config.sample_rate().0);
+    let output_channels = output_config.channels() as usize;
+    let input_channels = input_config.channels() as usize;
+    let slot_len = secs_to_frames(PRE_ROLL_SECS + CHIRP_SECS + TAIL_SECS, sample_rate);
+
+    println!(
+        "Self test: playing a chirp on {output_channels} output channel(s) of \"{}\" and recording {input_channels} input channel(s) of \"{}\".",
+        output_device.name()?,
+        input_device.name()?
+    );
+
+    let playback = Arc::new(Mutex::new(Playback {
+        frame: 0,
+        emitted: vec![None; output_channels],
+    }));
+    let capture = Arc::new(Mutex::new(Capture {
+        start: None,
+        channels: vec![Vec::new(); input_channels],
+    }));
+
+    let chirp = Arc::new(chirp(sample_rate));
+
+    let input_stream = build_input(input_device, &input_config, Arc::clone(&capture))?;
+    let output_stream = build_output(
+        output_device,
+        &output_config,
+        Arc::clone(&chirp),
+        slot_len,
+        Arc::clone(&playback),
+    )?;
+
+    input_stream.play()?;
+    output_stream.play()?;
+
+    std::thread::sleep(Duration::from_secs_f64(
+        (output_channels * slot_len) as f64 / sample_rate + TAIL_SECS,
+    ));
+
+    drop(output_stream);
+    drop(input_stream);
+
+    let emitted = playback.lock().unwrap().emitted.clone();
+    let (capture_start, captured) = {
+        let mut capture = capture.lock().unwrap();
+        (capture.start, std::mem::take(&mut capture.channels))
+    };
+
+    let Some(capture_start) = capture_start else {
+        bail!("No audio was received from the input device.");
+    };
+
+    let expected_starts = emitted
+        .iter()
+        .map(|emitted| {
+            emitted.and_then(|emitted| {
+                emitted
+                    .duration_since(&capture_start)
+                    .map(|offset| secs_to_frames(offset.as_secs_f64(), sample_rate))
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let reports = analyze(&captured, &expected_starts, slot_len, sample_rate);
+
+    let mut failed = false;
+    for report in &reports {
+        if let Some(input_channel) = report.input_channel {
+            println!(
+                "  Output {} -> Input {}: latency {:.2} ms, level {:.1} dBFS{}",
+                report.output_channel + 1,
+                input_channel + 1,
+                report.latency_ms,
+                report.peak_dbfs,
+                if input_channel == report.output_channel {
+                    ""
+                } else {
+                    " (channel mapping differs)"
+                }
+            );
+        } else {
+            failed = true;
+            println!(
+                "  Output {} -> no signal detected on any input.",
+                report.output_channel + 1
+            );
+        }
+    }
+
+    if failed {
+        bail!("Self test failed, check cabling and device routing.");
+    }
+
+    println!("Self test passed.");
+
+    Ok(())
+}
+
+/// Finds which input channel picked up each output channel's chirp.
+///
+/// `expected_starts` holds the frame index in the captured data where each output channel's chirp
+/// was emitted, latency is measured from there to the detected onset.
+#[allow(clippy::cast_precision_loss)]
+pub fn analyze(
+    captured: &[Vec<f32>],
+    expected_starts: &[Option<usize>],
+    window: usize,
+    sample_rate: f64,
+) -> Vec<ChannelReport> {
+    expected_starts
+        .iter()
+        .enumerate()
+        .map(|(output_channel, expected_start)| {
+            let mut report = ChannelReport {
+                output_channel,
+                input_channel: None,
+                latency_ms: 0.0,
+                peak_dbfs: f64::NEG_INFINITY,
+            };
+
+            let Some(start) = *expected_start else {
+                return report;
+            };
+
+            let mut loudest: Option<(usize, f32)> = None;
+            for (input_channel, samples) in captured.iter().enumerate() {
+                let end = (start + window).min(samples.len());
+                if start >= end {
+                    continue;
+                }
+                let peak = samples[start..end]
+                    .iter()
+                    .fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+                if !matches!(loudest, Some((_, loudest_peak)) if loudest_peak >= peak) {
+                    loudest = Some((input_channel, peak));
+                }
+            }
+
+            if let Some((input_channel, peak)) = loudest {
+                let peak_dbfs = 20.0 * f64::from(peak).log10();
+                report.peak_dbfs = peak_dbfs;
+                if peak_dbfs > DETECTION_THRESHOLD_DBFS {
+                    let samples = &captured[input_channel];
+                    let end = (start + window).min(samples.len());
+                    // Onset is the first sample reaching half of the peak.
+                    let onset = samples[start..end]
+                        .iter()
+                        .position(|sample| sample.abs() >= peak / 2.0)
+                        .unwrap_or(0);
+                    report.input_channel = Some(input_channel);
+                    report.latency_ms = onset as f64 / sample_rate * 1000.0;
+                }
+            }
+
+            report
+        })
+        .collect()
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn secs_to_frames(secs: f64, sample_rate: f64) -> usize {
+    (secs * sample_rate).round() as usize
+}
+
+/// A linear sine sweep with short fades to avoid clicks.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn chirp(sample_rate: f64) -> Vec<f32> {
+    let len = secs_to_frames(CHIRP_SECS, sample_rate);
+    let fade = secs_to_frames(0.005, sample_rate).max(1);
+    let sweep_rate = (CHIRP_END_HZ - CHIRP_START_HZ) / CHIRP_SECS;
+
+    (0..len)
+        .map(|i| {
+            let t = i as f64 / sample_rate;
+            let phase =
+                2.0 * std::f64::consts::PI * CHIRP_START_HZ.mul_add(t, sweep_rate * t * t / 2.0);
+            let gain = (i.min(len - 1 - i) as f64 / fade as f64).min(1.0);
+            (phase.sin() * CHIRP_AMPLITUDE * gain) as f32
+        })
+        .collect()
+}
+
+fn build_output(
+    device: &cpal::Device,
+    config: &cpal::SupportedStreamConfig,
+    chirp: Arc<Vec<f32>>,
+    slot_len: usize,
+    playback: Arc<Mutex<Playback>>,
+) -> Result<cpal::Stream> {
+    match config.sample_format() {
+        cpal::SampleFormat::I8 => output::<i8>(device, config, chirp, slot_len, playback),
+        cpal::SampleFormat::I16 => output::<i16>(device, config, chirp, slot_len, playback),
+        cpal::SampleFormat::I32 => output::<i32>(device, config, chirp, slot_len, playback),
+        cpal::SampleFormat::F32 => output::<f32>(device, config, chirp, slot_len, playback),
+        sample_format => bail!("Sample format {sample_format:?} is not supported by this program."),
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn output<T>(
+    device: &cpal::Device,
+    config: &cpal::SupportedStreamConfig,
+    chirp: Arc<Vec<f32>>,
+    slot_len: usize,
+    playback: Arc<Mutex<Playback>>,
+) -> Result<cpal::Stream>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels() as usize;
+    let sample_rate = f64::from(config.sample_rate().0);
+    let pre_roll = secs_to_frames(PRE_ROLL_SECS, sample_rate);
+
+    Ok(device.build_output_stream(
+        &config.clone().into(),
+        move |data: &mut [T], info: &cpal::OutputCallbackInfo| {
+            let mut playback = playback.lock().unwrap();
+            let first_frame = playback.frame;
+
+            for (i, frame) in data.chunks_mut(channels).enumerate() {
+                let position = first_frame + i;
+                let slot = position / slot_len;
+                let offset = position % slot_len;
+
+                if offset == pre_roll && slot < channels && playback.emitted[slot].is_none() {
+                    playback.emitted[slot] = info
+                        .timestamp()
+                        .playback
+                        .add(Duration::from_secs_f64(i as f64 / sample_rate));
+                }
+
+                for (channel, sample) in frame.iter_mut().enumerate() {
+                    *sample = if channel == slot && offset >= pre_roll {
+                        chirp
+                            .get(offset - pre_roll)
+                            .map_or(T::EQUILIBRIUM, |value| T::from_sample(*value))
+                    } else {
+                        T::EQUILIBRIUM
+                    };
+                }
+            }
+
+            playback.frame += data.len() / channels;
+        },
+        |err| eprintln!("An error occurred on the output stream: {err}"),
+        None,
+    )?)
+}
+
+/// Builds a stream which captures all channels of an input device, it captures while it plays.
+pub fn build_input(
+    device: &cpal::Device,
+    config: &cpal::SupportedStreamConfig,
+    capture: Arc<Mutex<Capture>>,
+) -> Result<cpal::Stream> {
+    match config.sample_format() {
+        cpal::SampleFormat::I8 => input::<i8>(device, config, capture),
+        cpal::SampleFormat::I16 => input::<i16>(device, config, capture),
+        cpal::SampleFormat::I32 => input::<i32>(device, config, capture),
+        cpal::SampleFormat::F32 => input::<f32>(device, config, capture),
+        sample_format => bail!("Sample format {sample_format:?} is not supported by this program."),
+    }
+}
+
+fn input<T>(
+    device: &cpal::Device,
+    config: &cpal::SupportedStreamConfig,
+    capture: Arc<Mutex<Capture>>,
+) -> Result<cpal::Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = config.channels() as usize;
+
+    Ok(device.build_input_stream(
+        &config.clone().into(),
+        move |data: &[T], info: &cpal::InputCallbackInfo| {
+            let mut capture = capture.lock().unwrap();
+            if capture.start.is_none() {
+                capture.start = Some(info.timestamp().capture);
+            }
+            for frame in data.chunks(channels) {
+                for (channel, sample) in frame.iter().enumerate() {
+                    capture.channels[channel].push(sample.to_sample::<f32>());
+                }
+            }
+        },
+        |err| eprintln!("An error occurred on the input stream: {err}"),
+        None,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analyze_finds_mapping_and_latency() {
+        let sample_rate = 1000.0;
+        let mut captured = vec![vec![0.0_f32; 400]; 2];
+        // Output 1 arrives on input 2, 10 frames late.
+        captured[1][10..20].fill(0.5);
+        // Output 2 arrives on input 1, 5 frames late.
+        captured[0][205..215].fill(0.25);
+
+        let reports = analyze(&captured, &[Some(0), Some(200)], 200, sample_rate);
+
+        assert_eq!(reports[0].input_channel, Some(1));
+        assert!((reports[0].latency_ms - 10.0).abs() < f64::EPSILON);
+        assert_eq!(reports[1].input_channel, Some(0));
+        assert!((reports[1].latency_ms - 5.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn analyze_reports_silence() {
+        let captured = vec![vec![0.0_f32; 100]];
+        let reports = analyze(&captured, &[Some(0), None], 100, 1000.0);
+        assert!(reports.iter().all(|report| report.input_channel.is_none()));
+    }
+}
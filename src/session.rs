@@ -0,0 +1,71 @@
+use serde::Deserialize;
+use std::fmt::Write;
+
+/// DAW session formats which can be written next to a take.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionFormat {
+    Reaper,
+}
+
+impl SessionFormat {
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Reaper => "rpp",
+        }
+    }
+
+    /// Makes a session with a track for each file, placed at `position` seconds on the timeline.
+    pub fn render(self, files: &[String], sample_rate: u32, length: f64, position: f64) -> String {
+        match self {
+            Self::Reaper => reaper_project(files, sample_rate, length, position),
+        }
+    }
+}
+
+/// A minimal REAPER project, files are referenced relative to the project.
+fn reaper_project(files: &[String], sample_rate: u32, length: f64, position: f64) -> String {
+    let mut project = String::new();
+    // Writing to a string does not fail.
+    let _ = writeln!(project, "<REAPER_PROJECT 0.1 \"6.0\" 0");
+    let _ = writeln!(project, "  SAMPLERATE {sample_rate} 0 0");
+    for file in files {
+        let name = file
+            .rsplit_once('.')
+            .map_or(file.as_str(), |(stem, _)| stem);
+        let _ = writeln!(project, "  <TRACK");
+        let _ = writeln!(project, "    NAME \"{name}\"");
+        let _ = writeln!(project, "    <ITEM");
+        let _ = writeln!(project, "      POSITION {position}");
+        let _ = writeln!(project, "      LENGTH {length}");
+        let _ = writeln!(project, "      NAME \"{file}\"");
+        let _ = writeln!(project, "      <SOURCE WAVE");
+        let _ = writeln!(project, "        FILE \"{file}\"");
+        let _ = writeln!(project, "      >");
+        let _ = writeln!(project, "    >");
+        let _ = writeln!(project, "  >");
+    }
+    let _ = writeln!(project, ">");
+    project
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reaper_project_references_files() {
+        let project = SessionFormat::Reaper.render(
+            &["Kick.wav".to_string(), "chn_2_part2.wav".to_string()],
+            48000,
+            1.5,
+            10.25,
+        );
+        assert!(project.starts_with("<REAPER_PROJECT"));
+        assert!(project.contains("  SAMPLERATE 48000 0 0\n"));
+        assert!(project.contains("    NAME \"chn_2_part2\"\n"));
+        assert!(project.contains("      POSITION 10.25\n      LENGTH 1.5\n"));
+        assert!(project.contains("        FILE \"Kick.wav\"\n"));
+        assert_eq!(project.matches("<TRACK").count(), 2);
+    }
+}
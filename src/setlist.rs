@@ -0,0 +1,84 @@
+use anyhow::{bail, Result};
+use camino::Utf8Path;
+
+/// Songs or scenes read from a text file, one per line, which name the takes in order.
+///
+/// Every new take is labelled with the current entry and moves on to the following one,
+/// past the last entry takes are not labelled anymore.
+#[derive(Debug, Clone)]
+pub struct Setlist {
+    entries: Vec<String>,
+    /// Index of the entry which labels the next take.
+    position: usize,
+}
+
+impl Setlist {
+    /// Reads a setlist, empty lines and lines starting with `#` are skipped.
+    pub fn load(path: &Utf8Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).map_err(|err| anyhow::anyhow!("{path}: {err}")),
+            Err(err) => bail!("Error reading setlist {path}: {err}"),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let entries = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if entries.is_empty() {
+            bail!("The setlist has no entries.");
+        }
+        Ok(Self {
+            entries,
+            position: 0,
+        })
+    }
+
+    /// The entry which labels the next take, none past the end of the setlist.
+    pub fn current(&self) -> Option<&str> {
+        self.entries.get(self.position).map(String::as_str)
+    }
+
+    /// Moves on to the following entry, the one after the last is the end of the setlist.
+    pub fn next(&mut self) {
+        self.position = (self.position + 1).min(self.entries.len());
+    }
+
+    /// Goes back to the previous entry, e.g. to record a song again.
+    pub const fn prev(&mut self) {
+        self.position = self.position.saturating_sub(1);
+    }
+
+    /// Where the setlist is, e.g. `Glass Harbour (2/12)`.
+    pub fn describe(&self) -> String {
+        self.current().map_or_else(
+            || "the end".to_string(),
+            |entry| format!("{entry} ({}/{})", self.position + 1, self.entries.len()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setlists_are_walked_through() {
+        let mut setlist = Setlist::parse("# Friday\nNeon Nights\n\n  Glass Harbour  \n").unwrap();
+        assert_eq!(setlist.current(), Some("Neon Nights"));
+        setlist.prev();
+        assert_eq!(setlist.current(), Some("Neon Nights"));
+        setlist.next();
+        assert_eq!(setlist.describe(), "Glass Harbour (2/2)");
+        setlist.next();
+        setlist.next();
+        assert_eq!(setlist.current(), None);
+        setlist.prev();
+        assert_eq!(setlist.current(), Some("Glass Harbour"));
+
+        assert!(Setlist::parse("# Nothing yet\n\n").is_err());
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{takes::Take, types::Action};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// How often the free space of the disk of the running take is checked.
+const SPACE_POLL_SECS: u64 = 10;
+/// Remaining recording times which are warned about once they are crossed.
+const WARN_AT_SECS: [u64; 4] = [3600, 1800, 600, 60];
+
+/// Starts a thread which estimates how long the running take can go on before its disk is full.
+///
+/// The estimate is printed when a take starts and whenever it drops below an hour, 30, 10 and 1 minutes,
+/// and sent to the listeners every time it is checked.
+pub fn watch(
+    writers: Arc<Mutex<Option<Take>>>,
+    to_listener_thread: crossbeam::channel::Sender<Action>,
+) {
+    std::thread::spawn(move || {
+        // The directory of the take which the last estimate was for and the estimate.
+        let mut last: Option<(Utf8PathBuf, u64)> = None;
+        loop {
+            std::thread::sleep(Duration::from_secs(SPACE_POLL_SECS));
+
+            let running = writers
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|take| (take.directory.clone(), take.bytes_per_second));
+            let Some((directory, bytes_per_second)) = running else {
+                last = None;
+                continue;
+            };
+            let Some(available) = available_bytes(&directory) else {
+                continue;
+            };
+            let remaining = remaining_secs(available, bytes_per_second);
+
+            match &last {
+                Some((take, before)) if *take == directory => {
+                    // The shortest time which was crossed, a big file may cross more than one.
+                    if let Some(mark) = WARN_AT_SECS
+                        .iter()
+                        .rev()
+                        .find(|mark| remaining < **mark && *before >= **mark)
+                    {
+                        println!(
+                            "Warning: less than {} of recording time left on the disk.",
+                            format_duration(*mark)
+                        );
+                    }
+                }
+                _ => println!(
+                    "About {} of recording time left on the disk.",
+                    format_duration(remaining)
+                ),
+            }
+            last = Some((directory, remaining));
+            // Nobody hears it without listeners.
+            to_listener_thread
+                .send(Action::RemainingTime(remaining))
+                .ok();
+        }
+    });
+}
+
+/// Seconds until a disk with some bytes available is full, when written to at a rate.
+const fn remaining_secs(available: u64, bytes_per_second: u64) -> u64 {
+    if bytes_per_second == 0 {
+        return u64::MAX;
+    }
+    available / bytes_per_second
+}
+
+/// e.g. `3h12m`, `12m` or `45s`.
+pub fn format_duration(secs: u64) -> String {
+    match (secs / 3600, secs % 3600 / 60) {
+        (0, 0) => format!("{secs}s"),
+        (0, minutes) => format!("{minutes}m"),
+        (hours, minutes) => format!("{hours}h{minutes:02}m"),
+    }
+}
+
+#[cfg(unix)]
+fn available_bytes(path: &Utf8Path) -> Option<u64> {
+    let output = std::process::Command::new("df")
+        .arg("-Pk")
+        .arg(path)
+        .output()
+        .ok()?;
+    parse_df(&String::from_utf8_lossy(&output.stdout))
+}
+
+#[cfg(windows)]
+fn available_bytes(path: &Utf8Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetDiskFreeSpaceExW(
+            directory: *const u16,
+            available: *mut u64,
+            total: *mut u64,
+            free: *mut u64,
+        ) -> i32;
+    }
+
+    let directory = path
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect::<Vec<_>>();
+    let mut available = 0;
+    // SAFETY: The path is null terminated and the sizes may be null.
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            directory.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    (ok != 0).then_some(available)
+}
+
+#[cfg(not(any(unix, windows)))]
+const fn available_bytes(_path: &Utf8Path) -> Option<u64> {
+    None
+}
+
+/// Parses the available kilobytes of `df -Pk <path>`, e.g. `/dev/sda1 482922 120394 338008 27% /`.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn parse_df(output: &str) -> Option<u64> {
+    let available = output.lines().nth(1)?.split_whitespace().nth(3)?;
+    available.parse::<u64>().ok()?.checked_mul(1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remaining_time_from_free_space() {
+        let df = "Filesystem     1024-blocks      Used Available Capacity Mounted on\n/dev/nvme0n1p2   482922000 120394000 338008000      27% /\n";
+        let available = parse_df(df).unwrap();
+        assert_eq!(available, 338_008_000 * 1024);
+        assert!(parse_df("df: /missing: No such file or directory\n").is_none());
+
+        // 16 channels of 24 bit audio at 48 kHz.
+        let remaining = remaining_secs(available, 16 * 3 * 48_000);
+        assert_eq!(format_duration(remaining), "41h43m");
+        assert_eq!(format_duration(3 * 3600 + 12 * 60 + 5), "3h12m");
+        assert_eq!(format_duration(12 * 60), "12m");
+        assert_eq!(format_duration(45), "45s");
+        assert_eq!(remaining_secs(1000, 0), u64::MAX);
+    }
+}
@@ -0,0 +1,452 @@
+use crate::{console, threads};
+use anyhow::{bail, Result};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, Seek, SeekFrom, Write},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+/// Audio is handed to the writing thread in chunks of this size.
+const CHUNK: usize = 64 * 1024;
+/// How long a waiting recording sleeps before it looks for room again.
+const WAIT: Duration = Duration::from_millis(5);
+
+/// What happens when the memory of `--ram-buffer` is full because the disk fell behind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// The recording waits until the disk made room, the device may drop audio meanwhile.
+    #[default]
+    Block,
+    /// The oldest audio in memory is dropped and left as silence in the files.
+    DropOldest,
+    /// The recording writes the audio in memory to the disk itself right away.
+    Flush,
+}
+
+impl FromStr for Policy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "block" => Ok(Self::Block),
+            "drop-oldest" => Ok(Self::DropOldest),
+            "flush" => Ok(Self::Flush),
+            _ => bail!("Unknown RAM buffer policy {s}, use one of block, drop-oldest or flush."),
+        }
+    }
+}
+
+/// How full the memory of a take got, for the status of the recorder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pressure {
+    /// Bytes waiting to be written now.
+    pub queued: u64,
+    /// The most bytes which waited at once since the take started.
+    pub peak: u64,
+    pub capacity: u64,
+    /// Bytes of audio which were dropped to make room.
+    pub dropped: u64,
+    /// Times the recording waited for the disk or wrote to it itself.
+    pub stalls: u64,
+}
+
+/// The memory shared by the files of a take.
+#[derive(Debug)]
+pub struct Budget {
+    capacity: u64,
+    policy: Policy,
+    queued: AtomicU64,
+    peak: AtomicU64,
+    dropped: AtomicU64,
+    stalls: AtomicU64,
+    /// Whether filling up was warned about, until the memory is half empty again.
+    warned: AtomicBool,
+}
+
+impl Budget {
+    pub const fn new(capacity: u64, policy: Policy) -> Self {
+        Self {
+            capacity,
+            policy,
+            queued: AtomicU64::new(0),
+            peak: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            stalls: AtomicU64::new(0),
+            warned: AtomicBool::new(false),
+        }
+    }
+
+    pub fn pressure(&self) -> Pressure {
+        Pressure {
+            queued: self.queued.load(Ordering::Relaxed),
+            peak: self.peak.load(Ordering::Relaxed),
+            capacity: self.capacity,
+            dropped: self.dropped.load(Ordering::Relaxed),
+            stalls: self.stalls.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Whether `bytes` more do not fit, a chunk always fits into empty memory.
+    fn is_full(&self, bytes: u64) -> bool {
+        let queued = self.queued.load(Ordering::Relaxed);
+        queued > 0 && queued + bytes > self.capacity
+    }
+
+    fn take(&self, bytes: u64) {
+        let queued = self.queued.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.peak.fetch_max(queued, Ordering::Relaxed);
+        if queued >= self.capacity / 4 * 3 && !self.warned.swap(true, Ordering::Relaxed) {
+            console::info(format!(
+                "Warning: the RAM buffer is {}% full, the disk falls behind the recording.",
+                queued * 100 / self.capacity.max(1)
+            ));
+        }
+    }
+
+    fn release(&self, bytes: u64) {
+        let queued = self.queued.fetch_sub(bytes, Ordering::Relaxed) - bytes;
+        if queued <= self.capacity / 2 {
+            self.warned.store(false, Ordering::Relaxed);
+        }
+    }
+
+    fn stalled(&self) {
+        if self.stalls.fetch_add(1, Ordering::Relaxed) == 0 {
+            let message = match self.policy {
+                Policy::Block => "the recording waits for the disk",
+                Policy::DropOldest => "the oldest audio in memory is dropped",
+                Policy::Flush => "the recording writes to the disk itself",
+            };
+            console::error(format!("Error: the RAM buffer is full, {message}."));
+        }
+    }
+}
+
+/// Something to do to the file, in the order it was asked for.
+#[derive(Debug)]
+enum Op {
+    Write(Vec<u8>),
+    /// Audio which was dropped, it is skipped and reads as silence.
+    Skip(u64),
+    Seek(u64),
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    ops: VecDeque<Op>,
+    closed: bool,
+    /// Why the file can not be written anymore.
+    failed: Option<String>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    queue: Mutex<Queue>,
+    /// Signalled when something is queued or the spool is closed.
+    queued: Condvar,
+    /// Held while writing, so what the recording writes itself stays in order.
+    file: Mutex<File>,
+}
+
+/// Keeps what is written to a file in memory and writes it from a thread of its own,
+/// so a stalling disk does not hold up the audio callback until the memory of the take is full.
+pub struct Spool {
+    shared: Arc<Shared>,
+    budget: Arc<Budget>,
+    chunk: Vec<u8>,
+    /// Where the next byte goes and how long the file will be, once everything is written.
+    position: u64,
+    len: u64,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Spool {
+    pub fn new(file: File, budget: Arc<Budget>) -> Self {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            queued: Condvar::new(),
+            file: Mutex::new(file),
+        });
+        let thread = {
+            let (shared, budget) = (shared.clone(), budget.clone());
+            std::thread::spawn(move || {
+                threads::tune(threads::Kind::Writer);
+                write_out(&shared, &budget);
+            })
+        };
+        Self {
+            shared,
+            budget,
+            chunk: Vec::with_capacity(CHUNK),
+            position: 0,
+            len: 0,
+            thread: Some(thread),
+        }
+    }
+
+    /// Hands the running chunk to the writing thread, making room for it as the policy says.
+    fn queue_chunk(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(CHUNK));
+        let bytes = chunk.len() as u64;
+        let mut dropped = false;
+        if self.budget.is_full(bytes) {
+            self.budget.stalled();
+            match self.budget.policy {
+                Policy::Block => self.wait_for_room(bytes)?,
+                Policy::Flush => {
+                    self.drain()?;
+                    // The rest of the memory belongs to other files.
+                    self.wait_for_room(bytes)?;
+                }
+                Policy::DropOldest => {
+                    let mut queue = self.shared.queue.lock().unwrap();
+                    dropped = make_room(&mut queue.ops, &self.budget, bytes);
+                }
+            }
+        }
+        let op = if dropped {
+            self.budget.dropped.fetch_add(bytes, Ordering::Relaxed);
+            Op::Skip(bytes)
+        } else {
+            self.budget.take(bytes);
+            Op::Write(chunk)
+        };
+        self.push(op)
+    }
+
+    fn wait_for_room(&self, bytes: u64) -> io::Result<()> {
+        while self.budget.is_full(bytes) {
+            self.check()?;
+            std::thread::sleep(WAIT);
+        }
+        Ok(())
+    }
+
+    fn push(&self, op: Op) -> io::Result<()> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        if let Some(failed) = queue.failed.clone() {
+            drop(queue);
+            if let Op::Write(bytes) = op {
+                self.budget.release(bytes.len() as u64);
+            }
+            return Err(io::Error::other(failed));
+        }
+        queue.ops.push_back(op);
+        drop(queue);
+        self.shared.queued.notify_one();
+        Ok(())
+    }
+
+    fn check(&self) -> io::Result<()> {
+        let failed = self.shared.queue.lock().unwrap().failed.clone();
+        failed.map_or(Ok(()), |failed| Err(io::Error::other(failed)))
+    }
+
+    /// Writes everything queued so far to the file from the calling thread.
+    fn drain(&self) -> io::Result<()> {
+        let mut file = self.shared.file.lock().unwrap();
+        let ops = std::mem::take(&mut self.shared.queue.lock().unwrap().ops);
+        if let Err(err) = apply(&mut file, ops, &self.budget) {
+            fail(&self.shared, &self.budget, &err);
+            return Err(err);
+        }
+        file.flush()
+    }
+}
+
+impl Write for Spool {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.chunk.extend_from_slice(buf);
+        self.position += buf.len() as u64;
+        self.len = self.len.max(self.position);
+        if self.chunk.len() >= CHUNK {
+            self.queue_chunk()?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Waits until everything is on the disk.
+    fn flush(&mut self) -> io::Result<()> {
+        self.queue_chunk()?;
+        self.drain()
+    }
+}
+
+impl Seek for Spool {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seeking before the start."))?;
+        // Asking where the cursor is does not need to reach the file.
+        if position != self.position {
+            self.queue_chunk()?;
+            self.push(Op::Seek(position))?;
+            self.position = position;
+        }
+        Ok(position)
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        self.queue_chunk().ok();
+        self.shared.queue.lock().unwrap().closed = true;
+        self.shared.queued.notify_one();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+        // A file ending with dropped audio still has its full length.
+        let file = self.shared.file.lock().unwrap();
+        if file
+            .metadata()
+            .is_ok_and(|metadata| metadata.len() < self.len)
+        {
+            file.set_len(self.len).ok();
+        }
+    }
+}
+
+/// Writes what is queued until the spool is closed and everything is written.
+fn write_out(shared: &Shared, budget: &Budget) {
+    loop {
+        // Waits without holding the file, so the recording can write it itself meanwhile.
+        {
+            let mut queue = shared.queue.lock().unwrap();
+            while queue.ops.is_empty() && !queue.closed {
+                queue = shared.queued.wait(queue).unwrap();
+            }
+            if queue.ops.is_empty() {
+                return;
+            }
+        }
+        let mut file = shared.file.lock().unwrap();
+        let Some(op) = shared.queue.lock().unwrap().ops.pop_front() else {
+            continue;
+        };
+        if let Err(err) = apply(&mut file, [op], budget) {
+            fail(shared, budget, &err);
+            return;
+        }
+    }
+}
+
+/// Applies operations to the file, the memory of every write is given back whether it was written or not.
+fn apply(file: &mut File, ops: impl IntoIterator<Item = Op>, budget: &Budget) -> io::Result<()> {
+    let mut result = Ok(());
+    for op in ops {
+        if result.is_ok() {
+            result = match &op {
+                Op::Write(bytes) => file.write_all(bytes),
+                Op::Skip(bytes) => file
+                    .seek(SeekFrom::Current(i64::try_from(*bytes).unwrap_or(i64::MAX)))
+                    .map(drop),
+                Op::Seek(position) => file.seek(SeekFrom::Start(*position)).map(drop),
+            };
+        }
+        if let Op::Write(bytes) = op {
+            budget.release(bytes.len() as u64);
+        }
+    }
+    result
+}
+
+/// Gives up on a file which can not be written, its memory is given back to the others.
+fn fail(shared: &Shared, budget: &Budget, err: &io::Error) {
+    let mut queue = shared.queue.lock().unwrap();
+    queue.failed.get_or_insert_with(|| err.to_string());
+    for op in std::mem::take(&mut queue.ops) {
+        if let Op::Write(bytes) = op {
+            budget.release(bytes.len() as u64);
+        }
+    }
+}
+
+/// Drops the oldest queued audio until `bytes` more fit, returns whether they have to be dropped too.
+fn make_room(ops: &mut VecDeque<Op>, budget: &Budget, bytes: u64) -> bool {
+    for op in ops.iter_mut() {
+        if !budget.is_full(bytes) {
+            return false;
+        }
+        if let Op::Write(queued) = op {
+            let dropped = queued.len() as u64;
+            *op = Op::Skip(dropped);
+            budget.release(dropped);
+            budget.dropped.fetch_add(dropped, Ordering::Relaxed);
+        }
+    }
+    // The rest of the memory belongs to other files.
+    budget.is_full(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn the_oldest_audio_is_dropped_to_make_room() {
+        let budget = Budget::new(10, Policy::DropOldest);
+        let mut ops = VecDeque::new();
+        for bytes in [4, 4] {
+            budget.take(bytes);
+            ops.push_back(Op::Write(vec![1; 4]));
+        }
+        ops.insert(1, Op::Seek(0));
+
+        assert!(!make_room(&mut ops, &budget, 4));
+        assert!(matches!(ops[0], Op::Skip(4)));
+        assert!(matches!(ops[2], Op::Write(_)));
+        assert_eq!(budget.pressure().queued, 4);
+        assert_eq!(budget.pressure().dropped, 4);
+        assert_eq!(budget.pressure().peak, 8);
+
+        // Memory held by other files is not dropped.
+        budget.take(4);
+        assert!(make_room(&mut VecDeque::new(), &budget, 4));
+        assert_eq!("drop-oldest".parse::<Policy>().unwrap(), Policy::DropOldest);
+        assert!("drop".parse::<Policy>().is_err());
+    }
+
+    #[test]
+    fn spooled_files_are_written_in_order() {
+        let dir = TempDir::new("spool");
+        let path = dir.path().join("Kick.wav");
+
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let budget = Arc::new(Budget::new(CHUNK as u64, Policy::Block));
+        let spool = Spool::new(File::create(&path).unwrap(), budget.clone());
+        let mut writer = hound::WavWriter::new(spool, spec).unwrap();
+        for sample in 0..100_000 {
+            writer
+                .write_sample(i16::try_from(sample % 1000).unwrap())
+                .unwrap();
+        }
+        writer.finalize().unwrap();
+        assert_eq!(budget.pressure().queued, 0);
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.duration(), 100_000);
+        assert!(reader
+            .samples::<i16>()
+            .enumerate()
+            .all(|(index, sample)| sample.unwrap() == i16::try_from(index % 1000).unwrap()));
+    }
+}
@@ -0,0 +1,135 @@
+use crate::meters::Meter;
+
+/// Peaks above this leave too little headroom, in dBFS.
+const HOT_PEAK_DB: f32 = -1.0;
+/// How far above the target a channel may average before it is too hot, in dB.
+const HOT_MARGIN_DB: f32 = 6.0;
+/// How far below the target a channel may average before it is too quiet, in dB.
+const QUIET_MARGIN_DB: f32 = 12.0;
+/// Changes smaller than this are not worth touching the preamp for, in dB.
+const SMALLEST_CHANGE_DB: f32 = 1.0;
+
+/// How a channel was gain staged, with the change in dB which would have brought it closer to the target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Silent,
+    /// It clipped, with the number of overs.
+    Clipped(u32, f32),
+    TooHot(f32),
+    TooQuiet(f32),
+    Good,
+}
+
+impl Verdict {
+    pub fn of(meter: &Meter, target_db: f32) -> Self {
+        if meter.rms_db == f32::NEG_INFINITY {
+            return Self::Silent;
+        }
+        let to_target = target_db - meter.rms_db;
+        if meter.overs > 0 || meter.peak_db > HOT_PEAK_DB {
+            // How far over it went is not known, at least the margin is taken off.
+            let lower = (-to_target).max(HOT_MARGIN_DB);
+            return if meter.overs > 0 {
+                Self::Clipped(meter.overs, lower)
+            } else {
+                Self::TooHot(lower)
+            };
+        }
+        if -to_target > HOT_MARGIN_DB {
+            return Self::TooHot(-to_target);
+        }
+        // Raising it is only worth it as far as its peaks stay clear of clipping.
+        let raise = to_target.min(HOT_PEAK_DB - meter.peak_db);
+        if to_target > QUIET_MARGIN_DB && raise >= SMALLEST_CHANGE_DB {
+            return Self::TooQuiet(raise);
+        }
+        Self::Good
+    }
+
+    fn advice(self) -> String {
+        match self {
+            Self::Silent => "silent, check the cable and the preamp or exclude it".to_string(),
+            Self::Clipped(overs, lower) => format!(
+                "clipped {overs} {}, lower the gain by about {lower:.0} dB",
+                if overs == 1 { "time" } else { "times" }
+            ),
+            Self::TooHot(lower) => format!("too hot, lower the gain by about {lower:.0} dB"),
+            Self::TooQuiet(raise) => format!("too quiet, raise the gain by about {raise:.0} dB"),
+            Self::Good => "good".to_string(),
+        }
+    }
+}
+
+/// Lines comparing the average level and the peak of named channels to a target, and what to change for the next take.
+pub fn report(channels: &[(String, Meter)], target_db: f32) -> Vec<String> {
+    let mut lines = vec![format!("Gain staging against {target_db} dBFS RMS:")];
+    lines.extend(channels.iter().map(|(name, meter)| {
+        let verdict = Verdict::of(meter, target_db);
+        if verdict == Verdict::Silent {
+            return format!("  {name}: {}.", verdict.advice());
+        }
+        format!(
+            "  {name}: {:.1} dBFS RMS, peak {:.1} dBFS, {}.",
+            meter.rms_db,
+            meter.peak_db,
+            verdict.advice()
+        )
+    }));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn meter(rms_db: f32, peak_db: f32, overs: u32) -> Meter {
+        Meter {
+            peak_db,
+            overs,
+            rms_db,
+        }
+    }
+
+    #[test]
+    fn channels_are_compared_to_the_target() {
+        let target = -18.0;
+        assert_eq!(Verdict::of(&meter(-20.0, -6.0, 0), target), Verdict::Good);
+        assert_eq!(
+            Verdict::of(&meter(-40.0, -22.0, 0), target),
+            Verdict::TooQuiet(21.0)
+        );
+        // Its peaks only leave room for 3 dB.
+        assert_eq!(
+            Verdict::of(&meter(-40.0, -4.0, 0), target),
+            Verdict::TooQuiet(3.0)
+        );
+        assert_eq!(Verdict::of(&meter(-40.0, -1.5, 0), target), Verdict::Good);
+        assert_eq!(
+            Verdict::of(&meter(-8.0, -2.0, 0), target),
+            Verdict::TooHot(10.0)
+        );
+        assert_eq!(
+            Verdict::of(&meter(-20.0, 0.0, 2), target),
+            Verdict::Clipped(2, 6.0)
+        );
+        assert_eq!(
+            Verdict::of(&meter(f32::NEG_INFINITY, f32::NEG_INFINITY, 0), target),
+            Verdict::Silent
+        );
+
+        assert_eq!(
+            report(
+                &[
+                    ("Kick".to_string(), meter(-40.0, -22.0, 0)),
+                    ("Room".to_string(), meter(f32::NEG_INFINITY, f32::NEG_INFINITY, 0)),
+                ],
+                target
+            ),
+            [
+                "Gain staging against -18 dBFS RMS:",
+                "  Kick: -40.0 dBFS RMS, peak -22.0 dBFS, too quiet, raise the gain by about 21 dB.",
+                "  Room: silent, check the cable and the preamp or exclude it.",
+            ]
+        );
+    }
+}
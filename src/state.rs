@@ -0,0 +1,128 @@
+use crate::types::Transport;
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+/// Name of the state file next to the configuration file.
+pub const STATE_FILE: &str = "state.json";
+
+/// What the recorder was doing, kept on disk so a restarted recorder knows whether it was interrupted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    pub transport: Transport,
+    /// Directory of the running take.
+    pub take: Option<String>,
+    /// Takes started by this recorder, across restarts.
+    pub takes: u64,
+    /// 1 indexed channels which are recorded.
+    pub channels: Vec<usize>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Where the state is saved and the state itself, none until [`open`] is called.
+static STATE: Mutex<Option<(Utf8PathBuf, State)>> = Mutex::new(None);
+
+/// Starts keeping the state in a file and returns the one a previous run left behind.
+///
+/// A previous run which left the state while recording was interrupted, e.g. it crashed or the machine lost power.
+pub fn open(path: Utf8PathBuf, channels: Vec<usize>) -> Option<State> {
+    let previous =
+        std::fs::read_to_string(&path).ok().and_then(|text| {
+            match serde_json::from_str::<State>(&text) {
+                Ok(state) => Some(state),
+                Err(err) => {
+                    eprintln!("Warning: {path} can not be read and is replaced: {err}");
+                    None
+                }
+            }
+        });
+    let state = State {
+        transport: Transport::Stopped,
+        take: None,
+        takes: previous.as_ref().map_or(0, |previous| previous.takes),
+        channels,
+        updated_at: Utc::now(),
+    };
+    save(&path, &state);
+    *STATE.lock().unwrap() = Some((path, state));
+    previous
+}
+
+/// Records that a take started recording into a directory.
+pub fn recording(take: &Utf8Path) {
+    update(|state| {
+        state.transport = Transport::Recording;
+        state.take = Some(take.to_string());
+        state.takes += 1;
+    });
+}
+
+/// Records that the running take stopped.
+pub fn stopped() {
+    update(|state| {
+        state.transport = Transport::Stopped;
+        state.take = None;
+    });
+}
+
+/// Records that the recorder was armed or disarmed.
+pub fn transport(transport: Transport) {
+    update(|state| state.transport = transport);
+}
+
+fn update(change: impl FnOnce(&mut State)) {
+    if let Some((path, state)) = STATE.lock().unwrap().as_mut() {
+        change(state);
+        state.updated_at = Utc::now();
+        save(path, state);
+    }
+}
+
+/// Writes the state next to its file and renames it over, so a crash never leaves half of it behind.
+fn save(path: &Utf8Path, state: &State) {
+    let write = || -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let temporary = path.with_extension("json.tmp");
+        std::fs::write(&temporary, serde_json::to_string_pretty(state)?)?;
+        std::fs::rename(&temporary, path)?;
+        Ok(())
+    };
+    if let Err(err) = write() {
+        eprintln!("Error saving the state to {path}: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn interrupted_runs_are_found_and_counting_goes_on() {
+        let dir = TempDir::new("state");
+        let path = dir.path().join(".smrec").join(STATE_FILE);
+
+        assert_eq!(open(path.clone(), vec![1, 2]), None);
+        recording(Utf8Path::new("rec_20231120_120000"));
+        stopped();
+        recording(Utf8Path::new("rec_20231120_130000"));
+
+        // As if the recorder crashed and started again.
+        let previous = open(path.clone(), vec![1, 2]).unwrap();
+        assert_eq!(previous.transport, Transport::Recording);
+        assert_eq!(previous.take.as_deref(), Some("rec_20231120_130000"));
+        assert_eq!(previous.takes, 2);
+        assert_eq!(previous.channels, [1, 2]);
+
+        transport(Transport::Armed);
+        let previous = open(path, Vec::new()).unwrap();
+        assert_eq!(previous.transport, Transport::Armed);
+        assert_eq!(previous.takes, 2);
+
+        *STATE.lock().unwrap() = None;
+    }
+}
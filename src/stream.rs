@@ -1,89 +1,1355 @@
-use crate::{wav::write_input_data, WriterHandles};
-use anyhow::{bail, Result};
-use cpal::{traits::DeviceTrait, FromSample, Sample};
-use std::sync::{Arc, Mutex};
+#[cfg(feature = "mock")]
+use crate::mock;
+use crate::{
+    apps,
+    clock::Clock,
+    console,
+    inject::{Generator, Signal},
+    meters::MeterTimes,
+    monitor,
+    processing::{Gate, Processing},
+    resample::{Pace, Quality, Resampler},
+    takes::Take,
+    tap::Taps,
+    threads,
+    types::Action,
+    verify,
+    wav::{self, update_header, write_input_data},
+};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use cpal::{
+    traits::{DeviceTrait, StreamTrait},
+    FromSample, Sample,
+};
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
-pub fn build(
+/// How far before a detected onset a take starts when starting on transients.
+const ONSET_LOOKBACK_FRAMES: usize = 64;
+/// How long frames are counted for before the rate they arrive at is compared to the sample rate.
+const RATE_CHECK_SECS: u64 = 2;
+/// How far off the rate frames arrive at may be before the device is considered to run at another sample rate.
+///
+/// It is well above the jitter of the callbacks and below the step between common sample rates, e.g. 44.1 and 48 kHz.
+const RATE_TOLERANCE: f64 = 0.03;
+/// How many windows in a row have to be off before the rate is reported, a single stall of the system is not a change.
+const RATE_CHECK_WINDOWS: usize = 3;
+
+/// Receives the interleaved samples of an input and when the first frame of them was captured.
+pub type Callback<T> = Box<dyn FnMut(&[T], DateTime<Utc>) + Send + 'static>;
+
+/// What an input records.
+pub enum Source {
+    Device(cpal::Device),
+    /// The stream an application plays, by the name of the application.
+    App(String),
+    /// A device of `--host mock`.
+    #[cfg(feature = "mock")]
+    Mock(mock::Device),
+}
+
+/// An input and the configuration it is recorded with.
+pub struct Input {
+    pub source: Source,
+    pub config: cpal::SupportedStreamConfig,
+    /// The configuration a device runs with when it is resampled to `config`, because it can not run at its sample rate.
+    pub native: Option<cpal::SupportedStreamConfig>,
+    /// How the device is resampled when it can not run at the sample rate of the first device, none to refuse it.
+    pub resample: Option<Quality>,
+    /// Whether the device is an output which is recorded through loopback, as WASAPI does.
+    pub loopback: bool,
+}
+
+impl Input {
+    /// Opens the first device with its default configuration and the others with the same sample rate and format.
+    ///
+    /// Devices which can not run like the first are resampled to it when a quality is given.
+    /// Output devices recorded through loopback come after the input devices, applications after all devices,
+    /// with the sample rate and format of the first device.
+    pub fn open(
+        devices: Vec<cpal::Device>,
+        loopback: Vec<cpal::Device>,
+        apps: Vec<String>,
+        resample: Option<Quality>,
+    ) -> Result<Vec<Self>> {
+        let devices = devices
+            .into_iter()
+            .map(|device| (device, false))
+            .chain(loopback.into_iter().map(|device| (device, true)))
+            .collect::<Vec<_>>();
+        let mut configs = configs(
+            devices.iter().map(|(device, loopback)| (device, *loopback)),
+            resample,
+        )?;
+        let first = configs.first().map(|(config, _)| config.clone());
+        configs.extend(apps.iter().map(|_| (app_config(first.as_ref()), None)));
+        for (app, (config, _)) in apps.iter().zip(&configs[devices.len()..]) {
+            console::trace(|| format!("app:{app} is recorded with {}.", describe(config)));
+        }
+        Ok(devices
+            .into_iter()
+            .map(|(device, loopback)| (Source::Device(device), loopback))
+            .chain(apps.into_iter().map(|app| (Source::App(app), false)))
+            .zip(configs)
+            .map(|((source, loopback), (config, native))| Self {
+                source,
+                config,
+                native,
+                resample,
+                loopback,
+            })
+            .collect())
+    }
+
+    /// Asks the devices for their configuration again, e.g. after they changed their sample rate.
+    pub fn reopen(inputs: &mut [Self]) -> Result<()> {
+        let resample = inputs.first().and_then(|input| input.resample);
+        let mut configs = configs(
+            inputs
+                .iter()
+                .filter_map(|input| Some((input.device()?, input.loopback))),
+            resample,
+        )?;
+        let first = configs.first().map(|(config, _)| config.clone());
+        for input in inputs.iter_mut() {
+            (input.config, input.native) = match input.source {
+                Source::Device(_) => configs.remove(0),
+                Source::App(_) => (app_config(first.as_ref()), None),
+                // Mock devices keep their configuration.
+                #[cfg(feature = "mock")]
+                Source::Mock(_) => continue,
+            };
+        }
+        Ok(())
+    }
+
+    /// The channel count the device reports now, none for applications and mock devices which keep theirs.
+    pub fn current_channels(&self) -> Result<Option<u16>> {
+        Ok(self.current_config()?.map(|config| config.channels()))
+    }
+
+    /// The sample rate the device reports now, none for applications and mock devices which keep theirs.
+    pub fn current_sample_rate(&self) -> Result<Option<u32>> {
+        Ok(self.current_config()?.map(|config| config.sample_rate().0))
+    }
+
+    /// The sample rate the device runs at, which is not the recorded one when it is resampled.
+    pub fn running_sample_rate(&self) -> u32 {
+        self.native.as_ref().unwrap_or(&self.config).sample_rate().0
+    }
+
+    fn current_config(&self) -> Result<Option<cpal::SupportedStreamConfig>> {
+        let Some(device) = self.device() else {
+            return Ok(None);
+        };
+        let default = if self.loopback {
+            device.default_output_config()
+        } else {
+            device.default_input_config()
+        };
+        let default = default.map_err(|err| {
+            anyhow!(
+                "Error asking {} for its configuration: {err}",
+                device.name().unwrap_or_default()
+            )
+        })?;
+        Ok(Some(default))
+    }
+
+    pub const fn device(&self) -> Option<&cpal::Device> {
+        match &self.source {
+            Source::Device(device) => Some(device),
+            Source::App(_) => None,
+            #[cfg(feature = "mock")]
+            Source::Mock(_) => None,
+        }
+    }
+
+    /// The name of the device, `app:<name>` for applications.
+    pub fn name(&self) -> String {
+        match &self.source {
+            Source::Device(device) if self.loopback => {
+                format!("loopback:{}", device.name().unwrap_or_default())
+            }
+            Source::Device(device) => device.name().unwrap_or_default(),
+            Source::App(app) => format!("app:{app}"),
+            #[cfg(feature = "mock")]
+            Source::Mock(device) => device.name.clone(),
+        }
+    }
+
+    /// A configuration with the channels of all inputs one after another.
+    pub fn combined_config(inputs: &[Self]) -> cpal::SupportedStreamConfig {
+        let first = &inputs[0].config;
+        cpal::SupportedStreamConfig::new(
+            inputs.iter().map(|input| input.config.channels()).sum(),
+            first.sample_rate(),
+            first.buffer_size().clone(),
+            first.sample_format(),
+        )
+    }
+}
+
+/// The default configuration of the first device and matching ones of the others.
+///
+/// Devices which can not match the first one are resampled to it when a quality is given,
+/// they are given the configuration they run with next to the one they are recorded with.
+/// Devices recorded through loopback are asked for their output configuration.
+fn configs<'a>(
+    devices: impl IntoIterator<Item = (&'a cpal::Device, bool)>,
+    resample: Option<Quality>,
+) -> Result<
+    Vec<(
+        cpal::SupportedStreamConfig,
+        Option<cpal::SupportedStreamConfig>,
+    )>,
+> {
+    let mut configs = Vec::<(_, Option<_>)>::new();
+    for (device, loopback) in devices {
+        let name = device.name().unwrap_or_default();
+        trace_supported(device, loopback, &name);
+        let default = if loopback {
+            device.default_output_config()
+        } else {
+            device.default_input_config()
+        };
+        let default =
+            default.map_err(|_| anyhow!("No default input config found for device {name}."))?;
+        console::trace(|| format!("{name}: its default config is {}.", describe(&default)));
+        let config = match configs.first() {
+            None => {
+                console::trace(|| {
+                    format!("{name}: the default config is used, it is the first device.")
+                });
+                (default, None)
+            }
+            Some((first, _)) => match (
+                matching_config(device, loopback, default.clone(), first),
+                resample,
+            ) {
+                (Ok(config), _) => (config, None),
+                (Err(_), Some(quality)) => {
+                    println!(
+                        "Device {} runs at {} Hz {:?}, it is resampled to {} Hz {:?} with {quality:?} quality.",
+                        device.name().unwrap_or_default(),
+                        default.sample_rate().0,
+                        default.sample_format(),
+                        first.sample_rate().0,
+                        first.sample_format()
+                    );
+                    (resampled_config(&default, first), Some(default))
+                }
+                (Err(err), None) => return Err(err),
+            },
+        };
+        console::trace(|| match &config {
+            (config, Some(native)) => format!(
+                "{name}: chosen {}, running at {}.",
+                describe(config),
+                describe(native)
+            ),
+            (config, None) => format!("{name}: chosen {}.", describe(config)),
+        });
+        configs.push(config);
+    }
+    Ok(configs)
+}
+
+/// Lists the configurations a device offers with `--verbose`.
+fn trace_supported(device: &cpal::Device, loopback: bool, name: &str) {
+    let supported = if loopback {
+        device
+            .supported_output_configs()
+            .map(Iterator::collect::<Vec<_>>)
+    } else {
+        device
+            .supported_input_configs()
+            .map(Iterator::collect::<Vec<_>>)
+    };
+    match supported {
+        Ok(supported) => {
+            for config in supported {
+                console::trace(|| format!("{name}: offers {}.", describe_range(&config)));
+            }
+        }
+        Err(err) => console::trace(|| format!("{name}: its configs can not be listed: {err}")),
+    }
+}
+
+fn describe(config: &cpal::SupportedStreamConfig) -> String {
+    format!(
+        "{} channels at {} Hz {:?}, {}",
+        config.channels(),
+        config.sample_rate().0,
+        config.sample_format(),
+        describe_buffer(config.buffer_size())
+    )
+}
+
+fn describe_range(config: &cpal::SupportedStreamConfigRange) -> String {
+    format!(
+        "{} channels at {} to {} Hz {:?}, {}",
+        config.channels(),
+        config.min_sample_rate().0,
+        config.max_sample_rate().0,
+        config.sample_format(),
+        describe_buffer(config.buffer_size())
+    )
+}
+
+fn describe_buffer(buffer_size: &cpal::SupportedBufferSize) -> String {
+    match buffer_size {
+        cpal::SupportedBufferSize::Range { min, max } => {
+            format!("buffers of {min} to {max} frames")
+        }
+        cpal::SupportedBufferSize::Unknown => "buffers of unknown size".to_string(),
+    }
+}
+
+/// Why a configuration can not record like the first device, none when it can.
+fn rejection(
+    config: &cpal::SupportedStreamConfigRange,
+    sample_rate: cpal::SampleRate,
+    sample_format: cpal::SampleFormat,
+) -> Option<String> {
+    if config.sample_format() != sample_format {
+        Some(format!(
+            "its format is {:?} instead of {sample_format:?}",
+            config.sample_format()
+        ))
+    } else if !(config.min_sample_rate()..=config.max_sample_rate()).contains(&sample_rate) {
+        Some(format!("it does not run at {} Hz", sample_rate.0))
+    } else {
+        None
+    }
+}
+
+/// The configuration a device is recorded with when it is resampled to run like the first device.
+pub fn resampled_config(
+    native: &cpal::SupportedStreamConfig,
+    first: &cpal::SupportedStreamConfig,
+) -> cpal::SupportedStreamConfig {
+    cpal::SupportedStreamConfig::new(
+        native.channels(),
+        first.sample_rate(),
+        native.buffer_size().clone(),
+        first.sample_format(),
+    )
+}
+
+/// Applications are recorded like the first device, or as 48 kHz float when there is no device.
+fn app_config(first: Option<&cpal::SupportedStreamConfig>) -> cpal::SupportedStreamConfig {
+    cpal::SupportedStreamConfig::new(
+        apps::CHANNELS,
+        first.map_or(
+            cpal::SampleRate(48_000),
+            cpal::SupportedStreamConfig::sample_rate,
+        ),
+        cpal::SupportedBufferSize::Unknown,
+        first.map_or(
+            cpal::SampleFormat::F32,
+            cpal::SupportedStreamConfig::sample_format,
+        ),
+    )
+}
+
+fn matching_config(
     device: &cpal::Device,
-    config: cpal::SupportedStreamConfig,
+    loopback: bool,
+    default: cpal::SupportedStreamConfig,
+    first: &cpal::SupportedStreamConfig,
+) -> Result<cpal::SupportedStreamConfig> {
+    let (sample_rate, sample_format) = (first.sample_rate(), first.sample_format());
+    let name = device.name().unwrap_or_default();
+    if default.sample_rate() == sample_rate && default.sample_format() == sample_format {
+        console::trace(|| format!("{name}: the default config runs like the first device."));
+        return Ok(default);
+    }
+    console::trace(|| {
+        format!(
+            "{name}: the default config does not run at {} Hz {sample_format:?} like the first device, looking for one which does.",
+            sample_rate.0
+        )
+    });
+    let mut supported = if loopback {
+        device.supported_output_configs()?.collect::<Vec<_>>()
+    } else {
+        device.supported_input_configs()?.collect::<Vec<_>>()
+    };
+    // Prefer the channel count of the default configuration.
+    supported.sort_by_key(|config| config.channels() != default.channels());
+    for config in supported {
+        match rejection(&config, sample_rate, sample_format) {
+            Some(reason) => console::trace(|| {
+                format!("{name}: rejected {}, {reason}.", describe_range(&config))
+            }),
+            None => return Ok(config.with_sample_rate(sample_rate)),
+        }
+    }
+    Err(anyhow!(
+        "Device {name} can not record {} Hz {sample_format:?} like the first device, devices recorded together need the same sample rate and format or --resample.",
+        sample_rate.0
+    ))
+}
+
+/// Which channels of a device are recorded to which files of the take.
+#[derive(Clone)]
+struct Route {
+    device_channels: usize,
+    /// Number of the first channel of the device across all inputs, 0 indexed.
+    first_channel: usize,
+    /// Frames the device delivers its audio late by, its frames are lined up as if they were captured that much earlier.
+    latency: usize,
+    /// 0 indexed channels of the device.
+    channels: Vec<usize>,
+    /// Index of the file of the take each channel is written to.
+    files: Vec<usize>,
+    /// Whether frames are expected to arrive at the sample rate, applications deliver nothing while they are paused.
+    check_rate: bool,
+    /// The frames of the first input, counted by its stream and followed by resampled ones.
+    pace: Arc<Pace>,
+}
+
+/// Starts a stream for every input which has channels to record or tapped channels.
+///
+/// Channels are numbered across the inputs in order, the monitor stream gets the channels of the first of them.
+/// Latencies are given in frames for every input.
+#[allow(clippy::too_many_arguments)]
+pub fn build_all(
+    inputs: &[Input],
+    latencies: &[usize],
     channels_to_record: &[usize],
-    writers_in_stream: Arc<Mutex<Option<WriterHandles>>>,
-) -> Result<cpal::Stream> {
+    clock: Clock,
+    writers_in_stream: &Arc<Mutex<Option<Take>>>,
+    mut monitor: Option<SocketAddr>,
+    to_main_thread: Option<&crossbeam::channel::Sender<Action>>,
+    processing: &Arc<Processing>,
+    taps: &Arc<Taps>,
+    inject: Option<Signal>,
+) -> Result<Vec<Stream>> {
+    let mut streams = Vec::with_capacity(inputs.len());
+    let mut first_channel = 0;
+    let pace = Arc::new(Pace::default());
+    for (input, latency) in inputs.iter().zip(latencies) {
+        let device_channels = input.config.channels() as usize;
+        let (channels, files) = channels_to_record
+            .iter()
+            .enumerate()
+            .filter(|(_, channel)| {
+                (first_channel..first_channel + device_channels).contains(channel)
+            })
+            .map(|(file, channel)| (channel - first_channel, file))
+            .unzip::<_, _, Vec<_>, Vec<_>>();
+        let route = Route {
+            device_channels,
+            first_channel,
+            latency: *latency,
+            channels,
+            files,
+            check_rate: !matches!(input.source, Source::App(_)),
+            pace: Arc::clone(&pace),
+        };
+        let tapped = taps.any_in(&(first_channel..first_channel + device_channels));
+        first_channel += device_channels;
+        if route.channels.is_empty() && !tapped {
+            continue;
+        }
+        streams.push(build(
+            input,
+            &route,
+            clock,
+            Arc::clone(writers_in_stream),
+            monitor.take(),
+            to_main_thread.cloned(),
+            Arc::clone(processing),
+            Arc::clone(taps),
+            inject,
+        )?);
+        if let (Some(device), true) = (input.device(), input.loopback) {
+            streams.push(play_silence(device)?);
+        }
+    }
+    Ok(streams)
+}
+
+#[allow(clippy::too_many_arguments, clippy::too_many_lines)]
+fn build(
+    input: &Input,
+    route: &Route,
+    clock: Clock,
+    writers_in_stream: Arc<Mutex<Option<Take>>>,
+    monitor: Option<SocketAddr>,
+    to_main_thread: Option<crossbeam::channel::Sender<Action>>,
+    processing: Arc<Processing>,
+    taps: Arc<Taps>,
+    inject: Option<Signal>,
+) -> Result<Stream> {
+    let config = input.config.clone();
+    let sample_rate = config.sample_rate().0;
+    let monitor = monitor
+        .map(|target| monitor::Sender::new(target, sample_rate, route.channels.len()))
+        .transpose()?;
+    let resampled = input.native.as_ref().zip(input.resample);
+    let (Source::Device(device), None) = (&input.source, resampled) else {
+        // Applications, mock devices and resampled devices deliver float and are written in the format of the devices.
+        let callback = match wav::writable(config.sample_format()) {
+            cpal::SampleFormat::I8 => process::<f32, i8>(
+                route.clone(),
+                sample_rate,
+                writers_in_stream,
+                monitor,
+                to_main_thread,
+                processing,
+                taps,
+                inject,
+            ),
+            cpal::SampleFormat::I16 => process::<f32, i16>(
+                route.clone(),
+                sample_rate,
+                writers_in_stream,
+                monitor,
+                to_main_thread,
+                processing,
+                taps,
+                inject,
+            ),
+            cpal::SampleFormat::I32 => process::<f32, i32>(
+                route.clone(),
+                sample_rate,
+                writers_in_stream,
+                monitor,
+                to_main_thread,
+                processing,
+                taps,
+                inject,
+            ),
+            cpal::SampleFormat::F32 => process::<f32, f32>(
+                route.clone(),
+                sample_rate,
+                writers_in_stream,
+                monitor,
+                to_main_thread,
+                processing,
+                taps,
+                inject,
+            ),
+            sample_format => {
+                bail!("Sample format {sample_format:?} is not supported by this program.")
+            }
+        };
+        return match (&input.source, resampled) {
+            (source, Some((native, quality))) => build_resampled(
+                source,
+                native,
+                quality,
+                sample_rate,
+                clock,
+                Arc::clone(&route.pace),
+                callback,
+            ),
+            (Source::App(app), None) => Ok(Stream::App(apps::Capture::start(
+                app,
+                sample_rate,
+                clock,
+                callback,
+            )?)),
+            #[cfg(feature = "mock")]
+            (Source::Mock(device), None) => Ok(Stream::Mock(mock::Stream::start(
+                device.channels,
+                sample_rate,
+                clock,
+                callback,
+            ))),
+            (Source::Device(_), None) => unreachable!(),
+        };
+    };
+    let config = config.into();
+    match input.config.sample_format() {
+        cpal::SampleFormat::I8 => build_device(
+            device,
+            &config,
+            clock,
+            process::<i8, i8>(
+                route.clone(),
+                sample_rate,
+                writers_in_stream,
+                monitor,
+                to_main_thread,
+                processing,
+                taps,
+                inject,
+            ),
+        ),
+        cpal::SampleFormat::I16 => build_device(
+            device,
+            &config,
+            clock,
+            process::<i16, i16>(
+                route.clone(),
+                sample_rate,
+                writers_in_stream,
+                monitor,
+                to_main_thread,
+                processing,
+                taps,
+                inject,
+            ),
+        ),
+        cpal::SampleFormat::I32 => build_device(
+            device,
+            &config,
+            clock,
+            process::<i32, i32>(
+                route.clone(),
+                sample_rate,
+                writers_in_stream,
+                monitor,
+                to_main_thread,
+                processing,
+                taps,
+                inject,
+            ),
+        ),
+        cpal::SampleFormat::F32 => build_device(
+            device,
+            &config,
+            clock,
+            process::<f32, f32>(
+                route.clone(),
+                sample_rate,
+                writers_in_stream,
+                monitor,
+                to_main_thread,
+                processing,
+                taps,
+                inject,
+            ),
+        ),
+        // Samples hound can not write are converted to the closest format it can.
+        cpal::SampleFormat::U8 => build_device(
+            device,
+            &config,
+            clock,
+            process::<u8, i8>(
+                route.clone(),
+                sample_rate,
+                writers_in_stream,
+                monitor,
+                to_main_thread,
+                processing,
+                taps,
+                inject,
+            ),
+        ),
+        cpal::SampleFormat::U16 => build_device(
+            device,
+            &config,
+            clock,
+            process::<u16, i16>(
+                route.clone(),
+                sample_rate,
+                writers_in_stream,
+                monitor,
+                to_main_thread,
+                processing,
+                taps,
+                inject,
+            ),
+        ),
+        cpal::SampleFormat::U32 => build_device(
+            device,
+            &config,
+            clock,
+            process::<u32, i32>(
+                route.clone(),
+                sample_rate,
+                writers_in_stream,
+                monitor,
+                to_main_thread,
+                processing,
+                taps,
+                inject,
+            ),
+        ),
+        cpal::SampleFormat::I64 => build_device(
+            device,
+            &config,
+            clock,
+            process::<i64, i32>(
+                route.clone(),
+                sample_rate,
+                writers_in_stream,
+                monitor,
+                to_main_thread,
+                processing,
+                taps,
+                inject,
+            ),
+        ),
+        cpal::SampleFormat::U64 => build_device(
+            device,
+            &config,
+            clock,
+            process::<u64, i32>(
+                route.clone(),
+                sample_rate,
+                writers_in_stream,
+                monitor,
+                to_main_thread,
+                processing,
+                taps,
+                inject,
+            ),
+        ),
+        cpal::SampleFormat::F64 => build_device(
+            device,
+            &config,
+            clock,
+            process::<f64, f32>(
+                route.clone(),
+                sample_rate,
+                writers_in_stream,
+                monitor,
+                to_main_thread,
+                processing,
+                taps,
+                inject,
+            ),
+        ),
+        sample_format => bail!("Sample format {sample_format:?} is not supported by this program."),
+    }
+}
+
+/// The input of a 0 indexed channel numbered across the inputs, with the 0 indexed channel of that input.
+pub fn find_channel(inputs: &[Input], channel: usize) -> Option<(&Input, usize)> {
+    let mut first_channel = 0;
+    for input in inputs {
+        let channels = input.config.channels() as usize;
+        if channel < first_channel + channels {
+            return Some((input, channel - first_channel));
+        }
+        first_channel += channels;
+    }
+    None
+}
+
+/// Receives the float samples of one channel of an input.
+pub type ChannelCallback = Box<dyn FnMut(&[f32]) + Send + 'static>;
+
+/// Builds the stream of a device which runs with another sample rate or format than it is recorded with.
+///
+/// Its audio is converted to float and resampled before it reaches the callback, following the pace of the first input.
+fn build_resampled(
+    source: &Source,
+    native: &cpal::SupportedStreamConfig,
+    quality: Quality,
+    sample_rate: u32,
+    clock: Clock,
+    pace: Arc<Pace>,
+    callback: Callback<f32>,
+) -> Result<Stream> {
+    let channels = native.channels() as usize;
+    let native_rate = native.sample_rate().0;
+    let resampler = Resampler::new(channels, native_rate, sample_rate, quality);
+    let device = match source {
+        Source::Device(device) => device,
+        Source::App(_) => bail!("Applications are recorded at the sample rate of the devices."),
+        #[cfg(feature = "mock")]
+        Source::Mock(device) => {
+            return Ok(Stream::Mock(mock::Stream::start(
+                device.channels,
+                native_rate,
+                clock,
+                resampled::<f32>(resampler, native_rate, pace, callback),
+            )))
+        }
+    };
+    let config = native.clone().into();
+    match native.sample_format() {
+        cpal::SampleFormat::I8 => build_device(
+            device,
+            &config,
+            clock,
+            resampled::<i8>(resampler, native_rate, pace, callback),
+        ),
+        cpal::SampleFormat::I16 => build_device(
+            device,
+            &config,
+            clock,
+            resampled::<i16>(resampler, native_rate, pace, callback),
+        ),
+        cpal::SampleFormat::I32 => build_device(
+            device,
+            &config,
+            clock,
+            resampled::<i32>(resampler, native_rate, pace, callback),
+        ),
+        cpal::SampleFormat::I64 => build_device(
+            device,
+            &config,
+            clock,
+            resampled::<i64>(resampler, native_rate, pace, callback),
+        ),
+        cpal::SampleFormat::U8 => build_device(
+            device,
+            &config,
+            clock,
+            resampled::<u8>(resampler, native_rate, pace, callback),
+        ),
+        cpal::SampleFormat::U16 => build_device(
+            device,
+            &config,
+            clock,
+            resampled::<u16>(resampler, native_rate, pace, callback),
+        ),
+        cpal::SampleFormat::U32 => build_device(
+            device,
+            &config,
+            clock,
+            resampled::<u32>(resampler, native_rate, pace, callback),
+        ),
+        cpal::SampleFormat::U64 => build_device(
+            device,
+            &config,
+            clock,
+            resampled::<u64>(resampler, native_rate, pace, callback),
+        ),
+        cpal::SampleFormat::F32 => build_device(
+            device,
+            &config,
+            clock,
+            resampled::<f32>(resampler, native_rate, pace, callback),
+        ),
+        cpal::SampleFormat::F64 => build_device(
+            device,
+            &config,
+            clock,
+            resampled::<f64>(resampler, native_rate, pace, callback),
+        ),
+        sample_format => bail!("Sample format {sample_format:?} is not supported by this program."),
+    }
+}
+
+/// Converts interleaved buffers to float and resamples them, stamped with the capture time of their first frame.
+///
+/// The ratio follows the rates this device and the first input are measured at, once they were counted long enough.
+fn resampled<T>(
+    mut resampler: Resampler,
+    native_rate: u32,
+    pace: Arc<Pace>,
+    mut callback: Callback<f32>,
+) -> Callback<T>
+where
+    T: Sample + Send + 'static,
+    f32: FromSample<T>,
+{
+    let (mut input, mut output) = (Vec::new(), Vec::new());
+    let own_pace = Pace::default();
+    Box::new(move |data: &[T], captured_at| {
+        own_pace.count(data.len() / resampler.channels(), Instant::now());
+        if let Some((from, to)) = own_pace.rate().zip(pace.rate()) {
+            resampler.follow(from, to);
+        }
+        input.clear();
+        input.extend(data.iter().map(|sample| sample.to_sample::<f32>()));
+        let first = resampler.process(&input, &mut output);
+        if output.is_empty() {
+            return;
+        }
+        // The first frame lies between the frames of the device, usually before the first of this buffer.
+        #[allow(clippy::cast_possible_truncation)]
+        let offset =
+            chrono::Duration::nanoseconds((first * 1e9 / f64::from(native_rate)).round() as i64);
+        callback(&output, captured_at + offset);
+    })
+}
+
+/// Builds the stream of a device which stamps its buffers with their capture time.
+fn build_device<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    clock: Clock,
+    mut callback: Callback<T>,
+) -> Result<Stream>
+where
+    T: cpal::SizedSample + 'static,
+{
     let stream_error_callback = move |err| {
-        eprintln!("An error occurred on the input stream: {err}");
+        console::error(format!("An error occurred on the input stream: {err}"));
     };
+    // The thread of the callback is only known once it is called.
+    let mut tuned = false;
+    Ok(Stream::Device(device.build_input_stream(
+        config,
+        move |data: &[T], info: &cpal::InputCallbackInfo| {
+            if !tuned {
+                threads::tune(threads::Kind::Audio);
+                tuned = true;
+            }
+            callback(data, clock.capture_time(info));
+        },
+        stream_error_callback,
+        None,
+    )?))
+}
+
+/// Plays silence through an output device which is recorded through loopback.
+///
+/// WASAPI delivers nothing through loopback while nothing plays, the silence keeps its channels in step with the other devices.
+fn play_silence(device: &cpal::Device) -> Result<Stream> {
+    let config = device.default_output_config()?;
+    Ok(Stream::Device(device.build_output_stream_raw(
+        &config.config(),
+        config.sample_format(),
+        // Zeros are silence in the formats of WASAPI.
+        |data: &mut cpal::Data, _: &cpal::OutputCallbackInfo| data.bytes_mut().fill(0),
+        |err| console::error(format!("An error occurred on the loopback silence: {err}")),
+        None,
+    )?))
+}
+
+/// A running input, the stream of a device, the capture of an application or a mock device.
+pub enum Stream {
+    Device(cpal::Stream),
+    App(apps::Capture),
+    #[cfg(feature = "mock")]
+    Mock(mock::Stream),
+}
+
+impl Stream {
+    pub fn play(&self) -> Result<()> {
+        match self {
+            Self::Device(stream) => stream.play()?,
+            Self::App(capture) => capture.play(),
+            #[cfg(feature = "mock")]
+            Self::Mock(stream) => stream.play(),
+        }
+        Ok(())
+    }
 
-    match config.sample_format() {
-        cpal::SampleFormat::I8 => Ok(device.build_input_stream(
-            &config.into(),
-            process::<i8, i8>(channels_to_record.to_vec(), writers_in_stream),
-            stream_error_callback,
-            None,
-        )?),
-        cpal::SampleFormat::I16 => Ok(device.build_input_stream(
-            &config.into(),
-            process::<i16, i16>(channels_to_record.to_vec(), writers_in_stream),
-            stream_error_callback,
-            None,
-        )?),
-        cpal::SampleFormat::I32 => Ok(device.build_input_stream(
-            &config.into(),
-            process::<i32, i32>(channels_to_record.to_vec(), writers_in_stream),
-            stream_error_callback,
-            None,
-        )?),
-        cpal::SampleFormat::F32 => Ok(device.build_input_stream(
-            &config.into(),
-            process::<f32, f32>(channels_to_record.to_vec(), writers_in_stream),
-            stream_error_callback,
-            None,
-        )?),
-        sample_format => bail!(
-            "Sample format {:?} is not supported by this program.",
-            sample_format
-        ),
-    }
-}
-
-#[allow(clippy::type_complexity)]
+    pub fn pause(&self) -> Result<()> {
+        match self {
+            Self::Device(stream) => stream.pause()?,
+            Self::App(capture) => capture.pause(),
+            #[cfg(feature = "mock")]
+            Self::Mock(stream) => stream.pause(),
+        }
+        Ok(())
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::too_many_lines)]
 fn process<T, U>(
-    channels_to_record: Vec<usize>,
-    writers_in_stream: Arc<Mutex<Option<WriterHandles>>>,
-) -> Box<dyn FnMut(&[T], &cpal::InputCallbackInfo) + Send + 'static>
+    route: Route,
+    sample_rate: u32,
+    writers_in_stream: Arc<Mutex<Option<Take>>>,
+    mut monitor: Option<monitor::Sender>,
+    to_main_thread: Option<crossbeam::channel::Sender<Action>>,
+    processing: Arc<Processing>,
+    taps: Arc<Taps>,
+    inject: Option<Signal>,
+) -> Callback<T>
 where
-    T: Sample,
-    U: Sample + hound::Sample + FromSample<T>,
+    T: Sample + Send + FromSample<f32> + 'static,
+    U: Sample + hound::Sample + FromSample<T> + FromSample<f32>,
+    i16: FromSample<T>,
+    i32: FromSample<T>,
+    f32: FromSample<T>,
 {
-    Box::new(move |data: &[T], _: &_| {
+    let mut pre_roll =
+        vec![VecDeque::<T>::with_capacity(ONSET_LOOKBACK_FRAMES); route.channels.len()];
+    // Frames left to drop to line up with the other streams, `None` until this stream wrote to the take.
+    let mut to_skip: Option<usize> = None;
+    let mut frames_since_header_update = 0;
+    // Frames this stream wrote to the take.
+    let mut written = 0;
+    let mut rate_check = route.check_rate.then(|| RateCheck::new(sample_rate));
+    // Resampled inputs keep pace with the first one.
+    let leads = route.first_channel == 0;
+    let mut gates = route
+        .channels
+        .iter()
+        .map(|_| Gate::new(sample_rate))
+        .collect::<Vec<_>>();
+    let meter_times = MeterTimes::new(sample_rate);
+    let mut generator = inject.map(|signal| Generator::new(signal, sample_rate));
+    let mut injected = Vec::new();
+    // The attenuated samples of a channel for its safety copy, grown once to the size of the buffers of the device.
+    let mut attenuated = Vec::<f32>::new();
+
+    Box::new(move |data: &[T], captured_at: DateTime<Utc>| {
+        let (frames, now) = (data.len() / route.device_channels, Instant::now());
+        if leads {
+            route.pace.count(frames, now);
+        }
+        if let Some(measured) = rate_check
+            .as_mut()
+            .and_then(|check| check.push(frames, now))
+        {
+            if let Some(to_main_thread) = &to_main_thread {
+                to_main_thread
+                    .send(Action::SampleRateChanged(measured))
+                    .expect("Internal thread error.");
+            }
+        }
+
+        // Taps get the channels as the device delivers them, whether a take runs or not.
+        taps.feed(data, route.first_channel, route.device_channels);
+
+        // The generated signal goes through everything the input would.
+        let data = if let Some(generator) = generator.as_mut() {
+            injected.clear();
+            injected.extend_from_slice(data);
+            generator.fill(&mut injected, route.device_channels);
+            &injected[..]
+        } else {
+            data
+        };
+
         // We really don't do much here. We just record the data to the files.
         // So avoiding continuous allocation is not a priority.
         // We have a lot of time to do processing in every call to this function, so we can afford to do some allocation.
         // Premature optimization is the root of all evil. :)
-        let mut channel_buffer = Vec::<Vec<T>>::with_capacity(channels_to_record.len());
+        let mut channel_buffer = Vec::<Vec<T>>::with_capacity(route.channels.len());
 
-        for _ in 0..channels_to_record.len() {
-            channel_buffer.push(Vec::with_capacity(data.len()));
+        for _ in 0..route.channels.len() {
+            channel_buffer.push(Vec::with_capacity(data.len() / route.device_channels));
         }
 
-        // Channels to record has an ascending order, so does the interleaved data.
-
         // Process the frame
-        for frame in data.chunks(channels_to_record.len()) {
-            // We have one sample for each channel in this frame since we're recording mono.
-
-            for (channel_idx, sample) in frame.iter().enumerate() {
-                // Put that sample in the corresponding channel buffer.
+        for frame in data.chunks(route.device_channels) {
+            for (channel_idx, channel) in route.channels.iter().enumerate() {
+                // Put the sample of the channel in the corresponding channel buffer.
                 // De-interleave the data in other words.
-                channel_buffer[channel_idx].push(*sample);
+                channel_buffer[channel_idx].push(frame[*channel]);
             }
         }
 
-        if let Some(writers) = writers_in_stream.lock().unwrap().as_ref() {
-            let writers_in_stream = writers.clone();
+        for ((buffer, channel), gate) in channel_buffer
+            .iter_mut()
+            .zip(&route.channels)
+            .zip(&mut gates)
+        {
+            processing.apply(route.first_channel + channel, buffer, gate);
+            processing
+                .meters()
+                .meter(route.first_channel + channel, buffer, &meter_times);
+        }
+
+        if let Some(monitor) = monitor.as_mut() {
+            let monitored = route
+                .channels
+                .iter()
+                .map(|channel| processing.monitored(route.first_channel + channel))
+                .collect::<Vec<_>>();
+            monitor.click(
+                processing.beat_clock().last(),
+                captured_at,
+                data.len() / route.device_channels,
+            );
+            monitor.send(&channel_buffer, &monitored);
+        }
+
+        if let Some(take) = writers_in_stream.lock().unwrap().as_mut() {
+            // Offset of the first written frame relative to the start of this buffer.
+            let mut first_frame: isize = 0;
+
+            if let Some(threshold) = take.onset_threshold {
+                if let Some(onset) = find_onset(&channel_buffer, threshold) {
+                    // Start a little before the onset so the attack is not cut.
+                    let lookback = onset.min(ONSET_LOOKBACK_FRAMES);
+                    let from_pre_roll =
+                        (ONSET_LOOKBACK_FRAMES - lookback).min(pre_roll_len(&pre_roll));
+                    for (channel, buffer) in channel_buffer.iter_mut().enumerate() {
+                        let ring = &mut pre_roll[channel];
+                        let mut data = ring.drain(ring.len() - from_pre_roll..).collect::<Vec<_>>();
+                        data.extend_from_slice(&buffer[onset - lookback..]);
+                        *buffer = data;
+                    }
+                    pre_roll.iter_mut().for_each(VecDeque::clear);
+                    #[allow(clippy::cast_possible_wrap)]
+                    {
+                        first_frame = onset as isize - (lookback + from_pre_roll) as isize;
+                    }
+                    take.onset_threshold = None;
+                    console::info("Transient detected, recording.".to_string());
+                } else {
+                    // Keep waiting and remember the tail for the look back, and a buffer more in case the transient
+                    // is detected by another stream whose buffers arrive later.
+                    for (ring, buffer) in pre_roll.iter_mut().zip(&channel_buffer) {
+                        ring.extend(buffer);
+                        let excess = ring
+                            .len()
+                            .saturating_sub(ONSET_LOOKBACK_FRAMES + buffer.len());
+                        ring.drain(..excess);
+                    }
+                    return;
+                }
+            } else if to_skip.is_none() && pre_roll_len(&pre_roll) > 0 {
+                // The transient was detected by the stream of another device, this one starts at the same point
+                // with what it kept while waiting.
+                let kept = pre_roll_len(&pre_roll);
+                for (ring, buffer) in pre_roll.iter_mut().zip(channel_buffer.iter_mut()) {
+                    let mut data = ring.drain(ring.len() - kept..).collect::<Vec<_>>();
+                    data.extend_from_slice(buffer);
+                    *buffer = data;
+                }
+                pre_roll.iter_mut().for_each(VecDeque::clear);
+                #[allow(clippy::cast_possible_wrap)]
+                {
+                    first_frame = -(kept as isize);
+                }
+            }
+
+            if to_skip.is_none() {
+                #[allow(clippy::cast_possible_wrap)]
+                let first_captured_at = Clock::offset_by_frames(
+                    captured_at,
+                    first_frame - route.latency as isize,
+                    sample_rate,
+                );
+                // The first stream to deliver stamps the take with the capture time of its very first frame,
+                // the streams of the other devices line up with it.
+                let started_at = *take.started_at.get_or_insert(first_captured_at);
+                let offset = Clock::frames_between(started_at, first_captured_at, sample_rate);
+                if offset > 0 {
+                    pad_with_silence(&mut channel_buffer, offset.unsigned_abs());
+                }
+                to_skip = Some(offset.min(0).unsigned_abs());
+            }
+            if let Some(to_skip) = to_skip.as_mut().filter(|to_skip| **to_skip > 0) {
+                let skipped = (*to_skip).min(channel_buffer.first().map_or(0, Vec::len));
+                for buffer in &mut channel_buffer {
+                    buffer.drain(..skipped);
+                }
+                *to_skip -= skipped;
+            }
+            if let (Some(stop_at), Some(started_at)) = (take.stop_at, take.started_at) {
+                let left = Clock::frames_between(started_at, stop_at, sample_rate)
+                    .max(0)
+                    .unsigned_abs()
+                    .saturating_sub(written);
+                if channel_buffer.first().map_or(0, Vec::len) >= left {
+                    for buffer in &mut channel_buffer {
+                        buffer.truncate(left);
+                    }
+                    if !take.stopping {
+                        take.stopping = true;
+                        if let Some(to_main_thread) = &to_main_thread {
+                            to_main_thread
+                                .send(Action::Stop)
+                                .expect("Internal thread error.");
+                        }
+                    }
+                }
+            }
+            written += channel_buffer.first().map_or(0, Vec::len);
+
+            if let Some((threshold, silence)) = take.silence {
+                if take.last_sound.is_none() || find_onset(&channel_buffer, threshold).is_some() {
+                    take.last_sound = Some(captured_at);
+                }
+                let silent_for = take
+                    .last_sound
+                    .and_then(|last_sound| (captured_at - last_sound).to_std().ok());
+                if !take.stopping && silent_for.is_some_and(|silent_for| silent_for >= silence) {
+                    take.stopping = true;
+                    console::info(format!(
+                        "Silent for {} seconds, splitting the take.",
+                        silence.as_secs_f32()
+                    ));
+                    if let Some(to_main_thread) = &to_main_thread {
+                        to_main_thread
+                            .send(Action::Split)
+                            .expect("Internal thread error.");
+                    }
+                }
+            }
+
+            let writers_in_stream = take.writers.clone();
             // Write the de-interleaved buffer to the files.
-            for (channel_idx, channel_data) in channel_buffer.iter().enumerate() {
-                write_input_data::<T, U>(channel_data, &writers_in_stream[channel_idx]);
+            for (channel_data, file) in channel_buffer.iter().zip(&route.files) {
+                let file = *file;
+                write_input_data::<T, U>(
+                    channel_data,
+                    &writers_in_stream[file],
+                    take.formats[file],
+                );
+                if let Some((gain, safety_writers)) = &take.safety {
+                    attenuated.clear();
+                    attenuated.extend(
+                        channel_data
+                            .iter()
+                            .map(|sample| f32::from_sample(*sample) * gain),
+                    );
+                    write_input_data::<f32, U>(
+                        &attenuated,
+                        &safety_writers[file],
+                        take.formats[file],
+                    );
+                }
+                if let Some(peaks) = take.peaks.get_mut(file) {
+                    for sample in channel_data {
+                        peaks.push(i16::from_sample(*sample)).ok();
+                    }
+                }
+            }
+
+            if let Some(header_update_frames) = take.header_update_frames {
+                frames_since_header_update += channel_buffer.first().map_or(0, Vec::len);
+                if frames_since_header_update >= header_update_frames {
+                    frames_since_header_update = 0;
+                    for (channel_data, file) in channel_buffer.iter().zip(&route.files) {
+                        update_header(&take.writers[*file]);
+                        if let Some((_, safety_writers)) = &take.safety {
+                            update_header(&safety_writers[*file]);
+                        }
+                        if let Some(verifier) = &take.verifier {
+                            verify::queue::<T, U>(
+                                verifier,
+                                &take.directory.join(&take.files[*file]),
+                                &take.writers[*file],
+                                channel_data,
+                                take.formats[*file],
+                            );
+                        }
+                    }
+                }
             }
         }
     })
 }
+
+/// Compares the rate frames arrive at to the sample rate of the stream.
+///
+/// Some systems switch the sample rate of a device while it is streaming, the files would then be played back at the wrong speed.
+struct RateCheck {
+    sample_rate: u32,
+    /// When counting started, after the first buffer since its frames were captured before.
+    since: Option<Instant>,
+    frames: usize,
+    /// How many windows in a row were off.
+    off: usize,
+}
+
+impl RateCheck {
+    const fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            since: None,
+            frames: 0,
+            off: 0,
+        }
+    }
+
+    /// Counts the frames of a buffer, returning the measured rate once when it was off for a few windows in a row.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    fn push(&mut self, frames: usize, now: Instant) -> Option<u32> {
+        let Some(since) = self.since else {
+            self.since = Some(now);
+            return None;
+        };
+        self.frames += frames;
+        let elapsed = now.duration_since(since);
+        if elapsed < Duration::from_secs(RATE_CHECK_SECS) {
+            return None;
+        }
+        let measured = self.frames as f64 / elapsed.as_secs_f64();
+        self.since = Some(now);
+        self.frames = 0;
+        let expected = f64::from(self.sample_rate);
+        if (measured - expected).abs() / expected <= RATE_TOLERANCE {
+            self.off = 0;
+            return None;
+        }
+        self.off += 1;
+        (self.off == RATE_CHECK_WINDOWS).then_some(measured.round() as u32)
+    }
+}
+
+/// Delays the channels by the given number of frames.
+fn pad_with_silence<T: Sample>(channel_buffer: &mut [Vec<T>], frames: usize) {
+    for buffer in channel_buffer {
+        buffer.splice(0..0, std::iter::repeat_n(T::EQUILIBRIUM, frames));
+    }
+}
+
+fn pre_roll_len<T>(pre_roll: &[VecDeque<T>]) -> usize {
+    pre_roll.iter().map(VecDeque::len).min().unwrap_or(0)
+}
+
+/// Finds the first frame where any channel reaches the threshold.
+fn find_onset<T>(channel_buffer: &[Vec<T>], threshold: f32) -> Option<usize>
+where
+    T: Sample,
+    f32: FromSample<T>,
+{
+    channel_buffer
+        .iter()
+        .filter_map(|channel| {
+            channel
+                .iter()
+                .position(|sample| f32::from_sample(*sample).abs() >= threshold)
+        })
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_rate_changes_are_reported_once() {
+        let start = Instant::now();
+        let mut check = RateCheck::new(48000);
+        // 480 frames every 10 ms.
+        let push = |check: &mut RateCheck, from: u64, to: u64, frames: usize| {
+            (from..to)
+                .filter_map(|tick| check.push(frames, start + Duration::from_millis(tick * 10)))
+                .collect::<Vec<_>>()
+        };
+        assert!(push(&mut check, 0, 601, 480).is_empty());
+        // A stall of the system delays the buffers of one window.
+        assert!(push(&mut check, 601, 801, 240).is_empty());
+        assert!(push(&mut check, 801, 1201, 480).is_empty());
+        // The device switched to 44.1 kHz.
+        assert_eq!(push(&mut check, 1201, 2000, 441), [44100]);
+        assert!(push(&mut check, 2000, 2500, 441).is_empty());
+    }
+
+    #[test]
+    fn configs_are_rejected_with_a_reason() {
+        let range = cpal::SupportedStreamConfigRange::new(
+            8,
+            cpal::SampleRate(44100),
+            cpal::SampleRate(96000),
+            cpal::SupportedBufferSize::Range { min: 32, max: 4096 },
+            cpal::SampleFormat::I32,
+        );
+        assert_eq!(
+            describe_range(&range),
+            "8 channels at 44100 to 96000 Hz I32, buffers of 32 to 4096 frames"
+        );
+        assert_eq!(
+            rejection(&range, cpal::SampleRate(48000), cpal::SampleFormat::I32),
+            None
+        );
+        assert_eq!(
+            rejection(&range, cpal::SampleRate(48000), cpal::SampleFormat::F32).unwrap(),
+            "its format is I32 instead of F32"
+        );
+        assert_eq!(
+            rejection(&range, cpal::SampleRate(192_000), cpal::SampleFormat::I32).unwrap(),
+            "it does not run at 192000 Hz"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use crate::{crash, types::Action};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::{
+    fmt::Write,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::OnceLock,
+};
+
+const DEFAULT_PORT: u16 = 514;
+/// `local0`, which sites route to their own applications.
+const FACILITY: u8 = 16;
+/// The id of the structured data, under the enterprise number RFC 5612 reserves for documentation.
+const SD_ID: &str = "smrec@32473";
+
+/// Severities of RFC 5424 which are used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error = 3,
+    Warning = 4,
+    Notice = 5,
+    Info = 6,
+}
+
+/// Sends log messages as RFC 5424 syslog datagrams over UDP.
+struct Shipper {
+    socket: UdpSocket,
+    to: SocketAddr,
+    hostname: String,
+}
+
+static SHIPPER: OnceLock<Shipper> = OnceLock::new();
+
+/// Parses `host[:port]`, the port is 514 unless given.
+pub fn parse_address(address: &str) -> Result<SocketAddr> {
+    if let Ok(address) = address.parse::<SocketAddr>() {
+        return Ok(address);
+    }
+    let with_port = if address.contains(':') {
+        address.to_string()
+    } else {
+        format!("{address}:{DEFAULT_PORT}")
+    };
+    with_port
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("{address} can not be resolved."))
+}
+
+/// Ships the log messages of the rest of the run to a syslog server.
+pub fn start(to: SocketAddr) -> Result<()> {
+    let bind = if to.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let shipper = Shipper {
+        socket: UdpSocket::bind(bind)?,
+        to,
+        hostname: hostname(),
+    };
+    SHIPPER.set(shipper).ok();
+    println!("Logging to syslog at {to}.");
+    Ok(())
+}
+
+/// Ships a message with the name of the event and its fields, when `--log-syslog` is given.
+///
+/// It is kept for crash reports too, when `--crash-reports` is given.
+pub fn log(severity: Severity, event: &str, fields: &[(&str, &str)], message: &str) {
+    crash::remember(event, message);
+    if let Some(shipper) = SHIPPER.get() {
+        shipper.ship(Utc::now(), severity, event, fields, message);
+    }
+}
+
+/// Ships the events sent to the listeners which are worth keeping, start, stop and split are logged with their take.
+pub fn action(action: &Action) {
+    match action {
+        Action::Err(code, message) => log(
+            Severity::Error,
+            "error",
+            &[
+                ("code", &code.code().to_string()),
+                ("subsystem", code.subsystem()),
+            ],
+            message,
+        ),
+        Action::Interrupted(take) => log(
+            Severity::Warning,
+            "interrupted",
+            &[("take", take)],
+            "The previous run was interrupted while recording.",
+        ),
+        Action::SampleRateChanged(rate) => log(
+            Severity::Warning,
+            "sample_rate_changed",
+            &[("rate", &rate.to_string())],
+            "The input changed its sample rate.",
+        ),
+        Action::TakeLocked(take) => log(Severity::Info, "lock", &[("take", take)], "Take locked."),
+        Action::Arm => log(Severity::Info, "arm", &[], "Armed."),
+        Action::Disarm => log(Severity::Info, "disarm", &[], "Disarmed."),
+        _ => {}
+    }
+}
+
+impl Shipper {
+    fn ship(
+        &self,
+        now: DateTime<Utc>,
+        severity: Severity,
+        event: &str,
+        fields: &[(&str, &str)],
+        message: &str,
+    ) {
+        let line = format(
+            now,
+            &self.hostname,
+            std::process::id(),
+            severity,
+            event,
+            fields,
+            message,
+        );
+        // Nobody is told about a server which is gone, the console has every message anyway.
+        self.socket.send_to(line.as_bytes(), self.to).ok();
+    }
+}
+
+/// e.g. `<133>1 2023-11-20T12:00:00.000Z stage-pi smrec 812 start [smrec@32473 take="rec_20231120_120000"] Take started.`
+fn format(
+    now: DateTime<Utc>,
+    hostname: &str,
+    pid: u32,
+    severity: Severity,
+    event: &str,
+    fields: &[(&str, &str)],
+    message: &str,
+) -> String {
+    let priority = FACILITY * 8 + severity as u8;
+    let structured_data = if fields.is_empty() {
+        "-".to_string()
+    } else {
+        let mut element = format!("[{SD_ID}");
+        for (name, value) in fields {
+            let _ = write!(element, " {name}=\"{}\"", escape(value));
+        }
+        element.push(']');
+        element
+    };
+    format!(
+        "<{priority}>1 {} {hostname} smrec {pid} {event} {structured_data} {message}",
+        now.to_rfc3339_opts(SecondsFormat::Millis, true)
+    )
+}
+
+/// Escapes the characters which end or escape a value of structured data.
+fn escape(value: &str) -> String {
+    value
+        .chars()
+        .flat_map(|character| match character {
+            '"' | '\\' | ']' => vec!['\\', character],
+            _ => vec![character],
+        })
+        .collect()
+}
+
+/// The name of this machine, `-` when it is not known.
+pub fn hostname() -> String {
+    let name = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .unwrap_or_default();
+    // Printable ASCII without spaces, as the header allows.
+    let name = name
+        .trim()
+        .chars()
+        .filter(char::is_ascii_graphic)
+        .take(255)
+        .collect::<String>();
+    if name.is_empty() {
+        "-".to_string()
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn messages_are_shipped_as_rfc5424() {
+        let now = Utc.with_ymd_and_hms(2023, 11, 20, 12, 0, 0).unwrap();
+        assert_eq!(
+            format(
+                now,
+                "stage-pi",
+                812,
+                Severity::Notice,
+                "start",
+                &[("take", "rec_\"a\"]")],
+                "Take started."
+            ),
+            "<133>1 2023-11-20T12:00:00.000Z stage-pi smrec 812 start [smrec@32473 take=\"rec_\\\"a\\\"\\]\"] Take started."
+        );
+
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let shipper = Shipper {
+            socket: UdpSocket::bind("127.0.0.1:0").unwrap(),
+            to: receiver.local_addr().unwrap(),
+            hostname: "-".to_string(),
+        };
+        shipper.ship(now, Severity::Error, "error", &[], "Disk full.");
+        let mut datagram = [0; 256];
+        let length = receiver.recv(&mut datagram).unwrap();
+        let datagram = std::str::from_utf8(&datagram[..length]).unwrap();
+        assert!(datagram.starts_with("<131>1 2023-11-20T12:00:00.000Z - smrec "));
+        assert!(datagram.ends_with(" error - Disk full."));
+
+        assert_eq!(
+            parse_address("127.0.0.1").unwrap(),
+            "127.0.0.1:514".parse().unwrap()
+        );
+        assert_eq!(parse_address("127.0.0.1:5140").unwrap().port(), 5140);
+    }
+}
@@ -0,0 +1,843 @@
+use crate::{
+    clock::TimestampZone,
+    config::safety_file_name,
+    console, fingerprint,
+    ixml::{Ixml, Track},
+    loudness, ntp,
+    peaks::Peaks,
+    session::SessionFormat,
+    spool::Budget,
+    syslog,
+    timeline::Timeline,
+    transcribe, verify,
+    wav::{self, ChannelFormat, Marker},
+    WriterHandles,
+};
+use anyhow::{bail, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+/// Marker file which flags a take directory as locked.
+const LOCK_MARKER: &str = ".locked";
+/// Advisory lock file of the recorder which is writing into a take directory.
+const CLAIM_MARKER: &str = ".recording";
+/// Length of the `YYYYMMDD_HHMMSS` stamp in take directory names.
+const STAMP_LEN: usize = 15;
+
+/// Threads measuring the files of finalized takes.
+static MEASURING: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+
+/// A take which is being recorded.
+#[allow(clippy::struct_excessive_bools)]
+pub struct Take {
+    /// ULID of the take, shared by its continuation parts.
+    pub id: String,
+    pub writers: WriterHandles,
+    /// Linear gain and writers of the attenuated safety copies of the channels.
+    pub safety: Option<(f32, WriterHandles)>,
+    /// File names of the writers in the take directory.
+    pub files: Vec<String>,
+    /// The format of each writer.
+    pub formats: Vec<ChannelFormat>,
+    /// Peaks files of each writer, empty when they are not enabled.
+    pub peaks: Vec<Peaks>,
+    /// Markers which are written into every file when the take is finalized.
+    pub markers: Vec<Marker>,
+    /// 1 indexed tracks which the writers record, the channels of the device unless `[map]` renumbers them.
+    pub channels: Vec<usize>,
+    /// Metadata and take number of the iXML chunks, none when they are not written.
+    pub ixml: Option<(Ixml, u32)>,
+    /// Silent placeholder files which are written with the length of the take when it is finalized.
+    pub virtual_channels: Vec<Utf8PathBuf>,
+    pub spec: hound::WavSpec,
+    /// Bytes written to the disk per second of audio, by all writers together.
+    pub bytes_per_second: u64,
+    pub directory: Utf8PathBuf,
+    /// Continuation part number, 1 for a fresh take.
+    pub part: usize,
+    pub sample_rate: u32,
+    /// Sample rate the files are stamped with instead of `sample_rate`, for tape transfers played back at another speed.
+    pub playback_rate: Option<u32>,
+    /// When the first frame of the take was captured, derived from the stream timestamps.
+    pub started_at: Option<DateTime<Utc>>,
+    /// When set, nothing captured from this time on is written and the take is stopped.
+    pub stop_at: Option<DateTime<Utc>>,
+    /// Whether a stream reached `stop_at` and asked the main thread to stop.
+    pub stopping: bool,
+    /// While set, nothing is written until a sample reaches this amplitude.
+    pub onset_threshold: Option<f32>,
+    /// Amplitude which every channel stays below while silent and how long the silence lasts until the take is split.
+    pub silence: Option<(f32, Duration)>,
+    /// When a channel last reached the silence amplitude, across all devices.
+    pub last_sound: Option<DateTime<Utc>>,
+    /// DAW session to write next to the files when the take is finalized.
+    pub session: Option<SessionFormat>,
+    /// The zone which metadata timestamps are written in.
+    pub timestamp: TimestampZone,
+    /// The timeline shared by the takes of the run, none unless `--session-timeline` is given.
+    pub timeline: Option<Timeline>,
+    /// Whether to measure the loudness of the files and record their replay gain.
+    pub replaygain: bool,
+    /// Whether to fingerprint the mixdown of the files.
+    pub fingerprint: bool,
+    /// Whether to tag the files with their channel name, date, album and track number.
+    pub tags: bool,
+    /// Offset of the system clock against an NTP server, measured while the take starts.
+    pub ntp: Option<ntp::Query>,
+    /// Speech-to-text command which transcribes the mixdown of the files.
+    pub transcribe: Option<String>,
+    /// Frames after which the headers are updated so the files can be read while they grow, none with `--ram-buffer`.
+    pub header_update_frames: Option<usize>,
+    /// Where the ends of the files are sent to be read back when their headers are updated, none unless `--verify` is given.
+    pub verifier: Option<crossbeam::channel::Sender<verify::Check>>,
+    /// Memory the files are written through, none unless `--ram-buffer` is given.
+    pub budget: Option<Arc<Budget>>,
+}
+
+impl Take {
+    /// Writes the headers of the files so they can be read and gives the frames of the longest, nothing else.
+    ///
+    /// This is all that is done when the recorder is going down.
+    pub fn finalize_files(&self) -> Result<u32> {
+        let mut frames = 0;
+        let safety_writers = self.safety.iter().flat_map(|(_, writers)| writers.iter());
+        for writer in self.writers.iter().chain(safety_writers) {
+            let writer = writer
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .take();
+            if let Some(writer) = writer {
+                frames = frames.max(writer.duration());
+                writer.finalize()?;
+            }
+        }
+        Ok(frames)
+    }
+
+    /// Finalizes the writers and writes the take metadata sidecar next to them.
+    pub fn finalize(self) -> Result<()> {
+        self.finish().map(Finished::analyze)
+    }
+
+    /// Like [`Take::finalize`], but the files are analyzed by [`Finished::analyze`], so the take can be renamed before.
+    #[allow(clippy::too_many_lines)]
+    pub fn finish(mut self) -> Result<Finished> {
+        let frames = self.finalize_files()?;
+        if let Some(pressure) = self.budget.as_ref().map(|budget| budget.pressure()) {
+            if pressure.stalls > 0 {
+                println!(
+                    "Warning: the RAM buffer was full {} times during the take and {} bytes of audio were dropped.",
+                    pressure.stalls, pressure.dropped
+                );
+            }
+        }
+
+        let session_sample = self
+            .timeline
+            .as_ref()
+            .zip(self.started_at)
+            .map(|(timeline, started_at)| timeline.sample_at(started_at, self.sample_rate));
+        // Sessions place the files on the shared timeline when it is kept.
+        #[allow(clippy::cast_precision_loss)]
+        let position = session_sample.map_or_else(
+            || self.position(),
+            |sample| sample as f64 / f64::from(self.sample_rate),
+        );
+
+        for path in &self.virtual_channels {
+            write_silence(path, self.spec, frames)?;
+        }
+
+        self.append_chunks()?;
+
+        for peaks in std::mem::take(&mut self.peaks) {
+            peaks.finalize()?;
+        }
+
+        let mut sidecar = std::fs::File::create(self.directory.join(sidecar_file_name(self.part)))?;
+        writeln!(sidecar, "id = \"{}\"", self.id)?;
+        if let Some(started_at) = self.started_at {
+            writeln!(
+                sidecar,
+                "started_at = \"{}\"",
+                self.timestamp.rfc3339(started_at)
+            )?;
+        }
+        writeln!(sidecar, "sample_rate = {}", self.sample_rate)?;
+        if let Some(playback_rate) = self.playback_rate {
+            writeln!(sidecar, "playback_rate = {playback_rate}")?;
+        }
+        writeln!(sidecar, "frames = {frames}")?;
+        writeln!(sidecar, "channels = {}", self.writers.len())?;
+        if let Some((ixml, take)) = &self.ixml {
+            writeln!(
+                sidecar,
+                "scene = {}",
+                toml::Value::String(ixml.scene().to_string())
+            )?;
+            writeln!(sidecar, "take_number = {take}")?;
+        }
+        if let Some(session_sample) = session_sample {
+            writeln!(sidecar, "session_sample = {session_sample}")?;
+        }
+        if let Some(query) = self.ntp.take() {
+            match query.wait() {
+                Ok(measurement) => {
+                    writeln!(
+                        sidecar,
+                        "ntp_server = {}",
+                        toml::Value::String(measurement.server)
+                    )?;
+                    writeln!(sidecar, "ntp_offset_ms = {:.3}", measurement.offset_ms)?;
+                    writeln!(sidecar, "ntp_delay_ms = {:.3}", measurement.delay_ms)?;
+                }
+                Err(err) => println!(
+                    "Warning: the clock could not be compared to NTP for {}: {err:#}",
+                    self.directory
+                ),
+            }
+        }
+        if let Some(session) = self.session {
+            // The editor plays the files at the rate they are stamped with.
+            let sample_rate = self.playback_rate.unwrap_or(self.sample_rate);
+            #[allow(clippy::cast_precision_loss)]
+            let length = f64::from(frames) / f64::from(sample_rate);
+            std::fs::write(
+                self.directory.join(
+                    Utf8Path::new(&sidecar_file_name(self.part))
+                        .with_extension(session.extension()),
+                ),
+                session.render(&self.files, sample_rate, length, position),
+            )?;
+        }
+
+        if let (Some(timeline), Some(session_sample)) = (&self.timeline, session_sample) {
+            timeline.append(
+                &self.directory,
+                self.part,
+                session_sample,
+                frames,
+                self.sample_rate,
+            )?;
+        }
+
+        release(&self.directory)?;
+        Ok(Finished {
+            directory: self.directory,
+            files: self.files,
+            part: self.part,
+            replaygain: self.replaygain,
+            fingerprint: self.fingerprint,
+            transcribe: self.transcribe,
+        })
+    }
+
+    /// Writes the markers, iXML metadata and tags into the finalized files.
+    fn append_chunks(&self) -> Result<()> {
+        let markers = if self.markers.is_empty() {
+            Vec::new()
+        } else {
+            wav::marker_chunks(&self.markers)
+        };
+        let safety_files = self.safety.iter().flat_map(|_| {
+            self.files
+                .iter()
+                .zip(&self.channels)
+                .map(|(file, channel)| (safety_file_name(file), Some(*channel)))
+        });
+        let files = self
+            .files
+            .iter()
+            .cloned()
+            .zip(self.channels.iter().copied().map(Some))
+            .chain(safety_files)
+            .map(|(file, channel)| (self.directory.join(file), channel))
+            // Placeholders do not belong to a channel of the recorder.
+            .chain(
+                self.virtual_channels
+                    .iter()
+                    .map(|path| (path.clone(), None)),
+            );
+        for (path, channel) in files {
+            let spec = hound::WavReader::open(&path)?.spec();
+            let mut chunks = markers.clone();
+            if let (Some((ixml, take)), Some(channel)) = (&self.ixml, channel) {
+                chunks.extend(wav::ixml_chunk(&ixml.render(
+                    *take,
+                    &Track {
+                        channel,
+                        name: path.file_stem().unwrap_or_default(),
+                    },
+                    spec.sample_rate,
+                    spec.bits_per_sample,
+                )));
+            }
+            if let (true, Some(channel)) = (self.tags, channel) {
+                chunks.extend(self.info_chunk(&path, channel));
+            }
+            if !chunks.is_empty() {
+                wav::append_chunks(&path, &chunks)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Tags of a file, the album is the project or else the name of the directory the takes are recorded into.
+    fn info_chunk(&self, path: &Utf8Path, channel: usize) -> Vec<u8> {
+        let date = self
+            .started_at
+            .map(|started_at| self.timestamp.rfc3339(started_at))
+            .map(|date| date.chars().take(10).collect::<String>())
+            .unwrap_or_default();
+        let album = self
+            .ixml
+            .as_ref()
+            .and_then(|(ixml, _)| ixml.project())
+            .or_else(|| self.directory.parent().and_then(Utf8Path::file_name))
+            .unwrap_or_default();
+        wav::info_chunk(&[
+            (*b"INAM", path.file_stem().unwrap_or_default()),
+            (*b"IPRD", album),
+            (*b"ICRD", &date),
+            (*b"ITRK", &channel.to_string()),
+            (*b"ISFT", concat!("smrec ", env!("CARGO_PKG_VERSION"))),
+        ])
+    }
+
+    /// Drops a marker at the number of frames written so far and returns its frame.
+    pub fn mark(&mut self, label: Option<String>) -> u32 {
+        let frame = self
+            .writers
+            .first()
+            .and_then(|writer| {
+                writer
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .map(hound::WavWriter::duration)
+            })
+            .unwrap_or_default();
+        self.markers.push(Marker { frame, label });
+        frame
+    }
+
+    /// Seconds from the start of the take to the start of this part.
+    fn position(&self) -> f64 {
+        let take_start = self
+            .directory
+            .file_name()
+            .and_then(take_stamp)
+            .and_then(|date| self.timestamp.parse_dirname(date));
+        match (self.part, take_start, self.started_at) {
+            (2.., Some(take_start), Some(started_at)) => {
+                #[allow(clippy::cast_precision_loss)]
+                let position = (started_at - take_start).num_milliseconds().max(0) as f64 / 1000.0;
+                position
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// A take whose files are finalized, which is analyzed on other threads.
+pub struct Finished {
+    /// Where the take is, the caller moves it along when the take is renamed.
+    pub directory: Utf8PathBuf,
+    files: Vec<String>,
+    part: usize,
+    replaygain: bool,
+    fingerprint: bool,
+    transcribe: Option<String>,
+}
+
+impl Finished {
+    /// Measures, fingerprints and transcribes the files in the directory of the take, none of it is waited for.
+    pub fn analyze(self) {
+        if self.replaygain || self.fingerprint {
+            self.measure_in_background();
+        }
+
+        if let Some(command) = self.transcribe {
+            let files = self
+                .files
+                .iter()
+                .map(|file| self.directory.join(file))
+                .collect();
+            transcribe::start(command, self.directory, files, self.part);
+        }
+    }
+
+    /// Fingerprints and measures the files on a thread of its own, the next take does not wait for it.
+    ///
+    /// What is found is appended to the sidecar, [`wait_for_measurements`] waits for it.
+    fn measure_in_background(&self) {
+        let directory = self.directory.clone();
+        let files = self.files.clone();
+        let sidecar = self.directory.join(sidecar_file_name(self.part));
+        let (fingerprint, replaygain) = (self.fingerprint, self.replaygain);
+        let measuring = std::thread::spawn(move || {
+            let appended = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&sidecar)
+                .map_err(anyhow::Error::from)
+                .and_then(|mut sidecar| {
+                    // The fingerprint is a key of the sidecar itself, it comes before the tables.
+                    if fingerprint {
+                        write_fingerprint(&directory, &files, &mut sidecar)?;
+                    }
+                    if replaygain {
+                        write_replaygain(&directory, &files, &mut sidecar)?;
+                    }
+                    Ok(())
+                });
+            if let Err(err) = appended {
+                console::error(format!("Error measuring the files of {directory}: {err:#}"));
+            }
+        });
+        let mut threads = MEASURING
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        threads.retain(|thread| !thread.is_finished());
+        threads.push(measuring);
+    }
+}
+
+/// Fingerprints the mixdown of the files and writes it to the sidecar.
+fn write_fingerprint(
+    directory: &Utf8Path,
+    files: &[String],
+    sidecar: &mut std::fs::File,
+) -> Result<()> {
+    let files = files
+        .iter()
+        .map(|file| directory.join(file))
+        .collect::<Vec<_>>();
+    writeln!(
+        sidecar,
+        "fingerprint = \"{}\"",
+        fingerprint::of_files(&files)?
+    )?;
+    Ok(())
+}
+
+/// Measures the files and writes a `[replaygain."<file>"]` table of each to the sidecar.
+fn write_replaygain(
+    directory: &Utf8Path,
+    files: &[String],
+    sidecar: &mut std::fs::File,
+) -> Result<()> {
+    for file in files {
+        let analysis = loudness::analyze_file(&directory.join(file))?;
+        writeln!(sidecar)?;
+        writeln!(
+            sidecar,
+            "[replaygain.{}]",
+            toml::Value::String(file.clone())
+        )?;
+        if let Some(gain) = analysis.track_gain() {
+            writeln!(sidecar, "track_gain = {gain:.2}")?;
+        }
+        writeln!(sidecar, "track_peak = {:.6}", analysis.peak)?;
+    }
+    Ok(())
+}
+
+/// Waits until the files of the finalized takes are measured, before the recorder exits.
+pub fn wait_for_measurements() {
+    let measuring = std::mem::take(
+        &mut *MEASURING
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner),
+    );
+    if !measuring.is_empty() {
+        println!("Waiting for the files of the last takes to be measured...");
+    }
+    for measuring in measuring {
+        measuring.join().ok();
+    }
+}
+
+fn write_silence(path: &Utf8Path, spec: hound::WavSpec, frames: u32) -> Result<()> {
+    let mut writer = wav::create_writer(path, spec, None, false)?;
+    for _ in 0..frames {
+        match spec.sample_format {
+            hound::SampleFormat::Float => writer.write_sample(0.0_f32)?,
+            hound::SampleFormat::Int => writer.write_sample(0_i32)?,
+        }
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+/// The metadata sidecar of a take, `take.toml` or `take_partN.toml` for continuation parts.
+fn sidecar_file_name(part: usize) -> String {
+    if part < 2 {
+        "take.toml".to_string()
+    } else {
+        format!("take_part{part}.toml")
+    }
+}
+
+/// A new ULID for a take, which sorts by the time it was made.
+pub fn new_id() -> String {
+    ulid::Ulid::new().to_string()
+}
+
+/// The ULID of a take, read from its metadata sidecar.
+pub fn take_id(take: &Utf8Path) -> Option<String> {
+    let sidecar = std::fs::read_to_string(take.join(sidecar_file_name(1))).ok()?;
+    let sidecar = sidecar.parse::<toml::Table>().ok()?;
+    Some(sidecar.get("id")?.as_str()?.to_string())
+}
+
+/// The acoustic fingerprint of a take, read from its metadata sidecar.
+pub fn take_fingerprint(take: &Utf8Path) -> Option<Vec<u32>> {
+    let sidecar = std::fs::read_to_string(take.join(sidecar_file_name(1))).ok()?;
+    let sidecar = sidecar.parse::<toml::Table>().ok()?;
+    fingerprint::decode(sidecar.get("fingerprint")?.as_str()?)
+}
+
+/// `<name>_<id>` before the extension of a file name, e.g. `Kick_01HFKQ7Z6S3M0V4W5X6Y7Z8A9B.wav`.
+pub fn file_name_with_id(name: &str, id: &str) -> String {
+    let path = Utf8Path::new(name);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(extension)) => format!("{stem}_{id}.{extension}"),
+        _ => format!("{name}_{id}"),
+    }
+}
+
+/// The take number of a take in a scene, read from its metadata sidecar.
+pub fn take_number(take: &Utf8Path, scene: &str) -> Option<u32> {
+    let sidecar = std::fs::read_to_string(take.join(sidecar_file_name(1))).ok()?;
+    let sidecar = sidecar.parse::<toml::Table>().ok()?;
+    if sidecar.get("scene")?.as_str()? != scene {
+        return None;
+    }
+    u32::try_from(sidecar.get("take_number")?.as_integer()?).ok()
+}
+
+/// The number following the highest take number of a scene in the output path.
+pub fn next_take_number(base: &Utf8Path, scene: &str) -> Result<u32> {
+    let mut last = 0;
+    for entry in base.read_dir_utf8()? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() && take_stamp(entry.file_name()).is_some() {
+            last = last.max(take_number(entry.path(), scene).unwrap_or_default());
+        }
+    }
+    Ok(last + 1)
+}
+
+/// The date stamp of a take directory name, `[<instance>_]rec_<stamp>[_<label>][_N]`.
+pub fn take_stamp(name: &str) -> Option<&str> {
+    name.match_indices("rec_").find_map(|(at, _)| {
+        let stamp = name.get(at + 4..at + 4 + STAMP_LEN)?;
+        let is_stamp = stamp
+            .char_indices()
+            .all(|(i, c)| if i == 8 { c == '_' } else { c.is_ascii_digit() });
+        is_stamp.then_some(stamp)
+    })
+}
+
+/// Finds the most recent take directory whose name starts with the prefix, e.g. `rec_`, in the output path.
+pub fn last_take_directory(base: &Utf8Path, prefix: &str) -> Result<Option<Utf8PathBuf>> {
+    let mut last: Option<Utf8PathBuf> = None;
+    for entry in base.read_dir_utf8()? {
+        let entry = entry?;
+        // Take directories are stamped with the date so the lexicographic maximum is the latest.
+        if entry.file_type()?.is_dir()
+            && entry.file_name().starts_with(prefix)
+            && take_stamp(entry.file_name()).is_some()
+            && last.as_ref().and_then(|last| last.file_name()) < Some(entry.file_name())
+        {
+            last = Some(entry.into_path());
+        }
+    }
+    Ok(last)
+}
+
+/// Creates a new take directory, a `_N` suffix is added when another recorder created the name first.
+pub fn create_directory(base: &Utf8Path, name: &str) -> Result<Utf8PathBuf> {
+    let mut attempt = 1;
+    loop {
+        let take = if attempt == 1 {
+            base.join(name)
+        } else {
+            base.join(format!("{name}_{attempt}"))
+        };
+        // Creating a directory is atomic, also on network shares, so only one recorder succeeds.
+        match std::fs::create_dir(&take) {
+            Ok(()) => return Ok(take),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(err) => bail!("Can not create take directory {take}: {err}"),
+        }
+    }
+}
+
+/// Claims a take directory for this recorder until [`release`] is called.
+///
+/// Fails if another recorder is writing into it. The claim of a recorder on this machine which is not running
+/// anymore, e.g. after it crashed, is taken over.
+pub fn claim(take: &Utf8Path, instance: &str) -> Result<()> {
+    let path = take.join(CLAIM_MARKER);
+    let mut stale_removed = false;
+    loop {
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(mut claim) => {
+                writeln!(
+                    claim,
+                    "{instance} {} {}",
+                    std::process::id(),
+                    syslog::hostname()
+                )?;
+                return Ok(());
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                let owner = std::fs::read_to_string(&path).unwrap_or_default();
+                if stale_removed || !is_stale(&owner) {
+                    bail!("Take {take} is being recorded by {}.", owner.trim());
+                }
+                println!(
+                    "Take {take} was claimed by {}, which is not running anymore.",
+                    owner.trim()
+                );
+                release(take)?;
+                stale_removed = true;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Whether a claim of `<instance> <process id> <host>` was left behind by a recorder which is not running.
+///
+/// Only processes of this machine can be looked up, claims of other machines on a shared directory are never stale.
+fn is_stale(owner: &str) -> bool {
+    let mut fields = owner.split_whitespace().skip(1);
+    let (Some(pid), Some(host)) = (fields.next(), fields.next()) else {
+        return false;
+    };
+    host == syslog::hostname() && pid.parse().is_ok_and(|pid| !platform::is_running(pid))
+}
+
+/// The take directories in the output directory in the order they were recorded.
+pub fn all(base: &Utf8Path) -> Result<Vec<Utf8PathBuf>> {
+    let mut takes = base
+        .read_dir_utf8()?
+        .filter_map(Result::ok)
+        .filter(|entry| take_stamp(entry.file_name()).is_some() && entry.path().is_dir())
+        .map(camino::Utf8DirEntry::into_path)
+        .collect::<Vec<_>>();
+    takes.sort();
+    Ok(takes)
+}
+
+/// Whether a recorder is writing into the take.
+pub fn is_claimed(take: &Utf8Path) -> bool {
+    std::fs::read_to_string(take.join(CLAIM_MARKER)).is_ok_and(|owner| !is_stale(&owner))
+}
+
+pub fn release(take: &Utf8Path) -> Result<()> {
+    match std::fs::remove_file(take.join(CLAIM_MARKER)) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Resolves a take given either as a path or as a directory name in the output path.
+pub fn resolve(base: &Utf8Path, name: &str) -> Result<Utf8PathBuf> {
+    let as_given = Utf8PathBuf::from(name);
+    let take = if as_given.is_dir() {
+        as_given
+    } else {
+        base.join(name)
+    };
+
+    if !take.is_dir() {
+        bail!("Take {name} was not found in {base}.");
+    }
+
+    Ok(take)
+}
+
+/// Whether the take is protected from deletion, rotation and retention.
+pub fn is_locked(take: &Utf8Path) -> bool {
+    take.join(LOCK_MARKER).exists()
+}
+
+/// Marks a take as locked and makes its directory and files read-only.
+pub fn lock(take: &Utf8Path) -> Result<()> {
+    if is_locked(take) {
+        println!("Take {take} is already locked.");
+        return Ok(());
+    }
+    // Its files, sidecar and claim are still written when it stops.
+    if is_claimed(take) {
+        bail!("Take {take} is still being recorded, it can be locked after it stops.");
+    }
+
+    std::fs::write(take.join(LOCK_MARKER), "")?;
+
+    for entry in take.read_dir_utf8()? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            set_readonly(entry.path())?;
+        }
+    }
+    // The directory goes last, it would refuse the marker file otherwise.
+    set_readonly(take)?;
+
+    println!("Take {take} is locked.");
+
+    Ok(())
+}
+
+fn set_readonly(path: &Utf8Path) -> Result<()> {
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    permissions.set_readonly(true);
+    std::fs::set_permissions(path, permissions)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+mod platform {
+    pub fn is_running(pid: u32) -> bool {
+        let Ok(pid) = libc::pid_t::try_from(pid) else {
+            return false;
+        };
+        // SAFETY: Signal 0 only checks whether the process exists, nothing is sent.
+        if unsafe { libc::kill(pid, 0) } == 0 {
+            return true;
+        }
+        // The process exists but belongs to another user.
+        std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ffi::c_void;
+
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+    const STILL_ACTIVE: u32 = 259;
+    const ERROR_ACCESS_DENIED: i32 = 5;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> *mut c_void;
+        fn GetExitCodeProcess(process: *mut c_void, code: *mut u32) -> i32;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+
+    pub fn is_running(pid: u32) -> bool {
+        // SAFETY: The handle is checked before it is used and closed after.
+        unsafe {
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+            if process.is_null() {
+                // The process exists but belongs to another user.
+                return std::io::Error::last_os_error().raw_os_error() == Some(ERROR_ACCESS_DENIED);
+            }
+            let mut code = 0;
+            let queried = GetExitCodeProcess(process, &raw mut code);
+            CloseHandle(process);
+            queried == 0 || code == STILL_ACTIVE
+        }
+    }
+}
+
+/// Processes can not be looked up, every claim is taken as held.
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    pub const fn is_running(_pid: u32) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn recorders_can_not_claim_the_same_take() {
+        let dir = TempDir::new("claims");
+        let base = dir.path();
+
+        let first = create_directory(base, "stage_rec_20231120_120000").unwrap();
+        let second = create_directory(base, "stage_rec_20231120_120000").unwrap();
+        assert_eq!(second.file_name(), Some("stage_rec_20231120_120000_2"));
+        assert_eq!(
+            take_stamp("stage_rec_20231120_120000_2"),
+            Some("20231120_120000")
+        );
+        assert_eq!(take_stamp("rec_2023"), None);
+        assert_eq!(
+            take_stamp("rec_20231120_120000_rec_song3"),
+            Some("20231120_120000")
+        );
+
+        claim(&first, "stage").unwrap();
+        assert!(is_claimed(&first));
+        assert!(claim(&first, "other").is_err());
+        release(&first).unwrap();
+        claim(&first, "other").unwrap();
+
+        // A recorder of this machine which is gone, one of another machine and one of an older version.
+        let host = syslog::hostname();
+        assert!(is_stale(&format!("stage {} {host}", u32::MAX)));
+        assert!(!is_stale(&format!("stage {} {host}", std::process::id())));
+        assert!(!is_stale(&format!("stage {} {host}_other", u32::MAX)));
+        assert!(!is_stale("stage 4242"));
+        std::fs::write(
+            first.join(CLAIM_MARKER),
+            format!("crashed {} {host}", u32::MAX),
+        )
+        .unwrap();
+        assert!(!is_claimed(&first));
+        claim(&first, "stage").unwrap();
+        assert!(is_claimed(&first));
+
+        assert_eq!(
+            last_take_directory(base, "stage_rec_").unwrap(),
+            Some(second)
+        );
+        assert_eq!(last_take_directory(base, "rec_").unwrap(), None);
+    }
+
+    #[test]
+    fn takes_being_recorded_can_not_be_locked() {
+        let dir = TempDir::new("lock");
+        let take = create_directory(dir.path(), "rec_20231120_120000").unwrap();
+        std::fs::write(take.join("Kick.wav"), "").unwrap();
+        claim(&take, "smrec").unwrap();
+
+        assert!(lock(&take).is_err());
+        assert!(!is_locked(&take));
+        assert!(!std::fs::metadata(take.join("Kick.wav"))
+            .unwrap()
+            .permissions()
+            .readonly());
+    }
+
+    #[test]
+    fn ids_are_unique_and_go_into_file_names() {
+        let (first, second) = (new_id(), new_id());
+        assert_eq!(first.len(), 26);
+        assert_ne!(first, second);
+        assert_eq!(
+            file_name_with_id("Kick.wav", &first),
+            format!("Kick_{first}.wav")
+        );
+        assert_eq!(file_name_with_id("Kick", &first), format!("Kick_{first}"));
+    }
+}
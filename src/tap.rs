@@ -0,0 +1,119 @@
+// Guard tracks and pipes take their channel from the streams the takes are recorded with, so every device is
+// opened once. While no take runs the streams are kept running for them with nothing to write.
+
+use crate::stream::{ChannelCallback, Stream};
+use cpal::{FromSample, Sample};
+use std::{
+    cell::RefCell,
+    ops::Range,
+    sync::{Mutex, PoisonError},
+};
+
+thread_local! {
+    /// Streams kept running between takes, they are not `Send` and stay on the main thread.
+    static IDLE: RefCell<Vec<Stream>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Channels handed on from the streams of the inputs, numbered across the inputs and 0 indexed.
+#[derive(Default)]
+pub struct Taps(Vec<Tap>);
+
+struct Tap {
+    channel: usize,
+    /// The samples of the channel, allocated once, and where they go.
+    feed: Mutex<(Vec<f32>, ChannelCallback)>,
+}
+
+impl std::fmt::Debug for Taps {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|tap| tap.channel))
+            .finish()
+    }
+}
+
+impl Taps {
+    pub fn add(&mut self, channel: usize, callback: ChannelCallback) {
+        self.0.push(Tap {
+            channel,
+            feed: Mutex::new((Vec::new(), callback)),
+        });
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether one of the channels is tapped.
+    pub fn any_in(&self, channels: &Range<usize>) -> bool {
+        self.0.iter().any(|tap| channels.contains(&tap.channel))
+    }
+
+    /// Hands the interleaved samples of an input on to the taps of its channels, which start at `first_channel`.
+    pub fn feed<T>(&self, data: &[T], first_channel: usize, device_channels: usize)
+    where
+        T: Sample,
+        f32: FromSample<T>,
+    {
+        for tap in &self.0 {
+            let Some(channel) = tap
+                .channel
+                .checked_sub(first_channel)
+                .filter(|channel| *channel < device_channels)
+            else {
+                continue;
+            };
+            let mut feed = tap.feed.lock().unwrap_or_else(PoisonError::into_inner);
+            let (samples, callback) = &mut *feed;
+            samples.clear();
+            samples.extend(
+                data.iter()
+                    .skip(channel)
+                    .step_by(device_channels)
+                    .map(|sample| sample.to_sample::<f32>()),
+            );
+            callback(samples);
+            drop(feed);
+        }
+    }
+}
+
+/// Keeps streams running for the taps until the next take starts, the ones kept before are stopped.
+pub fn keep_running(streams: Vec<Stream>) {
+    IDLE.with_borrow_mut(|idle| *idle = streams);
+}
+
+/// Whether streams are kept running for the taps.
+pub fn is_running() -> bool {
+    IDLE.with_borrow(|idle| !idle.is_empty())
+}
+
+/// Stops the streams kept running, once the streams of a take opened the devices again.
+pub fn stop() {
+    IDLE.with_borrow_mut(Vec::clear);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn tapped_channels_are_handed_on() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let mut taps = Taps::default();
+        let tapped = Arc::clone(&received);
+        // The second channel of a stereo input which comes after a mono one.
+        taps.add(
+            2,
+            Box::new(move |samples| tapped.lock().unwrap().extend_from_slice(samples)),
+        );
+        assert!(taps.any_in(&(1..3)));
+        assert!(!taps.any_in(&(0..1)));
+
+        taps.feed(&[0.5_f32], 0, 1);
+        taps.feed(&[0.1_f32, 0.2, 0.3, 0.4], 1, 2);
+        taps.feed(&[i16::MIN, 0], 1, 2);
+        assert_eq!(*received.lock().unwrap(), [0.2, 0.4, 0.0]);
+    }
+}
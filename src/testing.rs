@@ -0,0 +1,28 @@
+// Fixtures shared by the tests of the modules.
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+/// A directory for the files of a test, it is removed when the test ends, also when it fails.
+pub struct TempDir(Utf8PathBuf);
+
+impl TempDir {
+    /// Creates the directory in the temporary directory of the system, named after the test and the process.
+    pub fn new(name: &str) -> Self {
+        let path = Utf8PathBuf::try_from(std::env::temp_dir())
+            .unwrap()
+            .join(format!("smrec_{name}_{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Utf8Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // A test which failed might have left the directory half written, what is left is not worth a panic.
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,257 @@
+use crate::console;
+use anyhow::{bail, Result};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    OnceLock,
+};
+
+/// Threads whose scheduling can be tuned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// The threads which receive the buffers of the inputs and write them into the take.
+    Audio,
+    /// The threads which write the RAM buffer of `--ram-buffer` out to the disk.
+    Writer,
+}
+
+/// How the audio and writer threads are scheduled, given on the command line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tuning {
+    /// Real-time priority of the audio threads, 1 to 99, the writer threads run one below them.
+    pub priority: Option<u8>,
+    /// Cores the audio threads are pinned to, numbered from 0.
+    pub audio_cores: Vec<usize>,
+    /// Cores the writer threads are pinned to, numbered from 0.
+    pub writer_cores: Vec<usize>,
+}
+
+impl Tuning {
+    /// The priority and the cores of a kind of thread.
+    fn of(&self, kind: Kind) -> (Option<u8>, &[usize]) {
+        match kind {
+            Kind::Audio => (self.priority, &self.audio_cores),
+            // Getting the audio out of the inputs matters more than getting it onto the disk.
+            Kind::Writer => (
+                self.priority
+                    .map(|priority| priority.saturating_sub(1).max(1)),
+                &self.writer_cores,
+            ),
+        }
+    }
+}
+
+static TUNING: OnceLock<Tuning> = OnceLock::new();
+/// Whether a problem tuning a kind of thread was reported, there are many writer threads.
+static REPORTED: [AtomicBool; 2] = [AtomicBool::new(false), AtomicBool::new(false)];
+
+/// Tunes the threads of the rest of the run, the cores need to exist.
+pub fn configure(tuning: Tuning) -> Result<()> {
+    let cores = std::thread::available_parallelism().map_or(usize::MAX, usize::from);
+    if let Some(core) = tuning
+        .audio_cores
+        .iter()
+        .chain(&tuning.writer_cores)
+        .find(|core| **core >= cores)
+    {
+        bail!(
+            "There is no core {core}, cores are numbered from 0 to {}.",
+            cores - 1
+        );
+    }
+    if tuning != Tuning::default() {
+        TUNING.set(tuning).ok();
+    }
+    Ok(())
+}
+
+/// Applies the tuning to the calling thread, a thread which can not be tuned runs as it is.
+///
+/// It does not block, so it can be called from the audio threads.
+pub fn tune(kind: Kind) {
+    let Some(tuning) = TUNING.get() else {
+        return;
+    };
+    let (priority, cores) = tuning.of(kind);
+    // Core Audio calls back on time constrained threads of its own, a fixed priority would only demote them.
+    let priority = priority.filter(|_| !(cfg!(target_os = "macos") && kind == Kind::Audio));
+    let name = match kind {
+        Kind::Audio => "audio",
+        Kind::Writer => "writer",
+    };
+    let mut problems = Vec::new();
+    if let Some(priority) = priority {
+        if let Err(err) = platform::set_priority(priority) {
+            problems.push(format!(
+                "Error raising the priority of the {name} threads to {priority}: {err}"
+            ));
+        }
+    }
+    if !cores.is_empty() {
+        if let Err(err) = platform::pin(cores) {
+            problems.push(format!("Error pinning the {name} threads to cores: {err}"));
+        }
+    }
+    if !problems.is_empty() && !REPORTED[kind as usize].swap(true, Ordering::Relaxed) {
+        for problem in problems {
+            console::error(problem);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use anyhow::{bail, Result};
+
+    pub fn set_priority(priority: u8) -> Result<()> {
+        let param = libc::sched_param {
+            sched_priority: libc::c_int::from(priority),
+        };
+        // SAFETY: The parameter outlives the call.
+        let err = unsafe {
+            libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &raw const param)
+        };
+        if err != 0 {
+            bail!(
+                "{}, the user may need rtprio in /etc/security/limits.conf.",
+                std::io::Error::from_raw_os_error(err)
+            );
+        }
+        Ok(())
+    }
+
+    pub fn pin(cores: &[usize]) -> Result<()> {
+        // SAFETY: A set without cores is all zeros.
+        let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+        for core in cores {
+            if usize::try_from(libc::CPU_SETSIZE).is_ok_and(|size| *core >= size) {
+                bail!("Core {core} can not be pinned to.");
+            }
+            // SAFETY: The core is within the set.
+            unsafe { libc::CPU_SET(*core, &mut set) };
+        }
+        // SAFETY: Pid 0 is the calling thread and the set is as long as it is said to be.
+        if unsafe { libc::sched_setaffinity(0, std::mem::size_of_val(&set), &raw const set) } != 0 {
+            bail!("{}", std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Only the writer threads are given a priority, see [`tune`].
+#[cfg(target_os = "macos")]
+mod platform {
+    use anyhow::{bail, Result};
+
+    pub fn set_priority(priority: u8) -> Result<()> {
+        // SAFETY: The parameter is plain data, all zeros but the priority.
+        let mut param = unsafe { std::mem::zeroed::<libc::sched_param>() };
+        param.sched_priority = libc::c_int::from(priority);
+        // SAFETY: The parameter outlives the call.
+        let err = unsafe {
+            libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &raw const param)
+        };
+        if err != 0 {
+            bail!("{}", std::io::Error::from_raw_os_error(err));
+        }
+        Ok(())
+    }
+
+    pub fn pin(_cores: &[usize]) -> Result<()> {
+        bail!("macOS does not pin threads to cores.")
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use anyhow::{bail, Result};
+    use std::ffi::c_void;
+
+    const THREAD_PRIORITY_HIGHEST: i32 = 2;
+    const THREAD_PRIORITY_TIME_CRITICAL: i32 = 15;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentThread() -> *mut c_void;
+        fn SetThreadPriority(thread: *mut c_void, priority: i32) -> i32;
+        fn SetThreadAffinityMask(thread: *mut c_void, mask: usize) -> usize;
+    }
+
+    /// Windows has levels instead of numbers, the upper half of the priorities is time critical.
+    pub fn set_priority(priority: u8) -> Result<()> {
+        let level = if priority >= 50 {
+            THREAD_PRIORITY_TIME_CRITICAL
+        } else {
+            THREAD_PRIORITY_HIGHEST
+        };
+        // SAFETY: The pseudo handle of the calling thread is always valid.
+        if unsafe { SetThreadPriority(GetCurrentThread(), level) } == 0 {
+            bail!("{}", std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn pin(cores: &[usize]) -> Result<()> {
+        let [mask] = super::mask::<1>(cores)?;
+        // SAFETY: The pseudo handle of the calling thread is always valid.
+        #[allow(clippy::cast_possible_truncation)]
+        if unsafe { SetThreadAffinityMask(GetCurrentThread(), mask as usize) } == 0 {
+            bail!("{}", std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    use anyhow::{bail, Result};
+
+    pub fn set_priority(_priority: u8) -> Result<()> {
+        bail!("Real-time priorities are not supported on this platform.")
+    }
+
+    pub fn pin(_cores: &[usize]) -> Result<()> {
+        bail!("Pinning threads is not supported on this platform.")
+    }
+}
+
+/// A mask of cores in words of 64 bits.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn mask<const WORDS: usize>(cores: &[usize]) -> Result<[u64; WORDS]> {
+    let mut mask = [0; WORDS];
+    for core in cores {
+        let Some(word) = mask.get_mut(core / 64) else {
+            bail!("Core {core} can not be pinned to.");
+        };
+        *word |= 1 << (core % 64);
+    }
+    Ok(mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threads_are_tuned_by_kind() {
+        let tuning = Tuning {
+            priority: Some(70),
+            audio_cores: vec![2, 3],
+            writer_cores: vec![1],
+        };
+        assert_eq!(tuning.of(Kind::Audio), (Some(70), &[2, 3][..]));
+        assert_eq!(tuning.of(Kind::Writer), (Some(69), &[1][..]));
+        let lowest = Tuning {
+            priority: Some(1),
+            ..Tuning::default()
+        };
+        assert_eq!(lowest.of(Kind::Writer), (Some(1), &[][..]));
+
+        assert_eq!(mask::<2>(&[0, 3, 64]).unwrap(), [0b1001, 1]);
+        assert!(mask::<1>(&[64]).is_err());
+        assert!(configure(Tuning {
+            audio_cores: vec![usize::MAX - 1],
+            ..Tuning::default()
+        })
+        .is_err());
+    }
+}
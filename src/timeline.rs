@@ -0,0 +1,105 @@
+use crate::clock::{Clock, TimestampZone};
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use std::io::Write;
+
+/// The sample timeline shared by all takes of a run, written to `timeline_<session start>.toml` in the output directory.
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    path: Utf8PathBuf,
+    start: DateTime<Utc>,
+    timestamp: TimestampZone,
+}
+
+impl Timeline {
+    pub fn new(base: &Utf8Path, clock: &Clock, timestamp: TimestampZone) -> Self {
+        let start = clock.started();
+        Self {
+            path: base.join(format!("timeline_{}.toml", timestamp.dirname(start))),
+            start,
+            timestamp,
+        }
+    }
+
+    /// Frames from the start of the session to the given time.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn sample_at(&self, date: DateTime<Utc>, sample_rate: u32) -> u64 {
+        let nanos = (date - self.start)
+            .num_nanoseconds()
+            .unwrap_or_default()
+            .max(0);
+        (i128::from(nanos) * i128::from(sample_rate) / 1_000_000_000) as u64
+    }
+
+    /// Records where a take or a continuation part lies on the timeline.
+    pub fn append(
+        &self,
+        take: &Utf8Path,
+        part: usize,
+        session_sample: u64,
+        frames: u32,
+        sample_rate: u32,
+    ) -> Result<()> {
+        let is_new = !self.path.exists();
+        let mut timeline = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        if is_new {
+            writeln!(
+                timeline,
+                "session_start = \"{}\"",
+                self.timestamp.rfc3339(self.start)
+            )?;
+            writeln!(timeline, "sample_rate = {sample_rate}")?;
+            writeln!(timeline)?;
+        }
+        writeln!(timeline, "[[takes]]")?;
+        writeln!(
+            timeline,
+            "take = {}",
+            toml::Value::String(take.file_name().unwrap_or(take.as_str()).to_string())
+        )?;
+        writeln!(timeline, "part = {part}")?;
+        writeln!(timeline, "session_sample = {session_sample}")?;
+        writeln!(timeline, "frames = {frames}")?;
+        writeln!(timeline)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn takes_share_the_session_timeline() {
+        let dir = TempDir::new("timeline");
+        let base = dir.path();
+
+        let clock = Clock::new();
+        let timeline = Timeline::new(base, &clock, TimestampZone::Utc);
+        let later = clock.started() + chrono::Duration::milliseconds(1500);
+        assert_eq!(timeline.sample_at(later, 48000), 72000);
+        assert_eq!(timeline.sample_at(clock.started(), 48000), 0);
+
+        timeline
+            .append(&base.join("rec_1"), 1, 0, 48000, 48000)
+            .unwrap();
+        timeline
+            .append(&base.join("rec_2"), 1, 72000, 100, 48000)
+            .unwrap();
+
+        let written = std::fs::read_to_string(&timeline.path)
+            .unwrap()
+            .parse::<toml::Table>()
+            .unwrap();
+        assert_eq!(written["sample_rate"].as_integer(), Some(48000));
+        let takes = written["takes"].as_array().unwrap();
+        assert_eq!(takes.len(), 2);
+        assert_eq!(takes[1]["take"].as_str(), Some("rec_2"));
+        assert_eq!(takes[1]["session_sample"].as_integer(), Some(72000));
+    }
+}
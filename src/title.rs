@@ -0,0 +1,172 @@
+use std::{
+    fmt::Write as _,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+/// How often the elapsed time in the title is updated.
+const TITLE_UPDATE_SECS: u64 = 1;
+
+/// What the title shows.
+#[derive(Clone, Copy)]
+enum State {
+    Stopped,
+    Recording { since: Instant, channels: usize },
+}
+
+/// The state of the recorder, `None` until the title is first set. Titles are written while it is locked.
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+/// The command line the recorder was started with, read before the title is written over it.
+static COMMAND_LINE: OnceLock<String> = OnceLock::new();
+
+/// The command line the recorder was started with.
+///
+/// `std::env::args` reads the memory the title is written into, everything else reads the command line from here.
+pub fn command_line() -> &'static str {
+    COMMAND_LINE.get_or_init(|| std::env::args().collect::<Vec<_>>().join(" "))
+}
+
+/// Shows in the process title that a recording of some channels is running, with its elapsed time.
+pub fn recording(channels: usize) {
+    set(State::Recording {
+        since: Instant::now(),
+        channels,
+    });
+}
+
+/// Shows in the process title that nothing is recorded.
+pub fn stopped() {
+    set(State::Stopped);
+}
+
+fn set(state: State) {
+    command_line();
+    let mut current = STATE.lock().unwrap();
+    let first = current.replace(state).is_none();
+    platform::set(&text(state));
+    drop(current);
+    // The first call starts the thread which keeps the elapsed time current.
+    if first {
+        std::thread::spawn(|| loop {
+            std::thread::sleep(Duration::from_secs(TITLE_UPDATE_SECS));
+            let current = STATE.lock().unwrap();
+            if let Some(state @ State::Recording { .. }) = *current {
+                platform::set(&text(state));
+            }
+        });
+    }
+}
+
+/// e.g. `smrec ● REC 00:12:43 16ch`.
+fn text(state: State) -> String {
+    match state {
+        State::Stopped => "smrec ○ stopped".to_string(),
+        State::Recording { since, channels } => {
+            let elapsed = since.elapsed().as_secs();
+            let mut text = String::from("smrec ● REC ");
+            let _ = write!(
+                text,
+                "{:02}:{:02}:{:02} {channels}ch",
+                elapsed / 3600,
+                elapsed / 60 % 60,
+                elapsed % 60
+            );
+            text
+        }
+    }
+}
+
+// `ps` shows the command line of a process, which is read from the memory its arguments were passed in.
+// The title is written over them, so it can be as long as the original command line.
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod platform {
+    use std::sync::OnceLock;
+
+    /// Start and length of the memory of the command line.
+    static ARGUMENTS: OnceLock<Option<(usize, usize)>> = OnceLock::new();
+
+    pub fn set(title: &str) {
+        let Some((start, len)) = *ARGUMENTS.get_or_init(arguments) else {
+            return;
+        };
+        let bytes = title.as_bytes();
+        // Keeps a terminating nul.
+        let count = bytes.len().min(len.saturating_sub(1));
+        // SAFETY: The range holds the argument strings of the process. They are read into `COMMAND_LINE` before
+        // the first title is written and nothing reads them from here afterwards, titles are only written while
+        // `STATE` is locked so one thread writes at a time.
+        unsafe {
+            let start = start as *mut u8;
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), start, count);
+            std::ptr::write_bytes(start.add(count), 0, len - count);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn arguments() -> Option<(usize, usize)> {
+        // `arg_start` and `arg_end` are the 48th and 49th fields, counted from the state after the name.
+        let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+        let mut fields = stat.rsplit_once(')')?.1.split_whitespace().skip(45);
+        let start = fields.next()?.parse::<usize>().ok()?;
+        let end = fields.next()?.parse::<usize>().ok()?;
+        (end > start).then(|| (start, end - start))
+    }
+
+    #[cfg(target_os = "macos")]
+    fn arguments() -> Option<(usize, usize)> {
+        use std::ffi::CStr;
+
+        // SAFETY: The arguments are nul terminated strings which lie one after another from the first one on.
+        unsafe {
+            let argc = usize::try_from(*libc::_NSGetArgc())
+                .ok()
+                .filter(|argc| *argc > 0)?;
+            let argv = *libc::_NSGetArgv();
+            let first = *argv;
+            let last = *argv.add(argc - 1);
+            let end = last as usize + CStr::from_ptr(last).to_bytes().len() + 1;
+            Some((first as usize, end - first as usize))
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleTitleW(title: *const u16) -> i32;
+    }
+
+    /// Windows lists processes by their executable, the title of the console window is set instead.
+    pub fn set(title: &str) {
+        let title = title.encode_utf16().chain(Some(0)).collect::<Vec<_>>();
+        // SAFETY: The title is a nul terminated wide string which lives for the call.
+        unsafe { SetConsoleTitleW(title.as_ptr()) };
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+mod platform {
+    pub const fn set(_title: &str) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn titles_show_the_state() {
+        assert_eq!(text(State::Stopped), "smrec ○ stopped");
+        let since = Instant::now()
+            .checked_sub(Duration::from_secs(12 * 60 + 43))
+            .unwrap();
+        assert_eq!(
+            text(State::Recording {
+                since,
+                channels: 16
+            }),
+            "smrec ● REC 00:12:43 16ch"
+        );
+    }
+}
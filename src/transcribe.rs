@@ -0,0 +1,170 @@
+// Speech to text is left to a command, e.g. whisper.cpp, which is given a WAV of the mixdown of the take
+// in the format speech recognizers take.
+
+use crate::{console, wav};
+use anyhow::{bail, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{
+    process::Command,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Rate speech recognizers such as whisper.cpp take their audio at.
+const SAMPLE_RATE: u32 = 16_000;
+
+/// Tells the mixdowns of takes transcribed at the same time apart.
+static MIXDOWNS: AtomicU64 = AtomicU64::new(0);
+
+/// Transcribes the mixdown of a finalized take in the background and writes what the command prints into the take.
+///
+/// The path of a 16 kHz mono WAV of the mixdown is appended to the command.
+pub fn start(command: String, directory: Utf8PathBuf, files: Vec<Utf8PathBuf>, part: usize) {
+    std::thread::spawn(move || {
+        let transcript = directory.join(transcript_file_name(part));
+        match transcribe(&command, &files, &transcript) {
+            Ok(()) => println!("Transcript is written to {transcript}."),
+            Err(err) => console::error(format!("Error transcribing {directory}: {err:#}")),
+        }
+    });
+}
+
+fn transcribe(command: &str, files: &[Utf8PathBuf], transcript: &Utf8Path) -> Result<()> {
+    // The recognizer reads the audio from outside the take, so nothing else finds it there meanwhile.
+    // Take names come from the network, the path of the audio is made of nothing but numbers.
+    let audio = Utf8PathBuf::try_from(std::env::temp_dir())?.join(format!(
+        "smrec_transcribe_{}_{}.wav",
+        std::process::id(),
+        MIXDOWNS.fetch_add(1, Ordering::Relaxed)
+    ));
+    write_mixdown(files, &audio)?;
+    let output = shell(command, &audio).output();
+    std::fs::remove_file(&audio).ok();
+    let output = output.with_context(|| format!("Error running {command}."))?;
+    if !output.status.success() {
+        bail!(
+            "{command} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    std::fs::write(transcript, output.stdout)?;
+    Ok(())
+}
+
+/// Writes the mixdown as 16 bit mono at the rate of the recognizer, every sample the average of the ones it covers.
+fn write_mixdown(files: &[Utf8PathBuf], path: &Utf8Path) -> Result<()> {
+    let (mixdown, sample_rate) = wav::mixdown(files)?;
+    let mut writer = hound::WavWriter::create(
+        path,
+        hound::WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        },
+    )?;
+    #[allow(clippy::cast_possible_truncation)]
+    let to_i16 = |sample: f32| (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+    let step = f64::from(sample_rate) / f64::from(SAMPLE_RATE);
+    // The first sample of the mixdown after an output sample.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    let end = |output: u64| (output as f64 * step).ceil() as usize;
+    let (mut output, mut sum, mut count, mut last) = (1, 0.0, 0_u32, 0);
+    #[allow(clippy::cast_precision_loss)]
+    for (index, sample) in mixdown.enumerate() {
+        // Slower rates than the one of the recognizer repeat samples.
+        while index >= end(output) {
+            if count > 0 {
+                last = to_i16(sum / count as f32);
+                (sum, count) = (0.0, 0);
+            }
+            writer.write_sample(last)?;
+            output += 1;
+        }
+        sum += sample;
+        count += 1;
+    }
+    if count > 0 {
+        #[allow(clippy::cast_precision_loss)]
+        writer.write_sample(to_i16(sum / count as f32))?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+/// Runs the command with the path of the audio appended, as an argument of the shell rather than a part of its script.
+fn shell(line: &str, audio: &Utf8Path) -> Command {
+    let mut command;
+    if cfg!(windows) {
+        command = Command::new("cmd");
+        command.args(["/C", &format!("{line} \"{audio}\"")]);
+    } else {
+        command = Command::new("sh");
+        command.args(["-c", &format!("{line} \"$1\""), "sh", audio.as_str()]);
+    }
+    command
+}
+
+fn transcript_file_name(part: usize) -> String {
+    if part < 2 {
+        "transcript.txt".to_string()
+    } else {
+        format!("transcript_part{part}.txt")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn transcripts_are_what_the_command_prints() {
+        let dir = TempDir::new("transcribe");
+        let take = dir.path().join("rec_20231120_120000");
+        std::fs::create_dir_all(&take).unwrap();
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let files = ["Host.wav", "Guest.wav"].map(|name| take.join(name));
+        for (file, length) in files.iter().zip([48000, 24000]) {
+            let mut writer = hound::WavWriter::create(file, spec).unwrap();
+            for _ in 0..length {
+                writer.write_sample(8192_i16).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+
+        let audio = take.join("mixdown.wav");
+        write_mixdown(&files, &audio).unwrap();
+        let reader = hound::WavReader::open(&audio).unwrap();
+        assert_eq!(reader.spec().sample_rate, SAMPLE_RATE);
+        assert_eq!(reader.duration(), SAMPLE_RATE);
+        let samples = reader
+            .into_samples::<i16>()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        // Both speakers, then the host alone.
+        assert_eq!(samples[0], 16383);
+        assert_eq!(samples[15000], 8191);
+
+        let transcript = take.join(transcript_file_name(1));
+        transcribe("echo Hello there. #", &files, &transcript).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&transcript).unwrap().trim(),
+            "Hello there."
+        );
+        assert!(transcribe("exit 3 #", &files, &transcript).is_err());
+        // The audio is handed over as an argument, whatever the command or the take is called.
+        transcribe("printf %s", &files, &transcript).unwrap();
+        let audio = Utf8PathBuf::from(std::fs::read_to_string(&transcript).unwrap());
+        assert_eq!(audio.extension(), Some("wav"));
+        assert!(!audio.as_str().contains("20231120"));
+    }
+}
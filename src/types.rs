@@ -1,5 +1,79 @@
+#[derive(Clone)]
 pub enum Action {
     Stop,
     Start,
-    Err(String),
+    /// Makes the recorder ready to start, recordings can only be started when armed.
+    Arm,
+    Disarm,
+    /// Finalizes the running take and continues in a new one.
+    Split,
+    /// The system is about to sleep, the running take is finalized before it does.
+    Sleep,
+    /// The system woke up, a take finalized for sleep goes on in a new one.
+    Wake,
+    /// Ctrl-C was pressed, the running take is finalized and smrec exits.
+    Quit,
+    /// The input runs at the given sample rate instead of the one it was opened with.
+    SampleRateChanged(u32),
+    /// Stops the running take at the frame captured at the given time.
+    StopAt(chrono::DateTime<chrono::Utc>),
+    /// Sets the gain of a 0 indexed device channel in dB.
+    SetGain(usize, f32),
+    /// Sets the gate threshold of a 0 indexed device channel in dBFS, `None` turns the gate off.
+    SetGate(usize, Option<f32>),
+    /// Solos a 0 indexed device channel in the monitor stream or takes the solo back.
+    MonitorSolo(usize, bool),
+    /// Mutes a 0 indexed device channel in the monitor stream or unmutes it.
+    MonitorMute(usize, bool),
+    /// Drops a marker with an optional label at the current position of the running take.
+    Marker(Option<String>),
+    /// Drops a marker labelled with a numbered cue of the configuration, 1 to 128.
+    Cue(u8),
+    LockLastTake,
+    /// Labels the next take, its directory is named `rec_<date>_<label>`.
+    NextTakeName(String),
+    /// A MIDI Song Select picked a song of the setlist, its title labels the next take.
+    SongSelect(u8),
+    /// Moves the setlist on to the following entry, which labels the next take.
+    SetlistNext,
+    /// Moves the setlist back to the previous entry, which labels the next take.
+    SetlistPrev,
+    /// A beat of an external MIDI clock, heard at the given time.
+    Beat(std::time::Instant),
+    /// Changes the output directory from the next take on.
+    SetOut(String),
+    /// Stamps the files with a sample rate other than the recorded one from the next take on, `None` goes back to it.
+    PlaybackRate(Option<u32>),
+    /// Records a 0 indexed channel of the device too from the next take on.
+    IncludeChannel(usize),
+    /// Stops recording a 0 indexed channel of the device from the next take on.
+    ExcludeChannel(usize),
+    TakeLocked(String),
+    /// Seconds of recording time left on the disk of the running take.
+    RemainingTime(u64),
+    /// The previous run was interrupted while recording the take with the given name.
+    Interrupted(String),
+    /// Asks for the current configuration.
+    DumpConfig,
+    /// The current configuration, serialized.
+    ConfigDump(String),
+    /// Replaces the configuration with a serialized one.
+    RestoreConfig(String),
+    /// Asks for the state of the transport and the meters.
+    Status,
+    /// The state of the transport and the meters of every device channel since the take started.
+    StatusReport(Transport, Vec<crate::meters::Meter>),
+    /// How full the RAM buffer of the running take is, sent with the status when `--ram-buffer` is given.
+    RamBuffer(crate::spool::Pressure),
+    /// An error for the listeners, with its code.
+    Err(crate::error::Code, String),
+}
+
+/// State of the transport when it is controlled via OSC, MIDI or a schedule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Stopped,
+    Armed,
+    Recording,
 }
@@ -0,0 +1,226 @@
+use anyhow::{anyhow, bail, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use minisign_verify::{PublicKey, Signature};
+use serde::Deserialize;
+use std::process::Command;
+
+const LATEST_RELEASE: &str = "https://api.github.com/repos/alisomay/smrec/releases/latest";
+/// The minisign public key releases are signed with, given when `smrec` is built.
+const RELEASE_KEY: Option<&str> = option_env!("SMREC_RELEASE_KEY");
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Replaces the running binary with the latest release when it is newer and its signature is valid.
+pub fn run(check: bool) -> Result<()> {
+    let current = env!("CARGO_PKG_VERSION");
+    let release: Release = serde_json::from_slice(&fetch(LATEST_RELEASE)?)
+        .context("The latest release could not be read.")?;
+    let latest = release.tag_name.trim_start_matches('v');
+    if !is_newer(latest, current) {
+        println!("smrec {current} is the latest release.");
+        return Ok(());
+    }
+    println!("smrec {latest} is released, this is {current}.");
+    if check {
+        return Ok(());
+    }
+
+    let Some(key) = RELEASE_KEY else {
+        bail!("This build of smrec has no release key to verify updates with, it can not update itself.");
+    };
+    let name = asset_name()?;
+    let url = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .map(|asset| asset.browser_download_url.clone())
+            .ok_or_else(|| anyhow!("Release {latest} has no {name}."))
+    };
+    let archive = fetch(&url(&name)?)?;
+    let signature = String::from_utf8(fetch(&url(&format!("{name}.minisig"))?)?)?;
+    verify(key, &archive, &signature, &name, latest)?;
+
+    let exe = Utf8PathBuf::try_from(std::env::current_exe()?)?;
+    let binary = unpack(&archive, &name)?;
+    replace(&exe, &binary).with_context(|| {
+        format!("Error replacing {exe}, it may need to be updated by a user who can write it.")
+    })?;
+    println!("smrec is updated to {latest}, it is used from the next start.");
+    Ok(())
+}
+
+/// Downloads over HTTPS with `curl`, which the machines `smrec` runs on have anyway.
+fn fetch(url: &str) -> Result<Vec<u8>> {
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location"])
+        .args(["--proto", "=https", url])
+        .output()
+        .context("curl is needed to download updates.")?;
+    if !output.status.success() {
+        bail!(
+            "Error downloading {url}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// The archive CI packages the binary of this platform in for releases.
+fn asset_name() -> Result<String> {
+    use std::env::consts::{ARCH, OS};
+    Ok(match (OS, ARCH) {
+        ("linux", "x86_64" | "aarch64") => format!("smrec-Linux-{ARCH}-gnu.tar.gz"),
+        ("linux", "arm") => "smrec-Linux-armv7-gnueabihf.tar.gz".to_string(),
+        ("macos", "x86_64" | "aarch64") => format!("smrec-Darwin-{ARCH}.tar.gz"),
+        ("windows", "x86_64" | "aarch64") => format!("smrec-Windows-{ARCH}.zip"),
+        ("windows", "x86") => "smrec-Windows-i686.zip".to_string(),
+        _ => bail!("There are no releases for {OS} on {ARCH}."),
+    })
+}
+
+/// Takes the binary out of a release archive with `tar`, which Windows has as well and which reads zip there.
+fn unpack(archive: &[u8], name: &str) -> Result<Vec<u8>> {
+    let dir = private_dir()?;
+    let path = dir.join(name);
+    std::fs::write(&path, archive)?;
+    let status = Command::new("tar")
+        .arg("-xf")
+        .arg(&path)
+        .arg("-C")
+        .arg(&dir)
+        .status()
+        .context("tar is needed to unpack updates.");
+    let binary = std::fs::read(dir.join(format!("smrec{}", std::env::consts::EXE_SUFFIX)));
+    std::fs::remove_dir_all(&dir).ok();
+    if !status?.success() {
+        bail!("Error unpacking {name}.");
+    }
+    binary.with_context(|| format!("{name} has no smrec binary."))
+}
+
+/// A new directory only this user can enter, so the verified binary can not be swapped before it is read back.
+fn private_dir() -> Result<Utf8PathBuf> {
+    let dir = Utf8PathBuf::try_from(std::env::temp_dir())?
+        .join(format!("smrec_update_{}", ulid::Ulid::new()));
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    // Fails when the directory, or anything else by that name, is already there.
+    builder
+        .create(&dir)
+        .with_context(|| format!("Error creating {dir}."))?;
+    Ok(dir)
+}
+
+/// Versions are compared by their numbers, `0.10.0` is newer than `0.9.1`.
+fn is_newer(latest: &str, current: &str) -> bool {
+    let numbers = |version: &str| {
+        version
+            .split(['.', '-', '+'])
+            .map_while(|number| number.parse::<u64>().ok())
+            .collect::<Vec<_>>()
+    };
+    numbers(latest) > numbers(current)
+}
+
+/// The signature needs to be made with the release key for this very file and version, a signed archive of another
+/// platform or an older release served as the latest one does not do.
+fn verify(key: &str, binary: &[u8], signature: &str, name: &str, version: &str) -> Result<()> {
+    let key = PublicKey::from_base64(key.trim()).map_err(|err| anyhow!("{err}"))?;
+    let signature = Signature::decode(signature).map_err(|err| anyhow!("{err}"))?;
+    key.verify(binary, &signature, false)
+        .map_err(|err| anyhow!("The signature of {name} is not valid: {err}"))?;
+    let signed = |field: String| {
+        signature
+            .trusted_comment()
+            .split_whitespace()
+            .any(|signed| signed == field)
+    };
+    if !signed(format!("file:{name}")) {
+        bail!("The signature is not for {name}.");
+    }
+    if !signed(format!("version:{version}")) {
+        bail!("The signature of {name} is not for version {version}.");
+    }
+    Ok(())
+}
+
+/// Puts the new binary next to the running one and moves it over, Windows only lets the running one be renamed.
+fn replace(exe: &Utf8Path, binary: &[u8]) -> Result<()> {
+    let new = exe.with_extension("new");
+    std::fs::write(&new, binary)?;
+    std::fs::set_permissions(&new, std::fs::metadata(exe)?.permissions())?;
+    if cfg!(windows) {
+        let old = exe.with_extension("old");
+        std::fs::remove_file(&old).ok();
+        std::fs::rename(exe, &old)?;
+    }
+    std::fs::rename(&new, exe)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    const KEY: &str = "RWQBAgMEBQYHCAOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4";
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQBAgMEBQYHCJjl4lv4DZExYPBoeORkjWjXDWxBz8sM1Axj3GFP4LnZK/aWishMMAo952ZeqHd8h3DjtK/rhvTlmKAn5DpANgc=
+trusted comment: timestamp:1700000000\tfile:smrec-Linux-x86_64-gnu.tar.gz\tversion:9.9.9
+Y35JifLWVGvKaaRnHw8DKLLdzSoeDK1FjYHTw10Y6O5Zphlh2xY5suN+FlsKuAZWQLY84cy+SZfoFzrZRB4MDg==
+";
+
+    #[test]
+    fn only_newer_signed_releases_replace_the_running_binary() {
+        assert!(is_newer("0.10.0", "0.9.1"));
+        assert!(is_newer("1.0.0", "0.2.1"));
+        assert!(!is_newer("0.2.1", "0.2.1"));
+        assert!(!is_newer("0.2.0", "0.2.1-rc1"));
+
+        let name = "smrec-Linux-x86_64-gnu.tar.gz";
+        let archive = b"smrec 9.9.9 archive";
+        verify(KEY, archive, SIGNATURE, name, "9.9.9").unwrap();
+        assert!(verify(KEY, b"smrec 6.6.6 archive", SIGNATURE, name, "9.9.9").is_err());
+        assert!(verify(KEY, archive, SIGNATURE, "smrec-Windows-x86_64.zip", "9.9.9").is_err());
+        // An old release served as a newer one.
+        assert!(verify(KEY, archive, SIGNATURE, name, "10.0.0").is_err());
+
+        let dir = TempDir::new("update_test");
+        std::fs::write(dir.path().join("smrec"), "new").unwrap();
+        assert!(Command::new("tar")
+            .args(["-czf", name, "smrec"])
+            .current_dir(dir.path())
+            .status()
+            .unwrap()
+            .success());
+        let binary = unpack(&std::fs::read(dir.path().join(name)).unwrap(), name).unwrap();
+        assert_eq!(binary, b"new");
+        let private = private_dir().unwrap();
+        #[cfg(unix)]
+        assert_eq!(
+            std::os::unix::fs::PermissionsExt::mode(
+                &std::fs::metadata(&private).unwrap().permissions()
+            ) & 0o777,
+            0o700
+        );
+        std::fs::remove_dir(&private).unwrap();
+
+        let exe = dir.path().join("installed");
+        std::fs::write(&exe, "old").unwrap();
+        replace(&exe, &binary).unwrap();
+        assert_eq!(std::fs::read(&exe).unwrap(), b"new");
+        assert!(!exe.with_extension("new").exists());
+    }
+}
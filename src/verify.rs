@@ -0,0 +1,214 @@
+use crate::{
+    error::Code,
+    types::Action,
+    wav::{encode_samples, ChannelFormat},
+    WriterHandle,
+};
+use anyhow::{bail, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use cpal::{FromSample, Sample};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufReader, Read},
+};
+
+/// Samples at the end of a file which are read back and compared.
+const TAIL_SAMPLES: usize = 64;
+/// Checks waiting to be done, more are dropped rather than holding up the audio callback.
+const QUEUE: usize = 256;
+
+/// The end of a file as it should be on the disk after its header was updated.
+#[derive(Debug)]
+pub struct Check {
+    path: Utf8PathBuf,
+    /// Frames in the file when its header was updated.
+    frames: u32,
+    /// The last samples which were written, as they are encoded in the file.
+    tail: Vec<u8>,
+    bytes_per_sample: usize,
+}
+
+/// Starts a thread which reads back the end of the files being recorded whenever their headers are updated.
+///
+/// Files whose end does not decode or does not hold what was written to them are reported once per file,
+/// e.g. when a flaky USB enclosure corrupts the recording.
+pub fn start(
+    to_listener_thread: crossbeam::channel::Sender<Action>,
+) -> crossbeam::channel::Sender<Check> {
+    let (checks, received) = crossbeam::channel::bounded::<Check>(QUEUE);
+    std::thread::spawn(move || {
+        let mut reported = HashSet::new();
+        while let Ok(check) = received.recv() {
+            if reported.contains(&check.path) {
+                continue;
+            }
+            if let Err(err) = verify(&check) {
+                let message = format!(
+                    "Verifying {} failed, the storage may be corrupting the recording: {err}",
+                    check.path
+                );
+                eprintln!("Warning: {message}");
+                // Nobody hears it without listeners.
+                to_listener_thread
+                    .send(Action::Err(Code::Storage, message))
+                    .ok();
+                reported.insert(check.path);
+            }
+        }
+    });
+    checks
+}
+
+/// Queues a check of a file whose header was just updated, against the last samples written to it.
+///
+/// Never waits, it is called from the audio callback.
+pub fn queue<T, U>(
+    checks: &crossbeam::channel::Sender<Check>,
+    path: &Utf8Path,
+    writer: &WriterHandle,
+    written: &[T],
+    format: ChannelFormat,
+) where
+    T: Sample,
+    U: Sample + hound::Sample + FromSample<T>,
+    i16: FromSample<T>,
+    i32: FromSample<T>,
+    f32: FromSample<T>,
+{
+    let Ok(guard) = writer.try_lock() else {
+        return;
+    };
+    let Some(writer) = guard.as_ref() else {
+        return;
+    };
+    let (frames, bits_per_sample) = (writer.duration(), writer.spec().bits_per_sample);
+    drop(guard);
+
+    let tail = &written[written.len().saturating_sub(TAIL_SAMPLES)..];
+    if tail.is_empty() {
+        return;
+    }
+    checks
+        .try_send(Check {
+            path: path.to_path_buf(),
+            frames,
+            tail: encode_samples::<T, U>(tail, format, bits_per_sample),
+            bytes_per_sample: usize::from(bits_per_sample.div_ceil(8)),
+        })
+        .ok();
+}
+
+fn verify(check: &Check) -> Result<()> {
+    // What was written is flushed to the disk and read back from it, not from the cache of the system.
+    let file = File::options().read(true).write(true).open(&check.path)?;
+    file.sync_data()?;
+    uncached(&file)?;
+    let mut reader = hound::WavReader::new(BufReader::new(file))?;
+    if reader.duration() < check.frames {
+        bail!(
+            "{} frames are on the disk but {} were written.",
+            reader.duration(),
+            check.frames
+        );
+    }
+    let samples = u32::try_from(check.tail.len() / check.bytes_per_sample)?;
+    reader.seek(check.frames.saturating_sub(samples))?;
+    let mut on_disk = vec![0; check.tail.len()];
+    reader.into_inner().read_exact(&mut on_disk)?;
+    if on_disk != check.tail {
+        bail!("the last samples on the disk differ from the ones written.");
+    }
+    Ok(())
+}
+
+/// Drops the pages of a file from the page cache, they were written out before.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn uncached(file: &File) -> Result<()> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: The descriptor is open for the call.
+    let result = unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    if result != 0 {
+        bail!(
+            "the file can not be read around the cache: {}",
+            std::io::Error::from_raw_os_error(result)
+        );
+    }
+    Ok(())
+}
+
+/// Reads the file around the cache of the system from now on.
+#[cfg(target_os = "macos")]
+fn uncached(file: &File) -> Result<()> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: The descriptor is open for the call.
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1 {
+        bail!(
+            "the file can not be read around the cache: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+    Ok(())
+}
+
+/// Other systems read back through their cache, after it was written out.
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+#[allow(clippy::unnecessary_wraps)]
+const fn uncached(_file: &File) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use crate::wav::{create_writer, update_header, write_input_data};
+    use std::{
+        io::{Seek, SeekFrom, Write},
+        sync::{Arc, Mutex},
+    };
+
+    #[test]
+    fn corrupted_tails_are_found() {
+        let dir = TempDir::new("verify");
+        let path = dir.path().join("Kick.wav");
+
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 24,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let writer = Arc::new(Mutex::new(Some(
+            create_writer(&path, spec, None, false).unwrap(),
+        )));
+        let samples = (0..200_i16)
+            .map(|sample| f32::from(sample) / 200.0)
+            .collect::<Vec<_>>();
+        write_input_data::<f32, f32>(&samples, &writer, ChannelFormat::Int24);
+        update_header(&writer);
+
+        let (checks, received) = crossbeam::channel::unbounded();
+        queue::<f32, f32>(&checks, &path, &writer, &samples, ChannelFormat::Int24);
+        let check = received.try_recv().unwrap();
+        assert_eq!(check.tail.len(), TAIL_SAMPLES * 3);
+        verify(&check).unwrap();
+
+        // Flip a byte of the last sample on the disk.
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        file.seek(SeekFrom::End(-1)).unwrap();
+        let mut byte = [0];
+        file.read_exact(&mut byte).unwrap();
+        file.seek(SeekFrom::End(-1)).unwrap();
+        file.write_all(&[byte[0] ^ 0xFF]).unwrap();
+        assert!(verify(&check).is_err());
+
+        writer.lock().unwrap().take().unwrap().finalize().ok();
+    }
+}
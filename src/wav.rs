@@ -1,10 +1,247 @@
+use crate::{
+    files::Deferred,
+    spool::{Budget, Spool},
+};
+use anyhow::{anyhow, bail, Result};
+use camino::Utf8Path;
 use cpal::{FromSample, Sample};
+use serde::Deserialize;
 use std::{
     fs::File,
-    io::BufWriter,
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    str::FromStr,
     sync::{Arc, Mutex},
 };
 
+/// Writes a WAV file, directly or through the memory of `--ram-buffer`.
+pub type FileWriter = hound::WavWriter<WavFile>;
+
+/// Bytes of the `fact` chunk float files get in front of their data.
+const FACT_LEN: u64 = 12;
+
+/// Where a writer puts its bytes.
+pub enum Sink {
+    File(BufWriter<File>),
+    Spool(Spool),
+    /// A file which is opened only while it is written, for takes with more files than the system lets be open.
+    Deferred(Deferred),
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::File(file) => file.write(buf),
+            Self::Spool(spool) => spool.write(buf),
+            Self::Deferred(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::File(file) => file.flush(),
+            Self::Spool(spool) => spool.flush(),
+            Self::Deferred(file) => file.flush(),
+        }
+    }
+}
+
+impl Seek for Sink {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::File(file) => file.seek(pos),
+            Self::Spool(spool) => spool.seek(pos),
+            Self::Deferred(file) => file.seek(pos),
+        }
+    }
+}
+
+/// The file of a writer, which gives float files a `fact` chunk between the `fmt ` and the `data` chunk.
+///
+/// hound writes neither the chunk nor room for it, so the header is split as it is written and later writes are moved
+/// behind the chunk. The RIFF size and the data length hound rewrites are corrected on the way, the frames of the chunk
+/// are counted along with the data length.
+pub struct WavFile {
+    sink: Sink,
+    /// Where the `data` chunk starts as hound sees the file, none for integer files.
+    data_at: Option<u64>,
+    /// Bytes of a frame, to count the frames of the data.
+    frame_bytes: u32,
+    /// The position as hound sees the file.
+    position: u64,
+}
+
+impl WavFile {
+    fn new(sink: Sink, spec: hound::WavSpec) -> Self {
+        // hound writes WAVE_FORMAT_EXTENSIBLE for more than 2 channels or 16 bits, 24 bytes longer than PCMWAVEFORMAT.
+        let extensible = spec.channels > 2 || spec.bits_per_sample > 16;
+        Self {
+            sink,
+            data_at: (spec.sample_format == hound::SampleFormat::Float).then_some(if extensible {
+                60
+            } else {
+                36
+            }),
+            frame_bytes: u32::from(spec.bits_per_sample.div_ceil(8)) * u32::from(spec.channels),
+            position: 0,
+        }
+    }
+
+    /// Where a position hound sees is in the file.
+    const fn in_file(position: u64, data_at: u64) -> u64 {
+        if position < data_at {
+            position
+        } else {
+            position + FACT_LEN
+        }
+    }
+}
+
+impl Write for WavFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(data_at) = self.data_at else {
+            return self.sink.write(buf);
+        };
+        let at = self.position;
+        let end = at + buf.len() as u64;
+        let word = <[u8; 4]>::try_from(buf).map(u32::from_le_bytes);
+        if at < data_at && end > data_at {
+            // The header, the fact chunk goes in front of its data chunk.
+            #[allow(clippy::cast_possible_truncation)]
+            let (head, data) = buf.split_at((data_at - at) as usize);
+            self.sink.write_all(head)?;
+            self.sink.write_all(&fact_chunk(0))?;
+            self.sink.write_all(data)?;
+        } else if let (4, Ok(riff_size)) = (at, word) {
+            #[allow(clippy::cast_possible_truncation)]
+            let riff_size = riff_size.saturating_add(FACT_LEN as u32);
+            self.sink.write_all(&riff_size.to_le_bytes())?;
+        } else if let (true, Ok(data_length)) = (at == data_at + 4, word) {
+            self.sink.seek(SeekFrom::Start(data_at + 8))?;
+            self.sink
+                .write_all(&(data_length / self.frame_bytes).to_le_bytes())?;
+            self.sink
+                .seek(SeekFrom::Start(Self::in_file(at, data_at)))?;
+            self.sink.write_all(buf)?;
+        } else {
+            self.sink.write_all(buf)?;
+        }
+        self.position = end;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+impl Seek for WavFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let Some(data_at) = self.data_at else {
+            return self.sink.seek(pos);
+        };
+        let position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => {
+                let end = self.sink.seek(SeekFrom::End(offset))?;
+                Some(if end < data_at + FACT_LEN {
+                    end
+                } else {
+                    end - FACT_LEN
+                })
+            }
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start."))?;
+        self.sink
+            .seek(SeekFrom::Start(Self::in_file(position, data_at)))?;
+        self.position = position;
+        Ok(position)
+    }
+}
+
+/// The format a channel is written in, configured per channel in `[channel_formats]`.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub enum ChannelFormat {
+    /// The sample format of the device.
+    #[default]
+    Native,
+    Int16,
+    Int24,
+    Int32,
+    Float32,
+}
+
+impl FromStr for ChannelFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "wav" => Ok(Self::Native),
+            "wav:16" => Ok(Self::Int16),
+            "wav:24" => Ok(Self::Int24),
+            "wav:32" => Ok(Self::Int32),
+            "wav:f32" => Ok(Self::Float32),
+            // Encoders would run next to the writers of every channel, compressed files are made from the takes.
+            "opus" | "flac" | "mp3" | "aac" | "ogg" | "vorbis" => bail!(
+                "Unsupported channel format {s}, smrec writes WAV only and compressed formats are out of scope, convert the files of a take after it is recorded instead."
+            ),
+            _ => bail!(
+                "Unsupported channel format {s}, use one of wav, wav:16, wav:24, wav:32 or wav:f32."
+            ),
+        }
+    }
+}
+
+impl TryFrom<String> for ChannelFormat {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl ChannelFormat {
+    pub fn spec(self, config: &cpal::SupportedStreamConfig) -> hound::WavSpec {
+        let native = spec_from_config(config);
+        let (bits_per_sample, sample_format) = match self {
+            Self::Native => return native,
+            Self::Int16 => (16, hound::SampleFormat::Int),
+            Self::Int24 => (24, hound::SampleFormat::Int),
+            Self::Int32 => (32, hound::SampleFormat::Int),
+            Self::Float32 => (32, hound::SampleFormat::Float),
+        };
+        hound::WavSpec {
+            bits_per_sample,
+            sample_format,
+            ..native
+        }
+    }
+}
+
+/// Creates a writer, failing if the file exists so two recorders can not write into the same file.
+///
+/// Samples are kept in the memory of the take and written by a thread of their own when a budget is given,
+/// deferred writers keep the file closed between writes.
+pub fn create_writer(
+    path: &Utf8Path,
+    spec: hound::WavSpec,
+    budget: Option<&Arc<Budget>>,
+    deferred: bool,
+) -> Result<FileWriter> {
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|err| anyhow!("Can not create {path}: {err}"))?;
+    let file = match budget {
+        Some(budget) => Sink::Spool(Spool::new(file, budget.clone())),
+        None if deferred => Sink::Deferred(Deferred::new(path)),
+        None => Sink::File(BufWriter::new(file)),
+    };
+    Ok(hound::WavWriter::new(WavFile::new(file, spec), spec)?)
+}
+
 pub fn sample_format(format: cpal::SampleFormat) -> hound::SampleFormat {
     if format.is_float() {
         hound::SampleFormat::Float
@@ -13,31 +250,461 @@ pub fn sample_format(format: cpal::SampleFormat) -> hound::SampleFormat {
     }
 }
 
+/// The closest sample format hound can write, for devices which deliver samples it can not.
+pub const fn writable(format: cpal::SampleFormat) -> cpal::SampleFormat {
+    match format {
+        cpal::SampleFormat::U8 => cpal::SampleFormat::I8,
+        cpal::SampleFormat::U16 => cpal::SampleFormat::I16,
+        cpal::SampleFormat::U32 | cpal::SampleFormat::I64 | cpal::SampleFormat::U64 => {
+            cpal::SampleFormat::I32
+        }
+        cpal::SampleFormat::F64 => cpal::SampleFormat::F32,
+        format => format,
+    }
+}
+
+/// Warns when the samples of the device lose precision to be written, or refuses to record them when `strict`.
+pub fn check_narrowing(format: cpal::SampleFormat, strict: bool) -> Result<()> {
+    let written = writable(format);
+    if written.sample_size() >= format.sample_size() {
+        return Ok(());
+    }
+    if strict {
+        bail!("The device delivers {format:?} samples which can not be written to WAV files, run without --strict to record them as {written:?}.");
+    }
+    println!("Warning: the device delivers {format:?} samples which can not be written to WAV files, recording them as {written:?}.");
+    Ok(())
+}
+
 #[allow(clippy::cast_possible_truncation)]
 pub fn spec_from_config(config: &cpal::SupportedStreamConfig) -> hound::WavSpec {
+    let format = writable(config.sample_format());
     hound::WavSpec {
         // Hardcoded because channels will be always mono.
         channels: 1,
         sample_rate: config.sample_rate().0 as _,
-        // Truncation is safe because we're only using 8, 16, 24 and 32 bit samples.
-        bits_per_sample: (config.sample_format().sample_size() * 8) as _,
-        sample_format: sample_format(config.sample_format()),
+        // Truncation is safe because we're only writing 8, 16, 24 and 32 bit samples.
+        bits_per_sample: (format.sample_size() * 8) as _,
+        sample_format: sample_format(format),
     }
 }
 
 pub fn write_input_data<T, U>(
     input: &[T],
-    writer: &Arc<Mutex<Option<hound::WavWriter<BufWriter<File>>>>>,
+    writer: &Arc<Mutex<Option<FileWriter>>>,
+    format: ChannelFormat,
 ) where
     T: Sample,
     U: Sample + hound::Sample + FromSample<T>,
+    i16: FromSample<T>,
+    i32: FromSample<T>,
+    f32: FromSample<T>,
 {
     if let Ok(mut guard) = writer.try_lock() {
         if let Some(writer) = guard.as_mut() {
             for &sample in input {
-                let sample: U = U::from_sample(sample);
-                writer.write_sample(sample).ok();
+                match format {
+                    ChannelFormat::Native => writer.write_sample(U::from_sample(sample)).ok(),
+                    ChannelFormat::Int16 => writer.write_sample(i16::from_sample(sample)).ok(),
+                    // 24 bit samples are the most significant bits of a 32 bit sample.
+                    ChannelFormat::Int24 => writer.write_sample(i32::from_sample(sample) >> 8).ok(),
+                    ChannelFormat::Int32 => writer.write_sample(i32::from_sample(sample)).ok(),
+                    ChannelFormat::Float32 => writer.write_sample(f32::from_sample(sample)).ok(),
+                };
             }
         }
     }
 }
+
+/// Encodes samples the way [`write_input_data`] writes them to a file of the given bit depth.
+pub fn encode_samples<T, U>(input: &[T], format: ChannelFormat, bits_per_sample: u16) -> Vec<u8>
+where
+    T: Sample,
+    U: Sample + hound::Sample + FromSample<T>,
+    i16: FromSample<T>,
+    i32: FromSample<T>,
+    f32: FromSample<T>,
+{
+    let byte_width = bits_per_sample.div_ceil(8);
+    let mut bytes = Vec::with_capacity(input.len() * usize::from(byte_width));
+    for &sample in input {
+        let bytes = &mut bytes;
+        match format {
+            ChannelFormat::Native => hound::Sample::write_padded(
+                U::from_sample(sample),
+                bytes,
+                bits_per_sample,
+                byte_width,
+            ),
+            ChannelFormat::Int16 => hound::Sample::write_padded(
+                i16::from_sample(sample),
+                bytes,
+                bits_per_sample,
+                byte_width,
+            ),
+            ChannelFormat::Int24 => hound::Sample::write_padded(
+                i32::from_sample(sample) >> 8,
+                bytes,
+                bits_per_sample,
+                byte_width,
+            ),
+            ChannelFormat::Int32 => hound::Sample::write_padded(
+                i32::from_sample(sample),
+                bytes,
+                bits_per_sample,
+                byte_width,
+            ),
+            ChannelFormat::Float32 => hound::Sample::write_padded(
+                f32::from_sample(sample),
+                bytes,
+                bits_per_sample,
+                byte_width,
+            ),
+        }
+        .ok();
+    }
+    bytes
+}
+
+/// Rewrites the header with the current length so other programs can open the file while it is recorded.
+pub fn update_header(writer: &Arc<Mutex<Option<FileWriter>>>) {
+    if let Ok(mut guard) = writer.try_lock() {
+        if let Some(writer) = guard.as_mut() {
+            writer.flush().ok();
+        }
+    }
+}
+
+/// A marker dropped while recording, at a frame of the file it belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Marker {
+    pub frame: u32,
+    pub label: Option<String>,
+}
+
+/// Appends chunks to a finalized file and updates the RIFF size.
+///
+/// hound does not write chunks such as `cue ` or `iXML`, they are added after the data chunk and readers find them by scanning.
+pub fn append_chunks(path: &Utf8Path, chunks: &[u8]) -> Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)?;
+    let mut riff = [0; 4];
+    file.read_exact(&mut riff)?;
+    if &riff != b"RIFF" {
+        bail!("{path} is not a RIFF file, chunks can not be appended.");
+    }
+
+    let mut length = file.seek(SeekFrom::End(0))?;
+    // Chunks start at even offsets.
+    if length % 2 == 1 {
+        file.write_all(&[0])?;
+        length += 1;
+    }
+    let Ok(riff_size) = u32::try_from(length + chunks.len() as u64 - 8) else {
+        bail!("{path} is too large to append chunks.");
+    };
+    file.write_all(chunks)?;
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&riff_size.to_le_bytes())?;
+    Ok(())
+}
+
+/// Makes `cue ` and `LIST`/`adtl` chunks of the markers.
+pub fn marker_chunks(markers: &[Marker]) -> Vec<u8> {
+    let mut cue = Vec::new();
+    cue.extend_from_slice(
+        &u32::try_from(markers.len())
+            .unwrap_or(u32::MAX)
+            .to_le_bytes(),
+    );
+    let mut labels = b"adtl".to_vec();
+    for (id, marker) in (1_u32..).zip(markers) {
+        cue.extend_from_slice(&id.to_le_bytes());
+        // Position in the play order.
+        cue.extend_from_slice(&marker.frame.to_le_bytes());
+        cue.extend_from_slice(b"data");
+        // Chunk and block starts, 0 for a single data chunk.
+        cue.extend_from_slice(&0_u32.to_le_bytes());
+        cue.extend_from_slice(&0_u32.to_le_bytes());
+        cue.extend_from_slice(&marker.frame.to_le_bytes());
+
+        if let Some(label) = &marker.label {
+            let mut text = id.to_le_bytes().to_vec();
+            text.extend_from_slice(label.as_bytes());
+            text.push(0);
+            push_chunk(&mut labels, *b"labl", &text);
+        }
+    }
+
+    let mut chunks = Vec::new();
+    push_chunk(&mut chunks, *b"cue ", &cue);
+    if labels.len() > 4 {
+        push_chunk(&mut chunks, *b"LIST", &labels);
+    }
+    chunks
+}
+
+/// Makes an `iXML` chunk of a document.
+pub fn ixml_chunk(xml: &str) -> Vec<u8> {
+    let mut chunk = Vec::new();
+    push_chunk(&mut chunk, *b"iXML", xml.as_bytes());
+    chunk
+}
+
+/// Makes a `LIST` chunk of `INFO` tags, empty values are left out.
+///
+/// Players, DAWs and converters such as ffmpeg read them as the title, album, date and track number and carry them
+/// over into FLAC and Opus tags.
+pub fn info_chunk(tags: &[([u8; 4], &str)]) -> Vec<u8> {
+    let mut info = b"INFO".to_vec();
+    for (id, value) in tags {
+        if value.is_empty() {
+            continue;
+        }
+        // Values are zero terminated strings.
+        let mut text = value.as_bytes().to_vec();
+        text.push(0);
+        push_chunk(&mut info, *id, &text);
+    }
+    let mut chunk = Vec::new();
+    push_chunk(&mut chunk, *b"LIST", &info);
+    chunk
+}
+
+/// Makes the `fact` chunk which files in formats other than integer PCM must have, with their length in frames.
+///
+/// [`WavFile`] puts it in front of the data chunk, where readers which do not scan for chunks look for it.
+pub fn fact_chunk(frames: u32) -> Vec<u8> {
+    let mut chunk = Vec::new();
+    push_chunk(&mut chunk, *b"fact", &frames.to_le_bytes());
+    chunk
+}
+
+/// The samples of mono files summed as they are read, the longest file sets the length.
+pub struct Mixdown {
+    files: Vec<Box<dyn Iterator<Item = f32> + Send>>,
+}
+
+impl Iterator for Mixdown {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let mut mixed = None;
+        for file in &mut self.files {
+            if let Some(sample) = file.next() {
+                *mixed.get_or_insert(0.0) += sample;
+            }
+        }
+        mixed
+    }
+}
+
+/// Opens the files of a take for their mixdown, with the sample rate of the first of them.
+///
+/// A file which can not be read further ends there.
+pub fn mixdown(paths: &[impl AsRef<Utf8Path>]) -> Result<(Mixdown, u32)> {
+    let mut files = Vec::<Box<dyn Iterator<Item = f32> + Send>>::new();
+    let mut sample_rate = None;
+    for path in paths {
+        let reader = hound::WavReader::open(path.as_ref())?;
+        let spec = reader.spec();
+        sample_rate.get_or_insert(spec.sample_rate);
+        files.push(match spec.sample_format {
+            hound::SampleFormat::Float => {
+                Box::new(reader.into_samples::<f32>().map_while(Result::ok))
+            }
+            hound::SampleFormat::Int => {
+                #[allow(clippy::cast_precision_loss)]
+                let full_scale = (1_u32 << (spec.bits_per_sample - 1)) as f32;
+                #[allow(clippy::cast_precision_loss)]
+                Box::new(
+                    reader
+                        .into_samples::<i32>()
+                        .map_while(Result::ok)
+                        .map(move |sample| sample as f32 / full_scale),
+                )
+            }
+        });
+    }
+    let sample_rate = sample_rate.ok_or_else(|| anyhow!("There are no files to mix down."))?;
+    Ok((Mixdown { files }, sample_rate))
+}
+
+/// Appends a chunk, padded to an even length.
+fn push_chunk(to: &mut Vec<u8>, id: [u8; 4], data: &[u8]) {
+    to.extend_from_slice(&id);
+    to.extend_from_slice(&u32::try_from(data.len()).unwrap_or(u32::MAX).to_le_bytes());
+    to.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        to.push(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn markers_are_appended_as_chunks() {
+        let dir = TempDir::new("markers");
+        let path = dir.path().join("chn_1.wav");
+
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for sample in 0..100_i16 {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        append_chunks(
+            &path,
+            &marker_chunks(&[
+                Marker {
+                    frame: 10,
+                    label: Some("Verse".to_string()),
+                },
+                Marker {
+                    frame: 60,
+                    label: None,
+                },
+            ]),
+        )
+        .unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        let word = |at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+        assert_eq!(word(4) as usize, data.len() - 8);
+        let cue = data.windows(4).position(|id| id == b"cue ").unwrap();
+        assert_eq!(word(cue + 4), 4 + 2 * 24);
+        assert_eq!(word(cue + 8), 2);
+        assert_eq!(word(cue + 12 + 24 + 4), 60);
+        let labl = data.windows(4).position(|id| id == b"labl").unwrap();
+        assert_eq!(&data[labl + 12..labl + 18], b"Verse\0");
+
+        // The samples are still readable.
+        let samples = hound::WavReader::open(&path)
+            .unwrap()
+            .into_samples::<i16>()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(samples, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn float_files_get_a_fact_chunk() {
+        let dir = TempDir::new("fact");
+        let path = dir.path().join("chn_1.wav");
+
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = create_writer(&path, spec, None, false).unwrap();
+        for _ in 0..60 {
+            writer.write_sample(0.5_f32).unwrap();
+        }
+        // The header is rewritten while the file is recorded.
+        writer.flush().unwrap();
+        for _ in 0..40 {
+            writer.write_sample(0.5_f32).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        let word = |at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+        // WAVE_FORMAT_EXTENSIBLE with the IEEE float sub format.
+        assert_eq!(&data[20..22], &0xFFFE_u16.to_le_bytes());
+        assert_eq!(&data[44..46], &3_u16.to_le_bytes());
+        // The fact chunk sits between the fmt and the data chunk.
+        assert_eq!(&data[60..64], b"fact");
+        assert_eq!((word(64), word(68)), (4, 100));
+        assert_eq!(&data[72..76], b"data");
+        assert_eq!(word(76), 400);
+        assert_eq!(word(4) as usize, data.len() - 8);
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.duration(), 100);
+        assert!(reader
+            .samples::<f32>()
+            .all(|sample| (sample.unwrap() - 0.5).abs() < f32::EPSILON));
+    }
+
+    #[test]
+    fn tags_are_info_chunks() {
+        let chunk = info_chunk(&[(*b"INAM", "Kick"), (*b"IPRD", ""), (*b"ITRK", "12")]);
+        let mut expected = b"LIST".to_vec();
+        expected.extend_from_slice(&30_u32.to_le_bytes());
+        expected.extend_from_slice(b"INFO");
+        expected.extend_from_slice(b"INAM");
+        expected.extend_from_slice(&5_u32.to_le_bytes());
+        // Padded to an even length.
+        expected.extend_from_slice(b"Kick\0\0");
+        expected.extend_from_slice(b"ITRK");
+        expected.extend_from_slice(&3_u32.to_le_bytes());
+        expected.extend_from_slice(b"12\0\0");
+        assert_eq!(chunk, expected);
+    }
+
+    #[test]
+    fn growing_files_are_readable() {
+        let dir = TempDir::new("growing");
+        let path = dir.path().join("chn_1.wav");
+
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let writer = Arc::new(Mutex::new(Some(
+            create_writer(&path, spec, None, false).unwrap(),
+        )));
+        let write = |samples: std::ops::Range<i16>| {
+            let mut guard = writer.lock().unwrap();
+            for sample in samples {
+                guard.as_mut().unwrap().write_sample(sample).unwrap();
+            }
+        };
+
+        write(0..100);
+        update_header(&writer);
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.duration(), 100);
+
+        write(100..150);
+        update_header(&writer);
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.duration(), 150);
+
+        writer.lock().unwrap().take().unwrap().finalize().unwrap();
+    }
+
+    #[test]
+    fn unwritable_samples_are_narrowed() {
+        let config = cpal::SupportedStreamConfig::new(
+            1,
+            cpal::SampleRate(48000),
+            cpal::SupportedBufferSize::Unknown,
+            cpal::SampleFormat::F64,
+        );
+        let spec = spec_from_config(&config);
+        assert_eq!(spec.bits_per_sample, 32);
+        assert_eq!(spec.sample_format, hound::SampleFormat::Float);
+        assert_eq!(writable(cpal::SampleFormat::U16), cpal::SampleFormat::I16);
+        assert_eq!(writable(cpal::SampleFormat::I32), cpal::SampleFormat::I32);
+
+        assert!(check_narrowing(cpal::SampleFormat::F64, false).is_ok());
+        assert!(check_narrowing(cpal::SampleFormat::I64, true).is_err());
+        // Unsigned samples are written as signed ones of the same size without losing anything.
+        assert!(check_narrowing(cpal::SampleFormat::U32, true).is_ok());
+    }
+}